@@ -0,0 +1,196 @@
+//! Flashcards for [Anki](https://apps.ankiweb.net) from study notes, written
+//! as a text file Anki imports with File > Import.
+//!
+//! A card is a `Q:` line followed by an `A:` line, each of which may go on
+//! over the lines after it until a blank line, or a paragraph with Anki's
+//! own cloze deletions, like `{{c1::Paris}} is the capital of France`. Code
+//! blocks are left alone.
+
+use crate::error::NotesError;
+use crate::export;
+use crate::note::Note;
+use regex::Regex;
+use std::fs;
+use std::path::Path;
+use std::sync::LazyLock;
+
+static CLOZE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\{\{c\d+::").unwrap());
+
+/// Tells Anki how to read the file.
+const HEADER: &str = "#separator:tab\n#html:true\n#notetype column:1\n#tags column:4\n";
+
+#[derive(PartialEq, Debug)]
+pub enum Card {
+    Basic { front: String, back: String },
+    Cloze { text: String },
+}
+
+enum Block {
+    None,
+    Question(Vec<String>),
+    Answer(Vec<String>, Vec<String>),
+    Paragraph(Vec<String>),
+}
+
+/// Strips `marker`, such as `Q:`, from the start of `line`, also when the
+/// line is a list item.
+fn strip_marker<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+    let line = line.trim_start();
+    let line = line
+        .strip_prefix("- ")
+        .or_else(|| line.strip_prefix("* "))
+        .unwrap_or(line);
+    let rest = line.get(..marker.len())?;
+    rest.eq_ignore_ascii_case(marker)
+        .then(|| line[marker.len()..].trim())
+}
+
+/// The flashcards in `content`, in the order they appear.
+pub fn parse_cards(content: &str) -> Vec<Card> {
+    let mut cards = Vec::new();
+    let mut block = Block::None;
+    let finish = |block: Block, cards: &mut Vec<Card>| match block {
+        Block::Answer(front, back) => cards.push(Card::Basic {
+            front: front.join("\n"),
+            back: back.join("\n"),
+        }),
+        Block::Paragraph(lines) => {
+            let text = lines.join("\n");
+            if CLOZE.is_match(&text) {
+                cards.push(Card::Cloze { text });
+            }
+        }
+        // A question without an answer isn't a card
+        Block::None | Block::Question(_) => {}
+    };
+    let mut in_code = false;
+    for line in content.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+            finish(std::mem::replace(&mut block, Block::None), &mut cards);
+            continue;
+        }
+        if in_code {
+            continue;
+        }
+        if line.trim().is_empty() {
+            finish(std::mem::replace(&mut block, Block::None), &mut cards);
+        } else if let Some(question) = strip_marker(line, "Q:") {
+            finish(std::mem::replace(&mut block, Block::None), &mut cards);
+            block = Block::Question(vec![question.to_string()]);
+        } else if let Some(answer) = strip_marker(line, "A:") {
+            block = match block {
+                Block::Question(front) => Block::Answer(front, vec![answer.to_string()]),
+                other => {
+                    finish(other, &mut cards);
+                    Block::None
+                }
+            };
+        } else {
+            let line = line.trim().to_string();
+            match &mut block {
+                Block::Question(lines) | Block::Answer(_, lines) | Block::Paragraph(lines) => {
+                    lines.push(line)
+                }
+                Block::None => block = Block::Paragraph(vec![line]),
+            }
+        }
+    }
+    finish(block, &mut cards);
+    cards
+}
+
+/// A field of a card, as the HTML Anki shows. Tabs would end the field.
+fn field(text: &str) -> String {
+    export::escape_html(text)
+        .replace('\t', " ")
+        .replace('\n', "<br>")
+}
+
+/// Writes the flashcards in `notes` to `path`, tagged with their note's
+/// tags, and returns how many there are. Locked notes are skipped.
+pub fn export(notes: &[Note], path: &Path) -> Result<usize, NotesError> {
+    let mut file = HEADER.to_string();
+    let mut count = 0;
+    for note in notes.iter().filter(|note| !note.locked) {
+        let tags: Vec<String> = note
+            .tags
+            .iter()
+            .map(|tag| tag.split_whitespace().collect::<Vec<_>>().join("_"))
+            .collect();
+        let tags = tags.join(" ");
+        for card in parse_cards(&note.content) {
+            let row = match card {
+                Card::Basic { front, back } => {
+                    format!("Basic\t{}\t{}\t{}\n", field(&front), field(&back), tags)
+                }
+                Card::Cloze { text } => format!("Cloze\t{}\t\t{}\n", field(&text), tags),
+            };
+            file.push_str(&row);
+            count += 1;
+        }
+    }
+    if count == 0 {
+        return Err(NotesError::Invalid(
+            "The notes have no Q: and A: pairs or cloze deletions".into(),
+        ));
+    }
+    fs::write(path, file)
+        .map_err(|e| NotesError::Io(format!("Failed to write {}: {}", path.display(), e)))?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note;
+    use tempfile::TempDir;
+
+    #[test]
+    fn finds_question_answer_pairs_and_clozes() {
+        let content = "# Geography\n\n\
+                       Q: Capital of France?\n\
+                       A: Paris\n\
+                       on the Seine\n\n\
+                       - q: Longest river?\n\
+                       - a: The Nile\n\n\
+                       Q: Unanswered\n\n\
+                       {{c1::Canberra}} is the capital\n\
+                       of {{c2::Australia}}.\n\n\
+                       ```\nQ: in code\nA: skipped\n```\n";
+        assert_eq!(
+            parse_cards(content),
+            [
+                Card::Basic {
+                    front: "Capital of France?".into(),
+                    back: "Paris\non the Seine".into()
+                },
+                Card::Basic {
+                    front: "Longest river?".into(),
+                    back: "The Nile".into()
+                },
+                Card::Cloze {
+                    text: "{{c1::Canberra}} is the capital\nof {{c2::Australia}}.".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_an_anki_import_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("cards.txt");
+        let tags = vec!["study".to_string(), "world map".to_string()];
+        let notes = [
+            note::new("A".into(), "Q: 1 < 2?\nA: Yes".into(), tags, 1),
+            note::new("B".into(), "{{c1::H2O}} is water".into(), Vec::new(), 1),
+        ];
+        assert_eq!(export(&notes, &path).unwrap(), 2);
+        let file = fs::read_to_string(&path).unwrap();
+        assert!(file.starts_with(HEADER));
+        assert!(file.contains("Basic\t1 &lt; 2?\tYes\tstudy world_map\n"));
+        assert!(file.contains("Cloze\t{{c1::H2O}} is water\t\t\n"));
+
+        assert!(matches!(export(&[], &path), Err(NotesError::Invalid(_))));
+    }
+}
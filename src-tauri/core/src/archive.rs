@@ -0,0 +1,349 @@
+//! The whole vault as a single zip archive, for restoring it or moving it to
+//! another machine. Unlike a backup, which copies the data directory as it
+//! is, an archive can be read without the app and imported into a workspace
+//! that already has notes:
+//!
+//! - manifest.json records the format version, when the archive was made and
+//!   the notebooks
+//! - notes/ holds each note as `<title>.md`, with its fields in YAML
+//!   frontmatter the way the Markdown storage backend writes them
+//! - attachments/ holds each attachment the notes refer to, named after its
+//!   hash
+//!
+//! Notes are written decrypted, apart from the sealed content of locked ones.
+
+use crate::error::NotesError;
+use crate::import::{ImportStrategy, Imported, Importer};
+use crate::note::{self, slugify, Note};
+use crate::notebooks::{Notebook, Notebooks};
+use crate::storage;
+use crate::store::NotesStore;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path};
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+const MANIFEST: &str = "manifest.json";
+const NOTES_DIR: &str = "notes";
+const ATTACHMENTS_DIR: &str = "attachments";
+/// Bumped when archives change in a way older versions can't read.
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    exported_at: i64,
+    #[serde(default)]
+    notebooks: Vec<Notebook>,
+}
+
+/// What to do with a note in the archive that's already in the workspace.
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictStrategy {
+    KeepExisting,
+    Overwrite,
+    /// Keeps whichever version was edited last.
+    KeepNewer,
+    /// Imports the archived note alongside the existing one, with a new id.
+    Duplicate,
+}
+
+#[derive(Serialize, Default, Debug, PartialEq)]
+pub struct ArchiveReport {
+    /// Notes that weren't in the workspace, duplicates included.
+    pub imported: usize,
+    /// Existing notes replaced by the archived version.
+    pub updated: usize,
+    /// Archived notes left out in favor of the existing version.
+    pub skipped: usize,
+    /// Archived notes passed over as copies of other notes here.
+    pub duplicates: usize,
+    pub attachments: usize,
+    pub notebooks: usize,
+}
+
+/// Writes every note in `store`, trashed ones included, its attachments and
+/// `notebooks` to a new archive at `path`. Returns how many notes it holds.
+pub fn export(
+    store: &NotesStore,
+    notebooks: &[Notebook],
+    path: &Path,
+) -> Result<usize, NotesError> {
+    let notes = store.all_notes()?;
+    let partial = path.with_extension("zip.partial");
+    let written = write_archive(store, notes, notebooks, &partial).and_then(|_| {
+        fs::rename(&partial, path)
+            .map_err(|e| NotesError::Io(format!("Failed to write archive: {}", e)))
+    });
+    if let Err(e) = written {
+        let _ = fs::remove_file(&partial);
+        return Err(e);
+    }
+    Ok(notes.len())
+}
+
+fn write_archive(
+    store: &NotesStore,
+    notes: &[Note],
+    notebooks: &[Notebook],
+    dest: &Path,
+) -> Result<(), NotesError> {
+    let failed = |e: io::Error| NotesError::Io(format!("Failed to write archive: {}", e));
+    let mut zip = ZipWriter::new(File::create(dest).map_err(failed)?);
+    let options = SimpleFileOptions::default();
+    let manifest = Manifest {
+        version: FORMAT_VERSION,
+        exported_at: Utc::now().timestamp(),
+        notebooks: notebooks.to_vec(),
+    };
+    let json = serde_json::to_vec_pretty(&manifest)
+        .map_err(|e| NotesError::Serde(format!("Failed to serialize manifest: {}", e)))?;
+    zip.start_file(MANIFEST, options)
+        .map_err(io::Error::from)
+        .map_err(failed)?;
+    zip.write_all(&json).map_err(failed)?;
+
+    let mut names = HashSet::new();
+    for note in notes {
+        let slug = slugify(&note.title);
+        let mut name = format!("{}/{}.md", NOTES_DIR, slug);
+        let mut n = 2;
+        while !names.insert(name.clone()) {
+            name = format!("{}/{}-{}.md", NOTES_DIR, slug, n);
+            n += 1;
+        }
+        zip.start_file(name, options)
+            .map_err(io::Error::from)
+            .map_err(failed)?;
+        zip.write_all(storage::render_markdown_note(note)?.as_bytes())
+            .map_err(failed)?;
+    }
+
+    let attachments: BTreeSet<&str> = notes
+        .iter()
+        .flat_map(|note| &note.attachments)
+        .map(|attachment| attachment.id.as_str())
+        .collect();
+    for hash in attachments {
+        let content = store.read_attachment(hash)?;
+        zip.start_file(format!("{}/{}", ATTACHMENTS_DIR, hash), options)
+            .map_err(io::Error::from)
+            .map_err(failed)?;
+        zip.write_all(&content).map_err(failed)?;
+    }
+    zip.finish()
+        .map_err(io::Error::from)
+        .and_then(|mut file| file.flush())
+        .map_err(failed)
+}
+
+fn invalid(e: impl std::fmt::Display) -> NotesError {
+    NotesError::Invalid(format!("Not a valid vault archive: {}", e))
+}
+
+/// Imports the archive at `path` into `store` and `notebooks`, settling
+/// notes that are already there with `strategy`, and those that aren't but
+/// match other notes with `duplicates`.
+pub fn import(
+    store: &mut NotesStore,
+    notebooks: &mut Notebooks,
+    path: &Path,
+    strategy: ConflictStrategy,
+    duplicates: ImportStrategy,
+) -> Result<ArchiveReport, NotesError> {
+    let file =
+        File::open(path).map_err(|e| NotesError::Io(format!("Failed to open archive: {}", e)))?;
+    let mut zip = ZipArchive::new(file).map_err(invalid)?;
+    let manifest: Manifest = {
+        let entry = zip.by_name(MANIFEST).map_err(invalid)?;
+        serde_json::from_reader(entry).map_err(invalid)?
+    };
+    if manifest.version > FORMAT_VERSION {
+        return Err(NotesError::Invalid(
+            "This archive was made by a newer version of the app".into(),
+        ));
+    }
+
+    // Read in full first, so a damaged archive changes nothing
+    let mut notes = Vec::new();
+    let mut attachments = Vec::new();
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).map_err(invalid)?;
+        let Some(name) = entry.enclosed_name() else {
+            continue;
+        };
+        let parts: Vec<String> = name
+            .components()
+            .filter_map(|part| match part {
+                Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect();
+        let mut content = Vec::new();
+        match parts.as_slice() {
+            [dir, file] if dir == NOTES_DIR && file.ends_with(".md") => {
+                entry.read_to_end(&mut content).map_err(invalid)?;
+                let text = String::from_utf8(content)
+                    .map_err(|_| invalid(format!("{} is not UTF-8 text", file)))?;
+                let note = storage::parse_markdown_note(&text)
+                    .map_err(|e| invalid(format!("{}: {}", file, e)))?;
+                if !note::is_valid_id(&note.id) {
+                    return Err(invalid(format!("{} has an invalid note id", file)));
+                }
+                notes.push(note);
+            }
+            [dir, hash] if dir == ATTACHMENTS_DIR => {
+                entry.read_to_end(&mut content).map_err(invalid)?;
+                if storage::attachment_hash(&content) != *hash {
+                    return Err(invalid(format!(
+                        "attachment {} doesn't match its hash",
+                        hash
+                    )));
+                }
+                attachments.push((hash.clone(), content));
+            }
+            _ => {}
+        }
+    }
+
+    let mut report = ArchiveReport::default();
+    for (hash, content) in attachments {
+        if !store.has_attachment(&hash)? {
+            store.put_attachment(&content)?;
+            report.attachments += 1;
+        }
+    }
+    report.notebooks = notebooks.add_missing(manifest.notebooks)?;
+
+    let mut importer = Importer::new(store, duplicates)?;
+    for theirs in notes {
+        let Ok(ours) = store.get(&theirs.id) else {
+            match importer.add(store, theirs)? {
+                Imported::Created(_) => report.imported += 1,
+                Imported::Updated(_) => report.updated += 1,
+                Imported::Duplicate(_) => report.duplicates += 1,
+            }
+            continue;
+        };
+        let replace = match strategy {
+            _ if ours == theirs => false,
+            ConflictStrategy::KeepExisting => false,
+            ConflictStrategy::Overwrite => true,
+            ConflictStrategy::KeepNewer => theirs.updated_at > ours.updated_at,
+            ConflictStrategy::Duplicate => {
+                store.insert(Note {
+                    id: note::new_id(),
+                    ..theirs
+                })?;
+                report.imported += 1;
+                continue;
+            }
+        };
+        if replace {
+            store.upsert(theirs)?;
+            report.updated += 1;
+        } else {
+            report.skipped += 1;
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+    use tempfile::TempDir;
+
+    fn workspace() -> (TempDir, NotesStore, Notebooks) {
+        let dir = TempDir::new().unwrap();
+        let store = NotesStore::new(Storage::open(dir.path()).unwrap()).unwrap();
+        let notebooks = Notebooks::load(dir.path()).unwrap();
+        (dir, store, notebooks)
+    }
+
+    #[test]
+    fn moves_a_vault_through_an_archive() {
+        let (dir, mut store, mut notebooks) = workspace();
+        let notebook = notebooks.create("Work", None).unwrap();
+        let mut note = note::new("Plan".into(), "# Steps\n".into(), vec!["todo".into()], 1);
+        note.notebook_id = Some(notebook.id.clone());
+        let hash = store.put_attachment(b"diagram").unwrap();
+        note.attachments.push(note::Attachment {
+            id: hash.clone(),
+            name: "diagram.png".into(),
+            mime: "image/png".into(),
+            size: 7,
+            added_at: 1,
+            text: None,
+        });
+        let id = note.id.clone();
+        store.insert(note).unwrap();
+        store
+            .insert(note::new("Plan".into(), String::new(), Vec::new(), 2))
+            .unwrap();
+        let archive = dir.path().join("vault.zip");
+        assert_eq!(export(&store, notebooks.all(), &archive).unwrap(), 2);
+
+        let (_other_dir, mut other, mut other_notebooks) = workspace();
+        let report = import(
+            &mut other,
+            &mut other_notebooks,
+            &archive,
+            ConflictStrategy::KeepExisting,
+            ImportStrategy::SkipDuplicates,
+        )
+        .unwrap();
+        assert_eq!(
+            report,
+            ArchiveReport {
+                imported: 2,
+                attachments: 1,
+                notebooks: 1,
+                ..ArchiveReport::default()
+            }
+        );
+        assert!(other.get(&id).unwrap() == store.get(&id).unwrap());
+        assert_eq!(other.read_attachment(&hash).unwrap(), b"diagram");
+        assert_eq!(other_notebooks.get(&notebook.id).unwrap().name, "Work");
+
+        other
+            .update(&id, |note| note.title = "Mine".into())
+            .unwrap();
+        let again = |other: &mut NotesStore, notebooks: &mut Notebooks, strategy| {
+            import(
+                other,
+                notebooks,
+                &archive,
+                strategy,
+                ImportStrategy::AlwaysCreate,
+            )
+            .unwrap()
+        };
+        let report = again(
+            &mut other,
+            &mut other_notebooks,
+            ConflictStrategy::KeepNewer,
+        );
+        assert_eq!((report.updated, report.skipped), (0, 2));
+        let report = again(
+            &mut other,
+            &mut other_notebooks,
+            ConflictStrategy::Duplicate,
+        );
+        assert_eq!(report.imported, 1);
+        let report = again(
+            &mut other,
+            &mut other_notebooks,
+            ConflictStrategy::Overwrite,
+        );
+        assert_eq!(report.updated, 1);
+        assert_eq!(other.get(&id).unwrap().title, "Plan");
+        assert_eq!(other.all_notes().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,327 @@
+//! Summaries and title suggestions written by a language model, for those
+//! who set one up: any service with an OpenAI-compatible chat completions
+//! API, or a local [Ollama](https://ollama.com). Nothing is sent anywhere
+//! until it's set up.
+//!
+//! The service's settings are kept in assistant.json in the workspace's data
+//! directory, and its API key, if it needs one, in the OS keychain. Replies
+//! are streamed, so they can be shown as they're written.
+
+use crate::error::NotesError;
+use crate::fsutil;
+use crate::keychain::{self, Secret};
+use crate::note::Note;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use url::Url;
+
+const TIMEOUT: Duration = Duration::from_secs(300);
+/// The rest of a longer note is left out of the prompt.
+const MAX_PROMPT_CHARS: usize = 32_000;
+const MAX_TITLE_CHARS: usize = 100;
+const USER_AGENT: &str = concat!("min_notes/", env!("CARGO_PKG_VERSION"));
+
+const SUMMARY_PROMPT: &str = "Summarize the note the user sends in a few sentences, \
+    in the note's language. Reply with the summary only.";
+const TITLE_PROMPT: &str = "Suggest a short title for the note the user sends, in the \
+    note's language. Reply with the title only, without quotes.";
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AssistantApi {
+    /// `POST /chat/completions`, as OpenAI and most hosted services have it.
+    #[default]
+    OpenAi,
+    /// Ollama's own `POST /api/chat`.
+    Ollama,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AssistantConfig {
+    #[serde(default)]
+    pub api: AssistantApi,
+    /// Where the API is, e.g. `https://api.openai.com/v1` or
+    /// `http://localhost:11434`.
+    pub url: String,
+    pub model: String,
+}
+
+fn config_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("assistant.json")
+}
+
+/// Returns the assistant settings of the workspace in `data_dir`, or `None`
+/// if it isn't set up.
+pub fn load_config(data_dir: &Path) -> Result<Option<AssistantConfig>, NotesError> {
+    let path = config_path(data_dir);
+    if !path.exists() {
+        return Ok(None);
+    }
+    fsutil::read_with_backup(&path, |content| {
+        serde_json::from_str(content)
+            .map_err(|e| NotesError::Serde(format!("Failed to parse assistant settings: {}", e)))
+    })
+    .map(Some)
+}
+
+/// Saves `config` for workspace `workspace` in `data_dir`, with `api_key`
+/// kept in the keychain or, without one, any key kept before removed.
+pub fn configure(
+    data_dir: &Path,
+    workspace: &str,
+    config: &AssistantConfig,
+    api_key: Option<&str>,
+) -> Result<(), NotesError> {
+    let url = Url::parse(config.url.trim())
+        .map_err(|_| NotesError::Invalid(format!("{} is not a URL", config.url)))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(NotesError::Invalid(format!(
+            "{} is not an http or https URL",
+            config.url
+        )));
+    }
+    if config.model.trim().is_empty() {
+        return Err(NotesError::Invalid("Model cannot be empty".into()));
+    }
+    match api_key.map(str::trim).filter(|key| !key.is_empty()) {
+        Some(key) => keychain::set(Secret::AssistantKey, workspace, key)?,
+        None => {
+            keychain::clear(Secret::AssistantKey, workspace)?;
+        }
+    }
+    let json = serde_json::to_string_pretty(config)
+        .map_err(|e| NotesError::Serde(format!("Failed to serialize assistant settings: {}", e)))?;
+    fsutil::write_with_backup(&config_path(data_dir), json.as_bytes())
+        .map_err(|e| NotesError::Io(format!("Failed to write assistant settings: {}", e)))
+}
+
+/// The assistant set up for a workspace, ready to be asked.
+pub struct Assistant {
+    config: AssistantConfig,
+    api_key: Option<String>,
+}
+
+impl Assistant {
+    /// Returns the assistant of workspace `workspace` in `data_dir`.
+    pub fn load(data_dir: &Path, workspace: &str) -> Result<Self, NotesError> {
+        let config = load_config(data_dir)?
+            .ok_or_else(|| NotesError::Invalid("Set up the assistant first".into()))?;
+        let api_key = keychain::get(Secret::AssistantKey, workspace)?;
+        Ok(Assistant { config, api_key })
+    }
+
+    /// Writes a summary of `note`, passing the summary so far to `partial`
+    /// as it's written.
+    pub fn summarize(&self, note: &Note, partial: impl FnMut(&str)) -> Result<String, NotesError> {
+        if note.locked {
+            return Err(NotesError::Invalid(
+                "Remove the note's password before summarizing it".into(),
+            ));
+        }
+        let text = format!("# {}\n\n{}", note.title, note.content);
+        let summary = self.complete(SUMMARY_PROMPT, &text, partial)?;
+        Ok(summary.trim().to_string())
+    }
+
+    /// Suggests a title for a note of `content`, passing the suggestion so
+    /// far to `partial` as it's written.
+    pub fn suggest_title(
+        &self,
+        content: &str,
+        partial: impl FnMut(&str),
+    ) -> Result<String, NotesError> {
+        if content.trim().is_empty() {
+            return Err(NotesError::Invalid("The note is empty".into()));
+        }
+        let title = self.complete(TITLE_PROMPT, content, partial)?;
+        Ok(clean_title(&title))
+    }
+
+    /// Sends `text` to the model with the instructions in `system`, passing
+    /// the reply so far to `partial` as it comes in, and returns the reply.
+    fn complete(
+        &self,
+        system: &str,
+        text: &str,
+        mut partial: impl FnMut(&str),
+    ) -> Result<String, NotesError> {
+        let text = match text.char_indices().nth(MAX_PROMPT_CHARS) {
+            Some((end, _)) => &text[..end],
+            None => text,
+        };
+        let body = serde_json::json!({
+            "model": self.config.model,
+            "messages": [
+                { "role": "system", "content": system },
+                { "role": "user", "content": text },
+            ],
+            "stream": true,
+        });
+        let base = self.config.url.trim().trim_end_matches('/');
+        let url = match self.config.api {
+            AssistantApi::OpenAi => format!("{}/chat/completions", base),
+            AssistantApi::Ollama => format!("{}/api/chat", base),
+        };
+        let agent = ureq::AgentBuilder::new()
+            .timeout(TIMEOUT)
+            .user_agent(USER_AGENT)
+            .build();
+        let mut request = agent.post(&url).set("Content-Type", "application/json");
+        if let Some(key) = &self.api_key {
+            request = request.set("Authorization", &format!("Bearer {}", key));
+        }
+        let response = match request.send_string(&body.to_string()) {
+            Ok(response) => response,
+            Err(ureq::Error::Status(401 | 403, _)) => {
+                return Err(NotesError::Network(
+                    "The assistant service rejected the API key".into(),
+                ))
+            }
+            Err(ureq::Error::Status(status, response)) => {
+                let reply = response.into_string().unwrap_or_default();
+                return Err(NotesError::Network(format!(
+                    "The assistant service failed ({}): {}",
+                    status,
+                    error_message(&reply)
+                )));
+            }
+            Err(e) => {
+                return Err(NotesError::Network(format!(
+                    "Failed to reach the assistant service: {}",
+                    e
+                )))
+            }
+        };
+
+        let mut reply = String::new();
+        for line in BufReader::new(response.into_reader()).lines() {
+            let line = line.map_err(|e| {
+                NotesError::Network(format!("Failed to read the assistant's reply: {}", e))
+            })?;
+            match parse_chunk(self.config.api, &line)? {
+                Chunk::Text(text) if !text.is_empty() => {
+                    reply.push_str(&text);
+                    partial(&reply);
+                }
+                Chunk::Done => break,
+                _ => {}
+            }
+        }
+        Ok(reply)
+    }
+}
+
+#[derive(PartialEq, Debug)]
+enum Chunk {
+    Text(String),
+    Done,
+    /// A keep-alive, comment, or some other line without text.
+    Nothing,
+}
+
+/// Reads one line of a streamed reply: server-sent events of OpenAI's, or
+/// a JSON object per line of Ollama's.
+fn parse_chunk(api: AssistantApi, line: &str) -> Result<Chunk, NotesError> {
+    let line = line.trim();
+    let json = match api {
+        AssistantApi::OpenAi => match line.strip_prefix("data:").map(str::trim) {
+            Some("[DONE]") => return Ok(Chunk::Done),
+            Some(json) => json,
+            None => return Ok(Chunk::Nothing),
+        },
+        AssistantApi::Ollama if line.is_empty() => return Ok(Chunk::Nothing),
+        AssistantApi::Ollama => line,
+    };
+    let chunk: Value = serde_json::from_str(json)
+        .map_err(|e| NotesError::Serde(format!("Failed to parse the assistant's reply: {}", e)))?;
+    if chunk.get("error").is_some() {
+        return Err(NotesError::Network(format!(
+            "The assistant service failed: {}",
+            error_message(json)
+        )));
+    }
+    let text = match api {
+        AssistantApi::OpenAi => chunk.pointer("/choices/0/delta/content"),
+        AssistantApi::Ollama => chunk.pointer("/message/content"),
+    };
+    match text.and_then(Value::as_str) {
+        Some(text) => Ok(Chunk::Text(text.to_string())),
+        None if chunk.get("done") == Some(&Value::Bool(true)) => Ok(Chunk::Done),
+        None => Ok(Chunk::Nothing),
+    }
+}
+
+/// The message of an error reply, which both APIs have in `error`, as a
+/// string or in an object.
+fn error_message(reply: &str) -> String {
+    let json: Option<Value> = serde_json::from_str(reply).ok();
+    let error = json.as_ref().and_then(|json| json.get("error"));
+    error
+        .and_then(|error| error.get("message").unwrap_or(error).as_str())
+        .unwrap_or(reply)
+        .trim()
+        .to_string()
+}
+
+/// The first line of a suggested title, without the quotes or Markdown
+/// heading models tend to add anyway.
+fn clean_title(title: &str) -> String {
+    let title = title
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+    let title = title
+        .trim_start_matches('#')
+        .trim()
+        .trim_matches(['"', '\'', '“', '”', '*'])
+        .trim();
+    match title.char_indices().nth(MAX_TITLE_CHARS) {
+        Some((end, _)) => title[..end].trim_end().to_string(),
+        None => title.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_streamed_replies() {
+        let open_ai = |line| parse_chunk(AssistantApi::OpenAi, line).unwrap();
+        assert_eq!(
+            open_ai(r#"data: {"choices":[{"delta":{"content":"Hel"}}]}"#),
+            Chunk::Text("Hel".into())
+        );
+        assert_eq!(
+            open_ai(r#"data: {"choices":[{"delta":{}}]}"#),
+            Chunk::Nothing
+        );
+        assert_eq!(open_ai(": keep-alive"), Chunk::Nothing);
+        assert_eq!(open_ai("data: [DONE]"), Chunk::Done);
+
+        let ollama = |line| parse_chunk(AssistantApi::Ollama, line).unwrap();
+        assert_eq!(
+            ollama(r#"{"message":{"role":"assistant","content":"lo"},"done":false}"#),
+            Chunk::Text("lo".into())
+        );
+        assert_eq!(ollama(r#"{"done":true}"#), Chunk::Done);
+        assert!(matches!(
+            parse_chunk(AssistantApi::Ollama, r#"{"error":"model not found"}"#),
+            Err(NotesError::Network(message)) if message.ends_with("model not found")
+        ));
+    }
+
+    #[test]
+    fn cleans_suggested_titles() {
+        assert_eq!(clean_title("\n\"Weekly standup\"\nExtra"), "Weekly standup");
+        assert_eq!(clean_title("# **Trip plans**"), "Trip plans");
+        assert_eq!(
+            error_message(r#"{"error":{"message":"Rate limited"}}"#),
+            "Rate limited"
+        );
+    }
+}
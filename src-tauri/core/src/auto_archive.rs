@@ -0,0 +1,67 @@
+//! Archiving notes nobody has touched in a while, once a day, so the note
+//! list keeps to what's current. Pinned and favorite notes are kept where
+//! they are however old, since they were put there on purpose.
+
+use crate::note::Note;
+use chrono::{DateTime, Months};
+
+/// The notes outside the trash and archive last edited over `months` months
+/// before `now`, oldest first, leaving out pinned and favorite ones. None
+/// with `months` 0.
+pub fn stale(notes: &[Note], months: u32, now: i64) -> Vec<&Note> {
+    if months == 0 {
+        return Vec::new();
+    }
+    let Some(cutoff) = DateTime::from_timestamp(now, 0)
+        .and_then(|now| now.checked_sub_months(Months::new(months)))
+        .map(|cutoff| cutoff.timestamp())
+    else {
+        return Vec::new();
+    };
+    let mut stale: Vec<&Note> = notes
+        .iter()
+        .filter(|note| note.trashed_at.is_none() && !note.archived)
+        .filter(|note| !note.pinned && !note.favorite)
+        .filter(|note| note.updated_at < cutoff)
+        .collect();
+    stale.sort_by_key(|note| note.updated_at);
+    stale
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note;
+
+    #[test]
+    fn finds_notes_untouched_for_months() {
+        let day = 24 * 3600;
+        // 2024-06-15
+        let now = 1_718_409_600;
+        let new = |title: &str, updated_at: i64| {
+            let mut note = note::new(title.into(), String::new(), Vec::new(), 1);
+            note.updated_at = updated_at;
+            note
+        };
+        let mut pinned = new("Pinned", 1);
+        pinned.pinned = true;
+        let mut archived = new("Archived", 1);
+        archived.archived = true;
+        let notes = [
+            new("Recent", now - 10 * day),
+            new("Last year", now - 400 * day),
+            new("Spring", now - 100 * day),
+            pinned,
+            archived,
+        ];
+        let titles = |months| -> Vec<&str> {
+            stale(&notes, months, now)
+                .into_iter()
+                .map(|note| note.title.as_str())
+                .collect()
+        };
+        assert_eq!(titles(3), ["Last year", "Spring"]);
+        assert_eq!(titles(12), ["Last year"]);
+        assert!(titles(0).is_empty());
+    }
+}
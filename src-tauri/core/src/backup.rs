@@ -0,0 +1,276 @@
+//! Zip snapshots of a workspace's data files, kept in its backups/ folder.
+//! Each backup is named after the time it was taken so the newest ones can
+//! be found, and pruned, without opening them.
+
+use crate::datadir;
+use crate::error::NotesError;
+use chrono::{NaiveDateTime, Utc};
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use tracing::warn;
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+pub const BACKUPS_DIR: &str = "backups";
+const NAME_PREFIX: &str = "backup-";
+const NAME_FORMAT: &str = "%Y%m%d-%H%M%S";
+/// Where a backup is unpacked before it replaces the current data.
+const RESTORE_DIR: &str = "restoring";
+
+#[derive(Serialize, Clone)]
+pub struct BackupInfo {
+    pub path: PathBuf,
+    pub created_at: i64,
+    pub size: u64,
+}
+
+fn backups_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join(BACKUPS_DIR)
+}
+
+/// Returns the backups of the workspace in `data_dir`, newest first.
+pub fn list(data_dir: &Path) -> Result<Vec<BackupInfo>, NotesError> {
+    let dir = backups_dir(data_dir);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let entries = fs::read_dir(&dir)
+        .map_err(|e| NotesError::Io(format!("Failed to read backups directory: {}", e)))?;
+    let mut backups: Vec<BackupInfo> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            let stamp = name
+                .to_str()?
+                .strip_prefix(NAME_PREFIX)?
+                .strip_suffix(".zip")?;
+            let created_at = NaiveDateTime::parse_from_str(stamp, NAME_FORMAT).ok()?;
+            Some(BackupInfo {
+                path: entry.path(),
+                created_at: created_at.and_utc().timestamp(),
+                size: entry.metadata().ok()?.len(),
+            })
+        })
+        .collect();
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.created_at));
+    Ok(backups)
+}
+
+/// Zips the data files in `data_dir` into a new backup, then deletes all but
+/// the newest `keep` backups. The caller must keep the notes from changing
+/// while this runs.
+pub fn create(data_dir: &Path, keep: usize) -> Result<BackupInfo, NotesError> {
+    let dir = backups_dir(data_dir);
+    fs::create_dir_all(&dir)
+        .map_err(|e| NotesError::Io(format!("Failed to create backups directory: {}", e)))?;
+    let now = Utc::now();
+    let path = dir.join(format!("{}{}.zip", NAME_PREFIX, now.format(NAME_FORMAT)));
+    // Written under a temporary name so a failed backup isn't listed
+    let partial = path.with_extension("zip.partial");
+
+    let written = write_archive(data_dir, &partial).and_then(|_| fs::rename(&partial, &path));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&partial);
+        return Err(NotesError::Io(format!("Failed to write backup: {}", e)));
+    }
+
+    for old in list(data_dir)?.iter().skip(keep.max(1)) {
+        if let Err(e) = fs::remove_file(&old.path) {
+            warn!("Failed to remove old backup {}: {}", old.path.display(), e);
+        }
+    }
+    let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+    Ok(BackupInfo {
+        path,
+        created_at: now.timestamp(),
+        size,
+    })
+}
+
+/// The data files a backup holds: everything but the backups themselves.
+fn snapshot_entries(data_dir: &Path) -> Vec<PathBuf> {
+    datadir::entries(data_dir)
+        .into_iter()
+        .filter(|path| path.file_name().is_some_and(|name| name != BACKUPS_DIR))
+        .collect()
+}
+
+fn write_archive(data_dir: &Path, dest: &Path) -> io::Result<()> {
+    let mut zip = ZipWriter::new(File::create(dest)?);
+    let options = SimpleFileOptions::default();
+    let mut pending = snapshot_entries(data_dir);
+    while let Some(path) = pending.pop() {
+        let name = path
+            .strip_prefix(data_dir)
+            .map_err(io::Error::other)?
+            .components()
+            .map(|part| part.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if path.is_dir() {
+            zip.add_directory(name, options)?;
+            for entry in fs::read_dir(&path)? {
+                pending.push(entry?.path());
+            }
+        } else {
+            zip.start_file(name, options)?;
+            io::copy(&mut File::open(&path)?, &mut zip)?;
+        }
+    }
+    zip.finish()?.flush()
+}
+
+/// Unpacks the backup at `archive` next to the data in `data_dir`, checking
+/// that it only holds data files. Returns the folder it was unpacked into,
+/// to be passed to `replace_data`.
+pub fn unpack(data_dir: &Path, archive: &Path) -> Result<PathBuf, NotesError> {
+    let file =
+        File::open(archive).map_err(|e| NotesError::Io(format!("Failed to open backup: {}", e)))?;
+    let mut zip = ZipArchive::new(file)
+        .map_err(|e| NotesError::Invalid(format!("Not a valid backup: {}", e)))?;
+
+    let staging = backups_dir(data_dir).join(RESTORE_DIR);
+    if staging.exists() {
+        fs::remove_dir_all(&staging)
+            .map_err(|e| NotesError::Io(format!("Failed to clear restore folder: {}", e)))?;
+    }
+    let unpacked = (0..zip.len()).try_for_each(|i| {
+        let mut entry = zip
+            .by_index(i)
+            .map_err(|e| NotesError::Invalid(format!("Not a valid backup: {}", e)))?;
+        let name = entry
+            .enclosed_name()
+            .filter(|name| is_data_file(name))
+            .ok_or_else(|| NotesError::Invalid("Backup contains files other than notes".into()))?;
+        let dest = staging.join(name);
+        let written = if entry.is_dir() {
+            fs::create_dir_all(&dest)
+        } else {
+            dest.parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| File::create(&dest))
+                .and_then(|mut file| io::copy(&mut entry, &mut file).map(|_| ()))
+        };
+        written.map_err(|e| NotesError::Io(format!("Failed to unpack backup: {}", e)))
+    });
+    if let Err(e) = unpacked {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+    Ok(staging)
+}
+
+/// Whether `name`, a path inside a backup, falls under one of the data
+/// files a backup is made of.
+fn is_data_file(name: &Path) -> bool {
+    match name.components().next() {
+        Some(Component::Normal(first)) => {
+            first != BACKUPS_DIR && datadir::is_data_entry(&first.to_string_lossy())
+        }
+        _ => false,
+    }
+}
+
+/// Replaces the data files in `data_dir` with those unpacked into `staging`,
+/// then removes `staging`. Nothing may have the notes open meanwhile.
+pub fn replace_data(data_dir: &Path, staging: &Path) -> Result<(), NotesError> {
+    for path in snapshot_entries(data_dir) {
+        let removed = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        removed
+            .map_err(|e| NotesError::Io(format!("Failed to remove {}: {}", path.display(), e)))?;
+    }
+    let entries = fs::read_dir(staging)
+        .map_err(|e| NotesError::Io(format!("Failed to read restore folder: {}", e)))?;
+    for entry in entries {
+        let entry =
+            entry.map_err(|e| NotesError::Io(format!("Failed to read restore folder: {}", e)))?;
+        fs::rename(entry.path(), data_dir.join(entry.file_name())).map_err(|e| {
+            NotesError::Io(format!("Failed to restore {:?}: {}", entry.file_name(), e))
+        })?;
+    }
+    if let Err(e) = fs::remove_dir_all(staging) {
+        warn!("Failed to remove restore folder: {}", e);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn restores_data_files() {
+        let dir = TempDir::new().unwrap();
+        let data = dir.path();
+        write(&data.join("notes.db"), "db");
+        write(&data.join("history/a.json"), "[]");
+        write(&data.join("settings.json"), "kept out");
+        let backup = create(data, 10).unwrap();
+
+        write(&data.join("notes.db"), "changed");
+        fs::remove_dir_all(data.join("history")).unwrap();
+        write(&data.join("vault.json"), "added later");
+
+        let staging = unpack(data, &backup.path).unwrap();
+        assert!(!staging.join("settings.json").exists());
+        replace_data(data, &staging).unwrap();
+        assert_eq!(fs::read_to_string(data.join("notes.db")).unwrap(), "db");
+        assert_eq!(
+            fs::read_to_string(data.join("history/a.json")).unwrap(),
+            "[]"
+        );
+        assert!(!data.join("vault.json").exists());
+        assert!(!staging.exists());
+        // The backups themselves are left alone
+        assert_eq!(list(data).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn keeps_newest_backups() {
+        let dir = TempDir::new().unwrap();
+        let data = dir.path();
+        for stamp in ["20240101-000000", "20240102-000000", "20240103-000000"] {
+            write(
+                &data.join(BACKUPS_DIR).join(format!("backup-{}.zip", stamp)),
+                "",
+            );
+        }
+        write(&data.join(BACKUPS_DIR).join("notes.txt"), "not a backup");
+
+        let latest = create(data, 2).unwrap();
+        let backups = list(data).unwrap();
+        assert_eq!(backups.len(), 2);
+        assert_eq!(backups[0].path, latest.path);
+        assert!(backups[1].path.ends_with("backup-20240103-000000.zip"));
+        assert!(data.join(BACKUPS_DIR).join("notes.txt").exists());
+    }
+
+    #[test]
+    fn rejects_archive_with_other_files() {
+        let dir = TempDir::new().unwrap();
+        let archive = dir.path().join("other.zip");
+        let mut zip = ZipWriter::new(File::create(&archive).unwrap());
+        zip.start_file("../outside.txt", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"escape").unwrap();
+        zip.finish().unwrap();
+
+        let data = dir.path().join("data");
+        let result = unpack(&data, &archive);
+        assert!(matches!(result, Err(NotesError::Invalid(_))));
+        assert!(!dir.path().join("outside.txt").exists());
+        assert!(!data.join(BACKUPS_DIR).join(RESTORE_DIR).exists());
+    }
+}
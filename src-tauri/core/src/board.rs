@@ -0,0 +1,133 @@
+//! Kanban boards of notes, with a column for each value of a metadata field
+//! such as `status`, grouped here so the frontend only has to draw them.
+
+use crate::note::Note;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// A note as a card on a board.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct Card {
+    pub id: String,
+    pub title: String,
+    pub tags: Vec<String>,
+    pub color: Option<String>,
+    pub pinned: bool,
+    pub updated_at: i64,
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct Column {
+    /// The field's value on the column's cards, or `None` for the column of
+    /// notes without the field.
+    pub value: Option<String>,
+    pub cards: Vec<Card>,
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct Board {
+    pub key: String,
+    pub columns: Vec<Column>,
+}
+
+/// Groups `notes` by their value of metadata field `key`. The columns named
+/// in `order` come first, in that order and even if empty, then the other
+/// values alphabetically, then the notes without the field if there are
+/// any. Archived notes are left out. In each column, pinned cards come
+/// first, then the most recently updated.
+pub fn board(notes: &[Note], key: &str, order: &[String]) -> Board {
+    let mut by_value: BTreeMap<Option<&str>, Vec<&Note>> = BTreeMap::new();
+    for value in order {
+        by_value.entry(Some(value.as_str())).or_default();
+    }
+    for note in notes
+        .iter()
+        .filter(|note| note.trashed_at.is_none() && !note.archived)
+    {
+        let value = note.meta.get(key).map(String::as_str);
+        by_value.entry(value).or_default().push(note);
+    }
+
+    let rank = |value: &Option<&str>| match value {
+        Some(value) => order
+            .iter()
+            .position(|named| named == value)
+            .unwrap_or(order.len()),
+        None => order.len() + 1,
+    };
+    let mut columns: Vec<(Option<&str>, Vec<&Note>)> = by_value.into_iter().collect();
+    // The sort is stable, so the values left out of `order` stay alphabetical
+    columns.sort_by_key(|(value, _)| rank(value));
+
+    let columns = columns
+        .into_iter()
+        .map(|(value, mut notes)| {
+            notes.sort_by_key(|note| (!note.pinned, std::cmp::Reverse(note.updated_at)));
+            Column {
+                value: value.map(str::to_string),
+                cards: notes
+                    .into_iter()
+                    .map(|note| Card {
+                        id: note.id.clone(),
+                        title: note.title.clone(),
+                        tags: note.tags.clone(),
+                        color: note.color.clone(),
+                        pinned: note.pinned,
+                        updated_at: note.updated_at,
+                    })
+                    .collect(),
+            }
+        })
+        .collect();
+    Board {
+        key: key.to_string(),
+        columns,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note;
+
+    #[test]
+    fn groups_notes_by_field() {
+        let new = |title: &str, status: Option<&str>, updated_at: i64| {
+            let mut note = note::new(title.into(), String::new(), Vec::new(), 1);
+            note.updated_at = updated_at;
+            if let Some(status) = status {
+                note.meta.insert("status".into(), status.into());
+            }
+            note
+        };
+        let mut archived = new("Old", Some("done"), 1);
+        archived.archived = true;
+        let notes = [
+            new("Ship", Some("doing"), 1),
+            new("Plan", Some("doing"), 2),
+            new("Idea", None, 1),
+            new("Blocked", Some("waiting"), 1),
+            archived,
+        ];
+        let order = ["todo".to_string(), "doing".into(), "done".into()];
+        let board = board(&notes, "status", &order);
+        let columns: Vec<(Option<&str>, Vec<&str>)> = board
+            .columns
+            .iter()
+            .map(|column| {
+                let titles = column.cards.iter().map(|card| card.title.as_str());
+                (column.value.as_deref(), titles.collect())
+            })
+            .collect();
+        assert_eq!(
+            columns,
+            [
+                (Some("todo"), vec![]),
+                (Some("doing"), vec!["Plan", "Ship"]),
+                (Some("done"), vec![]),
+                (Some("waiting"), vec!["Blocked"]),
+                (None, vec!["Idea"]),
+            ]
+        );
+    }
+}
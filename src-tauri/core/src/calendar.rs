@@ -0,0 +1,130 @@
+//! Notes by date, for the calendar: the notes of a period, and how many
+//! were created and last edited on each day of a year.
+
+use crate::error::NotesError;
+use crate::note::Note;
+use crate::query::DateField;
+use chrono::{Datelike, NaiveDate, TimeZone};
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Serialize, PartialEq, Debug)]
+pub struct CalendarDay {
+    /// `YYYY-MM-DD`, in local time.
+    pub date: String,
+    pub created: usize,
+    /// Notes last edited that day.
+    pub updated: usize,
+}
+
+fn date_of(note: &Note, field: DateField) -> i64 {
+    match field {
+        DateField::Created => note.created_at,
+        DateField::Updated => note.updated_at,
+    }
+}
+
+/// The notes among `notes` whose `field` is from `start` until `end`, as
+/// Unix times, most recent first.
+pub fn notes_between(
+    notes: &[Note],
+    start: i64,
+    end: i64,
+    field: DateField,
+) -> Result<Vec<Note>, NotesError> {
+    if start >= end {
+        return Err(NotesError::Invalid(
+            "The period must end after it starts".into(),
+        ));
+    }
+    let mut found: Vec<Note> = notes
+        .iter()
+        .filter(|note| (start..end).contains(&date_of(note, field)))
+        .cloned()
+        .collect();
+    found.sort_by_key(|note| std::cmp::Reverse(date_of(note, field)));
+    Ok(found)
+}
+
+/// Every day of `year` in time zone `zone`, with how many of `notes` were
+/// created and last edited on it.
+pub fn heatmap<Tz: TimeZone>(
+    notes: &[Note],
+    year: i32,
+    zone: &Tz,
+) -> Result<Vec<CalendarDay>, NotesError> {
+    let first = NaiveDate::from_ymd_opt(year, 1, 1)
+        .ok_or_else(|| NotesError::Invalid(format!("{} is not a year", year)))?;
+    let day = |time: i64| {
+        zone.timestamp_opt(time, 0)
+            .single()
+            .map(|time| time.date_naive())
+    };
+    let mut counts: HashMap<NaiveDate, (usize, usize)> = HashMap::new();
+    for note in notes {
+        if let Some(created) = day(note.created_at) {
+            counts.entry(created).or_default().0 += 1;
+        }
+        if let Some(updated) = day(note.updated_at) {
+            counts.entry(updated).or_default().1 += 1;
+        }
+    }
+    Ok(first
+        .iter_days()
+        .take_while(|date| date.year() == year)
+        .map(|date| {
+            let (created, updated) = counts.get(&date).copied().unwrap_or_default();
+            CalendarDay {
+                date: date.format("%Y-%m-%d").to_string(),
+                created,
+                updated,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note;
+    use chrono::{FixedOffset, Utc};
+
+    #[test]
+    fn counts_notes_by_local_day() {
+        // 2024-02-29 23:30 in UTC, which is 2024-03-01 at UTC+2
+        let time = Utc
+            .with_ymd_and_hms(2024, 2, 29, 23, 30, 0)
+            .unwrap()
+            .timestamp();
+        let mut edited = note::new("Edited".into(), String::new(), Vec::new(), time - 86400);
+        edited.updated_at = time;
+        let notes = [
+            note::new("New".into(), String::new(), Vec::new(), time),
+            edited,
+        ];
+
+        let zone = FixedOffset::east_opt(2 * 3600).unwrap();
+        let days = heatmap(&notes, 2024, &zone).unwrap();
+        assert_eq!(days.len(), 366);
+        assert_eq!(
+            days[60],
+            CalendarDay {
+                date: "2024-03-01".into(),
+                created: 1,
+                updated: 2
+            }
+        );
+        assert_eq!((days[59].created, days[59].updated), (1, 0));
+
+        let titles = |field| {
+            notes_between(&notes, time - 3600, time + 1, field)
+                .unwrap()
+                .into_iter()
+                .map(|note| note.title)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(titles(DateField::Created), ["New"]);
+        assert_eq!(titles(DateField::Updated), ["New", "Edited"]);
+        assert!(notes_between(&notes, time, time, DateField::Created).is_err());
+    }
+}
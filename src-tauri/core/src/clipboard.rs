@@ -0,0 +1,102 @@
+//! Clipboard capture: text copied in any app is appended to the Inbox note
+//! while it's on, for collecting snippets without switching windows. The
+//! clipboard is polled from lib.rs; this decides which copies to keep.
+//!
+//! A copy is kept once the clipboard has held it for `SETTLE_TIME`, so of a
+//! quick run of copies only the last is. Text already an entry in the inbox
+//! isn't added again, and neither is what was copied before capture started.
+
+use std::time::{Duration, Instant};
+
+/// The title of the note copies are appended to, created if there's none.
+pub const INBOX_TITLE: &str = "Inbox";
+
+/// How long a copy must stay on the clipboard to be kept.
+pub const SETTLE_TIME: Duration = Duration::from_millis(1500);
+
+/// Follows what's on the clipboard, picking out the copies to keep.
+pub struct ClipboardWatch {
+    /// The last copy kept, or what was on the clipboard at the start.
+    kept: Option<String>,
+    /// A new copy, and when it was first seen.
+    pending: Option<(String, Instant)>,
+}
+
+impl ClipboardWatch {
+    /// Starts from `current`, the text on the clipboard now, which isn't
+    /// kept.
+    pub fn new(current: Option<String>) -> Self {
+        ClipboardWatch {
+            kept: current,
+            pending: None,
+        }
+    }
+
+    /// Notes that the clipboard held `current` at `now`, returning it if it's
+    /// a copy to keep.
+    pub fn observe(&mut self, current: Option<String>, now: Instant) -> Option<String> {
+        let Some(current) = current.filter(|text| !text.trim().is_empty()) else {
+            self.pending = None;
+            return None;
+        };
+        if self.kept.as_ref() == Some(&current) {
+            self.pending = None;
+            return None;
+        }
+        match &self.pending {
+            Some((text, since)) if *text == current => {
+                if now.duration_since(*since) < SETTLE_TIME {
+                    return None;
+                }
+                self.pending = None;
+                self.kept = Some(current.clone());
+                Some(current)
+            }
+            _ => {
+                self.pending = Some((current, now));
+                None
+            }
+        }
+    }
+}
+
+/// `content` with `text` appended as an entry of its own, or `None` if it's
+/// one already. Entries are separated by blank lines.
+pub fn append(content: &str, text: &str) -> Option<String> {
+    let text = text.trim();
+    if content.split("\n\n").any(|entry| entry.trim() == text) {
+        return None;
+    }
+    let content = content.trim_end();
+    if content.is_empty() {
+        return Some(text.to_string());
+    }
+    Some(format!("{}\n\n{}", content, text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_copies_once_they_settle() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let text = |text: &str| Some(text.to_string());
+        let mut watch = ClipboardWatch::new(text("Before"));
+        assert_eq!(watch.observe(text("Before"), at(5000)), None);
+
+        assert_eq!(watch.observe(text("First"), at(0)), None);
+        assert_eq!(watch.observe(text("Second"), at(500)), None);
+        assert_eq!(watch.observe(text("Second"), at(1000)), None);
+        assert_eq!(watch.observe(text("Second"), at(2000)), text("Second"));
+        assert_eq!(watch.observe(text("Second"), at(4000)), None);
+        assert_eq!(watch.observe(text("  "), at(4000)), None);
+        assert_eq!(watch.observe(text("  "), at(6000)), None);
+
+        assert_eq!(append("", " code "), text("code"));
+        assert_eq!(append("code\n", "link"), text("code\n\nlink"));
+        assert_eq!(append("code\n\nlink", "link"), None);
+        assert_eq!(append("linked list", "link"), text("linked list\n\nlink"));
+    }
+}
@@ -0,0 +1,307 @@
+//! A small HTTP server on 127.0.0.1 through which a browser extension or
+//! bookmarklet can save clippings as new notes while the app is running.
+//!
+//! `POST /clip` takes a JSON body such as
+//! `{"title": "…", "text": "…", "url": "…", "tags": ["…"]}`, every field
+//! optional, and must carry `Authorization: Bearer <token>` with the token
+//! shown in the app's settings. It answers `201` with `{"id": "…"}`.
+
+use crate::error::NotesError;
+use crate::fsutil;
+use crate::keychain::{self, Secret};
+use crate::workspace;
+use serde::Deserialize;
+use serde_json::json;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use tiny_http::{Header, Method, Response, Server};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Larger request bodies are refused unread.
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// What the extension sends.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct Clip {
+    pub title: Option<String>,
+    /// The selected text, or whatever else should go in the note.
+    pub text: Option<String>,
+    /// The page it was clipped from.
+    pub url: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl Clip {
+    /// The title of the note: the one given, else the page's host.
+    pub fn title(&self) -> String {
+        let given = self.title.as_deref().map(str::trim).unwrap_or_default();
+        if !given.is_empty() {
+            return given.to_string();
+        }
+        self.url
+            .as_deref()
+            .and_then(|url| url::Url::parse(url).ok())
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_else(|| "Web clip".to_string())
+    }
+
+    /// The content of the note: the text, followed by a link to the page.
+    pub fn content(&self) -> String {
+        let text = self.text.as_deref().unwrap_or_default().trim_end();
+        match self.url.as_deref().map(str::trim) {
+            Some(url) if !url.is_empty() && text.is_empty() => format!("Source: <{}>\n", url),
+            Some(url) if !url.is_empty() => format!("{}\n\nSource: <{}>\n", text, url),
+            _ if text.is_empty() => String::new(),
+            _ => format!("{}\n", text),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenFile {
+    token: String,
+}
+
+/// Returns the token saved in the keychain, first saving a new one if there's
+/// none or `regenerate` is set. A token older versions saved at `legacy_path`
+/// is moved into the keychain.
+pub fn token(legacy_path: &Path, regenerate: bool) -> Result<String, NotesError> {
+    if !regenerate {
+        if let Some(token) = keychain::get(Secret::ClipperToken, workspace::DEFAULT_ID)? {
+            return Ok(token);
+        }
+    }
+    let token = if !regenerate && legacy_path.exists() {
+        let saved: TokenFile = fsutil::read_with_backup(legacy_path, |content| {
+            serde_json::from_str(content)
+                .map_err(|e| NotesError::Serde(format!("Failed to parse clipper token: {}", e)))
+        })?;
+        saved.token
+    } else {
+        format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+    };
+    keychain::set(Secret::ClipperToken, workspace::DEFAULT_ID, &token)?;
+    if legacy_path.exists() {
+        fsutil::remove_with_backup(legacy_path)
+            .map_err(|e| NotesError::Io(format!("Failed to remove clipper token file: {}", e)))?;
+    }
+    Ok(token)
+}
+
+/// Compares in time that depends only on the lengths, so the token can't be
+/// guessed a byte at a time.
+fn same_token(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// The parts of a request that decide the response.
+struct Request<'a> {
+    method: &'a Method,
+    path: &'a str,
+    authorization: Option<&'a str>,
+    body: &'a [u8],
+}
+
+/// Returns the status and JSON body to answer `request` with, saving the
+/// clip with `save` if it's a valid one.
+fn respond(
+    request: &Request,
+    token: &str,
+    save: &dyn Fn(Clip) -> Result<String, NotesError>,
+) -> (u16, serde_json::Value) {
+    let error = |status, message: &str| (status, json!({ "error": message }));
+    if request.path.split('?').next() != Some("/clip") {
+        return error(404, "Not found");
+    }
+    if *request.method != Method::Post {
+        return error(405, "Use POST");
+    }
+    let authorized = request
+        .authorization
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| same_token(given.trim(), token));
+    if !authorized {
+        return error(401, "Missing or wrong token");
+    }
+    let clip: Clip = match serde_json::from_slice(request.body) {
+        Ok(clip) => clip,
+        Err(e) => return error(400, &format!("Invalid clip: {}", e)),
+    };
+    match save(clip) {
+        Ok(id) => (201, json!({ "id": id })),
+        Err(e) => error(500, &e.to_string()),
+    }
+}
+
+/// A running clipper server, stopped when dropped.
+pub struct Clipper {
+    server: Arc<Server>,
+    thread: Option<JoinHandle<()>>,
+    pub port: u16,
+}
+
+impl Clipper {
+    /// Listens on 127.0.0.1:`port` and saves each clip with `save`, which
+    /// returns the new note's id.
+    pub fn start(
+        port: u16,
+        token: String,
+        save: impl Fn(Clip) -> Result<String, NotesError> + Send + 'static,
+    ) -> Result<Self, NotesError> {
+        let server = Server::http(("127.0.0.1", port))
+            .map_err(|e| NotesError::Io(format!("Failed to listen on port {}: {}", port, e)))?;
+        let server = Arc::new(server);
+        let listening = Arc::clone(&server);
+        let thread = thread::spawn(move || {
+            for request in listening.incoming_requests() {
+                serve(request, &token, &save);
+            }
+        });
+        info!("Web clipper listening on 127.0.0.1:{}", port);
+        Ok(Clipper {
+            server,
+            thread: Some(thread),
+            port,
+        })
+    }
+}
+
+impl Drop for Clipper {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn serve(
+    mut request: tiny_http::Request,
+    token: &str,
+    save: &dyn Fn(Clip) -> Result<String, NotesError>,
+) {
+    let header = |name: &str, value: &str| Header::from_bytes(name, value).ok();
+    // Extensions and bookmarklets are served from other origins, and
+    // preflight requests carry no token
+    let cors = [
+        header("Access-Control-Allow-Origin", "*"),
+        header("Access-Control-Allow-Methods", "POST"),
+        header(
+            "Access-Control-Allow-Headers",
+            "Authorization, Content-Type",
+        ),
+    ];
+    let (status, body) = if *request.method() == Method::Options {
+        (204, serde_json::Value::Null)
+    } else if request.body_length().unwrap_or(0) > MAX_BODY_BYTES {
+        (413, json!({ "error": "Clip is too large" }))
+    } else {
+        let mut body = Vec::new();
+        let read = request
+            .as_reader()
+            .take(MAX_BODY_BYTES as u64 + 1)
+            .read_to_end(&mut body);
+        let authorization = request
+            .headers()
+            .iter()
+            .find(|header| header.field.equiv("Authorization"))
+            .map(|header| header.value.as_str().to_string());
+        match read {
+            Ok(_) if body.len() > MAX_BODY_BYTES => (413, json!({ "error": "Clip is too large" })),
+            Ok(_) => respond(
+                &Request {
+                    method: request.method(),
+                    path: request.url(),
+                    authorization: authorization.as_deref(),
+                    body: &body,
+                },
+                token,
+                save,
+            ),
+            Err(e) => (
+                400,
+                json!({ "error": format!("Failed to read clip: {}", e) }),
+            ),
+        }
+    };
+
+    let mut response = if body.is_null() {
+        Response::from_string(String::new())
+    } else {
+        Response::from_string(body.to_string())
+            .with_header(header("Content-Type", "application/json").unwrap())
+    }
+    .with_status_code(status);
+    for header in cors.into_iter().flatten() {
+        response.add_header(header);
+    }
+    if let Err(e) = request.respond(response) {
+        warn!("Failed to answer web clipper request: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn builds_notes_from_clips() {
+        let clip = Clip {
+            text: Some("Quote\n".into()),
+            url: Some("https://example.com/a".into()),
+            ..Clip::default()
+        };
+        assert_eq!(clip.title(), "example.com");
+        assert_eq!(clip.content(), "Quote\n\nSource: <https://example.com/a>\n");
+        assert_eq!(Clip::default().title(), "Web clip");
+        assert_eq!(Clip::default().content(), "");
+    }
+
+    #[test]
+    fn saves_only_authorized_clips() {
+        let saved = RefCell::new(Vec::new());
+        let save = |clip: Clip| {
+            saved.borrow_mut().push(clip.title());
+            Ok("new-id".to_string())
+        };
+        let request = |method, path, authorization, body: &'static str| {
+            let request = Request {
+                method: &method,
+                path,
+                authorization,
+                body: body.as_bytes(),
+            };
+            respond(&request, "secret", &save).0
+        };
+        assert_eq!(request(Method::Post, "/clip", None, "{}"), 401);
+        assert_eq!(
+            request(Method::Post, "/clip", Some("Bearer nope"), "{}"),
+            401
+        );
+        assert_eq!(
+            request(Method::Get, "/clip", Some("Bearer secret"), ""),
+            405
+        );
+        assert_eq!(request(Method::Post, "/", Some("Bearer secret"), "{}"), 404);
+        assert_eq!(
+            request(Method::Post, "/clip", Some("Bearer secret"), "["),
+            400
+        );
+        let body = r#"{"title": "Saved"}"#;
+        assert_eq!(
+            request(Method::Post, "/clip", Some("Bearer secret"), body),
+            201
+        );
+        assert_eq!(*saved.borrow(), ["Saved"]);
+    }
+}
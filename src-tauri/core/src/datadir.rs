@@ -0,0 +1,147 @@
+//! Moving the notes data directory somewhere else, e.g. into a Dropbox
+//! folder.
+
+use crate::backup;
+use crate::error::NotesError;
+use crate::fsutil;
+use crate::storage;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Everything kept in the data directory, besides `.bak` copies. The SQLite
+/// shared-memory file is rebuilt on open, so it's left behind. settings.json
+/// always stays in the app data directory.
+const ENTRIES: &[&str] = &[
+    "notes.db",
+    "notes.db-wal",
+    "notes.lock",
+    "notes",
+    "notes-git",
+    "notes-store.json",
+    "notes-shards",
+    "history",
+    "attachments",
+    "storage.json",
+    "vault.json",
+    "notes.json",
+    "sync.json",
+    "sync_state.json",
+    "s3_sync.json",
+    "s3_sync_state.json",
+    "folder_sync.json",
+    "folder_sync_state.json",
+    "server_sync.json",
+    "server_sync_state.json",
+    "crdt.bin",
+    "templates.json",
+    "notebooks.json",
+    "shares.json",
+    "email.json",
+    "link_previews.json",
+    "embeddings.json",
+    "assistant.json",
+    "saved_searches.json",
+    "recent.json",
+    "schema.json",
+    storage::JOURNAL_FILE,
+    storage::QUARANTINE_DIR,
+    backup::BACKUPS_DIR,
+];
+
+/// Whether `name` is one of the entries of a data directory or its `.bak`
+/// copy.
+pub fn is_data_entry(name: &str) -> bool {
+    let name = name.strip_suffix(".bak").unwrap_or(name);
+    ENTRIES.contains(&name)
+}
+
+/// Returns the data files present in `dir`, backups included.
+pub fn entries(dir: &Path) -> Vec<PathBuf> {
+    ENTRIES
+        .iter()
+        .flat_map(|name| {
+            let path = dir.join(name);
+            [fsutil::backup_path(&path), path]
+        })
+        .filter(|path| path.exists())
+        .collect()
+}
+
+/// Creates `to` if needed and checks that the notes in `from` can move there.
+pub fn prepare(from: &Path, to: &Path) -> Result<(), NotesError> {
+    if !to.is_absolute() {
+        return Err(NotesError::Invalid(
+            "Notes directory must be an absolute path".into(),
+        ));
+    }
+    fs::create_dir_all(to)
+        .map_err(|e| NotesError::Io(format!("Failed to create notes directory: {}", e)))?;
+
+    let canonical = |path: &Path| {
+        fs::canonicalize(path)
+            .map_err(|e| NotesError::Io(format!("Failed to resolve notes directory: {}", e)))
+    };
+    let (from, to) = (canonical(from)?, canonical(to)?);
+    if from == to {
+        return Err(NotesError::Invalid("Notes are already stored there".into()));
+    }
+    if to.starts_with(&from) || from.starts_with(&to) {
+        return Err(NotesError::Invalid(
+            "Notes directory can't be inside the current one, or contain it".into(),
+        ));
+    }
+    if !entries(&to).is_empty() {
+        return Err(NotesError::Invalid(format!(
+            "{} already contains notes",
+            to.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Copies every data file from `from` into `to`. On failure, whatever was
+/// copied is removed again so `to` is left as it was.
+pub fn copy_data(from: &Path, to: &Path) -> Result<(), NotesError> {
+    for source in entries(from) {
+        let dest = to.join(source.file_name().unwrap_or_default());
+        if let Err(e) = copy_recursive(&source, &dest) {
+            remove_data(to);
+            return Err(NotesError::Io(format!(
+                "Failed to copy {}: {}",
+                source.display(),
+                e
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Deletes the data files in `dir`, once they've been copied elsewhere.
+/// Failures are only logged, since the notes themselves are safe.
+pub fn remove_data(dir: &Path) {
+    for path in entries(dir) {
+        let removed = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        if let Err(e) = removed {
+            warn!("Failed to remove {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn copy_recursive(source: &Path, dest: &Path) -> io::Result<()> {
+    if source.is_dir() {
+        fs::create_dir_all(dest)?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &dest.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        fs::copy(source, dest).map(|_| ())
+    }
+}
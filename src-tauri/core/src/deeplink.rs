@@ -0,0 +1,155 @@
+//! `min-notes://` links, which other apps and web pages can use to open a
+//! note or start a new one:
+//!
+//! - `min-notes://note/<id>` opens the note with that id
+//! - `min-notes://new?title=…&content=…` creates a note; both parameters are
+//!   optional and URL-encoded
+//! - `min-notes://share?subject=…&text=…&url=…` captures what another app
+//!   shared, as the mobile apps' share targets in `src-tauri/mobile` pass it
+//!   on; all three are optional, as share sheets fill in different ones
+
+use crate::error::NotesError;
+use crate::note;
+use url::Url;
+
+pub const SCHEME: &str = "min-notes";
+
+#[derive(PartialEq, Debug)]
+pub enum DeepLink {
+    OpenNote(String),
+    NewNote {
+        title: String,
+        content: String,
+    },
+    /// The text to capture, with the subject, if any, as its first line.
+    Share(String),
+}
+
+pub fn parse(link: &str) -> Result<DeepLink, NotesError> {
+    let invalid = || NotesError::Invalid(format!("Unsupported link: {}", link));
+    let url = Url::parse(link).map_err(|_| invalid())?;
+    if url.scheme() != SCHEME {
+        return Err(invalid());
+    }
+    let path = url.path().trim_matches('/');
+    match url.host_str() {
+        Some("note") if note::is_valid_id(path) => Ok(DeepLink::OpenNote(path.to_string())),
+        Some("new") if path.is_empty() => {
+            let mut title = String::new();
+            let mut content = String::new();
+            for (key, value) in url.query_pairs() {
+                match key.as_ref() {
+                    "title" => title = value.into_owned(),
+                    "content" => content = value.into_owned(),
+                    _ => {}
+                }
+            }
+            Ok(DeepLink::NewNote { title, content })
+        }
+        Some("share") if path.is_empty() => {
+            let (mut subject, mut text, mut shared_url) = (None, None, None);
+            for (key, value) in url.query_pairs() {
+                match key.as_ref() {
+                    "subject" => subject = Some(value.trim().to_string()),
+                    "text" => text = Some(value.trim().to_string()),
+                    "url" => shared_url = Some(value.trim().to_string()),
+                    _ => {}
+                }
+            }
+            // Browsers share a page's address in the text as well as the url
+            if shared_url
+                .as_ref()
+                .is_some_and(|shared| text.as_ref().is_some_and(|text| text.contains(shared)))
+            {
+                shared_url = None;
+            }
+            let parts: Vec<String> = [subject, text, shared_url]
+                .into_iter()
+                .flatten()
+                .filter(|part| !part.is_empty())
+                .collect();
+            Ok(DeepLink::Share(parts.join("\n")))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Follows the first of `links`, those the app was launched with, with
+/// `follow`, returning the note to open once the main window is up. A share
+/// from another app launches the app with one on mobile.
+pub fn follow_launch<L: AsRef<str>>(
+    links: &[L],
+    follow: impl FnOnce(DeepLink) -> Result<String, NotesError>,
+) -> Result<Option<String>, NotesError> {
+    match links.first() {
+        Some(link) => follow(parse(link.as_ref())?).map(Some),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_links() {
+        assert_eq!(
+            parse("min-notes://note/5f0c-ab").unwrap(),
+            DeepLink::OpenNote("5f0c-ab".into())
+        );
+        assert_eq!(
+            parse("min-notes://new?title=Read%20later&content=a+b%0Ac&x=1").unwrap(),
+            DeepLink::NewNote {
+                title: "Read later".into(),
+                content: "a b\nc".into()
+            }
+        );
+        assert_eq!(
+            parse("min-notes://share?subject=Soup&text=See%20https%3A%2F%2Fa.b&url=https://a.b")
+                .unwrap(),
+            DeepLink::Share("Soup\nSee https://a.b".into())
+        );
+        assert_eq!(
+            parse("min-notes://share?text=&url=https%3A%2F%2Fa.b").unwrap(),
+            DeepLink::Share("https://a.b".into())
+        );
+        // As the share targets encode them, with `+` escaped
+        assert_eq!(
+            parse("min-notes://share?subject=C%2B%2B&text=a%20b%0Ac").unwrap(),
+            DeepLink::Share("C++\na b\nc".into())
+        );
+        for link in [
+            "min-notes://note/",
+            "min-notes://note/a/b",
+            "min-notes://edit/a",
+            "https://note/a",
+        ] {
+            assert!(
+                matches!(parse(link), Err(NotesError::Invalid(_))),
+                "{}",
+                link
+            );
+        }
+    }
+
+    #[test]
+    fn follows_the_launch_link() {
+        let mut followed = Vec::new();
+        let launch = |links: &[&str], followed: &mut Vec<DeepLink>| {
+            follow_launch(links, |link| {
+                followed.push(link);
+                Ok("5f0c-ab".to_string())
+            })
+        };
+        let links = ["min-notes://share?text=Soup%20recipe", "min-notes://note/a"];
+        assert_eq!(
+            launch(&links, &mut followed).unwrap(),
+            Some("5f0c-ab".into())
+        );
+        assert_eq!(followed, [DeepLink::Share("Soup recipe".into())]);
+
+        assert_eq!(launch(&[], &mut followed).unwrap(), None);
+        assert!(launch(&["min-notes://edit/a"], &mut followed).is_err());
+        assert_eq!(followed.len(), 1);
+    }
+}
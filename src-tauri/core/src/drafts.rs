@@ -0,0 +1,43 @@
+//! Autosaved note content waiting to be written. The frontend may send a
+//! draft on every keystroke; drafts are coalesced per note and flushed at
+//! most once per autosave interval, so typing doesn't rewrite the store on
+//! every change.
+
+use std::collections::HashMap;
+
+#[derive(Default)]
+pub struct Drafts {
+    /// Latest unsaved content by note id.
+    pending: HashMap<String, String>,
+    /// Whether a flush is already on its way.
+    scheduled: bool,
+}
+
+impl Drafts {
+    /// Records the latest content of note `id`, replacing any earlier draft.
+    /// Returns true if no flush was scheduled yet, meaning the caller should
+    /// schedule one.
+    pub fn stage(&mut self, id: String, content: String) -> bool {
+        self.pending.insert(id, content);
+        !std::mem::replace(&mut self.scheduled, true)
+    }
+
+    /// Drops the draft of note `id`, e.g. because an explicit save replaced it.
+    pub fn discard(&mut self, id: &str) {
+        self.pending.remove(id);
+    }
+
+    /// Removes and returns every pending draft.
+    pub fn take(&mut self) -> HashMap<String, String> {
+        self.scheduled = false;
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Puts back drafts that failed to save, unless newer ones were staged in
+    /// the meantime. They're retried with the next flush.
+    pub fn restore(&mut self, drafts: HashMap<String, String>) {
+        for (id, content) in drafts {
+            self.pending.entry(id).or_insert(content);
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! Sending a note by email over SMTP, e.g. to yourself right after a
+//! meeting. The message has an HTML part rendered from the note's Markdown
+//! and a plain text one for mail clients that don't show HTML.
+//!
+//! The server settings are kept in email.json in the workspace's data
+//! directory, and the password in the OS keychain.
+
+use crate::error::NotesError;
+use crate::export;
+use crate::fsutil;
+use crate::keychain::{self, Secret};
+use crate::note::Note;
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpSecurity {
+    /// TLS from the start, usually on port 465.
+    #[default]
+    Tls,
+    /// Upgraded with STARTTLS, usually on port 587.
+    StartTls,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EmailConfig {
+    pub host: String,
+    /// The usual port for `security` when unset.
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    pub username: String,
+    /// The sender, e.g. `Me <me@example.com>`.
+    pub from: String,
+}
+
+fn config_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("email.json")
+}
+
+fn mailbox(address: &str) -> Result<Mailbox, NotesError> {
+    address
+        .trim()
+        .parse()
+        .map_err(|_| NotesError::Invalid(format!("{} is not an email address", address)))
+}
+
+impl EmailConfig {
+    fn transport(&self, password: String) -> Result<SmtpTransport, NotesError> {
+        let builder = match self.security {
+            SmtpSecurity::Tls => SmtpTransport::relay(&self.host),
+            SmtpSecurity::StartTls => SmtpTransport::starttls_relay(&self.host),
+        }
+        .map_err(|e| NotesError::Network(format!("Failed to set up SMTP: {}", e)))?;
+        let builder = match self.port {
+            Some(port) => builder.port(port),
+            None => builder,
+        };
+        Ok(builder
+            .credentials(Credentials::new(self.username.clone(), password))
+            .timeout(Some(TIMEOUT))
+            .build())
+    }
+}
+
+/// Returns the email settings of the workspace in `data_dir`, or `None` if
+/// sending email isn't set up.
+pub fn load_config(data_dir: &Path) -> Result<Option<EmailConfig>, NotesError> {
+    let path = config_path(data_dir);
+    if !path.exists() {
+        return Ok(None);
+    }
+    fsutil::read_with_backup(&path, |content| {
+        serde_json::from_str(content)
+            .map_err(|e| NotesError::Serde(format!("Failed to parse email settings: {}", e)))
+    })
+    .map(Some)
+}
+
+/// Checks that `config` and `password` get into the SMTP server, then saves
+/// them for workspace `workspace` in `data_dir`.
+pub fn configure(
+    data_dir: &Path,
+    workspace: &str,
+    config: &EmailConfig,
+    password: &str,
+) -> Result<(), NotesError> {
+    if config.host.trim().is_empty() {
+        return Err(NotesError::Invalid("SMTP server cannot be empty".into()));
+    }
+    mailbox(&config.from)?;
+    let connected = config
+        .transport(password.to_string())?
+        .test_connection()
+        .map_err(|e| NotesError::Network(format!("Failed to connect to SMTP server: {}", e)))?;
+    if !connected {
+        return Err(NotesError::Network(
+            "The SMTP server didn't accept the connection".into(),
+        ));
+    }
+    keychain::set(Secret::SmtpPassword, workspace, password)?;
+    let json = serde_json::to_string_pretty(config)
+        .map_err(|e| NotesError::Serde(format!("Failed to serialize email settings: {}", e)))?;
+    fsutil::write_with_backup(&config_path(data_dir), json.as_bytes())
+        .map_err(|e| NotesError::Io(format!("Failed to write email settings: {}", e)))
+}
+
+/// Builds the email of `note` from `from` to `to`, with the note's title as
+/// its subject.
+fn message(note: &Note, from: &str, to: &str) -> Result<Message, NotesError> {
+    if note.locked {
+        return Err(NotesError::Invalid(
+            "Remove the note's password before emailing it".into(),
+        ));
+    }
+    Message::builder()
+        .from(mailbox(from)?)
+        .to(mailbox(to)?)
+        .subject(note.title.as_str())
+        .multipart(MultiPart::alternative_plain_html(
+            export::render_text(note),
+            export::render_html(note),
+        ))
+        .map_err(|e| NotesError::Internal(format!("Failed to build email: {}", e)))
+}
+
+/// Emails `note` to `to` with the settings of workspace `workspace` in
+/// `data_dir`.
+pub fn send(data_dir: &Path, workspace: &str, note: &Note, to: &str) -> Result<(), NotesError> {
+    let config = load_config(data_dir)?
+        .ok_or_else(|| NotesError::Invalid("Set up sending email first".into()))?;
+    let message = message(note, &config.from, to)?;
+    let password = keychain::require(Secret::SmtpPassword, workspace)?;
+    config
+        .transport(password)?
+        .send(&message)
+        .map_err(|e| NotesError::Network(format!("Failed to send email: {}", e)))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note;
+
+    #[test]
+    fn builds_plain_and_html_parts() {
+        let note = note::new(
+            "Standup".into(),
+            "- shipped **sync**\n- next: search\n".into(),
+            Vec::new(),
+            1,
+        );
+        let email = message(&note, "Me <me@example.com>", "me@example.com").unwrap();
+        let email = String::from_utf8(email.formatted()).unwrap();
+        assert!(email.contains("Subject: Standup"));
+        assert!(email.contains("multipart/alternative"));
+        assert!(email.contains("Content-Type: text/plain; charset=utf-8"));
+        assert!(email.contains("Content-Type: text/html; charset=utf-8"));
+        assert!(email.contains("<strong>sync</strong>"));
+
+        assert!(matches!(
+            message(&note, "Me <me@example.com>", "not an address"),
+            Err(NotesError::Invalid(_))
+        ));
+    }
+}
@@ -0,0 +1,123 @@
+//! The error type returned by every command.
+//!
+//! Errors reach the frontend as `{ kind, message }`, so the UI can react to
+//! e.g. a locked vault or a missing note without matching on message text.
+//! The message is meant to be shown to the user as is.
+
+use crate::note::Note;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NotesError {
+    /// Reading or writing a file failed.
+    Io(String),
+    /// A file or payload couldn't be parsed or serialized.
+    Serde(String),
+    Database(String),
+    Search(String),
+    /// Encryption or key derivation failed, or a password was wrong.
+    Crypto(String),
+    /// A sync server couldn't be reached or rejected a request.
+    Network(String),
+    Git(String),
+    Keychain(String),
+    NotFound(String),
+    /// The vault must be unlocked first.
+    Locked,
+    /// Another process kept the notes locked for too long.
+    Busy,
+    /// Two versions of the same data disagree and can't be merged
+    /// automatically.
+    Conflict(String),
+    /// A note was saved elsewhere after the version being written was read.
+    /// The stored copy is sent to the frontend as `current`, under the
+    /// `conflict` kind.
+    Stale(Box<Note>),
+    /// The request itself was invalid, e.g. an empty name or a relative path.
+    Invalid(String),
+    /// A field of a note broke one of the limits on what notes may contain.
+    /// `field` is sent to the frontend too, so it can point at the input.
+    Validation {
+        field: &'static str,
+        message: String,
+    },
+    Internal(String),
+}
+
+impl NotesError {
+    /// The error's kind as sent to the frontend.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            NotesError::Io(_) => "io",
+            NotesError::Serde(_) => "serde",
+            NotesError::Database(_) => "database",
+            NotesError::Search(_) => "search",
+            NotesError::Crypto(_) => "crypto",
+            NotesError::Network(_) => "network",
+            NotesError::Git(_) => "git",
+            NotesError::Keychain(_) => "keychain",
+            NotesError::NotFound(_) => "notFound",
+            NotesError::Locked => "locked",
+            NotesError::Busy => "busy",
+            NotesError::Conflict(_) | NotesError::Stale(_) => "conflict",
+            NotesError::Invalid(_) => "invalid",
+            NotesError::Validation { .. } => "validation",
+            NotesError::Internal(_) => "internal",
+        }
+    }
+}
+
+impl fmt::Display for NotesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotesError::Locked => f.write_str("Vault is locked"),
+            NotesError::Stale(_) => {
+                f.write_str("This note was changed elsewhere since it was opened")
+            }
+            NotesError::Busy => f.write_str(
+                "The notes store is locked by another process. Try again once it's done.",
+            ),
+            NotesError::Io(message)
+            | NotesError::Serde(message)
+            | NotesError::Database(message)
+            | NotesError::Search(message)
+            | NotesError::Crypto(message)
+            | NotesError::Network(message)
+            | NotesError::Git(message)
+            | NotesError::Keychain(message)
+            | NotesError::NotFound(message)
+            | NotesError::Conflict(message)
+            | NotesError::Invalid(message)
+            | NotesError::Validation { message, .. }
+            | NotesError::Internal(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for NotesError {}
+
+impl Serialize for NotesError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let field = match self {
+            NotesError::Validation { field, .. } => Some(*field),
+            _ => None,
+        };
+        let current = match self {
+            NotesError::Stale(current) => Some(current),
+            _ => None,
+        };
+        let len = 2 + field.is_some() as usize + current.is_some() as usize;
+        let mut error = serializer.serialize_struct("NotesError", len)?;
+        error.serialize_field("kind", self.kind())?;
+        error.serialize_field("message", &self.to_string())?;
+        if let Some(field) = field {
+            error.serialize_field("field", field)?;
+        }
+        if let Some(current) = current {
+            error.serialize_field("current", current)?;
+        }
+        error.end()
+    }
+}
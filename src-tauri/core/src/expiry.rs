@@ -0,0 +1,77 @@
+//! Expiry times set on notes meant to be short-lived, like one-time codes or
+//! a shopping list. Like reminders they're saved with the note; a background
+//! task in lib.rs trashes or deletes each note once its time has come.
+
+use crate::note::Note;
+use serde::{Deserialize, Serialize};
+
+/// What becomes of a note when it expires.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExpiryAction {
+    /// Moved to the trash, from where it can still be restored.
+    #[default]
+    Trash,
+    /// Deleted for good, along with its history.
+    Delete,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Expiry {
+    pub at: i64,
+    #[serde(default)]
+    pub action: ExpiryAction,
+}
+
+/// The ids of the notes that have expired by `now`, with what's to become of
+/// each. Trashed notes only expire if they're to be deleted.
+pub fn expired(notes: &[Note], now: i64) -> Vec<(String, ExpiryAction)> {
+    notes
+        .iter()
+        .filter_map(|note| {
+            let expiry = note.expiry.filter(|expiry| expiry.at <= now)?;
+            let pending = note.trashed_at.is_none() || expiry.action == ExpiryAction::Delete;
+            pending.then(|| (note.id.clone(), expiry.action))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note;
+
+    #[test]
+    fn finds_expired_notes() {
+        let expiring = |title: &str, at: i64, action: ExpiryAction| {
+            let mut note = note::new(title.into(), String::new(), Vec::new(), 0);
+            note.expiry = Some(Expiry { at, action });
+            note
+        };
+        let mut trashed = expiring("Trashed", 50, ExpiryAction::Trash);
+        trashed.trashed_at = Some(60);
+        let mut deleted = expiring("Deleted", 50, ExpiryAction::Delete);
+        deleted.trashed_at = Some(60);
+        let notes = [
+            note::new("Kept".into(), String::new(), Vec::new(), 0),
+            expiring("Code", 100, ExpiryAction::Delete),
+            expiring("Later", 200, ExpiryAction::Trash),
+            trashed,
+            deleted,
+        ];
+        let expired: Vec<(&str, ExpiryAction)> = expired(&notes, 100)
+            .into_iter()
+            .map(|(id, action)| {
+                let note = notes.iter().find(|note| note.id == id).unwrap();
+                (note.title.as_str(), action)
+            })
+            .collect();
+        assert_eq!(
+            expired,
+            [
+                ("Code", ExpiryAction::Delete),
+                ("Deleted", ExpiryAction::Delete)
+            ]
+        );
+    }
+}
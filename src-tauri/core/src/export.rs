@@ -0,0 +1,449 @@
+//! Rendering notes to files outside the app.
+
+use crate::error::NotesError;
+use crate::links;
+use crate::note::{slugify, Note};
+use printpdf::{BuiltinFont, IndirectFontRef, Mm, PdfDocument, PdfDocumentReference};
+use pulldown_cmark::{html, Event, Options, Parser, Tag, TagEnd};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    Pdf,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+            ExportFormat::Pdf => "pdf",
+        }
+    }
+}
+
+pub fn render(note: &Note, format: ExportFormat) -> Result<Vec<u8>, NotesError> {
+    match format {
+        ExportFormat::Markdown => Ok(render_markdown(note).into_bytes()),
+        ExportFormat::Html => Ok(render_html(note).into_bytes()),
+        ExportFormat::Pdf => render_pdf(&note.title, &note.content),
+    }
+}
+
+pub fn export_note(note: &Note, format: ExportFormat, path: &Path) -> Result<(), NotesError> {
+    fs::write(path, render(note, format)?)
+        .map_err(|e| NotesError::Io(format!("Failed to write export: {}", e)))
+}
+
+/// Writes each note to its own file in `dir`, named after its title. Returns
+/// the paths written.
+pub fn export_notes(
+    notes: &[Note],
+    format: ExportFormat,
+    dir: &Path,
+) -> Result<Vec<PathBuf>, NotesError> {
+    fs::create_dir_all(dir)
+        .map_err(|e| NotesError::Io(format!("Failed to create export directory: {}", e)))?;
+
+    let mut paths = Vec::with_capacity(notes.len());
+    for note in notes {
+        let slug = slugify(&note.title);
+        let mut path = dir.join(format!("{}.{}", slug, format.extension()));
+        let mut n = 2;
+        while path.exists() || paths.contains(&path) {
+            path = dir.join(format!("{}-{}.{}", slug, n, format.extension()));
+            n += 1;
+        }
+        export_note(note, format, &path)?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// Exports a selection of notes with their attachments, which go in an
+/// attachments folder beside the export. With `combined`, the notes are
+/// written one after another to the file at `dest`; otherwise each goes in
+/// its own file in the folder `dest`. `[[links]]` between the notes become
+/// links within the export. Returns the paths of the files written, not
+/// counting attachments.
+pub fn export_selection(
+    notes: &[Note],
+    read_attachment: impl Fn(&str) -> Result<Vec<u8>, NotesError>,
+    format: ExportFormat,
+    dest: &Path,
+    combined: bool,
+) -> Result<Vec<PathBuf>, NotesError> {
+    let failed = |e: std::io::Error| NotesError::Io(format!("Failed to write export: {}", e));
+    let (dir, file) = if combined {
+        let file = match dest.extension() {
+            Some(_) => dest.to_path_buf(),
+            None => dest.with_extension(format.extension()),
+        };
+        (
+            file.parent().unwrap_or(Path::new(".")).to_path_buf(),
+            Some(file),
+        )
+    } else {
+        (dest.to_path_buf(), None)
+    };
+    fs::create_dir_all(&dir).map_err(failed)?;
+
+    // Where each note ends up: its file, or its heading in the combined file
+    let mut taken = HashSet::new();
+    let targets: Vec<String> = notes
+        .iter()
+        .map(|note| {
+            let slug = slugify(&note.title);
+            let mut n = 1;
+            loop {
+                let name = match n {
+                    1 => slug.clone(),
+                    n => format!("{}-{}", slug, n),
+                };
+                n += 1;
+                let target = match file {
+                    Some(_) => format!("#{}", name),
+                    None => format!("{}.{}", name, format.extension()),
+                };
+                let free = file.is_some() || !dir.join(&target).exists();
+                if free && taken.insert(target.clone()) {
+                    return target;
+                }
+            }
+        })
+        .collect();
+
+    let attachment_paths = write_attachments(notes, read_attachment, &dir)?;
+
+    let linked: Vec<Note> = notes
+        .iter()
+        .map(|note| {
+            let mut content = links::rewrite_links(&note.content, |title| {
+                let index = notes
+                    .iter()
+                    .position(|other| links::same_title(&other.title, title))?;
+                Some(targets[index].clone())
+            });
+            if !note.attachments.is_empty() && format != ExportFormat::Pdf {
+                content = content.trim_end().to_string();
+                content.push_str("\n\n## Attachments\n\n");
+                for attachment in &note.attachments {
+                    let path = &attachment_paths[attachment.id.as_str()];
+                    content.push_str(&format!("- [{}](<{}>)\n", attachment.name, path));
+                }
+            }
+            Note {
+                content,
+                ..note.clone()
+            }
+        })
+        .collect();
+
+    let Some(file) = file else {
+        let mut paths = Vec::with_capacity(linked.len());
+        for (note, target) in linked.iter().zip(&targets) {
+            let path = dir.join(target);
+            export_note(note, format, &path)?;
+            paths.push(path);
+        }
+        return Ok(paths);
+    };
+    let title = file
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let rendered = match format {
+        ExportFormat::Markdown => linked
+            .iter()
+            .map(render_markdown)
+            .collect::<Vec<_>>()
+            .join("\n---\n\n")
+            .into_bytes(),
+        ExportFormat::Html => {
+            let mut body = String::new();
+            for (note, target) in linked.iter().zip(&targets) {
+                body.push_str(&format!(
+                    "<section id=\"{}\">\n<h1>{}</h1>\n",
+                    escape_html(&target[1..]),
+                    escape_html(&note.title)
+                ));
+                html::push_html(
+                    &mut body,
+                    Parser::new_ext(&note.content, markdown_options()),
+                );
+                body.push_str("</section>\n");
+            }
+            html_document(&title, &body).into_bytes()
+        }
+        ExportFormat::Pdf => {
+            let content: Vec<String> = linked
+                .iter()
+                .map(|note| format!("# {}\n\n{}", note.title, note.content))
+                .collect();
+            render_pdf(&title, &content.join("\n\n"))?
+        }
+    };
+    fs::write(&file, rendered).map_err(failed)?;
+    Ok(vec![file])
+}
+
+/// Writes the attachments of `notes` to an attachments folder in `dir`, each
+/// once, named after the file it was attached from. Returns their paths
+/// relative to `dir`, by attachment id.
+pub(crate) fn write_attachments<'a>(
+    notes: &'a [Note],
+    read_attachment: impl Fn(&str) -> Result<Vec<u8>, NotesError>,
+    dir: &Path,
+) -> Result<HashMap<&'a str, String>, NotesError> {
+    let failed = |e: std::io::Error| NotesError::Io(format!("Failed to write export: {}", e));
+    let mut attachment_paths: HashMap<&str, String> = HashMap::new();
+    let mut attachment_names = HashSet::new();
+    for attachment in notes.iter().flat_map(|note| &note.attachments) {
+        if attachment_paths.contains_key(attachment.id.as_str()) {
+            continue;
+        }
+        let name = Path::new(&attachment.name)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| attachment.id.clone());
+        let (stem, extension) = match name.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{}", extension)),
+            _ => (name.as_str(), String::new()),
+        };
+        let mut path = format!("attachments/{}", name);
+        let mut n = 2;
+        while !attachment_names.insert(path.clone()) {
+            path = format!("attachments/{}-{}{}", stem, n, extension);
+            n += 1;
+        }
+        fs::create_dir_all(dir.join("attachments")).map_err(failed)?;
+        fs::write(dir.join(&path), read_attachment(&attachment.id)?).map_err(failed)?;
+        attachment_paths.insert(&attachment.id, path);
+    }
+    Ok(attachment_paths)
+}
+
+pub(crate) fn markdown_options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_FOOTNOTES
+}
+
+fn render_markdown(note: &Note) -> String {
+    format!("# {}\n\n{}\n", note.title, note.content.trim_end())
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Renders a standalone HTML document with the note's Markdown as its body.
+pub(crate) fn render_html(note: &Note) -> String {
+    let mut body = format!("<h1>{}</h1>\n", escape_html(&note.title));
+    html::push_html(
+        &mut body,
+        Parser::new_ext(&note.content, markdown_options()),
+    );
+    html_document(&note.title, &body)
+}
+
+pub(crate) fn html_document(title: &str, body: &str) -> String {
+    let title = escape_html(title);
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ font-family: system-ui, sans-serif; max-width: 46rem; margin: 2rem auto; padding: 0 1rem; line-height: 1.6; }}
+pre, code {{ font-family: ui-monospace, monospace; background: #f4f4f5; }}
+pre {{ padding: 0.75rem; overflow-x: auto; }}
+table {{ border-collapse: collapse; }}
+td, th {{ border: 1px solid #d4d4d8; padding: 0.25rem 0.5rem; }}
+</style>
+</head>
+<body>
+{body}</body>
+</html>
+"#
+    )
+}
+
+/// Renders the note as plain text, with its Markdown flattened the way the
+/// PDF renderer does.
+pub(crate) fn render_text(note: &Note) -> String {
+    let mut text = format!("{}\n\n", note.title);
+    for line in markdown_to_lines(&note.content) {
+        text.push_str(&line);
+        text.push('\n');
+    }
+    text
+}
+
+/// Flattens Markdown into plain lines of text for the PDF renderer.
+fn markdown_to_lines(markdown: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    let mut list_depth = 0usize;
+
+    for event in Parser::new_ext(markdown, markdown_options()) {
+        match event {
+            Event::Start(Tag::List(_)) => list_depth += 1,
+            Event::End(TagEnd::List(_)) => list_depth = list_depth.saturating_sub(1),
+            Event::Start(Tag::Item) => {
+                current.push_str(&"  ".repeat(list_depth.saturating_sub(1)));
+                current.push_str("• ");
+            }
+            Event::TaskListMarker(done) => current.push_str(if done { "[x] " } else { "[ ] " }),
+            Event::Text(text) | Event::Code(text) => current.push_str(&text),
+            Event::SoftBreak => current.push(' '),
+            Event::HardBreak => lines.push(std::mem::take(&mut current)),
+            Event::End(TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::Item) => {
+                lines.push(std::mem::take(&mut current));
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                lines.extend(current.lines().map(str::to_string));
+                current.clear();
+            }
+            Event::Rule => lines.push("-".repeat(40)),
+            _ => {}
+        }
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+/// Greedily wraps `line` into chunks of at most `width` characters.
+fn wrap(line: &str, width: usize) -> Vec<String> {
+    let mut wrapped = Vec::new();
+    let mut current = String::new();
+    for word in line.split(' ') {
+        if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > width {
+            wrapped.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    wrapped.push(current);
+    wrapped
+}
+
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 20.0;
+const BODY_SIZE: f32 = 11.0;
+const LINE_HEIGHT: f32 = 5.5;
+const WRAP_CHARS: usize = 90;
+
+/// Lays out `markdown` under `title` as plain text on A4 pages using the
+/// built-in Helvetica fonts, which cover Latin-1 but not other scripts.
+fn render_pdf(title: &str, markdown: &str) -> Result<Vec<u8>, NotesError> {
+    let (doc, page, layer) = PdfDocument::new(title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Text");
+    let font = add_font(&doc, BuiltinFont::Helvetica)?;
+    let bold = add_font(&doc, BuiltinFont::HelveticaBold)?;
+
+    let mut layer = doc.get_page(page).get_layer(layer);
+    let mut y = PAGE_HEIGHT - MARGIN;
+    layer.use_text(title, 18.0, Mm(MARGIN), Mm(y), &bold);
+    y -= LINE_HEIGHT * 2.5;
+
+    for line in markdown_to_lines(markdown) {
+        for chunk in wrap(&line, WRAP_CHARS) {
+            if y < MARGIN {
+                let (page, new_layer) = doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Text");
+                layer = doc.get_page(page).get_layer(new_layer);
+                y = PAGE_HEIGHT - MARGIN;
+            }
+            layer.use_text(chunk, BODY_SIZE, Mm(MARGIN), Mm(y), &font);
+            y -= LINE_HEIGHT;
+        }
+    }
+
+    doc.save_to_bytes()
+        .map_err(|e| NotesError::Internal(format!("Failed to render PDF: {}", e)))
+}
+
+fn add_font(doc: &PdfDocumentReference, font: BuiltinFont) -> Result<IndirectFontRef, NotesError> {
+    doc.add_builtin_font(font)
+        .map_err(|e| NotesError::Internal(format!("Failed to load PDF font: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::{self, Attachment};
+    use tempfile::TempDir;
+
+    fn selection() -> Vec<Note> {
+        let mut plan = note::new(
+            "Plan".into(),
+            "See [[Notes|my notes]].".into(),
+            Vec::new(),
+            1,
+        );
+        plan.attachments.push(Attachment {
+            id: "abc".into(),
+            name: "diagram one.png".into(),
+            mime: "image/png".into(),
+            size: 3,
+            added_at: 1,
+            text: None,
+        });
+        let notes = note::new(
+            "Notes".into(),
+            "Back to [[plan]], not [[Elsewhere]].".into(),
+            Vec::new(),
+            1,
+        );
+        vec![plan, notes]
+    }
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn exports_a_selection_with_links_and_attachments() {
+        let dir = TempDir::new().unwrap();
+        let blob = |id: &str| Ok(id.as_bytes().to_vec());
+        let paths = export_selection(
+            &selection(),
+            blob,
+            ExportFormat::Markdown,
+            dir.path(),
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            paths,
+            [dir.path().join("plan.md"), dir.path().join("notes.md")]
+        );
+        assert!(read(&paths[0]).contains("See [my notes](notes.md)."));
+        assert!(read(&paths[0]).contains("- [diagram one.png](<attachments/diagram one.png>)"));
+        assert!(read(&paths[1]).contains("Back to [plan](plan.md), not [[Elsewhere]]."));
+        assert_eq!(read(&dir.path().join("attachments/diagram one.png")), "abc");
+
+        let combined = dir.path().join("out/selection");
+        let paths =
+            export_selection(&selection(), blob, ExportFormat::Html, &combined, true).unwrap();
+        assert_eq!(paths, [dir.path().join("out/selection.html")]);
+        let html = read(&paths[0]);
+        assert!(html.contains(r#"<section id="notes">"#));
+        assert!(html.contains(r##"<a href="#notes">my notes</a>"##));
+    }
+}
@@ -0,0 +1,113 @@
+//! Filesystem helpers shared by the on-disk stores.
+
+use crate::error::NotesError;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Writes `contents` to a temp file next to `path`, flushes it to disk, and
+/// renames it over `path`. A crash at any point leaves either the old file or
+/// the new one, never a truncated mix.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let tmp_path = with_suffix(path, ".tmp");
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&tmp_path, path)?;
+    sync_parent_dir(path)
+}
+
+/// Like `write_atomic`, but first copies the current contents of `path` to
+/// `<path>.bak`, keeping one rolling backup.
+pub fn write_with_backup(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if path.exists() {
+        fs::copy(path, backup_path(path))?;
+    }
+    write_atomic(path, contents)
+}
+
+/// Reads and parses `path`, falling back to its `.bak` copy if the file can't
+/// be read or parsed. The original error is returned if the backup doesn't
+/// help either.
+pub fn read_with_backup<T>(
+    path: &Path,
+    parse: impl Fn(&str) -> Result<T, NotesError>,
+) -> Result<T, NotesError> {
+    let error = match fs::read_to_string(path) {
+        Ok(text) => match parse(&text) {
+            Ok(value) => return Ok(value),
+            Err(e) => e,
+        },
+        Err(e) => NotesError::Io(e.to_string()),
+    };
+
+    let backup = backup_path(path);
+    let value = fs::read_to_string(&backup)
+        .ok()
+        .and_then(|text| parse(&text).ok())
+        .ok_or_else(|| error.clone())?;
+    warn!(
+        "Loaded {} from its backup because the file is unusable: {}",
+        path.display(),
+        error
+    );
+    Ok(value)
+}
+
+/// Removes `path` along with its backup, if any.
+pub fn remove_with_backup(path: &Path) -> io::Result<()> {
+    fs::remove_file(path)?;
+    match fs::remove_file(backup_path(path)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Returns how many bytes the file at `path`, or every file under the
+/// directory at `path`, takes up. Anything that can't be read counts as
+/// nothing.
+pub fn size_on_disk(path: &Path) -> u64 {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| size_on_disk(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+pub fn backup_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".bak")
+}
+
+/// Appends `suffix` to the file name of `path`.
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Makes a rename durable by flushing the directory entry that records it.
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => File::open(dir)?.sync_all(),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,87 @@
+//! Where notes were written, for listing the notes from a place. Notes are
+//! tagged with the device's location as they're created on mobile, when the
+//! user has turned that on.
+
+use crate::error::NotesError;
+use crate::note::Note;
+use serde::{Deserialize, Serialize};
+
+/// The mean radius of the Earth, in metres.
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Location {
+    /// Degrees north of the equator, negative to the south.
+    pub latitude: f64,
+    /// Degrees east of Greenwich, negative to the west.
+    pub longitude: f64,
+}
+
+impl Location {
+    pub fn new(latitude: f64, longitude: f64) -> Result<Self, NotesError> {
+        if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+            return Err(NotesError::Invalid(format!(
+                "Not a place on Earth: {}, {}",
+                latitude, longitude
+            )));
+        }
+        Ok(Location {
+            latitude,
+            longitude,
+        })
+    }
+
+    /// The distance to `other` along the Earth's surface, in metres.
+    pub fn distance_to(&self, other: &Location) -> f64 {
+        let (lat1, lat2) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (other.longitude - self.longitude).to_radians();
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_M * a.sqrt().min(1.0).asin()
+    }
+}
+
+/// The notes outside the trash written within `radius` metres of `center`,
+/// nearest first.
+pub fn near<'a>(notes: &'a [Note], center: &Location, radius: f64) -> Vec<&'a Note> {
+    let mut near: Vec<(f64, &Note)> = notes
+        .iter()
+        .filter(|note| note.trashed_at.is_none())
+        .filter_map(|note| Some((note.location?.distance_to(center), note)))
+        .filter(|(distance, _)| *distance <= radius)
+        .collect();
+    near.sort_by(|a, b| a.0.total_cmp(&b.0));
+    near.into_iter().map(|(_, note)| note).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note;
+
+    #[test]
+    fn finds_notes_written_nearby() {
+        let at = |title: &str, latitude, longitude| {
+            let mut note = note::new(title.into(), String::new(), Vec::new(), 0);
+            note.location = Some(Location::new(latitude, longitude).unwrap());
+            note
+        };
+        let home = Location::new(51.5007, -0.1246).unwrap();
+        let notes = [
+            at("Across the river", 51.5033, -0.1196),
+            at("Next door", 51.5008, -0.1246),
+            at("Paris", 48.8584, 2.2945),
+            note::new("Nowhere".into(), String::new(), Vec::new(), 0),
+        ];
+        let titles = |radius| -> Vec<&str> {
+            near(&notes, &home, radius)
+                .into_iter()
+                .map(|note| note.title.as_str())
+                .collect()
+        };
+        assert_eq!(titles(1000.0), ["Next door", "Across the river"]);
+        assert_eq!(titles(400_000.0).len(), 3);
+        assert!((home.distance_to(&notes[2].location.unwrap()) - 340_000.0).abs() < 5000.0);
+        assert!(Location::new(91.0, 0.0).is_err());
+    }
+}
@@ -0,0 +1,160 @@
+//! The notes graph: every note as a node, joined by its `[[links]]` and by
+//! the tags it shares with other notes, ready for the frontend to lay out.
+
+use crate::links::LinkGraph;
+use crate::note::Note;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Serialize)]
+pub struct GraphTag {
+    pub name: String,
+    /// A CSS color, the same for the tag every time.
+    pub color: String,
+}
+
+#[derive(Serialize)]
+pub struct GraphNode {
+    pub id: String,
+    pub title: String,
+    pub tags: Vec<GraphTag>,
+}
+
+#[derive(Serialize, PartialEq, Debug)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum GraphEdge {
+    /// `source` contains a `[[link]]` to `target`.
+    Link { source: String, target: String },
+    /// Two notes with tags in common. Undirected, so `source` is always the
+    /// smaller id.
+    Tag {
+        source: String,
+        target: String,
+        tags: Vec<String>,
+    },
+}
+
+#[derive(Serialize)]
+pub struct NotesGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+/// Builds the graph of `notes`, whose links are in `links`.
+pub fn build(notes: &[Note], links: &LinkGraph) -> NotesGraph {
+    let nodes = notes
+        .iter()
+        .map(|note| GraphNode {
+            id: note.id.clone(),
+            title: note.title.clone(),
+            tags: note
+                .tags
+                .iter()
+                .map(|tag| GraphTag {
+                    name: tag.clone(),
+                    color: tag_color(tag),
+                })
+                .collect(),
+        })
+        .collect();
+
+    let ids: BTreeSet<&str> = notes.iter().map(|note| note.id.as_str()).collect();
+    let mut edges: Vec<GraphEdge> = links
+        .edges()
+        .into_iter()
+        .filter(|(source, target)| ids.contains(source.as_str()) && ids.contains(target.as_str()))
+        .map(|(source, target)| GraphEdge::Link { source, target })
+        .collect();
+
+    let mut by_tag: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for note in notes {
+        for tag in &note.tags {
+            by_tag.entry(tag).or_default().push(&note.id);
+        }
+    }
+    let mut shared: BTreeMap<(&str, &str), Vec<String>> = BTreeMap::new();
+    for (tag, tagged) in by_tag {
+        for (i, a) in tagged.iter().enumerate() {
+            for b in &tagged[i + 1..] {
+                let pair = if a < b { (*a, *b) } else { (*b, *a) };
+                shared.entry(pair).or_default().push(tag.to_string());
+            }
+        }
+    }
+    edges.extend(
+        shared
+            .into_iter()
+            .map(|((source, target), tags)| GraphEdge::Tag {
+                source: source.to_string(),
+                target: target.to_string(),
+                tags,
+            }),
+    );
+    NotesGraph { nodes, edges }
+}
+
+/// Picks a hue from a hash of `tag`, so each tag keeps its color without
+/// colors having to be stored.
+fn tag_color(tag: &str) -> String {
+    let hash = tag.bytes().fold(0x811c_9dc5u32, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    });
+    format!("hsl({}, 65%, 55%)", hash % 360)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note;
+
+    fn note(id: &str, title: &str, content: &str, tags: &[&str]) -> Note {
+        let tags = tags.iter().map(|tag| tag.to_string()).collect();
+        let mut note = note::new(title.into(), content.into(), tags, 0);
+        note.id = id.into();
+        note
+    }
+
+    #[test]
+    fn joins_notes_by_links_and_shared_tags() {
+        let notes = [
+            note(
+                "a",
+                "Alpha",
+                "See [[Beta]] and [[Alpha]]",
+                &["rust", "work"],
+            ),
+            note("b", "Beta", "", &["work", "rust"]),
+            note("c", "Gamma", "[[Nowhere]]", &["work"]),
+        ];
+        let mut links = LinkGraph::default();
+        links.rebuild(&notes);
+        let graph = build(&notes, &links);
+
+        assert_eq!(graph.nodes.len(), 3);
+        assert_eq!(graph.nodes[0].tags[0].color, tag_color("rust"));
+        assert_eq!(
+            graph.edges,
+            [
+                GraphEdge::Link {
+                    source: "a".into(),
+                    target: "b".into()
+                },
+                GraphEdge::Tag {
+                    source: "a".into(),
+                    target: "b".into(),
+                    tags: vec!["rust".into(), "work".into()]
+                },
+                GraphEdge::Tag {
+                    source: "a".into(),
+                    target: "c".into(),
+                    tags: vec!["work".into()]
+                },
+                GraphEdge::Tag {
+                    source: "b".into(),
+                    target: "c".into(),
+                    tags: vec!["work".into()]
+                },
+            ]
+        );
+    }
+}
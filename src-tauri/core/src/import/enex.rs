@@ -0,0 +1,127 @@
+//! Evernote .enex exports: an XML list of notes whose bodies are ENML, a
+//! restricted XHTML dialect.
+
+use super::{ImportedItem, ImportedNote};
+use crate::error::NotesError;
+use chrono::NaiveDateTime;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::fs;
+use std::path::Path;
+
+/// Fields of the `<note>` element currently being read.
+#[derive(Default)]
+struct RawNote {
+    title: String,
+    content: String,
+    created: String,
+    updated: String,
+    tags: Vec<String>,
+}
+
+pub fn read(path: &Path) -> Result<Vec<ImportedItem>, NotesError> {
+    let xml = fs::read_to_string(path)
+        .map_err(|e| NotesError::Io(format!("Failed to read ENEX file: {}", e)))?;
+    let mut reader = Reader::from_str(&xml);
+
+    let mut items = Vec::new();
+    let mut current: Option<RawNote> = None;
+    // Name of the innermost element directly under <note>; resources and
+    // their attributes are skipped.
+    let mut field: Option<String> = None;
+    let mut depth_in_note = 0usize;
+
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| NotesError::Serde(format!("Failed to parse ENEX file: {}", e)))?;
+        match event {
+            Event::Start(e) => {
+                let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+                if name == "note" {
+                    current = Some(RawNote::default());
+                    depth_in_note = 0;
+                } else if current.is_some() {
+                    depth_in_note += 1;
+                    field = (depth_in_note == 1).then_some(name);
+                }
+            }
+            Event::End(e) => {
+                if e.name().as_ref() == b"note" {
+                    if let Some(raw) = current.take() {
+                        items.push(ImportedItem {
+                            source: raw.title.clone(),
+                            note: convert(raw),
+                        });
+                    }
+                } else if current.is_some() {
+                    depth_in_note = depth_in_note.saturating_sub(1);
+                    field = None;
+                }
+            }
+            Event::Text(text) => {
+                let text = text
+                    .unescape()
+                    .map_err(|e| NotesError::Serde(format!("Failed to parse ENEX file: {}", e)))?;
+                append_field(current.as_mut(), field.as_deref(), &text);
+            }
+            Event::CData(data) => {
+                let text = String::from_utf8_lossy(&data);
+                append_field(current.as_mut(), field.as_deref(), &text);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(items)
+}
+
+fn append_field(note: Option<&mut RawNote>, field: Option<&str>, text: &str) {
+    let Some(note) = note else { return };
+    match field {
+        Some("title") => note.title.push_str(text),
+        Some("content") => note.content.push_str(text),
+        Some("created") => note.created.push_str(text),
+        Some("updated") => note.updated.push_str(text),
+        Some("tag") => note.tags.push(text.to_string()),
+        _ => {}
+    }
+}
+
+fn convert(raw: RawNote) -> Result<ImportedNote, NotesError> {
+    Ok(ImportedNote {
+        title: raw.title.trim().to_string(),
+        content: enml_to_markdown(&raw.content),
+        created_at: parse_date(&raw.created)?,
+        updated_at: parse_date(&raw.updated)?,
+        tags: raw.tags,
+    })
+}
+
+/// ENEX dates look like `20240517T120000Z`.
+fn parse_date(date: &str) -> Result<Option<i64>, NotesError> {
+    let date = date.trim();
+    if date.is_empty() {
+        return Ok(None);
+    }
+    NaiveDateTime::parse_from_str(date, "%Y%m%dT%H%M%SZ")
+        .map(|date| Some(date.and_utc().timestamp()))
+        .map_err(|e| NotesError::Serde(format!("Invalid date {:?}: {}", date, e)))
+}
+
+/// Converts the `<en-note>` body to Markdown, dropping the XML prolog and
+/// doctype that precede it.
+fn enml_to_markdown(enml: &str) -> String {
+    let body = match enml.find("<en-note") {
+        Some(start) => {
+            let inner_start = enml[start..]
+                .find('>')
+                .map(|end| start + end + 1)
+                .unwrap_or(enml.len());
+            let inner_end = enml.rfind("</en-note>").unwrap_or(enml.len());
+            &enml[inner_start..inner_end.max(inner_start)]
+        }
+        None => enml,
+    };
+    html2md::parse_html(body).trim().to_string()
+}
@@ -0,0 +1,199 @@
+//! A folder of Markdown and plain-text files, one note per file. A note is
+//! titled after its file's first `# Heading`, or the file name if it has
+//! none, and tagged with the `tags` in its YAML frontmatter if there is any.
+
+use super::{ImportedItem, ImportedNote};
+use crate::error::NotesError;
+use serde_yaml::Value;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// What was found in a folder: the files read as notes, and the others.
+pub struct FolderContents {
+    pub items: Vec<ImportedItem>,
+    /// Files that aren't Markdown or text, relative to the folder.
+    pub skipped: Vec<String>,
+}
+
+/// Reads the .md and .txt files in `dir`, and in its subfolders if
+/// `recursive`. Hidden files and folders, such as .git or .obsidian, are
+/// left out entirely.
+pub fn read(dir: &Path, recursive: bool) -> Result<FolderContents, NotesError> {
+    if !dir.is_dir() {
+        return Err(NotesError::NotFound(format!(
+            "{} is not a folder",
+            dir.display()
+        )));
+    }
+    let mut contents = FolderContents {
+        items: Vec::new(),
+        skipped: Vec::new(),
+    };
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(folder) = pending.pop() {
+        let entries = fs::read_dir(&folder)
+            .map_err(|e| NotesError::Io(format!("Failed to read {}: {}", folder.display(), e)))?;
+        let mut entries: Vec<_> = entries.flatten().collect();
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let path = entry.path();
+            let source = path
+                .strip_prefix(dir)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .into_owned();
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            // Not followed through symlinks, which could loop
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                if recursive {
+                    pending.push(path);
+                }
+                continue;
+            }
+            if !file_type.is_file() || !is_note_file(&path) {
+                contents.skipped.push(source);
+                continue;
+            }
+            contents.items.push(ImportedItem {
+                source,
+                note: read_file(&path),
+            });
+        }
+    }
+    Ok(contents)
+}
+
+/// Whether the file at `path` is read as a note: Markdown or plain text.
+pub fn is_note_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("txt"))
+}
+
+/// Reads one Markdown or text file as a note, dated from the file.
+pub fn read_file(path: &Path) -> Result<ImportedNote, NotesError> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| NotesError::Io(format!("Failed to read {}: {}", path.display(), e)))?;
+    let metadata = fs::metadata(path)
+        .map_err(|e| NotesError::Io(format!("Failed to read {}: {}", path.display(), e)))?;
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut note = parse(&stem, &text);
+    let timestamp = |time: std::io::Result<SystemTime>| {
+        time.ok()
+            .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|since| since.as_secs() as i64)
+    };
+    note.updated_at = timestamp(metadata.modified());
+    // Not every file system records when a file was created
+    note.created_at = timestamp(metadata.created()).or(note.updated_at);
+    Ok(note)
+}
+
+/// Splits off the frontmatter and the leading `# Heading` of a file named
+/// `stem`, as written by `export_note`.
+pub fn parse(stem: &str, text: &str) -> ImportedNote {
+    let text = text.replace("\r\n", "\n");
+    let (tags, body) = match split_frontmatter(&text) {
+        Some((front, body)) => (frontmatter_tags(front), body),
+        None => (Vec::new(), text.as_str()),
+    };
+
+    let trimmed = body.trim_start_matches('\n');
+    let (first_line, rest) = trimmed.split_once('\n').unwrap_or((trimmed, ""));
+    let (title, content) = match first_line.strip_prefix("# ") {
+        Some(heading) if !heading.trim().is_empty() => {
+            (heading.trim().to_string(), rest.trim_start_matches('\n'))
+        }
+        _ => (stem.to_string(), body),
+    };
+    ImportedNote {
+        title,
+        content: content.to_string(),
+        created_at: None,
+        updated_at: None,
+        tags,
+    }
+}
+
+/// Returns the YAML between a leading `---` line and the next one, and the
+/// text after it.
+fn split_frontmatter(text: &str) -> Option<(&str, &str)> {
+    let rest = text.strip_prefix("---\n")?;
+    match rest.find("\n---\n") {
+        Some(end) => Some((&rest[..end], &rest[end + 5..])),
+        None => rest.strip_suffix("\n---").map(|front| (front, "")),
+    }
+}
+
+/// Reads `tags` as either a list or a comma-separated string; they're
+/// trimmed by `into_note`. Frontmatter that isn't valid YAML is ignored.
+fn frontmatter_tags(front: &str) -> Vec<String> {
+    let Ok(Value::Mapping(fields)) = serde_yaml::from_str::<Value>(front) else {
+        return Vec::new();
+    };
+    match fields.get("tags") {
+        Some(Value::Sequence(tags)) => tags
+            .iter()
+            .filter_map(|tag| match tag {
+                Value::String(tag) => Some(tag.clone()),
+                Value::Number(n) => Some(n.to_string()),
+                _ => None,
+            })
+            .collect(),
+        Some(Value::String(tags)) => tags.split(',').map(str::to_string).collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn parses_heading_and_frontmatter() {
+        let note = parse(
+            "file",
+            "---\ntags: [work, 2024]\nstatus: x\n---\n\n# Weekly plan\n\nBody\n",
+        );
+        assert_eq!(note.title, "Weekly plan");
+        assert_eq!(note.content, "Body\n");
+        assert_eq!(note.tags, ["work", "2024"]);
+
+        let note = parse("file", "---\ntags: a, b\n---\nNo heading\n# Later\n");
+        assert_eq!(note.title, "file");
+        assert_eq!(note.content, "No heading\n# Later\n");
+        assert_eq!(note.tags, ["a", " b"]);
+    }
+
+    #[test]
+    fn reads_notes_and_skips_other_files() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::create_dir_all(root.join(".obsidian")).unwrap();
+        fs::write(root.join("a.md"), "# A\n").unwrap();
+        fs::write(root.join("image.png"), "").unwrap();
+        fs::write(root.join("sub/b.TXT"), "text").unwrap();
+        fs::write(root.join(".obsidian/c.md"), "").unwrap();
+
+        let flat = read(root, false).unwrap();
+        assert_eq!(flat.items.len(), 1);
+        assert_eq!(flat.skipped, ["image.png"]);
+
+        let all = read(root, true).unwrap();
+        assert_eq!(all.items.len(), 2);
+        let b = all.items[1].note.as_ref().unwrap();
+        assert_eq!((b.title.as_str(), b.content.as_str()), ("b", "text"));
+        assert!(b.updated_at.is_some());
+    }
+}
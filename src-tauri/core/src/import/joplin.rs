@@ -0,0 +1,181 @@
+//! Joplin exports. Both the .jex archive and the raw export directory hold one
+//! `<id>.md` file per item: the item's title and body followed by a block of
+//! `key: value` properties, where `type_` says what kind of item it is.
+
+use super::{ImportedItem, ImportedNote};
+use crate::error::NotesError;
+use chrono::DateTime;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+const TYPE_NOTE: &str = "1";
+const TYPE_TAG: &str = "5";
+const TYPE_NOTE_TAG: &str = "6";
+
+struct Item {
+    source: String,
+    title: String,
+    body: String,
+    props: HashMap<String, String>,
+}
+
+impl Item {
+    fn prop(&self, key: &str) -> &str {
+        self.props.get(key).map(String::as_str).unwrap_or("")
+    }
+
+    /// Prefers the user-editable `user_<kind>_time` over the sync
+    /// bookkeeping `<kind>_time`.
+    fn time(&self, kind: &str) -> Result<Option<i64>, NotesError> {
+        match parse_time(self.prop(&format!("user_{}_time", kind)))? {
+            Some(time) => Ok(Some(time)),
+            None => parse_time(self.prop(&format!("{}_time", kind))),
+        }
+    }
+}
+
+pub fn read_raw_dir(dir: &Path) -> Result<Vec<ImportedItem>, NotesError> {
+    let entries = fs::read_dir(dir)
+        .map_err(|e| NotesError::Io(format!("Failed to read Joplin export directory: {}", e)))?;
+    let mut files = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "md") {
+            let text = fs::read_to_string(&path)
+                .map_err(|e| NotesError::Io(format!("Failed to read {}: {}", path.display(), e)))?;
+            files.push((entry.file_name().to_string_lossy().into_owned(), text));
+        }
+    }
+    Ok(convert(files))
+}
+
+pub fn read_jex(path: &Path) -> Result<Vec<ImportedItem>, NotesError> {
+    let file = fs::File::open(path)
+        .map_err(|e| NotesError::Io(format!("Failed to open JEX file: {}", e)))?;
+    let mut archive = tar::Archive::new(file);
+    let entries = archive
+        .entries()
+        .map_err(|e| NotesError::Io(format!("Failed to read JEX file: {}", e)))?;
+
+    let mut files = Vec::new();
+    for entry in entries {
+        let mut entry =
+            entry.map_err(|e| NotesError::Io(format!("Failed to read JEX file: {}", e)))?;
+        let name = entry
+            .path()
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        // Attachments live under resources/; only top-level items matter here
+        if name.contains('/') || !name.ends_with(".md") {
+            continue;
+        }
+        let mut text = String::new();
+        entry
+            .read_to_string(&mut text)
+            .map_err(|e| NotesError::Io(format!("Failed to read {} from JEX file: {}", name, e)))?;
+        files.push((name, text));
+    }
+    Ok(convert(files))
+}
+
+/// Splits an item file into title, body, and the trailing property block.
+fn parse_item(source: String, text: &str) -> Item {
+    let lines: Vec<&str> = text.lines().collect();
+
+    let mut props = HashMap::new();
+    let mut end = lines.len();
+    while end > 0 {
+        let line = lines[end - 1];
+        match line
+            .split_once(": ")
+            .or_else(|| line.strip_suffix(':').map(|k| (k, "")))
+        {
+            Some((key, value))
+                if !key.is_empty() && key.chars().all(|c| c.is_ascii_lowercase() || c == '_') =>
+            {
+                props.insert(key.to_string(), value.to_string());
+                end -= 1;
+            }
+            _ => break,
+        }
+    }
+
+    let content = &lines[..end];
+    let title = content.first().copied().unwrap_or("").to_string();
+    let body = content
+        .iter()
+        .skip(2)
+        .copied()
+        .collect::<Vec<&str>>()
+        .join("\n")
+        .trim_end()
+        .to_string();
+
+    Item {
+        source,
+        title,
+        body,
+        props,
+    }
+}
+
+fn parse_time(value: &str) -> Result<Option<i64>, NotesError> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| Some(time.timestamp()))
+        .map_err(|e| NotesError::Serde(format!("Invalid date {:?}: {}", value, e)))
+}
+
+fn convert(files: Vec<(String, String)>) -> Vec<ImportedItem> {
+    let items: Vec<Item> = files
+        .into_iter()
+        .map(|(source, text)| parse_item(source, &text))
+        .collect();
+
+    let tag_names: HashMap<&str, &str> = items
+        .iter()
+        .filter(|item| item.prop("type_") == TYPE_TAG)
+        .map(|item| (item.prop("id"), item.title.as_str()))
+        .collect();
+    let mut note_tags: HashMap<&str, Vec<String>> = HashMap::new();
+    for link in items
+        .iter()
+        .filter(|item| item.prop("type_") == TYPE_NOTE_TAG)
+    {
+        if let Some(tag) = tag_names.get(link.prop("tag_id")) {
+            note_tags
+                .entry(link.prop("note_id"))
+                .or_default()
+                .push(tag.to_string());
+        }
+    }
+
+    items
+        .iter()
+        .filter(|item| item.prop("type_") == TYPE_NOTE)
+        .map(|item| {
+            let note = item
+                .time("created")
+                .and_then(|created| Ok((created, item.time("updated")?)))
+                .map(|(created_at, updated_at)| ImportedNote {
+                    title: item.title.clone(),
+                    content: item.body.clone(),
+                    created_at,
+                    updated_at,
+                    tags: note_tags.get(item.prop("id")).cloned().unwrap_or_default(),
+                });
+            ImportedItem {
+                source: if item.title.is_empty() {
+                    item.source.clone()
+                } else {
+                    item.title.clone()
+                },
+                note,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,279 @@
+//! Importing notes exported from other apps.
+
+mod enex;
+pub mod folder;
+mod joplin;
+
+use crate::error::NotesError;
+use crate::note::{self, normalize_tags, Note};
+use crate::store::NotesStore;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportFormat {
+    /// An Evernote .enex export.
+    Enex,
+    /// A Joplin .jex archive.
+    Jex,
+    /// A directory produced by Joplin's "RAW - Joplin Export Directory".
+    JoplinRaw,
+}
+
+/// What to do with an imported note that matches one already here.
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStrategy {
+    /// Passes over notes with the same title and content as an existing one,
+    /// reporting them as duplicates.
+    #[default]
+    SkipDuplicates,
+    /// Replaces the content and tags of the existing note with the same
+    /// title, keeping its id, history and everything else.
+    OverwriteByTitle,
+    /// Imports every note as a new one.
+    AlwaysCreate,
+}
+
+/// A note read from an export, before it has been given an id.
+pub struct ImportedNote {
+    pub title: String,
+    pub content: String,
+    pub created_at: Option<i64>,
+    pub updated_at: Option<i64>,
+    pub tags: Vec<String>,
+}
+
+impl ImportedNote {
+    pub fn into_note(self) -> Note {
+        let now = Utc::now().timestamp();
+        let created_at = self.created_at.unwrap_or(now);
+        Note {
+            id: note::new_id(),
+            title: if self.title.trim().is_empty() {
+                "Untitled".to_string()
+            } else {
+                self.title
+            },
+            content: self.content,
+            created_at,
+            updated_at: self.updated_at.unwrap_or(created_at),
+            tags: normalize_tags(self.tags),
+            trashed_at: None,
+            pinned: false,
+            archived: false,
+            favorite: false,
+            locked: false,
+            content_hash: String::new(),
+            color: None,
+            notebook_id: None,
+            attachments: Vec::new(),
+            reminders: Vec::new(),
+            expiry: None,
+            location: None,
+            meta: BTreeMap::new(),
+        }
+    }
+}
+
+/// One entry of an export: either a parsed note or the reason it couldn't be
+/// read. `source` names the entry for the import report.
+pub struct ImportedItem {
+    pub source: String,
+    pub note: Result<ImportedNote, NotesError>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct ImportItemResult {
+    pub source: String,
+    pub id: Option<String>,
+    pub error: Option<NotesError>,
+    /// The existing note this one was a copy of, in which case it wasn't
+    /// imported.
+    pub duplicate_of: Option<String>,
+    /// Whether the note replaced the existing one `id`, rather than being
+    /// added.
+    pub updated: bool,
+}
+
+#[derive(Serialize, Clone, Default)]
+pub struct ImportReport {
+    pub imported: usize,
+    /// Existing notes overwritten by an imported one.
+    pub updated: usize,
+    pub failed: usize,
+    pub duplicates: usize,
+    pub items: Vec<ImportItemResult>,
+    /// Files that were passed over because they don't hold notes.
+    pub skipped: Vec<String>,
+}
+
+impl ImportReport {
+    pub fn record(&mut self, source: String, result: Result<Imported, NotesError>) {
+        let mut item = ImportItemResult {
+            source,
+            id: None,
+            error: None,
+            duplicate_of: None,
+            updated: false,
+        };
+        match result {
+            Ok(Imported::Created(id)) => {
+                self.imported += 1;
+                item.id = Some(id);
+            }
+            Ok(Imported::Updated(id)) => {
+                self.updated += 1;
+                item.id = Some(id);
+                item.updated = true;
+            }
+            Ok(Imported::Duplicate(existing_id)) => {
+                self.duplicates += 1;
+                item.duplicate_of = Some(existing_id);
+            }
+            Err(error) => {
+                self.failed += 1;
+                item.error = Some(error);
+            }
+        }
+        self.items.push(item);
+    }
+
+    /// Whether the import changed any notes.
+    pub fn changed(&self) -> bool {
+        self.imported + self.updated > 0
+    }
+}
+
+/// What became of an imported note, by the id of the note it went into.
+#[derive(PartialEq, Debug)]
+pub enum Imported {
+    Created(String),
+    Updated(String),
+    /// Passed over as a copy of this existing note.
+    Duplicate(String),
+}
+
+fn title_key(title: &str) -> String {
+    title.trim().to_lowercase()
+}
+
+/// Adds imported notes to a store, settling those that match a note outside
+/// the trash with an `ImportStrategy`. Duplicates are found by title and
+/// content hash, and notes imported earlier count too.
+pub struct Importer {
+    strategy: ImportStrategy,
+    by_title: HashMap<String, String>,
+    by_content: HashMap<(String, String), String>,
+}
+
+impl Importer {
+    pub fn new(store: &NotesStore, strategy: ImportStrategy) -> Result<Self, NotesError> {
+        let mut importer = Importer {
+            strategy,
+            by_title: HashMap::new(),
+            by_content: HashMap::new(),
+        };
+        // Newest first, so the oldest of several notes with a title wins
+        for note in store.notes()?.iter().rev() {
+            let title = title_key(&note.title);
+            importer.remember(&note.id, title, note.content_hash.clone());
+        }
+        Ok(importer)
+    }
+
+    fn remember(&mut self, id: &str, title: String, content_hash: String) {
+        self.by_content
+            .insert((title.clone(), content_hash), id.to_string());
+        self.by_title.insert(title, id.to_string());
+    }
+
+    pub fn add(&mut self, store: &mut NotesStore, note: Note) -> Result<Imported, NotesError> {
+        let title = title_key(&note.title);
+        let key = (title.clone(), note::content_hash(&note.content));
+        match self.strategy {
+            ImportStrategy::SkipDuplicates => {
+                if let Some(id) = self.by_content.get(&key) {
+                    return Ok(Imported::Duplicate(id.clone()));
+                }
+            }
+            ImportStrategy::OverwriteByTitle => {
+                if let Some(id) = self.by_title.get(&title).cloned() {
+                    let content_hash = store
+                        .update(&id, |existing| {
+                            existing.content = note.content;
+                            existing.tags = note.tags;
+                            existing.updated_at = Utc::now().timestamp();
+                        })?
+                        .content_hash
+                        .clone();
+                    self.remember(&id, title, content_hash);
+                    return Ok(Imported::Updated(id));
+                }
+            }
+            ImportStrategy::AlwaysCreate => {}
+        }
+        let id = note.id.clone();
+        store.insert(note)?;
+        self.remember(&id, key.0, key.1);
+        Ok(Imported::Created(id))
+    }
+}
+
+/// Reads every note in the export at `path`. Errors that make the whole
+/// export unreadable are returned directly; problems with individual notes
+/// are reported per item.
+pub fn read_export(path: &Path, format: ImportFormat) -> Result<Vec<ImportedItem>, NotesError> {
+    match format {
+        ImportFormat::Enex => enex::read(path),
+        ImportFormat::Jex => joplin::read_jex(path),
+        ImportFormat::JoplinRaw => joplin::read_raw_dir(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+    use tempfile::TempDir;
+
+    fn imported(title: &str, content: &str) -> Note {
+        note::new(title.into(), content.into(), vec!["imported".into()], 1)
+    }
+
+    #[test]
+    fn settles_duplicates_with_the_strategy() {
+        let dir = TempDir::new().unwrap();
+        let mut store = NotesStore::new(Storage::open(dir.path()).unwrap()).unwrap();
+        let existing = note::new("Plan".into(), "Steps\n".into(), Vec::new(), 1);
+        let id = existing.id.clone();
+        store.insert(existing).unwrap();
+
+        let mut skip = Importer::new(&store, ImportStrategy::SkipDuplicates).unwrap();
+        let outcome = skip.add(&mut store, imported(" plan", "Steps\n")).unwrap();
+        assert_eq!(outcome, Imported::Duplicate(id.clone()));
+        let outcome = skip.add(&mut store, imported("Plan", "Other\n")).unwrap();
+        assert!(matches!(outcome, Imported::Created(_)));
+        let outcome = skip.add(&mut store, imported("Plan", "Other\n")).unwrap();
+        assert!(matches!(outcome, Imported::Duplicate(_)));
+        assert_eq!(store.notes().unwrap().len(), 2);
+
+        let mut overwrite = Importer::new(&store, ImportStrategy::OverwriteByTitle).unwrap();
+        let outcome = overwrite
+            .add(&mut store, imported("Plan", "New\n"))
+            .unwrap();
+        assert_eq!(outcome, Imported::Updated(id.clone()));
+        let updated = store.get(&id).unwrap();
+        assert_eq!(updated.content, "New\n");
+        assert_eq!(updated.tags, ["imported"]);
+        assert_eq!(updated.content_hash, note::content_hash("New\n"));
+
+        let mut create = Importer::new(&store, ImportStrategy::AlwaysCreate).unwrap();
+        let outcome = create.add(&mut store, imported("Plan", "New\n")).unwrap();
+        assert!(matches!(outcome, Imported::Created(new_id) if new_id != id));
+        assert_eq!(store.notes().unwrap().len(), 3);
+    }
+}
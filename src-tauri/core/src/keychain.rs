@@ -0,0 +1,125 @@
+//! Secrets kept in the OS keychain rather than in the app's files: sync
+//! credentials, the web clipper's token, tokens for sharing notes and, once
+//! asked to remember it, the vault's data key.
+
+use crate::error::NotesError;
+use crate::workspace;
+use serde::{Deserialize, Serialize};
+
+const SERVICE: &str = "min-notes";
+
+/// A secret the app keeps in the keychain.
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Secret {
+    /// The vault's data key, so the notes open without the master password.
+    VaultKey,
+    /// The vault's data key again, handed out only after a biometric check.
+    BiometricKey,
+    WebDavPassword,
+    /// The S3 keys and sync passphrase.
+    S3Credentials,
+    ClipperToken,
+    ServerToken,
+    /// A GitHub token with the `gist` scope, for sharing notes as gists.
+    GistToken,
+    /// The token of a custom paste service, for sharing notes.
+    PasteToken,
+    /// The password of the SMTP server notes are emailed through.
+    SmtpPassword,
+    /// The API key of the service that writes summaries and titles.
+    AssistantKey,
+}
+
+impl Secret {
+    /// The entry's name, which predates this module for the sync secrets.
+    fn name(self) -> &'static str {
+        match self {
+            Secret::VaultKey => "vault-key",
+            Secret::BiometricKey => "biometric-key",
+            Secret::WebDavPassword => "webdav-sync",
+            Secret::S3Credentials => "s3-sync",
+            Secret::ClipperToken => "clipper-token",
+            Secret::ServerToken => "server-sync",
+            Secret::GistToken => "gist-token",
+            Secret::PasteToken => "paste-token",
+            Secret::SmtpPassword => "smtp-password",
+            Secret::AssistantKey => "assistant-key",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Secret::VaultKey => "vault key",
+            Secret::BiometricKey => "biometric unlock key",
+            Secret::WebDavPassword => "WebDAV password",
+            Secret::S3Credentials => "S3 credentials",
+            Secret::ClipperToken => "clipper token",
+            Secret::ServerToken => "sync server token",
+            Secret::GistToken => "GitHub gist token",
+            Secret::PasteToken => "paste service token",
+            Secret::SmtpPassword => "SMTP password",
+            Secret::AssistantKey => "assistant API key",
+        }
+    }
+
+    /// Whether each workspace has its own. The clipper serves the whole app.
+    fn per_workspace(self) -> bool {
+        self != Secret::ClipperToken
+    }
+}
+
+/// Opens the entry for `secret` of workspace `workspace`. The default
+/// workspace keeps the unsuffixed names used before there were other
+/// workspaces.
+fn entry(secret: Secret, workspace: &str) -> Result<keyring::Entry, NotesError> {
+    let user = if !secret.per_workspace() || workspace == workspace::DEFAULT_ID {
+        secret.name().to_string()
+    } else {
+        format!("{}:{}", secret.name(), workspace)
+    };
+    keyring::Entry::new(SERVICE, &user)
+        .map_err(|e| NotesError::Keychain(format!("Failed to open keychain: {}", e)))
+}
+
+/// Returns `secret` of workspace `workspace`, or `None` if it isn't saved.
+pub fn get(secret: Secret, workspace: &str) -> Result<Option<String>, NotesError> {
+    match entry(secret, workspace)?.get_password() {
+        Ok(value) => Ok(Some(value)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(NotesError::Keychain(format!(
+            "Failed to read {} from keychain: {}",
+            secret.label(),
+            e
+        ))),
+    }
+}
+
+/// Like `get`, for secrets that must have been saved.
+pub fn require(secret: Secret, workspace: &str) -> Result<String, NotesError> {
+    get(secret, workspace)?
+        .ok_or_else(|| NotesError::Keychain(format!("No {} in the keychain", secret.label())))
+}
+
+pub fn set(secret: Secret, workspace: &str, value: &str) -> Result<(), NotesError> {
+    entry(secret, workspace)?.set_password(value).map_err(|e| {
+        NotesError::Keychain(format!(
+            "Failed to save {} to keychain: {}",
+            secret.label(),
+            e
+        ))
+    })
+}
+
+/// Removes `secret` of workspace `workspace`, returning whether it was saved.
+pub fn clear(secret: Secret, workspace: &str) -> Result<bool, NotesError> {
+    match entry(secret, workspace)?.delete_credential() {
+        Ok(()) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(e) => Err(NotesError::Keychain(format!(
+            "Failed to remove {} from keychain: {}",
+            secret.label(),
+            e
+        ))),
+    }
+}
@@ -0,0 +1,60 @@
+//! Everything min_notes does with notes that doesn't need a window: the note
+//! model, storage backends and encryption, search, import and export, sync,
+//! and the app's settings and workspaces. The Tauri app wraps it in commands.
+
+pub mod anki;
+pub mod archive;
+pub mod assistant;
+pub mod auto_archive;
+pub mod backup;
+pub mod board;
+pub mod calendar;
+pub mod clipboard;
+pub mod clipper;
+pub mod datadir;
+pub mod deeplink;
+pub mod drafts;
+pub mod email;
+pub mod error;
+pub mod expiry;
+pub mod export;
+pub mod fsutil;
+pub mod geo;
+pub mod graph;
+pub mod import;
+pub mod keychain;
+pub mod links;
+pub mod logging;
+pub mod markdown;
+pub mod models;
+pub mod note;
+pub mod notebooks;
+pub mod ocr;
+pub mod photo;
+pub mod previews;
+pub mod query;
+pub mod recent;
+pub mod related;
+pub mod reminders;
+pub mod replace;
+pub mod saved_searches;
+pub mod search;
+pub mod semantic;
+pub mod settings;
+pub mod share;
+pub mod site;
+pub mod snapshot;
+pub mod stats;
+pub mod storage;
+pub mod store;
+pub mod sync;
+pub mod tasks;
+pub mod templates;
+pub mod transcribe;
+pub mod undo;
+pub mod upgrade;
+pub mod usage;
+pub mod validate;
+pub mod vault;
+pub mod watch;
+pub mod workspace;
@@ -0,0 +1,277 @@
+//! `[[Note Title]]` links between notes.
+//!
+//! Links name their target by title, so they're resolved when asked for
+//! rather than when saved: renaming a note or creating one with a linked
+//! title changes where existing links point.
+
+use crate::note::Note;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// A note at one end of a link.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct LinkedNote {
+    pub id: String,
+    pub title: String,
+}
+
+/// A link as written in a note, and the note it points to if there's one
+/// with that title.
+#[derive(Serialize, PartialEq, Debug)]
+pub struct OutgoingLink {
+    pub target: String,
+    pub note: Option<LinkedNote>,
+}
+
+struct Entry {
+    title: String,
+    /// Link targets in the order they first appear, without duplicates.
+    links: Vec<String>,
+}
+
+/// The links of every note given to it. Like the search index, it's kept in
+/// memory and only holds notes that aren't trashed.
+#[derive(Default)]
+pub struct LinkGraph {
+    notes: HashMap<String, Entry>,
+}
+
+impl LinkGraph {
+    pub fn rebuild(&mut self, notes: &[Note]) {
+        self.notes.clear();
+        for note in notes {
+            self.upsert(note);
+        }
+    }
+
+    pub fn upsert(&mut self, note: &Note) {
+        let mut links: Vec<String> = Vec::new();
+        for target in parse_links(&note.content) {
+            if !links.iter().any(|link| same_title(link, &target)) {
+                links.push(target);
+            }
+        }
+        self.notes.insert(
+            note.id.clone(),
+            Entry {
+                title: note.title.clone(),
+                links,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, id: &str) {
+        self.notes.remove(id);
+    }
+
+    /// Returns the links in note `id`, or `None` if the graph doesn't hold it.
+    pub fn outgoing(&self, id: &str) -> Option<Vec<OutgoingLink>> {
+        let entry = self.notes.get(id)?;
+        Some(
+            entry
+                .links
+                .iter()
+                .map(|target| OutgoingLink {
+                    target: target.clone(),
+                    note: self.resolve(target),
+                })
+                .collect(),
+        )
+    }
+
+    /// Returns the notes linking to note `id`, sorted by title, or `None` if
+    /// the graph doesn't hold it.
+    pub fn backlinks(&self, id: &str) -> Option<Vec<LinkedNote>> {
+        let title = &self.notes.get(id)?.title;
+        let mut sources: Vec<LinkedNote> = self
+            .notes
+            .iter()
+            .filter(|(source, entry)| {
+                *source != id && entry.links.iter().any(|link| same_title(link, title))
+            })
+            .map(|(source, entry)| LinkedNote {
+                id: source.clone(),
+                title: entry.title.clone(),
+            })
+            .collect();
+        sources.sort_by(|a, b| a.title.cmp(&b.title).then_with(|| a.id.cmp(&b.id)));
+        Some(sources)
+    }
+
+    /// Returns every link between two notes in the graph as `(source,
+    /// target)` ids, sorted. Links of a note to itself are left out.
+    pub fn edges(&self) -> Vec<(String, String)> {
+        let mut edges: Vec<(String, String)> = self
+            .notes
+            .iter()
+            .flat_map(|(source, entry)| {
+                entry.links.iter().filter_map(move |target| {
+                    let target = self.resolve(target)?.id;
+                    (target != *source).then(|| (source.clone(), target))
+                })
+            })
+            .collect();
+        edges.sort();
+        edges.dedup();
+        edges
+    }
+
+    /// Finds the note titled `target`. If several are, the one with the
+    /// smallest id is picked so the answer doesn't change between calls.
+    pub fn resolve(&self, target: &str) -> Option<LinkedNote> {
+        self.notes
+            .iter()
+            .filter(|(_, entry)| same_title(&entry.title, target))
+            .min_by(|a, b| a.0.cmp(b.0))
+            .map(|(id, entry)| LinkedNote {
+                id: id.clone(),
+                title: entry.title.clone(),
+            })
+    }
+}
+
+/// Titles match case-insensitively, ignoring surrounding whitespace.
+pub fn same_title(a: &str, b: &str) -> bool {
+    a.trim().to_lowercase() == b.trim().to_lowercase()
+}
+
+/// Returns the target titles of the `[[links]]` in `content`, in order.
+/// `[[Title|shown text]]` and `[[Title#Heading]]` both link to "Title".
+pub fn parse_links(content: &str) -> Vec<String> {
+    let mut links = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("[[") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find("]]") else {
+            break;
+        };
+        let inner = &rest[..end];
+        // `[[` inside the brackets starts a new link, e.g. `[[a [[b]]`
+        if let Some(nested) = inner.rfind("[[") {
+            rest = &rest[nested..];
+            continue;
+        }
+        rest = &rest[end + 2..];
+        if inner.contains('\n') {
+            continue;
+        }
+        let target = inner.split(['|', '#']).next().unwrap_or("").trim();
+        if !target.is_empty() {
+            links.push(target.to_string());
+        }
+    }
+    links
+}
+
+/// Rewrites the `[[links]]` in `content` as Markdown links to the URL `url`
+/// returns for their target title, leaving those it returns `None` for as
+/// they are. The link text is the link's shown text, if it has one.
+pub fn rewrite_links(content: &str, mut url: impl FnMut(&str) -> Option<String>) -> String {
+    let mut rewritten = String::with_capacity(content.len());
+    let mut rest = content;
+    // Scans like `parse_links`
+    while let Some(start) = rest.find("[[") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("]]") else {
+            break;
+        };
+        let inner = &after[..end];
+        if let Some(nested) = inner.rfind("[[") {
+            rewritten.push_str(&rest[..start + 2 + nested]);
+            rest = &after[nested..];
+            continue;
+        }
+        rewritten.push_str(&rest[..start]);
+        let link = &rest[start..start + end + 4];
+        rest = &after[end + 2..];
+        let target = inner.split(['|', '#']).next().unwrap_or("").trim();
+        let url = if inner.contains('\n') || target.is_empty() {
+            None
+        } else {
+            url(target)
+        };
+        match url {
+            Some(url) => {
+                let text = inner
+                    .split_once('|')
+                    .map_or(target, |(_, text)| text.trim());
+                rewritten.push_str(&format!("[{}]({})", text, url));
+            }
+            None => rewritten.push_str(link),
+        }
+    }
+    rewritten.push_str(rest);
+    rewritten
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note;
+
+    fn note(id: &str, title: &str, content: &str) -> Note {
+        let mut note = note::new(title.into(), content.into(), Vec::new(), 0);
+        note.id = id.into();
+        note
+    }
+
+    #[test]
+    fn parses_links() {
+        assert_eq!(
+            parse_links("See [[Alpha]], [[Beta|the second]] and [[ Gamma#Intro ]]."),
+            ["Alpha", "Beta", "Gamma"]
+        );
+        assert_eq!(parse_links("[[a [[b]] [[]] [[c\nd]] [[e"), ["b"]);
+    }
+
+    #[test]
+    fn rewrites_links() {
+        let url = |target: &str| (target != "Other").then(|| format!("{}.md", target));
+        assert_eq!(
+            rewrite_links("[[a [[b]] [[B|see b]] [[Other]] [[c#Intro]] [[e", url),
+            "[[a [b](b.md) [see b](B.md) [[Other]] [c](c.md) [[e"
+        );
+    }
+
+    #[test]
+    fn resolves_links_by_title() {
+        let mut graph = LinkGraph::default();
+        graph.rebuild(&[
+            note(
+                "1",
+                "Alpha",
+                "Links to [[beta]] and [[Missing]], then [[Beta]] again",
+            ),
+            note("2", "Beta", "Back to [[Alpha]]"),
+            note("3", "Gamma", "Also [[BETA]]"),
+        ]);
+
+        let outgoing = graph.outgoing("1").unwrap();
+        assert_eq!(outgoing.len(), 2);
+        assert_eq!(outgoing[0].note.as_ref().unwrap().id, "2");
+        assert_eq!(outgoing[1].target, "Missing");
+        assert!(outgoing[1].note.is_none());
+
+        let backlinks: Vec<String> = graph
+            .backlinks("2")
+            .unwrap()
+            .into_iter()
+            .map(|n| n.title)
+            .collect();
+        assert_eq!(backlinks, ["Alpha", "Gamma"]);
+        assert!(graph.backlinks("4").is_none());
+    }
+
+    #[test]
+    fn follows_renames_and_removals() {
+        let mut graph = LinkGraph::default();
+        graph.rebuild(&[note("1", "Alpha", "[[Beta]]"), note("2", "Old", "")]);
+        assert!(graph.outgoing("1").unwrap()[0].note.is_none());
+
+        graph.upsert(&note("2", "Beta", ""));
+        assert_eq!(graph.backlinks("2").unwrap().len(), 1);
+
+        graph.remove("1");
+        assert!(graph.backlinks("2").unwrap().is_empty());
+    }
+}
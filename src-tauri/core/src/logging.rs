@@ -0,0 +1,161 @@
+//! Logging to files in the app data directory's logs/, rotated daily. The
+//! most recent warnings and errors are also kept in memory so they can be
+//! included in the diagnostics users attach to bug reports.
+
+use crate::error::NotesError;
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{Builder, Rotation};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// How many days of log files are kept.
+const MAX_LOG_FILES: usize = 7;
+const MAX_RECENT_ERRORS: usize = 50;
+
+/// A warning or error that was logged since the app started.
+#[derive(Serialize, Clone)]
+pub struct LoggedError {
+    pub logged_at: i64,
+    /// `"WARN"` or `"ERROR"`.
+    pub level: String,
+    /// The module that logged it.
+    pub target: String,
+    pub message: String,
+}
+
+/// The last `MAX_RECENT_ERRORS` warnings and errors, oldest first.
+#[derive(Clone, Default)]
+pub struct RecentErrors(Arc<Mutex<VecDeque<LoggedError>>>);
+
+impl RecentErrors {
+    pub fn list(&self) -> Vec<LoggedError> {
+        self.0.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+impl<S: Subscriber> Layer<S> for RecentErrors {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        // Levels compare by verbosity, so this skips info and below
+        if *metadata.level() > Level::WARN {
+            return;
+        }
+        let mut message = MessageVisitor::default();
+        event.record(&mut message);
+
+        let mut errors = self.0.lock().unwrap();
+        if errors.len() == MAX_RECENT_ERRORS {
+            errors.pop_front();
+        }
+        errors.push_back(LoggedError {
+            logged_at: Utc::now().timestamp(),
+            level: metadata.level().to_string(),
+            target: metadata.target().to_string(),
+            message: message.0,
+        });
+    }
+}
+
+/// Formats an event's message followed by any other fields as `name=value`.
+#[derive(Default)]
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+        // Writing to a String can't fail
+        let _ = if field.name() == "message" {
+            write!(self.0, "{:?}", value)
+        } else {
+            write!(self.0, "{}={:?}", field.name(), value)
+        };
+    }
+}
+
+/// Keeps logging running. Dropping it stops writing to the log file.
+pub struct Logging {
+    dir: PathBuf,
+    recent_errors: RecentErrors,
+    _guard: WorkerGuard,
+}
+
+impl Logging {
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn recent_errors(&self) -> Vec<LoggedError> {
+        self.recent_errors.list()
+    }
+}
+
+/// Starts logging info and above to daily files in `dir`, and also to stderr
+/// in debug builds.
+pub fn init(dir: &Path) -> Result<Logging, NotesError> {
+    let appender = Builder::new()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("min_notes")
+        .filename_suffix("log")
+        .max_log_files(MAX_LOG_FILES)
+        .build(dir)
+        .map_err(|e| NotesError::Io(format!("Failed to open log file: {}", e)))?;
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+    let recent_errors = RecentErrors::default();
+
+    tracing_subscriber::registry()
+        .with(LevelFilter::INFO)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(writer)
+                .with_ansi(false),
+        )
+        .with(
+            cfg!(debug_assertions)
+                .then(|| tracing_subscriber::fmt::layer().with_writer(std::io::stderr)),
+        )
+        .with(recent_errors.clone())
+        .try_init()
+        .map_err(|e| NotesError::Internal(format!("Failed to start logging: {}", e)))?;
+
+    Ok(Logging {
+        dir: dir.to_path_buf(),
+        recent_errors,
+        _guard: guard,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_only_recent_warnings_and_errors() {
+        let recent = RecentErrors::default();
+        let subscriber = tracing_subscriber::registry().with(recent.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("Not kept");
+            for i in 0..MAX_RECENT_ERRORS {
+                tracing::warn!("Warning {}", i);
+            }
+            tracing::error!(id = "a", "Failed");
+        });
+
+        let errors = recent.list();
+        assert_eq!(errors.len(), MAX_RECENT_ERRORS);
+        assert_eq!(errors[0].message, "Warning 1");
+        let last = errors.last().unwrap();
+        assert_eq!(last.level, "ERROR");
+        assert_eq!(last.message, "Failed id=\"a\"");
+    }
+}
@@ -0,0 +1,135 @@
+//! Rendering notes' Markdown to HTML for the preview, so it looks the same
+//! everywhere and needs no JavaScript libraries.
+//!
+//! Code blocks are highlighted with [syntect](https://github.com/trishume/syntect)
+//! by the language of their fence, as spans with `hl-` classes that
+//! `highlight_css` styles. `[[links]]` become `min-notes://note/<id>` links
+//! to the notes they name. The HTML is sanitized, since notes may hold raw
+//! HTML from anywhere, e.g. a clipped page.
+
+use crate::deeplink;
+use crate::error::NotesError;
+use crate::export;
+use crate::links;
+use pulldown_cmark::{html, CodeBlockKind, Event, Parser, Tag, TagEnd};
+use std::sync::LazyLock;
+use syntect::highlighting::ThemeSet;
+use syntect::html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+const LIGHT_THEME: &str = "InspiredGitHub";
+const DARK_THEME: &str = "base16-ocean.dark";
+
+/// Highlights `code` as `language`, or returns `None` for a language syntect
+/// doesn't know.
+fn highlight(code: &str, language: &str) -> Option<String> {
+    let syntax = SYNTAXES.find_syntax_by_token(language)?;
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, &SYNTAXES, CLASS_STYLE);
+    for line in LinesWithEndings::from(code) {
+        generator
+            .parse_html_for_line_which_includes_newline(line)
+            .ok()?;
+    }
+    Some(generator.finalize())
+}
+
+/// Renders `markdown` as sanitized HTML, with `resolve` returning the id
+/// of the note a `[[link]]` names, if there's one.
+pub fn render(markdown: &str, mut resolve: impl FnMut(&str) -> Option<String>) -> String {
+    let markdown = links::rewrite_links(markdown, |title| {
+        resolve(title).map(|id| format!("{}://note/{}", deeplink::SCHEME, id))
+    });
+
+    let mut events = Vec::new();
+    let mut code: Option<(String, String)> = None;
+    for event in Parser::new_ext(&markdown, export::markdown_options()) {
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => {
+                        info.split_whitespace().next().unwrap_or("").to_string()
+                    }
+                    CodeBlockKind::Indented => String::new(),
+                };
+                code = Some((language, String::new()));
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                let (language, text) = code.take().unwrap_or_default();
+                let highlighted =
+                    highlight(&text, &language).unwrap_or_else(|| export::escape_html(&text));
+                let class = match language.as_str() {
+                    "" => String::new(),
+                    language => format!(" class=\"language-{}\"", export::escape_html(language)),
+                };
+                events.push(Event::Html(
+                    format!("<pre><code{}>{}</code></pre>\n", class, highlighted).into(),
+                ));
+            }
+            Event::Text(text) if code.is_some() => {
+                if let Some((_, code)) = &mut code {
+                    code.push_str(&text);
+                }
+            }
+            event => events.push(event),
+        }
+    }
+    let mut html = String::new();
+    html::push_html(&mut html, events.into_iter());
+    sanitize(&html)
+}
+
+/// Drops scripts, event handlers, and whatever else in `html` could run
+/// code or load from elsewhere, keeping what the renderer itself writes.
+fn sanitize(html: &str) -> String {
+    ammonia::Builder::default()
+        .add_url_schemes([deeplink::SCHEME, "attachment"])
+        .add_tags(["input"])
+        .add_tag_attributes("input", ["type", "checked", "disabled"])
+        .add_tag_attributes("span", ["class"])
+        .add_tag_attributes("code", ["class"])
+        .add_tag_attributes("sup", ["class"])
+        .add_tag_attributes("div", ["class", "id"])
+        .clean(html)
+        .to_string()
+}
+
+/// The CSS for highlighted code, in the colors of a light or `dark` theme.
+pub fn highlight_css(dark: bool) -> Result<String, NotesError> {
+    let themes = ThemeSet::load_defaults();
+    let theme = &themes.themes[if dark { DARK_THEME } else { LIGHT_THEME }];
+    css_for_theme_with_class_style(theme, CLASS_STYLE)
+        .map_err(|e| NotesError::Internal(format!("Failed to make highlighting CSS: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_sanitized_html() {
+        let markdown = "# Plan\n\n\
+                        - [x] Ship [[Sync]] and [[Nowhere]]\n\n\
+                        | a | b |\n|---|---|\n| 1 | 2 |\n\n\
+                        Note[^1]\n\n[^1]: A footnote.\n\n\
+                        ```rust\nfn main() {}\n```\n\n\
+                        <script>alert(1)</script>\
+                        <a href=\"javascript:alert(1)\" onclick=\"x()\">x</a>\n";
+        let html = render(markdown, |title| {
+            (title == "Sync").then(|| "abc".to_string())
+        });
+        assert!(html.contains("<h1>Plan</h1>"));
+        assert!(html.contains("<input disabled=\"\" type=\"checkbox\" checked=\"\">"));
+        assert!(html.contains("href=\"min-notes://note/abc\""));
+        assert!(html.contains("[[Nowhere]]"));
+        assert!(html.contains("<td>1</td>"));
+        assert!(html.contains("class=\"footnote-definition\""));
+        assert!(html.contains("<code class=\"language-rust\"><span class=\"hl-source hl-rust\">"));
+        for gone in ["<script", "alert", "onclick"] {
+            assert!(!html.contains(gone), "{} is still there", gone);
+        }
+        assert!(highlight_css(true).unwrap().contains(".hl-"));
+    }
+}
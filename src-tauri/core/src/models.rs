@@ -0,0 +1,51 @@
+//! Machine learning models the app runs on the device, such as for OCR.
+//! They're too large to ship with the app, so each is downloaded the first
+//! time it's needed and kept in a models directory shared by every
+//! workspace.
+
+use crate::error::NotesError;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::info;
+
+const TIMEOUT: Duration = Duration::from_secs(600);
+const MAX_MODEL_BYTES: u64 = 500 * 1024 * 1024;
+const USER_AGENT: &str = concat!("min_notes/", env!("CARGO_PKG_VERSION"));
+
+/// Returns the path of the file `name` in `models_dir`, downloading it from
+/// `url` first if it isn't there.
+pub fn fetch(models_dir: &Path, name: &str, url: &str) -> Result<PathBuf, NotesError> {
+    let path = models_dir.join(name);
+    if path.exists() {
+        return Ok(path);
+    }
+    info!("Downloading model {}", name);
+    let failed = |e: &dyn std::fmt::Display| {
+        NotesError::Network(format!("Failed to download model {}: {}", name, e))
+    };
+    let response = ureq::AgentBuilder::new()
+        .timeout(TIMEOUT)
+        .user_agent(USER_AGENT)
+        .build()
+        .get(url)
+        .call()
+        .map_err(|e| failed(&e))?;
+    let mut model = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_MODEL_BYTES)
+        .read_to_end(&mut model)
+        .map_err(|e| failed(&e))?;
+
+    let write = || -> io::Result<()> {
+        fs::create_dir_all(models_dir)?;
+        // Written aside first, so an interrupted download isn't loaded later
+        let partial = path.with_extension("partial");
+        fs::write(&partial, &model)?;
+        fs::rename(&partial, &path)
+    };
+    write().map_err(|e| NotesError::Io(format!("Failed to save model {}: {}", name, e)))?;
+    Ok(path)
+}
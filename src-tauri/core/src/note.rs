@@ -0,0 +1,519 @@
+use crate::expiry::Expiry;
+use crate::geo::Location;
+use crate::reminders::Reminder;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+const MAX_SLUG_CHARS: usize = 60;
+/// The longest a summary's preview gets, in characters.
+const PREVIEW_CHARS: usize = 200;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Note {
+    pub id: String,
+    pub title: String,
+    pub content: String,
+    // Files written before notes tracked edits separately only have `timestamp`.
+    #[serde(alias = "timestamp")]
+    pub created_at: i64,
+    #[serde(default)]
+    pub updated_at: i64,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// When the note was moved to the trash, or `None` if it hasn't been.
+    #[serde(default)]
+    pub trashed_at: Option<i64>,
+    /// Pinned notes are listed before all others.
+    #[serde(default)]
+    pub pinned: bool,
+    /// Archived notes are kept out of the way but, unlike trashed ones, are
+    /// never purged.
+    #[serde(default)]
+    pub archived: bool,
+    /// Favorites can be listed on their own, to reach them quickly.
+    #[serde(default)]
+    pub favorite: bool,
+    /// A color label, as `#rrggbb`.
+    #[serde(default)]
+    pub color: Option<String>,
+    /// The notebook the note is in, or `None` if it isn't in one.
+    #[serde(default)]
+    pub notebook_id: Option<String>,
+    /// Locked notes keep their content sealed with a password of their own,
+    /// and are passed to the UI without it.
+    #[serde(default)]
+    pub locked: bool,
+    /// The SHA-256 of `content`, kept up to date by the store. Empty in notes
+    /// that didn't come from one.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub content_hash: String,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    #[serde(default)]
+    pub reminders: Vec<Reminder>,
+    /// When the note is to be trashed or deleted, if it's short-lived.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry: Option<Expiry>,
+    /// Where the note was written, if it was tagged with a place.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>,
+    /// Fields of the user's own, such as `status` or `priority`, kept in the
+    /// frontmatter of a Markdown note.
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        deserialize_with = "scalars"
+    )]
+    pub meta: BTreeMap<String, String>,
+}
+
+/// A file attached to a note. Its content is kept in the attachment store
+/// under `id`, the SHA-256 of the file, so identical files are stored once.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Attachment {
+    pub id: String,
+    pub name: String,
+    pub mime: String,
+    pub size: u64,
+    pub added_at: i64,
+    /// The text read from an image attachment by OCR, once it has been.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+/// What the note list shows of a note, without its content, which may be
+/// large.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct NoteSummary {
+    pub id: String,
+    pub title: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub tags: Vec<String>,
+    pub pinned: bool,
+    pub archived: bool,
+    pub favorite: bool,
+    pub color: Option<String>,
+    pub notebook_id: Option<String>,
+    pub locked: bool,
+    /// The start of the content, on one line. Empty for locked notes.
+    pub preview: String,
+}
+
+/// The names of a note's own fields, which its metadata fields can't have
+/// since they share the frontmatter of a Markdown note.
+pub const FIELDS: &[&str] = &[
+    "id",
+    "title",
+    "content",
+    "timestamp",
+    "created_at",
+    "updated_at",
+    "tags",
+    "trashed_at",
+    "pinned",
+    "archived",
+    "favorite",
+    "color",
+    "notebook_id",
+    "locked",
+    "content_hash",
+    "attachments",
+    "reminders",
+    "expiry",
+    "location",
+    "meta",
+];
+
+/// Reads metadata fields whose values may also be numbers or booleans, as
+/// frontmatter written by hand often has them. Empty fields are dropped.
+pub(crate) fn scalars<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<String, String>, D::Error> {
+    let map = BTreeMap::<String, Value>::deserialize(deserializer)?;
+    let mut meta = BTreeMap::new();
+    for (key, value) in map {
+        let value = match value {
+            Value::Null => continue,
+            Value::String(value) => value,
+            Value::Number(_) | Value::Bool(_) => value.to_string(),
+            _ => {
+                return Err(serde::de::Error::custom(format!(
+                    "metadata field {} is not text, a number, or a boolean",
+                    key
+                )))
+            }
+        };
+        meta.insert(key, value);
+    }
+    Ok(meta)
+}
+
+/// Trims and de-duplicates tags, dropping empty ones, and returns them sorted.
+pub fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut tags: Vec<String> = tags
+        .into_iter()
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+/// Whether `id` is safe to use in a file name: ASCII letters, digits, `-` and
+/// `_` only, as in the UUIDs given to new notes.
+pub fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 64
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// The SHA-256 of `content`, in hex, as kept in `Note::content_hash`.
+pub fn content_hash(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+/// Returns a fresh note id. These are UUIDv7s, which begin with their
+/// creation time, so sorting ids sorts notes by when they were created.
+pub fn new_id() -> String {
+    Uuid::now_v7().to_string()
+}
+
+/// Returns a new note with a fresh id, created at `now`.
+pub fn new(title: String, content: String, tags: Vec<String>, now: i64) -> Note {
+    Note {
+        id: new_id(),
+        title,
+        content,
+        created_at: now,
+        updated_at: now,
+        tags,
+        trashed_at: None,
+        pinned: false,
+        archived: false,
+        favorite: false,
+        locked: false,
+        content_hash: String::new(),
+        color: None,
+        notebook_id: None,
+        attachments: Vec::new(),
+        reminders: Vec::new(),
+        expiry: None,
+        location: None,
+        meta: BTreeMap::new(),
+    }
+}
+
+/// Returns a copy of `note` with a fresh id, saved at `now`. The copy isn't
+/// pinned, so it doesn't crowd the top of the list, and has no reminders or
+/// expiry.
+pub fn duplicate(note: &Note, now: i64) -> Note {
+    Note {
+        id: new_id(),
+        title: format!("{} (copy)", note.title),
+        created_at: now,
+        updated_at: now,
+        trashed_at: None,
+        pinned: false,
+        reminders: Vec::new(),
+        expiry: None,
+        location: None,
+        ..note.clone()
+    }
+}
+
+impl Note {
+    /// Drops the sealed content of a locked note, which is of no use outside
+    /// the store.
+    pub fn redact(mut self) -> Note {
+        if self.locked {
+            self.content.clear();
+        }
+        self
+    }
+
+    pub fn summary(&self) -> NoteSummary {
+        let preview = match self.locked {
+            true => String::new(),
+            false => {
+                let words = self.content.split_whitespace().collect::<Vec<_>>();
+                words.join(" ").chars().take(PREVIEW_CHARS).collect()
+            }
+        };
+        NoteSummary {
+            id: self.id.clone(),
+            title: self.title.clone(),
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            tags: self.tags.clone(),
+            pinned: self.pinned,
+            archived: self.archived,
+            favorite: self.favorite,
+            color: self.color.clone(),
+            notebook_id: self.notebook_id.clone(),
+            locked: self.locked,
+            preview,
+        }
+    }
+}
+
+/// Changes to make to many notes at once. Fields left out change nothing.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct NotePatch {
+    pub add_tags: Vec<String>,
+    pub remove_tags: Vec<String>,
+    /// The notebook to move the notes into, or `null` to take them out of
+    /// their notebooks.
+    #[serde(deserialize_with = "present")]
+    pub notebook_id: Option<Option<String>>,
+    pub pinned: Option<bool>,
+    pub archived: Option<bool>,
+    pub favorite: Option<bool>,
+    /// Moves the notes to the trash, or restores them from it.
+    pub trashed: Option<bool>,
+}
+
+/// Tells a field that's `null` apart from one that's missing.
+fn present<'de, D: Deserializer<'de>, T: Deserialize<'de>>(
+    deserializer: D,
+) -> Result<Option<T>, D::Error> {
+    T::deserialize(deserializer).map(Some)
+}
+
+impl NotePatch {
+    /// Applies the changes to `note` at `now`.
+    pub fn apply(&self, note: &mut Note, now: i64) {
+        if !self.add_tags.is_empty() || !self.remove_tags.is_empty() {
+            let mut tags = std::mem::take(&mut note.tags);
+            tags.retain(|tag| !self.remove_tags.contains(tag));
+            tags.extend(self.add_tags.iter().cloned());
+            note.tags = normalize_tags(tags);
+            note.updated_at = now;
+        }
+        if let Some(notebook_id) = &self.notebook_id {
+            note.notebook_id = notebook_id.clone();
+        }
+        if let Some(pinned) = self.pinned {
+            note.pinned = pinned;
+        }
+        if let Some(archived) = self.archived {
+            note.archived = archived;
+        }
+        if let Some(favorite) = self.favorite {
+            note.favorite = favorite;
+        }
+        match self.trashed {
+            Some(true) if note.trashed_at.is_none() => note.trashed_at = Some(now),
+            Some(false) => note.trashed_at = None,
+            _ => {}
+        }
+    }
+}
+
+/// The order merged notes are joined in.
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeStrategy {
+    /// In the order they were picked.
+    #[default]
+    Selected,
+    /// Oldest created first.
+    Oldest,
+    /// Most recently updated first.
+    Newest,
+}
+
+/// Joins `notes` into one new note, saved at `now`. Each note's content is
+/// headed by its title and separated from the next by a rule. The new note
+/// is titled after the first note and has its color and notebook, and has
+/// the tags, attachments, and metadata of all, the earliest note's value of
+/// a field winning. It's a favorite if any of them was.
+pub fn merge(notes: &[Note], strategy: MergeStrategy, now: i64) -> Note {
+    let mut notes: Vec<&Note> = notes.iter().collect();
+    match strategy {
+        MergeStrategy::Selected => {}
+        MergeStrategy::Oldest => notes.sort_by_key(|note| note.created_at),
+        MergeStrategy::Newest => notes.sort_by_key(|note| std::cmp::Reverse(note.updated_at)),
+    }
+
+    let content = notes
+        .iter()
+        .map(|note| format!("# {}\n\n{}", note.title, note.content.trim_end()))
+        .collect::<Vec<_>>()
+        .join("\n\n---\n\n");
+    let mut attachments: Vec<Attachment> = Vec::new();
+    for attachment in notes.iter().flat_map(|note| &note.attachments) {
+        if !attachments.iter().any(|a| a.id == attachment.id) {
+            attachments.push(attachment.clone());
+        }
+    }
+    let mut meta = BTreeMap::new();
+    for (key, value) in notes.iter().flat_map(|note| &note.meta) {
+        meta.entry(key.clone()).or_insert_with(|| value.clone());
+    }
+    Note {
+        id: new_id(),
+        title: notes
+            .first()
+            .map(|note| note.title.clone())
+            .unwrap_or_default(),
+        content,
+        created_at: now,
+        updated_at: now,
+        tags: normalize_tags(notes.iter().flat_map(|note| note.tags.clone()).collect()),
+        trashed_at: None,
+        pinned: false,
+        archived: false,
+        favorite: notes.iter().any(|note| note.favorite),
+        locked: false,
+        content_hash: String::new(),
+        color: notes.first().and_then(|note| note.color.clone()),
+        notebook_id: notes.first().and_then(|note| note.notebook_id.clone()),
+        attachments,
+        reminders: Vec::new(),
+        expiry: None,
+        location: None,
+        meta,
+    }
+}
+
+/// Whether `color` is a color label notes can have: `#` and six hex digits.
+pub fn is_valid_color(color: &str) -> bool {
+    color.len() == 7
+        && color
+            .strip_prefix('#')
+            .is_some_and(|hex| hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Turns a title into a lowercase, hyphen-separated file name stem.
+pub fn slugify(title: &str) -> String {
+    let mut slug = String::new();
+    for c in title.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug: String = slug
+        .trim_end_matches('-')
+        .chars()
+        .take(MAX_SLUG_CHARS)
+        .collect();
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "untitled".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expiry::ExpiryAction;
+
+    #[test]
+    fn lists_every_field() {
+        let mut note = new("Title".into(), "Body".into(), vec!["tag".into()], 1);
+        // Filled in, so no field is left out of the serialized note
+        note.content_hash = content_hash(&note.content);
+        note.expiry = Some(Expiry {
+            at: 2,
+            action: ExpiryAction::Trash,
+        });
+        note.location = Some(Location {
+            latitude: 0.0,
+            longitude: 0.0,
+        });
+        note.meta.insert("status".into(), "draft".into());
+        let Value::Object(fields) = serde_json::to_value(&note).unwrap() else {
+            panic!("a note serializes to an object");
+        };
+        for field in fields.keys() {
+            assert!(FIELDS.contains(&field.as_str()), "{} is missing", field);
+        }
+    }
+
+    fn note(id: &str, title: &str, created_at: i64, tags: &[&str]) -> Note {
+        Note {
+            id: id.into(),
+            title: title.into(),
+            content: format!("Body of {}\n", title),
+            created_at,
+            updated_at: created_at,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            trashed_at: None,
+            pinned: true,
+            archived: false,
+            favorite: false,
+            locked: false,
+            content_hash: String::new(),
+            color: None,
+            notebook_id: None,
+            attachments: Vec::new(),
+            reminders: Vec::new(),
+            expiry: None,
+            location: None,
+            meta: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn duplicates_with_fresh_id() {
+        let original = note("a", "Plan", 1, &["work"]);
+        let copy = duplicate(&original, 10);
+        assert_ne!(copy.id, original.id);
+        assert!(is_valid_id(&copy.id));
+        assert_eq!(copy.title, "Plan (copy)");
+        assert_eq!(copy.content, original.content);
+        assert_eq!(copy.tags, ["work"]);
+        assert_eq!((copy.created_at, copy.pinned), (10, false));
+    }
+
+    #[test]
+    fn summarizes_without_content() {
+        let mut long = note("a", "Long", 1, &[]);
+        long.content = "# Long\n\n".to_string() + &"word ".repeat(100);
+        let summary = long.summary();
+        assert!(summary.preview.starts_with("# Long word word"));
+        assert_eq!(summary.preview.chars().count(), PREVIEW_CHARS);
+
+        long.locked = true;
+        assert!(long.summary().preview.is_empty());
+    }
+
+    #[test]
+    fn merges_in_chosen_order() {
+        let notes = [
+            note("b", "Second", 2, &["b", "shared"]),
+            note("a", "First", 1, &["shared", "a"]),
+        ];
+        let merged = merge(&notes, MergeStrategy::Oldest, 10);
+        assert_eq!(merged.title, "First");
+        assert_eq!(
+            merged.content,
+            "# First\n\nBody of First\n\n---\n\n# Second\n\nBody of Second"
+        );
+        assert_eq!(merged.tags, ["a", "b", "shared"]);
+
+        let merged = merge(&notes, MergeStrategy::Selected, 10);
+        assert_eq!(merged.title, "Second");
+    }
+
+    #[test]
+    fn new_ids_sort_by_creation() {
+        let ids: Vec<String> = (0..100).map(|_| new_id()).collect();
+        let mut sorted = ids.clone();
+        sorted.sort();
+        assert_eq!(ids, sorted);
+        assert!(ids.iter().all(|id| is_valid_id(id)));
+    }
+}
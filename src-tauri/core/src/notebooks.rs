@@ -0,0 +1,199 @@
+//! Notebooks, which hold notes and other notebooks, kept in notebooks.json
+//! in the workspace's data directory. Each note records the notebook it's in,
+//! if any, in its `notebook_id`.
+
+use crate::error::NotesError;
+use crate::fsutil;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Notebook {
+    pub id: String,
+    pub name: String,
+    /// The notebook this one is inside, or `None` at the top level.
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    pub created_at: i64,
+}
+
+pub struct Notebooks {
+    path: PathBuf,
+    notebooks: Vec<Notebook>,
+}
+
+impl Notebooks {
+    /// Loads the notebooks of the workspace in `data_dir`.
+    pub fn load(data_dir: &Path) -> Result<Self, NotesError> {
+        let path = data_dir.join("notebooks.json");
+        let notebooks = if path.exists() {
+            fsutil::read_with_backup(&path, |content| {
+                serde_json::from_str(content)
+                    .map_err(|e| NotesError::Serde(format!("Failed to parse notebooks: {}", e)))
+            })?
+        } else {
+            Vec::new()
+        };
+        Ok(Notebooks { path, notebooks })
+    }
+
+    fn save(&self) -> Result<(), NotesError> {
+        let json = serde_json::to_string_pretty(&self.notebooks)
+            .map_err(|e| NotesError::Serde(format!("Failed to serialize notebooks: {}", e)))?;
+        fsutil::write_with_backup(&self.path, json.as_bytes())
+            .map_err(|e| NotesError::Io(format!("Failed to write notebooks: {}", e)))
+    }
+
+    /// Every notebook, in the order created.
+    pub fn all(&self) -> &[Notebook] {
+        &self.notebooks
+    }
+
+    pub fn get(&self, id: &str) -> Result<&Notebook, NotesError> {
+        self.notebooks
+            .iter()
+            .find(|notebook| notebook.id == id)
+            .ok_or_else(|| NotesError::NotFound("Notebook not found".into()))
+    }
+
+    pub fn create(
+        &mut self,
+        name: &str,
+        parent_id: Option<String>,
+    ) -> Result<Notebook, NotesError> {
+        let name = checked_name(name)?;
+        if let Some(parent_id) = &parent_id {
+            self.get(parent_id)?;
+        }
+        let notebook = Notebook {
+            id: Uuid::now_v7().to_string(),
+            name,
+            parent_id,
+            created_at: Utc::now().timestamp(),
+        };
+        self.notebooks.push(notebook.clone());
+        self.save()?;
+        Ok(notebook)
+    }
+
+    /// Adds those of `notebooks` that aren't here yet, by id, such as the
+    /// notebooks of an imported archive. Returns how many were added.
+    pub fn add_missing(&mut self, notebooks: Vec<Notebook>) -> Result<usize, NotesError> {
+        let known: HashSet<String> = self.notebooks.iter().map(|n| n.id.clone()).collect();
+        let before = self.notebooks.len();
+        self.notebooks.extend(
+            notebooks
+                .into_iter()
+                .filter(|notebook| !known.contains(&notebook.id)),
+        );
+        let added = self.notebooks.len() - before;
+        if added > 0 {
+            self.save()?;
+        }
+        Ok(added)
+    }
+
+    /// Renames notebook `id` and moves it into `parent_id`, which can't be
+    /// the notebook itself or one inside it.
+    pub fn update(
+        &mut self,
+        id: &str,
+        name: &str,
+        parent_id: Option<String>,
+    ) -> Result<Notebook, NotesError> {
+        let name = checked_name(name)?;
+        if let Some(parent_id) = &parent_id {
+            self.get(parent_id)?;
+            if self.descendants(id)?.contains(parent_id) {
+                return Err(NotesError::Invalid(
+                    "A notebook can't be moved into itself".into(),
+                ));
+            }
+        }
+        let notebook = self
+            .notebooks
+            .iter_mut()
+            .find(|notebook| notebook.id == id)
+            .ok_or_else(|| NotesError::NotFound("Notebook not found".into()))?;
+        notebook.name = name;
+        notebook.parent_id = parent_id;
+        let notebook = notebook.clone();
+        self.save()?;
+        Ok(notebook)
+    }
+
+    /// Deletes notebook `id`, moving the notebooks inside it up into its
+    /// parent. Returns the parent, where the notebook's notes should go.
+    pub fn delete(&mut self, id: &str) -> Result<Option<String>, NotesError> {
+        let parent_id = self.get(id)?.parent_id.clone();
+        self.notebooks.retain(|notebook| notebook.id != id);
+        for notebook in &mut self.notebooks {
+            if notebook.parent_id.as_deref() == Some(id) {
+                notebook.parent_id = parent_id.clone();
+            }
+        }
+        self.save()?;
+        Ok(parent_id)
+    }
+
+    /// Returns the ids of notebook `id` and every notebook inside it, however
+    /// deeply.
+    pub fn descendants(&self, id: &str) -> Result<HashSet<String>, NotesError> {
+        self.get(id)?;
+        let mut found = HashSet::from([id.to_string()]);
+        let mut pending = vec![id.to_string()];
+        while let Some(parent) = pending.pop() {
+            for notebook in &self.notebooks {
+                if notebook.parent_id.as_ref() == Some(&parent) && found.insert(notebook.id.clone())
+                {
+                    pending.push(notebook.id.clone());
+                }
+            }
+        }
+        Ok(found)
+    }
+}
+
+fn checked_name(name: &str) -> Result<String, NotesError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(NotesError::Invalid("Notebook name cannot be empty".into()));
+    }
+    Ok(name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn nests_notebooks() {
+        let dir = TempDir::new().unwrap();
+        let mut notebooks = Notebooks::load(dir.path()).unwrap();
+        let work = notebooks.create("Work", None).unwrap();
+        let project = notebooks.create("Project", Some(work.id.clone())).unwrap();
+        let meetings = notebooks
+            .create("Meetings", Some(project.id.clone()))
+            .unwrap();
+        assert_eq!(notebooks.descendants(&work.id).unwrap().len(), 3);
+        assert!(matches!(
+            notebooks.update(&work.id, "Work", Some(meetings.id.clone())),
+            Err(NotesError::Invalid(_))
+        ));
+
+        assert_eq!(
+            notebooks.delete(&project.id).unwrap(),
+            Some(work.id.clone())
+        );
+        let notebooks = Notebooks::load(dir.path()).unwrap();
+        assert_eq!(
+            notebooks.get(&meetings.id).unwrap().parent_id,
+            Some(work.id)
+        );
+        assert_eq!(notebooks.all().len(), 2);
+    }
+}
@@ -0,0 +1,64 @@
+//! Reading the text in image attachments, so screenshots and photos of
+//! whiteboards turn up in search. Recognition runs on the device with
+//! [ocrs](https://github.com/robertknight/ocrs).
+
+use crate::error::NotesError;
+use crate::models;
+use ocrs::{ImageSource, OcrEngine, OcrEngineParams};
+use rten::Model;
+use std::path::Path;
+
+const MODELS_URL: &str = "https://ocrs-models.s3-accelerate.amazonaws.com";
+const DETECTION_MODEL: &str = "text-detection.rten";
+const RECOGNITION_MODEL: &str = "text-recognition.rten";
+
+/// Whether attachments of type `mime` are images OCR can read.
+pub fn is_image(mime: &str) -> bool {
+    matches!(mime, "image/png" | "image/jpeg" | "image/webp")
+}
+
+/// Text recognition, with its models loaded.
+pub struct Ocr {
+    engine: OcrEngine,
+}
+
+impl Ocr {
+    /// Loads the models in `models_dir`, downloading those that aren't
+    /// there yet.
+    pub fn load(models_dir: &Path) -> Result<Self, NotesError> {
+        let model = |name: &str| {
+            let path = models::fetch(models_dir, name, &format!("{}/{}", MODELS_URL, name))?;
+            Model::load_file(&path).map_err(|e| {
+                NotesError::Internal(format!("Failed to load OCR model {}: {}", name, e))
+            })
+        };
+        let engine = OcrEngine::new(OcrEngineParams {
+            detection_model: Some(model(DETECTION_MODEL)?),
+            recognition_model: Some(model(RECOGNITION_MODEL)?),
+            ..OcrEngineParams::default()
+        })
+        .map_err(|e| NotesError::Internal(format!("Failed to start OCR: {}", e)))?;
+        Ok(Ocr { engine })
+    }
+
+    /// Returns the text in the PNG, JPEG, or WebP image `image`, a line per
+    /// line of text.
+    pub fn read_text(&self, image: &[u8]) -> Result<String, NotesError> {
+        let image = image::load_from_memory(image)
+            .map_err(|e| NotesError::Invalid(format!("Failed to read image: {}", e)))?
+            .into_rgb8();
+        let failed = |e: &dyn std::fmt::Display| {
+            NotesError::Internal(format!("Failed to read text in image: {}", e))
+        };
+        let source =
+            ImageSource::from_bytes(image.as_raw(), image.dimensions()).map_err(|e| failed(&e))?;
+        let input = self.engine.prepare_input(source).map_err(|e| failed(&e))?;
+        let text = self.engine.get_text(&input).map_err(|e| failed(&e))?;
+        Ok(text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+}
@@ -0,0 +1,89 @@
+//! Preparing photos taken in the app for attaching. Phones save photos with
+//! EXIF metadata, which can include where they were taken and the device
+//! they were taken with, and at sizes far larger than a note needs, so a
+//! photo is re-encoded without its metadata and scaled down first.
+
+use crate::error::NotesError;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageReader};
+use std::io::Cursor;
+
+/// JPEG quality that photos are re-encoded at, out of 100.
+const JPEG_QUALITY: u8 = 85;
+
+#[derive(Clone, Copy, Debug)]
+pub struct PhotoOptions {
+    /// Whether EXIF and other metadata are dropped.
+    pub strip_metadata: bool,
+    /// Photos wider or taller than this many pixels are scaled down to fit,
+    /// or 0 to keep them at full size.
+    pub max_dimension: u32,
+}
+
+/// Returns photo `bytes` as it's to be attached, and its type. A photo that
+/// needs neither stripping nor scaling is returned as it is; others are
+/// turned upright, since the orientation goes with the metadata, and saved
+/// as JPEG.
+pub fn prepare(bytes: Vec<u8>, options: PhotoOptions) -> Result<(Vec<u8>, String), NotesError> {
+    let invalid =
+        |e: &dyn std::fmt::Display| NotesError::Invalid(format!("Unreadable photo: {}", e));
+    let reader = || {
+        ImageReader::new(Cursor::new(bytes.as_slice()))
+            .with_guessed_format()
+            .map_err(|e| invalid(&e))
+    };
+    let format = reader()?
+        .format()
+        .ok_or_else(|| NotesError::Invalid("Photo is not a PNG, JPEG or WebP image".into()))?;
+    let (width, height) = reader()?.into_dimensions().map_err(|e| invalid(&e))?;
+    let oversized = options.max_dimension > 0 && width.max(height) > options.max_dimension;
+    if !options.strip_metadata && !oversized {
+        return Ok((bytes, format.to_mime_type().to_string()));
+    }
+
+    let mut decoder = reader()?.into_decoder().map_err(|e| invalid(&e))?;
+    let orientation = decoder.orientation().map_err(|e| invalid(&e))?;
+    let mut image = DynamicImage::from_decoder(decoder).map_err(|e| invalid(&e))?;
+    image.apply_orientation(orientation);
+    if oversized {
+        let max = options.max_dimension;
+        image = image.resize(max, max, FilterType::Lanczos3);
+    }
+    let mut jpeg = Vec::new();
+    image
+        .to_rgb8()
+        .write_with_encoder(JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY))
+        .map_err(|e| NotesError::Internal(format!("Failed to encode photo: {}", e)))?;
+    Ok((jpeg, "image/jpeg".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageFormat, RgbImage};
+
+    #[test]
+    fn strips_and_scales_down_photos() {
+        let mut png = Vec::new();
+        RgbImage::new(400, 100)
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        let options = |strip_metadata, max_dimension| PhotoOptions {
+            strip_metadata,
+            max_dimension,
+        };
+
+        let (kept, mime) = prepare(png.clone(), options(false, 1000)).unwrap();
+        assert_eq!((kept == png, mime.as_str()), (true, "image/png"));
+
+        let (scaled, mime) = prepare(png.clone(), options(false, 200)).unwrap();
+        assert_eq!(mime, "image/jpeg");
+        let scaled = image::load_from_memory(&scaled).unwrap();
+        assert_eq!((scaled.width(), scaled.height()), (200, 50));
+
+        let (stripped, _) = prepare(png, options(true, 0)).unwrap();
+        assert_eq!(image::load_from_memory(&stripped).unwrap().width(), 400);
+        assert!(prepare(b"not a photo".to_vec(), options(true, 0)).is_err());
+    }
+}
@@ -0,0 +1,334 @@
+//! Previews of the links pasted into notes: the title and description of
+//! each page, fetched once and kept so the UI can show them as cards.
+//!
+//! Only bare URLs get a preview, not the targets of Markdown links, which
+//! have a text of their own. Pages that can't be reached, e.g. while offline,
+//! are left for the next save to try again, while pages that answer with an
+//! error or aren't HTML get an empty preview, so they aren't fetched over
+//! and over. The previews are kept in link_previews.json in the workspace's
+//! data directory.
+
+use crate::error::NotesError;
+use crate::fsutil;
+use chrono::Utc;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use std::time::Duration;
+use tracing::debug;
+use url::Url;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Metadata is in the head, so the rest of a large page isn't read.
+const MAX_PAGE_BYTES: u64 = 512 * 1024;
+const MAX_TITLE_CHARS: usize = 200;
+const MAX_DESCRIPTION_CHARS: usize = 500;
+const USER_AGENT: &str = concat!("min_notes/", env!("CARGO_PKG_VERSION"));
+
+static TITLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<title[^>]*>(.*?)</title>").unwrap());
+static META: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)<meta\s[^>]*>").unwrap());
+static ATTRIBUTE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?s)([a-zA-Z:-]+)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap());
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct LinkPreview {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The page's `og:image`, as an absolute URL.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    pub fetched_at: i64,
+}
+
+/// The bare http and https URLs in `content`, in the order they first
+/// appear, without duplicates.
+pub fn find_urls(content: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut urls = Vec::new();
+    for word in content.split_whitespace() {
+        let word = word.trim_start_matches(['(', '<', '"', '\'']);
+        if !word.starts_with("http://") && !word.starts_with("https://") {
+            continue;
+        }
+        let word = word.trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '>', '"', '\'']);
+        let Ok(url) = Url::parse(word) else {
+            continue;
+        };
+        if url.host_str().is_some() && seen.insert(word.to_string()) {
+            urls.push(word.to_string());
+        }
+    }
+    urls
+}
+
+/// Fetches a preview of each of `urls`, skipping those that can't be
+/// reached.
+pub fn fetch(urls: &[String]) -> Vec<LinkPreview> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(TIMEOUT)
+        .user_agent(USER_AGENT)
+        .build();
+    urls.iter()
+        .filter_map(|url| match fetch_one(&agent, url) {
+            Ok(preview) => Some(preview),
+            Err(e) => {
+                debug!("Failed to fetch preview of {}: {}", url, e);
+                None
+            }
+        })
+        .collect()
+}
+
+fn fetch_one(agent: &ureq::Agent, url: &str) -> Result<LinkPreview, NotesError> {
+    let empty = LinkPreview {
+        url: url.to_string(),
+        title: None,
+        description: None,
+        image: None,
+        fetched_at: Utc::now().timestamp(),
+    };
+    let response = match agent.get(url).call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(_, _)) => return Ok(empty),
+        Err(e) => return Err(NotesError::Network(e.to_string())),
+    };
+    let content_type = response.content_type().to_ascii_lowercase();
+    if content_type != "text/html" && content_type != "application/xhtml+xml" {
+        return Ok(empty);
+    }
+    // The final URL, after redirects, is what relative image URLs resolve
+    // against
+    let base = Url::parse(response.get_url()).ok();
+    let mut page = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_PAGE_BYTES)
+        .read_to_end(&mut page)
+        .map_err(|e| NotesError::Network(e.to_string()))?;
+    Ok(LinkPreview {
+        fetched_at: empty.fetched_at,
+        ..parse_page(url, &String::from_utf8_lossy(&page), base.as_ref())
+    })
+}
+
+/// Takes what a preview shows from the head of an HTML page, preferring
+/// Open Graph tags to the page's own title and description.
+pub(crate) fn parse_page(url: &str, html: &str, base: Option<&Url>) -> LinkPreview {
+    let mut meta = BTreeMap::new();
+    for tag in META.find_iter(html) {
+        let mut key = None;
+        let mut content = None;
+        for attribute in ATTRIBUTE.captures_iter(tag.as_str()) {
+            let value = attribute
+                .get(2)
+                .or(attribute.get(3))
+                .map_or("", |m| m.as_str());
+            match attribute[1].to_ascii_lowercase().as_str() {
+                "property" | "name" => key = Some(value.to_ascii_lowercase()),
+                "content" => content = Some(value),
+                _ => {}
+            }
+        }
+        if let (Some(key), Some(content)) = (key, content) {
+            meta.entry(key).or_insert_with(|| clean(content));
+        }
+    }
+    let mut take = |keys: &[&str]| {
+        keys.iter()
+            .find_map(|key| meta.remove(*key))
+            .filter(|value| !value.is_empty())
+    };
+    let title = take(&["og:title", "twitter:title"]).or_else(|| {
+        TITLE
+            .captures(html)
+            .map(|title| clean(&title[1]))
+            .filter(|title| !title.is_empty())
+    });
+    let description = take(&["og:description", "twitter:description", "description"]);
+    let image = take(&["og:image", "twitter:image"]).and_then(|image| match base {
+        Some(base) => base.join(&image).ok().map(String::from),
+        None => Url::parse(&image).ok().map(String::from),
+    });
+    LinkPreview {
+        url: url.to_string(),
+        title: title.map(|title| truncate(title, MAX_TITLE_CHARS)),
+        description: description.map(|text| truncate(text, MAX_DESCRIPTION_CHARS)),
+        image,
+        fetched_at: 0,
+    }
+}
+
+/// Decodes the common HTML entities in `text` and collapses its whitespace.
+fn clean(text: &str) -> String {
+    let text = text
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn truncate(text: String, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}…", text[..end].trim_end()),
+        None => text,
+    }
+}
+
+/// The previews of every note in one workspace.
+pub struct LinkPreviews {
+    path: PathBuf,
+    /// By note id, in the order the links appear in the note.
+    notes: BTreeMap<String, Vec<LinkPreview>>,
+}
+
+impl LinkPreviews {
+    /// Loads the previews of the workspace in `data_dir`.
+    pub fn load(data_dir: &Path) -> Result<Self, NotesError> {
+        let path = data_dir.join("link_previews.json");
+        let notes = if path.exists() {
+            fsutil::read_with_backup(&path, |content| {
+                serde_json::from_str(content)
+                    .map_err(|e| NotesError::Serde(format!("Failed to parse link previews: {}", e)))
+            })?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(LinkPreviews { path, notes })
+    }
+
+    fn save(&self) -> Result<(), NotesError> {
+        let json = serde_json::to_string_pretty(&self.notes)
+            .map_err(|e| NotesError::Serde(format!("Failed to serialize link previews: {}", e)))?;
+        fsutil::write_with_backup(&self.path, json.as_bytes())
+            .map_err(|e| NotesError::Io(format!("Failed to write link previews: {}", e)))
+    }
+
+    /// The previews of note `id`'s links.
+    pub fn get(&self, id: &str) -> &[LinkPreview] {
+        self.notes.get(id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Which of `urls`, the links of note `id`, have no preview yet.
+    pub fn missing(&self, id: &str, urls: &[String]) -> Vec<String> {
+        let previews = self.get(id);
+        urls.iter()
+            .filter(|url| !previews.iter().any(|preview| &preview.url == *url))
+            .cloned()
+            .collect()
+    }
+
+    /// Sets the previews of note `id`, whose links are now `urls`, to
+    /// `fetched` and the previews it already has of the others. Returns
+    /// whether they changed.
+    pub fn set(
+        &mut self,
+        id: &str,
+        urls: &[String],
+        fetched: Vec<LinkPreview>,
+    ) -> Result<bool, NotesError> {
+        let current = self.get(id);
+        let previews: Vec<LinkPreview> = urls
+            .iter()
+            .filter_map(|url| {
+                fetched
+                    .iter()
+                    .chain(current)
+                    .find(|preview| &preview.url == url)
+                    .cloned()
+            })
+            .collect();
+        if previews == current {
+            return Ok(false);
+        }
+        if previews.is_empty() {
+            self.notes.remove(id);
+        } else {
+            self.notes.insert(id.to_string(), previews);
+        }
+        self.save()?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn finds_bare_urls() {
+        let content = "See https://example.com/a, and (https://example.com/b).\n\
+                       [docs](https://example.com/c) https://example.com/a\n\
+                       Not a link: https:// or ftp://example.com";
+        assert_eq!(
+            find_urls(content),
+            vec!["https://example.com/a", "https://example.com/b"]
+        );
+    }
+
+    #[test]
+    fn reads_page_metadata() {
+        let html = r#"<html><head>
+            <title>Fallback</title>
+            <meta property="og:title" content="Rust &amp; Notes">
+            <meta name='description' content='A  short
+                description'>
+            <meta property="og:image" content="/card.png">
+            </head><body></body></html>"#;
+        let base = Url::parse("https://example.com/post/1").unwrap();
+        let preview = parse_page("https://example.com/post/1", html, Some(&base));
+        assert_eq!(preview.title.as_deref(), Some("Rust & Notes"));
+        assert_eq!(preview.description.as_deref(), Some("A short description"));
+        assert_eq!(
+            preview.image.as_deref(),
+            Some("https://example.com/card.png")
+        );
+
+        let preview = parse_page("https://example.com", "<title> Plain </title>", None);
+        assert_eq!(preview.title.as_deref(), Some("Plain"));
+        assert_eq!(preview.description, None);
+    }
+
+    #[test]
+    fn keeps_previews_of_links_still_in_the_note() {
+        let dir = TempDir::new().unwrap();
+        let preview = |url: &str, title: &str| LinkPreview {
+            url: url.into(),
+            title: Some(title.into()),
+            description: None,
+            image: None,
+            fetched_at: 1,
+        };
+        let (a, b) = (
+            "https://a.example".to_string(),
+            "https://b.example".to_string(),
+        );
+        let mut previews = LinkPreviews::load(dir.path()).unwrap();
+        assert!(previews
+            .set("note", std::slice::from_ref(&a), vec![preview(&a, "A")])
+            .unwrap());
+        assert_eq!(
+            previews.missing("note", &[a.clone(), b.clone()]),
+            vec![b.clone()]
+        );
+
+        // b couldn't be fetched, so only a has a preview
+        let urls = [b.clone(), a.clone()];
+        assert!(!previews.set("note", &urls, Vec::new()).unwrap());
+        assert!(previews.set("note", &urls, vec![preview(&b, "B")]).unwrap());
+        let previews = LinkPreviews::load(dir.path()).unwrap();
+        assert_eq!(previews.get("note"), [preview(&b, "B"), preview(&a, "A")]);
+    }
+}
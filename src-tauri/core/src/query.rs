@@ -0,0 +1,379 @@
+//! The search query syntax:
+//!
+//! - words match notes that contain them in the title or content, and
+//!   `"quoted phrases"` match those words in that order
+//! - `title:`, `content:` and `tag:` limit a word or phrase to one field,
+//!   e.g. `title:"weekly plan"` or `tag:work`
+//! - every term must match unless terms are joined with `OR`; `AND` may be
+//!   written out too
+//! - `NOT term` or `-term` leaves out notes matching the term
+//! - parentheses group terms, e.g. `tag:work (draft OR todo)`
+//! - `created:` and `updated:` match notes by date: in a year, month, or
+//!   day, like `created:2024-03`; in a range, like `updated:2024-01..2024-06`,
+//!   either end of which may be left open; or before or after one, like
+//!   `updated:>=2024-05-01`
+//!
+//! Parsing never fails: anything that can't be read as syntax is searched
+//! for as text, so a half-typed query still finds notes.
+
+use chrono::{Datelike, Months, NaiveDate};
+use serde::Deserialize;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Where a term is looked for.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Scope {
+    /// The title or the content.
+    Any,
+    Title,
+    Content,
+    Tag,
+}
+
+/// Which of a note's dates a date term is about.
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DateField {
+    #[default]
+    Created,
+    Updated,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Query {
+    /// A word or phrase.
+    Text {
+        scope: Scope,
+        text: String,
+    },
+    /// Notes dated on or after the day `from` and before the day `until`,
+    /// in local time. Either may be open.
+    Date {
+        field: DateField,
+        from: Option<NaiveDate>,
+        until: Option<NaiveDate>,
+    },
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    Not(Box<Query>),
+}
+
+#[derive(PartialEq, Debug)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Text(Scope, String),
+    Date(DateField, Option<NaiveDate>, Option<NaiveDate>),
+}
+
+fn scope_named(name: &str) -> Option<Scope> {
+    match name.to_lowercase().as_str() {
+        "title" => Some(Scope::Title),
+        "content" | "body" => Some(Scope::Content),
+        "tag" => Some(Scope::Tag),
+        _ => None,
+    }
+}
+
+fn date_field_named(name: &str) -> Option<DateField> {
+    match name.to_lowercase().as_str() {
+        "created" => Some(DateField::Created),
+        "updated" | "edited" => Some(DateField::Updated),
+        _ => None,
+    }
+}
+
+/// The first day of the year, month, or day `text` names, and the first
+/// day after it.
+fn period(text: &str) -> Option<(NaiveDate, NaiveDate)> {
+    let parts: Vec<&str> = text.split('-').collect();
+    let number = |part: &str| part.parse::<u32>().ok();
+    let year = parts[0]
+        .parse::<i32>()
+        .ok()
+        .filter(|_| parts[0].len() == 4)?;
+    match parts[1..] {
+        [] => {
+            let start = NaiveDate::from_ymd_opt(year, 1, 1)?;
+            Some((start, start.with_year(year + 1)?))
+        }
+        [month] => {
+            let start = NaiveDate::from_ymd_opt(year, number(month)?, 1)?;
+            Some((start, start.checked_add_months(Months::new(1))?))
+        }
+        [month, day] => {
+            let start = NaiveDate::from_ymd_opt(year, number(month)?, number(day)?)?;
+            Some((start, start.succ_opt()?))
+        }
+        _ => None,
+    }
+}
+
+/// Reads the dates a date term matches, `from` inclusive and `until`
+/// exclusive.
+fn date_range(text: &str) -> Option<(Option<NaiveDate>, Option<NaiveDate>)> {
+    if let Some(date) = text.strip_prefix(">=") {
+        Some((Some(period(date)?.0), None))
+    } else if let Some(date) = text.strip_prefix("<=") {
+        Some((None, Some(period(date)?.1)))
+    } else if let Some(date) = text.strip_prefix('>') {
+        Some((Some(period(date)?.1), None))
+    } else if let Some(date) = text.strip_prefix('<') {
+        Some((None, Some(period(date)?.0)))
+    } else if let Some((from, until)) = text.split_once("..") {
+        let from = match from {
+            "" => None,
+            from => Some(period(from)?.0),
+        };
+        let until = match until {
+            "" => None,
+            until => Some(period(until)?.1),
+        };
+        (from.is_some() || until.is_some()).then_some((from, until))
+    } else {
+        let (from, until) = period(text)?;
+        Some((Some(from), Some(until)))
+    }
+}
+
+/// Reads up to the closing quote, or the end of `input` if there's none.
+fn read_phrase(chars: &mut Peekable<Chars>) -> String {
+    chars.by_ref().take_while(|&c| c != '"').collect()
+}
+
+/// Reads a word that may start with a field name, taking the phrase after a
+/// `field:` from `chars`. Returns `None` for a field name on its own.
+fn scoped_word(word: &str, chars: &mut Peekable<Chars>) -> Option<Token> {
+    if let Some((field, rest)) = word
+        .split_once(':')
+        .and_then(|(name, rest)| Some((date_field_named(name)?, rest)))
+    {
+        return match date_range(rest) {
+            Some((from, until)) => Some(Token::Date(field, from, until)),
+            None if rest.is_empty() => None,
+            None => Some(Token::Text(Scope::Any, word.to_string())),
+        };
+    }
+    let Some((scope, rest)) = word
+        .split_once(':')
+        .and_then(|(name, rest)| Some((scope_named(name)?, rest)))
+    else {
+        return Some(Token::Text(Scope::Any, word.to_string()));
+    };
+    if !rest.is_empty() {
+        Some(Token::Text(scope, rest.to_string()))
+    } else if chars.peek() == Some(&'"') {
+        chars.next();
+        Some(Token::Text(scope, read_phrase(chars)))
+    } else {
+        None
+    }
+}
+
+fn tokenize(input: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '"' => {
+                chars.next();
+                tokens.push(Token::Text(Scope::Any, read_phrase(&mut chars)));
+            }
+            '-' => {
+                chars.next();
+                if chars.peek().is_some_and(|c| !c.is_whitespace()) {
+                    tokens.push(Token::Not);
+                }
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '(' | ')' | '"') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                let token = match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => match scoped_word(&word, &mut chars) {
+                        Some(token) => token,
+                        // A field name and nothing else
+                        None => continue,
+                    },
+                };
+                tokens.push(token);
+            }
+        }
+    }
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn or(&mut self) -> Option<Query> {
+        let mut parts: Vec<Query> = self.and().into_iter().collect();
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            parts.extend(self.and());
+        }
+        combine(parts, Query::Or)
+    }
+
+    fn and(&mut self) -> Option<Query> {
+        let mut parts = Vec::new();
+        loop {
+            match self.peek() {
+                None | Some(Token::Close) | Some(Token::Or) => break,
+                Some(Token::And) => self.pos += 1,
+                Some(_) => parts.extend(self.unary()),
+            }
+        }
+        combine(parts, Query::And)
+    }
+
+    fn unary(&mut self) -> Option<Query> {
+        let token = self.tokens.get(self.pos)?;
+        self.pos += 1;
+        match token {
+            Token::Not => self.unary().map(|query| Query::Not(Box::new(query))),
+            Token::Open => {
+                let query = self.or();
+                if self.peek() == Some(&Token::Close) {
+                    self.pos += 1;
+                }
+                query
+            }
+            Token::Text(scope, text) => {
+                let text = text.trim();
+                (!text.is_empty()).then(|| Query::Text {
+                    scope: *scope,
+                    text: text.to_string(),
+                })
+            }
+            &Token::Date(field, from, until) => Some(Query::Date { field, from, until }),
+            // Operators with nothing to apply to
+            Token::Close | Token::And | Token::Or => None,
+        }
+    }
+}
+
+fn combine(mut parts: Vec<Query>, group: fn(Vec<Query>) -> Query) -> Option<Query> {
+    match parts.len() {
+        0 => None,
+        1 => parts.pop(),
+        _ => Some(group(parts)),
+    }
+}
+
+/// Parses `input`, returning `None` if there's nothing in it to search for.
+pub fn parse(input: &str) -> Option<Query> {
+    let mut parser = Parser {
+        tokens: tokenize(input),
+        pos: 0,
+    };
+    let mut parts = Vec::new();
+    while parser.pos < parser.tokens.len() {
+        parts.extend(parser.or());
+        // Skip a `)` that closes nothing
+        if parser.peek() == Some(&Token::Close) {
+            parser.pos += 1;
+        }
+    }
+    combine(parts, Query::And)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(scope: Scope, text: &str) -> Query {
+        Query::Text {
+            scope,
+            text: text.into(),
+        }
+    }
+
+    #[test]
+    fn parses_fields_phrases_and_operators() {
+        assert_eq!(
+            parse(r#"title:"weekly plan" tag:work -draft (a OR NOT b) AND c"#),
+            Some(Query::And(vec![
+                text(Scope::Title, "weekly plan"),
+                text(Scope::Tag, "work"),
+                Query::Not(Box::new(text(Scope::Any, "draft"))),
+                Query::Or(vec![
+                    text(Scope::Any, "a"),
+                    Query::Not(Box::new(text(Scope::Any, "b")))
+                ]),
+                text(Scope::Any, "c"),
+            ]))
+        );
+    }
+
+    #[test]
+    fn reads_half_typed_queries_as_text() {
+        assert_eq!(
+            parse(r#"url:x.com ) (OR "open phrase"#),
+            Some(Query::And(vec![
+                text(Scope::Any, "url:x.com"),
+                text(Scope::Any, "open phrase"),
+            ]))
+        );
+        assert_eq!(parse("title: AND - \"\""), None);
+    }
+
+    #[test]
+    fn parses_date_ranges() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d);
+        let range = |field, from, until| Query::Date { field, from, until };
+        assert_eq!(
+            parse("created:2024-02 -updated:>=2024-05-01"),
+            Some(Query::And(vec![
+                range(DateField::Created, date(2024, 2, 1), date(2024, 3, 1)),
+                Query::Not(Box::new(range(DateField::Updated, date(2024, 5, 1), None))),
+            ]))
+        );
+        assert_eq!(
+            parse("updated:2023..2024-06-30"),
+            Some(range(
+                DateField::Updated,
+                date(2023, 1, 1),
+                date(2024, 7, 1)
+            ))
+        );
+        assert_eq!(
+            parse("created:<2024"),
+            Some(range(DateField::Created, None, date(2024, 1, 1)))
+        );
+        assert_eq!(
+            parse("created:2024-13"),
+            Some(text(Scope::Any, "created:2024-13"))
+        );
+    }
+}
@@ -0,0 +1,218 @@
+//! The notes opened most recently, kept in recent.json in the workspace's
+//! data directory, and the quick switcher that jumps to a note by typing a
+//! few letters of its title.
+//!
+//! Titles are matched the way fzf matches file names, by
+//! [nucleo](https://github.com/helix-editor/nucleo): the letters typed must
+//! appear in order, and matches at the start of words, in a row, or in a
+//! recently opened note rank higher.
+
+use crate::error::NotesError;
+use crate::fsutil;
+use crate::note::Note;
+use crate::search::Highlight;
+use nucleo_matcher::pattern::{CaseMatching, Normalization, Pattern};
+use nucleo_matcher::{Config, Matcher, Utf32Str};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// How many opened notes are remembered.
+const MAX_RECENT: usize = 50;
+/// Added to the score of the most recently opened note, and less to those
+/// opened before it.
+const RECENT_BONUS: u32 = 30;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Opened {
+    pub id: String,
+    pub opened_at: i64,
+}
+
+pub struct Recent {
+    path: PathBuf,
+    /// Most recently opened first.
+    opened: Vec<Opened>,
+}
+
+impl Recent {
+    /// Loads the recently opened notes of the workspace in `data_dir`.
+    pub fn load(data_dir: &Path) -> Result<Self, NotesError> {
+        let path = data_dir.join("recent.json");
+        let opened = if path.exists() {
+            fsutil::read_with_backup(&path, |content| {
+                serde_json::from_str(content)
+                    .map_err(|e| NotesError::Serde(format!("Failed to parse recent notes: {}", e)))
+            })?
+        } else {
+            Vec::new()
+        };
+        Ok(Recent { path, opened })
+    }
+
+    /// Records that note `id` was opened at `now`.
+    pub fn record(&mut self, id: &str, now: i64) -> Result<(), NotesError> {
+        self.opened.retain(|opened| opened.id != id);
+        self.opened.insert(
+            0,
+            Opened {
+                id: id.to_string(),
+                opened_at: now,
+            },
+        );
+        self.opened.truncate(MAX_RECENT);
+        let json = serde_json::to_string_pretty(&self.opened)
+            .map_err(|e| NotesError::Serde(format!("Failed to serialize recent notes: {}", e)))?;
+        fsutil::write_with_backup(&self.path, json.as_bytes())
+            .map_err(|e| NotesError::Io(format!("Failed to write recent notes: {}", e)))
+    }
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct RecentNote {
+    pub id: String,
+    pub title: String,
+    pub opened_at: i64,
+}
+
+/// Up to `limit` of `notes` in the order they were last opened, most
+/// recently first.
+pub fn recent_notes(notes: &[Note], recent: &Recent, limit: usize) -> Vec<RecentNote> {
+    let by_id: HashMap<&str, &Note> = notes.iter().map(|note| (note.id.as_str(), note)).collect();
+    recent
+        .opened
+        .iter()
+        .filter_map(|opened| {
+            let note = by_id.get(opened.id.as_str())?;
+            Some(RecentNote {
+                id: note.id.clone(),
+                title: note.title.clone(),
+                opened_at: opened.opened_at,
+            })
+        })
+        .take(limit)
+        .collect()
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct SwitchHit {
+    pub id: String,
+    pub title: String,
+    pub score: u32,
+    /// The letters of `title` that matched.
+    pub highlights: Vec<Highlight>,
+}
+
+/// Up to `limit` of `notes` whose titles fuzzily match `query`, best first.
+/// With nothing typed yet, the recently opened notes.
+pub fn quick_switch(notes: &[Note], recent: &Recent, query: &str, limit: usize) -> Vec<SwitchHit> {
+    if query.trim().is_empty() {
+        return recent_notes(notes, recent, limit)
+            .into_iter()
+            .map(|note| SwitchHit {
+                id: note.id,
+                title: note.title,
+                score: 0,
+                highlights: Vec::new(),
+            })
+            .collect();
+    }
+    let rank: HashMap<&str, u32> = recent
+        .opened
+        .iter()
+        .enumerate()
+        .map(|(rank, opened)| (opened.id.as_str(), rank as u32))
+        .collect();
+    let pattern = Pattern::parse(query, CaseMatching::Smart, Normalization::Smart);
+    let mut matcher = Matcher::new(Config::DEFAULT);
+    let mut buf = Vec::new();
+    let mut indices = Vec::new();
+    let mut hits: Vec<SwitchHit> = notes
+        .iter()
+        .filter_map(|note| {
+            indices.clear();
+            let title = Utf32Str::new(&note.title, &mut buf);
+            let score = pattern.indices(title, &mut matcher, &mut indices)?;
+            let bonus = rank
+                .get(note.id.as_str())
+                .map_or(0, |rank| RECENT_BONUS.saturating_sub(*rank));
+            indices.sort_unstable();
+            indices.dedup();
+            Some(SwitchHit {
+                id: note.id.clone(),
+                title: note.title.clone(),
+                score: score + bonus,
+                highlights: runs(&indices),
+            })
+        })
+        .collect();
+    hits.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then_with(|| a.title.len().cmp(&b.title.len()))
+            .then_with(|| a.title.cmp(&b.title))
+    });
+    hits.truncate(limit);
+    hits
+}
+
+/// Joins the sorted character `indices` into runs of consecutive ones.
+fn runs(indices: &[u32]) -> Vec<Highlight> {
+    let mut runs: Vec<Highlight> = Vec::new();
+    for &index in indices {
+        let index = index as usize;
+        match runs.last_mut() {
+            Some(run) if run.end == index => run.end += 1,
+            _ => runs.push(Highlight {
+                start: index,
+                end: index + 1,
+            }),
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note;
+    use tempfile::TempDir;
+
+    #[test]
+    fn switches_to_fuzzy_matches_and_recent_notes() {
+        let dir = TempDir::new().unwrap();
+        let new = |title: &str| note::new(title.into(), String::new(), Vec::new(), 1);
+        let notes = [
+            new("Meeting notes"),
+            new("Monthly metrics"),
+            new("Groceries"),
+        ];
+        let mut recent = Recent::load(dir.path()).unwrap();
+        recent.record(&notes[2].id, 10).unwrap();
+        recent.record(&notes[1].id, 20).unwrap();
+        recent.record(&notes[2].id, 30).unwrap();
+
+        let recent = Recent::load(dir.path()).unwrap();
+        let titles: Vec<String> = recent_notes(&notes, &recent, 10)
+            .into_iter()
+            .map(|note| note.title)
+            .collect();
+        assert_eq!(titles, ["Groceries", "Monthly metrics"]);
+
+        let hits = quick_switch(&notes, &recent, "mtng", 10);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].title, "Meeting notes");
+        assert_eq!(
+            hits[0].highlights,
+            [
+                Highlight { start: 0, end: 1 },
+                Highlight { start: 3, end: 4 },
+                Highlight { start: 5, end: 7 },
+            ]
+        );
+        let hits = quick_switch(&notes, &recent, "m", 10);
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].title, "Monthly metrics");
+        assert_eq!(quick_switch(&notes, &recent, " ", 1).len(), 1);
+    }
+}
@@ -0,0 +1,217 @@
+//! Suggestions of notes related to the one being read, scored here so the
+//! frontend doesn't need every note's content to compare them.
+//!
+//! A note is related to another by the tags they share, by how close they
+//! are in the link graph, and by how similar their text is: the cosine of
+//! their embeddings when semantic search has embedded both, or of their
+//! TF-IDF vectors otherwise.
+
+use crate::error::NotesError;
+use crate::links::LinkGraph;
+use crate::note::Note;
+use crate::semantic::Embeddings;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+const TAG_WEIGHT: f32 = 0.3;
+const LINK_WEIGHT: f32 = 0.3;
+const TEXT_WEIGHT: f32 = 0.4;
+/// Words shorter than this are too common to say what a note is about.
+const MIN_WORD_CHARS: usize = 3;
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct RelatedNote {
+    pub id: String,
+    pub title: String,
+    /// From 0 to 1.
+    pub score: f32,
+    pub shared_tags: Vec<String>,
+    /// Whether either note links to the other.
+    pub linked: bool,
+}
+
+/// The `k` of `notes` most related to note `id`, most related first. Notes
+/// with nothing in common with it are left out.
+pub fn related(
+    id: &str,
+    notes: &[Note],
+    links: &LinkGraph,
+    embeddings: Option<&Embeddings>,
+    k: usize,
+) -> Result<Vec<RelatedNote>, NotesError> {
+    let note = notes
+        .iter()
+        .find(|note| note.id == id)
+        .ok_or_else(|| NotesError::NotFound("Note not found".into()))?;
+
+    // Links count whichever way they go
+    let mut neighbors: HashMap<String, HashSet<String>> = HashMap::new();
+    for (source, target) in links.edges() {
+        neighbors
+            .entry(source.clone())
+            .or_default()
+            .insert(target.clone());
+        neighbors.entry(target).or_default().insert(source);
+    }
+    let none = HashSet::new();
+    let linked_to = |other: &str| neighbors.get(other).unwrap_or(&none);
+    let own_neighbors = linked_to(id);
+
+    let tfidf = TfIdf::new(notes);
+    let own_vector = tfidf.vector(note);
+    let own_embedding = embeddings.and_then(|embeddings| embeddings.get(id));
+    let own_tags: BTreeSet<&String> = note.tags.iter().collect();
+
+    let mut found: Vec<RelatedNote> = notes
+        .iter()
+        .filter(|other| other.id != id)
+        .filter_map(|other| {
+            let tags: BTreeSet<&String> = other.tags.iter().collect();
+            let shared_tags: Vec<String> = own_tags
+                .intersection(&tags)
+                .map(|tag| tag.to_string())
+                .collect();
+            let tag_score = match own_tags.union(&tags).count() {
+                0 => 0.0,
+                all => shared_tags.len() as f32 / all as f32,
+            };
+
+            let linked = own_neighbors.contains(&other.id);
+            let link_score = if linked {
+                1.0
+            } else if !own_neighbors.is_disjoint(linked_to(&other.id)) {
+                0.5
+            } else {
+                0.0
+            };
+
+            let embedding = embeddings.and_then(|embeddings| embeddings.get(&other.id));
+            let text_score = match (own_embedding, embedding) {
+                (Some(a), Some(b)) => dot(a, b).max(0.0),
+                _ => cosine(&own_vector, &tfidf.vector(other)),
+            };
+
+            let score =
+                TAG_WEIGHT * tag_score + LINK_WEIGHT * link_score + TEXT_WEIGHT * text_score;
+            (score > 0.0).then(|| RelatedNote {
+                id: other.id.clone(),
+                title: other.title.clone(),
+                score,
+                shared_tags,
+                linked,
+            })
+        })
+        .collect();
+    found.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.title.cmp(&b.title))
+    });
+    found.truncate(k);
+    Ok(found)
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+fn cosine(a: &HashMap<String, f32>, b: &HashMap<String, f32>) -> f32 {
+    let length = |vector: &HashMap<String, f32>| vector.values().map(|w| w * w).sum::<f32>().sqrt();
+    let lengths = length(a) * length(b);
+    if lengths == 0.0 {
+        return 0.0;
+    }
+    let dot: f32 = a
+        .iter()
+        .filter_map(|(word, weight)| Some(weight * b.get(word)?))
+        .sum();
+    dot / lengths
+}
+
+/// How rare each word is among the notes, to weigh the words of one by.
+struct TfIdf {
+    idf: HashMap<String, f32>,
+}
+
+impl TfIdf {
+    fn new(notes: &[Note]) -> Self {
+        let mut documents: HashMap<String, usize> = HashMap::new();
+        for note in notes {
+            let unique: HashSet<String> = words(note).collect();
+            for word in unique {
+                *documents.entry(word).or_default() += 1;
+            }
+        }
+        let total = notes.len() as f32;
+        let idf = documents
+            .into_iter()
+            .map(|(word, count)| (word, (total / count as f32).ln()))
+            .collect();
+        TfIdf { idf }
+    }
+
+    fn vector(&self, note: &Note) -> HashMap<String, f32> {
+        let mut counts: HashMap<String, f32> = HashMap::new();
+        for word in words(note) {
+            *counts.entry(word).or_default() += 1.0;
+        }
+        counts
+            .into_iter()
+            .map(|(word, count)| {
+                let idf = self.idf.get(&word).copied().unwrap_or_default();
+                (word, count * idf)
+            })
+            .collect()
+    }
+}
+
+/// The lowercased words of `note`'s title and, unless it's locked, content.
+fn words(note: &Note) -> impl Iterator<Item = String> + '_ {
+    let content = if note.locked { "" } else { &note.content };
+    note.title
+        .split(|c: char| !c.is_alphanumeric())
+        .chain(content.split(|c: char| !c.is_alphanumeric()))
+        .filter(|word| word.chars().count() >= MIN_WORD_CHARS)
+        .map(str::to_lowercase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note;
+
+    #[test]
+    fn ranks_by_tags_links_and_text() {
+        let new = |title: &str, content: &str, tags: &[&str]| {
+            let tags = tags.iter().map(|tag| tag.to_string()).collect();
+            note::new(title.into(), content.into(), tags, 1)
+        };
+        let notes = [
+            new("Sourdough", "Feed the starter flour and water", &["baking"]),
+            new("Starter", "Starter needs flour daily", &[]),
+            new("Bread", "See [[Sourdough]]", &["baking"]),
+            new("Rye", "[[Bread]] with rye", &[]),
+            new("Taxes", "File by April", &["admin"]),
+        ];
+        let mut links = LinkGraph::default();
+        links.rebuild(&notes);
+
+        let found = related(&notes[0].id, &notes, &links, None, 10).unwrap();
+        let titles: Vec<&str> = found.iter().map(|note| note.title.as_str()).collect();
+        assert_eq!(titles, ["Bread", "Rye", "Starter"]);
+        assert_eq!(found[0].shared_tags, ["baking"]);
+        assert!(found[0].linked);
+        assert!(!found[1].linked && found[1].shared_tags.is_empty());
+
+        assert_eq!(
+            related(&notes[0].id, &notes, &links, None, 1)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(matches!(
+            related("missing", &notes, &links, None, 10),
+            Err(NotesError::NotFound(_))
+        ));
+    }
+}
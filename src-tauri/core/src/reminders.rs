@@ -0,0 +1,161 @@
+//! Reminders set on notes. They're saved with the note, so they outlast
+//! restarts; a background task in lib.rs notifies the user when one is due.
+
+use crate::note::Note;
+use chrono::{DateTime, Days, Local, Months, TimeZone};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// How often a reminder comes back after it fires. Repeats follow the local
+/// clock, so a daily 9:00 reminder stays at 9:00 across daylight saving.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Repeat {
+    #[default]
+    Never,
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Reminder {
+    pub id: String,
+    pub due_at: i64,
+    #[serde(default)]
+    pub repeat: Repeat,
+}
+
+/// A reminder and the note it's set on, as listed to the UI.
+#[derive(Serialize)]
+pub struct ReminderInfo {
+    pub id: String,
+    pub note_id: String,
+    pub note_title: String,
+    pub due_at: i64,
+    pub repeat: Repeat,
+}
+
+impl Reminder {
+    pub fn new(due_at: i64, repeat: Repeat) -> Self {
+        Reminder {
+            id: Uuid::new_v4().to_string(),
+            due_at,
+            repeat,
+        }
+    }
+
+    /// When a reminder that fired at `now` is due again: its first repeat
+    /// after `now`, so one missed while the app was closed fires only once.
+    /// `None` if it doesn't repeat.
+    fn next_after(&self, now: i64) -> Option<i64> {
+        let mut next = Local.timestamp_opt(self.due_at, 0).earliest()?;
+        while next.timestamp() <= now {
+            next = advance(next, self.repeat)?;
+        }
+        Some(next.timestamp())
+    }
+}
+
+fn advance(time: DateTime<Local>, repeat: Repeat) -> Option<DateTime<Local>> {
+    match repeat {
+        Repeat::Never => None,
+        Repeat::Daily => time.checked_add_days(Days::new(1)),
+        Repeat::Weekly => time.checked_add_days(Days::new(7)),
+        Repeat::Monthly => time.checked_add_months(Months::new(1)),
+    }
+}
+
+pub fn is_due(reminders: &[Reminder], now: i64) -> bool {
+    reminders.iter().any(|reminder| reminder.due_at <= now)
+}
+
+/// Moves the reminders that are due at `now` on to their next repeat,
+/// dropping those that don't repeat.
+pub fn fire_due(reminders: &mut Vec<Reminder>, now: i64) {
+    reminders.retain_mut(|reminder| {
+        if reminder.due_at > now {
+            return true;
+        }
+        match reminder.next_after(now) {
+            Some(next) => {
+                reminder.due_at = next;
+                true
+            }
+            None => false,
+        }
+    });
+}
+
+/// Every reminder on a note that isn't trashed, soonest first.
+pub fn list(notes: &[Note]) -> Vec<ReminderInfo> {
+    let mut reminders: Vec<ReminderInfo> = notes
+        .iter()
+        .filter(|note| note.trashed_at.is_none())
+        .flat_map(|note| {
+            note.reminders.iter().map(|reminder| ReminderInfo {
+                id: reminder.id.clone(),
+                note_id: note.id.clone(),
+                note_title: note.title.clone(),
+                due_at: reminder.due_at,
+                repeat: reminder.repeat,
+            })
+        })
+        .collect();
+    reminders.sort_by(|a, b| a.due_at.cmp(&b.due_at).then_with(|| a.id.cmp(&b.id)));
+    reminders
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(y: i32, m: u32, d: u32, h: u32) -> i64 {
+        Local
+            .with_ymd_and_hms(y, m, d, h, 0, 0)
+            .earliest()
+            .unwrap()
+            .timestamp()
+    }
+
+    fn reminder(id: &str, due_at: i64, repeat: Repeat) -> Reminder {
+        Reminder {
+            id: id.into(),
+            due_at,
+            repeat,
+        }
+    }
+
+    #[test]
+    fn fires_due_reminders() {
+        let now = at(2024, 3, 10, 12);
+        let mut reminders = vec![
+            reminder("once", at(2024, 3, 10, 9), Repeat::Never),
+            reminder("later", at(2024, 3, 11, 9), Repeat::Never),
+            reminder("daily", at(2024, 3, 10, 9), Repeat::Daily),
+        ];
+        assert!(is_due(&reminders, now));
+        fire_due(&mut reminders, now);
+        assert_eq!(
+            reminders,
+            [
+                reminder("later", at(2024, 3, 11, 9), Repeat::Never),
+                reminder("daily", at(2024, 3, 11, 9), Repeat::Daily),
+            ]
+        );
+        assert!(!is_due(&reminders, now));
+    }
+
+    #[test]
+    fn missed_repeats_fire_once() {
+        let mut reminders = vec![
+            reminder("weekly", at(2024, 1, 1, 9), Repeat::Weekly),
+            reminder("monthly", at(2024, 1, 31, 9), Repeat::Monthly),
+        ];
+        fire_due(&mut reminders, at(2024, 3, 10, 12));
+        assert_eq!(reminders[0].due_at, at(2024, 3, 11, 9));
+        // A month without the 31st moves it to that month's last day, which
+        // later repeats keep
+        assert_eq!(reminders[1].due_at, at(2024, 3, 29, 9));
+    }
+}
@@ -0,0 +1,102 @@
+//! Find and replace across the contents of every note.
+
+use crate::error::NotesError;
+use crate::note::Note;
+use regex::{NoExpand, Regex};
+use serde::Serialize;
+
+/// How many matches a note has, and so how many replacements it gets.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct NoteMatches {
+    pub id: String,
+    pub title: String,
+    pub matches: usize,
+}
+
+/// A pattern to replace, either matched literally or as a regular
+/// expression. A regex replacement can refer to groups as `$1` or `$name`.
+pub struct Replacer {
+    pattern: Regex,
+    replacement: String,
+    expand: bool,
+}
+
+impl Replacer {
+    pub fn new(pattern: &str, replacement: &str, regex: bool) -> Result<Self, NotesError> {
+        if pattern.is_empty() {
+            return Err(NotesError::Invalid("Search pattern cannot be empty".into()));
+        }
+        let source = if regex {
+            pattern.to_string()
+        } else {
+            regex::escape(pattern)
+        };
+        let pattern = Regex::new(&source)
+            .map_err(|e| NotesError::Invalid(format!("Invalid regular expression: {}", e)))?;
+        // It would match between every character
+        if pattern.is_match("") {
+            return Err(NotesError::Invalid(
+                "Search pattern must not match empty text".into(),
+            ));
+        }
+        Ok(Replacer {
+            pattern,
+            replacement: replacement.to_string(),
+            expand: regex,
+        })
+    }
+
+    pub fn count(&self, content: &str) -> usize {
+        self.pattern.find_iter(content).count()
+    }
+
+    pub fn replace(&self, content: &str) -> String {
+        let replaced = if self.expand {
+            self.pattern.replace_all(content, self.replacement.as_str())
+        } else {
+            self.pattern
+                .replace_all(content, NoExpand(&self.replacement))
+        };
+        replaced.into_owned()
+    }
+
+    /// Returns the notes in `notes` with at least one match, in the order
+    /// given.
+    pub fn matches(&self, notes: &[Note]) -> Vec<NoteMatches> {
+        notes
+            .iter()
+            .filter_map(|note| {
+                let matches = self.count(&note.content);
+                (matches > 0).then(|| NoteMatches {
+                    id: note.id.clone(),
+                    title: note.title.clone(),
+                    matches,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_literally() {
+        let replacer = Replacer::new("a.b", "$1", false).unwrap();
+        assert_eq!(replacer.count("a.b axb a.b"), 2);
+        assert_eq!(replacer.replace("a.b axb a.b"), "$1 axb $1");
+    }
+
+    #[test]
+    fn replaces_regex_with_groups() {
+        let replacer = Replacer::new(r"(\d+)-(\d+)", "$2-$1", true).unwrap();
+        assert_eq!(replacer.replace("1-2 and 30-40"), "2-1 and 40-30");
+        for pattern in ["(", "x*", ""] {
+            assert!(matches!(
+                Replacer::new(pattern, "", true),
+                Err(NotesError::Invalid(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! Saved searches, which the sidebar lists like folders holding whatever
+//! notes match them at the time, kept in saved_searches.json in the
+//! workspace's data directory.
+
+use crate::error::NotesError;
+use crate::fsutil;
+use crate::query;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct SavedSearch {
+    pub id: String,
+    pub name: String,
+    /// In the syntax described in `query`.
+    pub query: String,
+    pub created_at: i64,
+}
+
+pub struct SavedSearches {
+    path: PathBuf,
+    searches: Vec<SavedSearch>,
+}
+
+impl SavedSearches {
+    /// Loads the saved searches of the workspace in `data_dir`.
+    pub fn load(data_dir: &Path) -> Result<Self, NotesError> {
+        let path = data_dir.join("saved_searches.json");
+        let searches = if path.exists() {
+            fsutil::read_with_backup(&path, |content| {
+                serde_json::from_str(content).map_err(|e| {
+                    NotesError::Serde(format!("Failed to parse saved searches: {}", e))
+                })
+            })?
+        } else {
+            Vec::new()
+        };
+        Ok(SavedSearches { path, searches })
+    }
+
+    fn save(&self) -> Result<(), NotesError> {
+        let json = serde_json::to_string_pretty(&self.searches)
+            .map_err(|e| NotesError::Serde(format!("Failed to serialize saved searches: {}", e)))?;
+        fsutil::write_with_backup(&self.path, json.as_bytes())
+            .map_err(|e| NotesError::Io(format!("Failed to write saved searches: {}", e)))
+    }
+
+    /// Every saved search, in the order saved.
+    pub fn all(&self) -> &[SavedSearch] {
+        &self.searches
+    }
+
+    pub fn get(&self, id: &str) -> Result<&SavedSearch, NotesError> {
+        self.searches
+            .iter()
+            .find(|search| search.id == id)
+            .ok_or_else(|| NotesError::NotFound("Saved search not found".into()))
+    }
+
+    /// Saves `query` as `name`, replacing saved search `id` if given.
+    pub fn save_search(
+        &mut self,
+        id: Option<&str>,
+        name: &str,
+        query: &str,
+    ) -> Result<SavedSearch, NotesError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(NotesError::Invalid("Name cannot be empty".into()));
+        }
+        let query = query.trim();
+        if query::parse(query).is_none() {
+            return Err(NotesError::Invalid("There is nothing to search for".into()));
+        }
+        let search = match id {
+            Some(id) => {
+                let search = self
+                    .searches
+                    .iter_mut()
+                    .find(|search| search.id == id)
+                    .ok_or_else(|| NotesError::NotFound("Saved search not found".into()))?;
+                search.name = name.to_string();
+                search.query = query.to_string();
+                search.clone()
+            }
+            None => {
+                let search = SavedSearch {
+                    id: Uuid::now_v7().to_string(),
+                    name: name.to_string(),
+                    query: query.to_string(),
+                    created_at: Utc::now().timestamp(),
+                };
+                self.searches.push(search.clone());
+                search
+            }
+        };
+        self.save()?;
+        Ok(search)
+    }
+
+    pub fn delete(&mut self, id: &str) -> Result<(), NotesError> {
+        self.get(id)?;
+        self.searches.retain(|search| search.id != id);
+        self.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn saves_and_replaces_searches() {
+        let dir = TempDir::new().unwrap();
+        let mut searches = SavedSearches::load(dir.path()).unwrap();
+        let saved = searches
+            .save_search(None, " This year ", "created:>=2024 tag:work")
+            .unwrap();
+        assert_eq!(saved.name, "This year");
+        assert!(matches!(
+            searches.save_search(None, "Empty", "title: -"),
+            Err(NotesError::Invalid(_))
+        ));
+
+        searches
+            .save_search(Some(&saved.id), "Work", "tag:work")
+            .unwrap();
+        let reloaded = SavedSearches::load(dir.path()).unwrap();
+        assert_eq!(reloaded.all().len(), 1);
+        assert_eq!(reloaded.get(&saved.id).unwrap().query, "tag:work");
+
+        searches.delete(&saved.id).unwrap();
+        assert!(matches!(
+            searches.delete(&saved.id),
+            Err(NotesError::NotFound(_))
+        ));
+    }
+}
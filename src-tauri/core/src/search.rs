@@ -0,0 +1,626 @@
+//! Full-text search over note titles, contents, tags, and the text read from
+//! their images, plus the graph of links between notes and the checklist
+//! items in them. Queries are written in the syntax described in `query`.
+//!
+//! The index lives in memory: it is rebuilt from storage at startup and kept
+//! current by the commands that create, update, and delete notes.
+
+use crate::error::NotesError;
+use crate::links::LinkGraph;
+use crate::note::Note;
+use crate::query::{self, DateField, Query, Scope};
+use crate::tasks::TaskList;
+use chrono::{Local, NaiveDate, TimeZone};
+use regex::Regex;
+use serde::Serialize;
+use std::ops::Bound;
+use tantivy::collector::TopDocs;
+use tantivy::query::{
+    AllQuery, BooleanQuery, BoostQuery, EmptyQuery, Occur, PhraseQuery, Query as TantivyQuery,
+    RangeQuery, TermQuery,
+};
+use tantivy::schema::{Field, IndexRecordOption, Schema, Value, INDEXED, STORED, STRING, TEXT};
+use tantivy::snippet::SnippetGenerator;
+use tantivy::{doc, Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term};
+
+const WRITER_MEMORY_BYTES: usize = 15_000_000;
+/// The longest a result's snippet gets, in characters.
+const SNIPPET_CHARS: usize = 150;
+/// How many notes `rebuild_with_progress` indexes between reports.
+const PROGRESS_INTERVAL: usize = 100;
+
+#[derive(Serialize)]
+pub struct SearchHit {
+    pub id: String,
+    pub title: String,
+    pub score: f32,
+    /// A passage of the content around the best match, or its start if only
+    /// the title matched.
+    pub snippet: String,
+    /// Where the query matched in `snippet`.
+    pub highlights: Vec<Highlight>,
+    /// Whether `snippet` is text read from one of the note's images, because
+    /// only that matched.
+    pub in_attachment: bool,
+    /// Where the query matched in `title`.
+    pub title_highlights: Vec<Highlight>,
+}
+
+/// A matched span of text, as offsets in characters (Unicode scalar values,
+/// not bytes) from the start of the text, end exclusive.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct Highlight {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Converts byte ranges in `text`, which must fall on character boundaries,
+/// into highlights.
+fn to_highlights(
+    text: &str,
+    ranges: impl IntoIterator<Item = std::ops::Range<usize>>,
+) -> Vec<Highlight> {
+    ranges
+        .into_iter()
+        .map(|range| {
+            let start = text[..range.start].chars().count();
+            Highlight {
+                start,
+                end: start + text[range].chars().count(),
+            }
+        })
+        .collect()
+}
+
+/// The first `SNIPPET_CHARS` characters of `content`, for results that
+/// didn't match in it.
+fn leading_snippet(content: &str) -> String {
+    content.trim_start().chars().take(SNIPPET_CHARS).collect()
+}
+
+pub struct SearchIndex {
+    index: Index,
+    writer: IndexWriter,
+    reader: IndexReader,
+    id: Field,
+    title: Field,
+    content: Field,
+    /// The OCR text of the note's attachments.
+    attachment_text: Field,
+    /// Lowercased, so `tag:` matches regardless of case.
+    tags: Field,
+    created_at: Field,
+    updated_at: Field,
+    links: LinkGraph,
+    tasks: TaskList,
+}
+
+impl SearchIndex {
+    pub fn new() -> Result<Self, NotesError> {
+        let mut schema_builder = Schema::builder();
+        let id = schema_builder.add_text_field("id", STRING | STORED);
+        let title = schema_builder.add_text_field("title", TEXT | STORED);
+        // Stored so result snippets can be cut from it
+        let content = schema_builder.add_text_field("content", TEXT | STORED);
+        let attachment_text = schema_builder.add_text_field("attachment_text", TEXT | STORED);
+        let tags = schema_builder.add_text_field("tags", STRING);
+        let created_at = schema_builder.add_i64_field("created_at", INDEXED);
+        let updated_at = schema_builder.add_i64_field("updated_at", INDEXED);
+        let index = Index::create_in_ram(schema_builder.build());
+
+        let writer = index
+            .writer_with_num_threads(1, WRITER_MEMORY_BYTES)
+            .map_err(|e| {
+                NotesError::Search(format!("Failed to create search index writer: {}", e))
+            })?;
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()
+            .map_err(|e| {
+                NotesError::Search(format!("Failed to create search index reader: {}", e))
+            })?;
+
+        Ok(SearchIndex {
+            index,
+            writer,
+            reader,
+            id,
+            title,
+            content,
+            attachment_text,
+            tags,
+            created_at,
+            updated_at,
+            links: LinkGraph::default(),
+            tasks: TaskList::default(),
+        })
+    }
+
+    pub fn links(&self) -> &LinkGraph {
+        &self.links
+    }
+
+    pub fn tasks(&self) -> &TaskList {
+        &self.tasks
+    }
+
+    /// Replaces the whole index with `notes`.
+    pub fn rebuild(&mut self, notes: &[Note]) -> Result<(), NotesError> {
+        self.rebuild_with_progress(notes, |_| {})
+    }
+
+    /// Like `rebuild`, calling `progress` with how many of `notes` are
+    /// indexed every `PROGRESS_INTERVAL` of them.
+    pub fn rebuild_with_progress(
+        &mut self,
+        notes: &[Note],
+        mut progress: impl FnMut(usize),
+    ) -> Result<(), NotesError> {
+        self.links.rebuild(notes);
+        self.tasks.rebuild(notes);
+        self.writer
+            .delete_all_documents()
+            .map_err(|e| NotesError::Search(format!("Failed to clear search index: {}", e)))?;
+        for (done, note) in notes.iter().enumerate() {
+            self.add(note)?;
+            if (done + 1) % PROGRESS_INTERVAL == 0 {
+                progress(done + 1);
+            }
+        }
+        self.commit()
+    }
+
+    /// Indexes `note`, replacing any previous version of it.
+    pub fn upsert(&mut self, note: &Note) -> Result<(), NotesError> {
+        self.links.upsert(note);
+        self.tasks.upsert(note);
+        self.writer
+            .delete_term(Term::from_field_text(self.id, &note.id));
+        self.add(note)?;
+        self.commit()
+    }
+
+    pub fn remove(&mut self, id: &str) -> Result<(), NotesError> {
+        self.links.remove(id);
+        self.tasks.remove(id);
+        self.writer.delete_term(Term::from_field_text(self.id, id));
+        self.commit()
+    }
+
+    /// Returns up to `limit` notes matching `query`, best match first. Title
+    /// matches are weighted above content matches.
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>, NotesError> {
+        let Some(query) = query::parse(query) else {
+            return Ok(Vec::new());
+        };
+        let query = self.build(&query)?;
+
+        let searcher = self.reader.searcher();
+        let top_docs = searcher
+            .search(&query, &TopDocs::with_limit(limit).order_by_score())
+            .map_err(|e| NotesError::Search(format!("Failed to search notes: {}", e)))?;
+        let snippet_generator = |field: Field| {
+            SnippetGenerator::create(&searcher, &*query, field)
+                .map_err(|e| NotesError::Search(format!("Failed to highlight results: {}", e)))
+        };
+        let mut content_snippets = snippet_generator(self.content)?;
+        content_snippets.set_max_num_chars(SNIPPET_CHARS);
+        let mut attachment_snippets = snippet_generator(self.attachment_text)?;
+        attachment_snippets.set_max_num_chars(SNIPPET_CHARS);
+        let mut title_snippets = snippet_generator(self.title)?;
+
+        let mut hits = Vec::with_capacity(top_docs.len());
+        for (score, address) in top_docs {
+            let doc: TantivyDocument = searcher
+                .doc(address)
+                .map_err(|e| NotesError::Search(format!("Failed to read search result: {}", e)))?;
+            let field_text = |field: Field| {
+                doc.get_first(field)
+                    .and_then(|value| value.as_str())
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            let title = field_text(self.title);
+            let content = field_text(self.content);
+
+            // A title is always shown whole, so its snippet must cover it
+            title_snippets.set_max_num_chars(title.len());
+            let title_snippet = title_snippets.snippet(&title);
+            let title_highlights = to_highlights(&title, title_snippet.highlighted().to_vec());
+
+            let mut snippet = content_snippets.snippet(&content);
+            let mut in_attachment = false;
+            if snippet.is_empty() {
+                let attachment = attachment_snippets.snippet(&field_text(self.attachment_text));
+                if !attachment.is_empty() {
+                    snippet = attachment;
+                    in_attachment = true;
+                }
+            }
+            let (snippet, highlights) = if snippet.is_empty() {
+                (leading_snippet(&content), Vec::new())
+            } else {
+                let fragment = snippet.fragment();
+                (
+                    fragment.to_string(),
+                    to_highlights(fragment, snippet.highlighted().to_vec()),
+                )
+            };
+            hits.push(SearchHit {
+                id: field_text(self.id),
+                title,
+                score,
+                snippet,
+                highlights,
+                in_attachment,
+                title_highlights,
+            });
+        }
+        Ok(hits)
+    }
+
+    /// Turns a parsed query into one the index can run.
+    fn build(&self, query: &Query) -> Result<Box<dyn TantivyQuery>, NotesError> {
+        Ok(match query {
+            Query::Text {
+                scope: Scope::Tag,
+                text,
+            } => Box::new(TermQuery::new(
+                Term::from_field_text(self.tags, &text.to_lowercase()),
+                IndexRecordOption::Basic,
+            )),
+            Query::Text { scope, text } => {
+                let fields: &[(Field, f32)] = match scope {
+                    Scope::Title => &[(self.title, 1.0)],
+                    Scope::Content => &[(self.content, 1.0), (self.attachment_text, 0.5)],
+                    _ => &[
+                        (self.title, 2.0),
+                        (self.content, 1.0),
+                        (self.attachment_text, 0.5),
+                    ],
+                };
+                let mut clauses = Vec::new();
+                for &(field, boost) in fields {
+                    let query = self.text_query(field, text)?;
+                    let query: Box<dyn TantivyQuery> = if boost == 1.0 {
+                        query
+                    } else {
+                        Box::new(BoostQuery::new(query, boost))
+                    };
+                    clauses.push((Occur::Should, query));
+                }
+                Box::new(BooleanQuery::new(clauses))
+            }
+            Query::Date { field, from, until } => {
+                let field = match field {
+                    DateField::Created => self.created_at,
+                    DateField::Updated => self.updated_at,
+                };
+                let bound = |date: &Option<NaiveDate>, bound: fn(Term) -> Bound<Term>| {
+                    date.map_or(Bound::Unbounded, |date| {
+                        bound(Term::from_field_i64(field, local_midnight(date)))
+                    })
+                };
+                match (from, until) {
+                    (None, None) => Box::new(AllQuery),
+                    _ => Box::new(RangeQuery::new(
+                        bound(from, Bound::Included),
+                        bound(until, Bound::Excluded),
+                    )),
+                }
+            }
+            Query::And(parts) => {
+                let mut clauses = Vec::new();
+                for part in parts {
+                    clauses.push(match part {
+                        Query::Not(inner) => (Occur::MustNot, self.build(inner)?),
+                        _ => (Occur::Must, self.build(part)?),
+                    });
+                }
+                Box::new(with_positive_clause(clauses))
+            }
+            Query::Or(parts) => {
+                let mut clauses = Vec::new();
+                for part in parts {
+                    clauses.push((Occur::Should, self.build(part)?));
+                }
+                Box::new(BooleanQuery::new(clauses))
+            }
+            Query::Not(inner) => Box::new(with_positive_clause(vec![(
+                Occur::MustNot,
+                self.build(inner)?,
+            )])),
+        })
+    }
+
+    /// Matches `text` in `field` the way it's indexed: as a single term, or
+    /// as a phrase if it's split into several.
+    fn text_query(&self, field: Field, text: &str) -> Result<Box<dyn TantivyQuery>, NotesError> {
+        let mut analyzer = self
+            .index
+            .tokenizer_for_field(field)
+            .map_err(|e| NotesError::Search(format!("Failed to read search query: {}", e)))?;
+        let mut terms = Vec::new();
+        analyzer
+            .token_stream(text)
+            .process(&mut |token| terms.push(Term::from_field_text(field, &token.text)));
+        Ok(match terms.len() {
+            0 => Box::new(EmptyQuery),
+            1 => Box::new(TermQuery::new(
+                terms.remove(0),
+                IndexRecordOption::WithFreqs,
+            )),
+            _ => Box::new(PhraseQuery::new(terms)),
+        })
+    }
+
+    fn add(&mut self, note: &Note) -> Result<(), NotesError> {
+        let attachment_text = match note.locked {
+            true => String::new(),
+            false => note
+                .attachments
+                .iter()
+                .filter_map(|attachment| attachment.text.as_deref())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        };
+        let mut document = doc!(
+            self.id => note.id.as_str(),
+            self.title => note.title.as_str(),
+            // Locked content is ciphertext, and must not leak into snippets
+            self.content => if note.locked { "" } else { note.content.as_str() },
+            self.attachment_text => attachment_text,
+            self.created_at => note.created_at,
+            self.updated_at => note.updated_at,
+        );
+        for tag in &note.tags {
+            document.add_text(self.tags, tag.to_lowercase());
+        }
+        self.writer
+            .add_document(document)
+            .map_err(|e| NotesError::Search(format!("Failed to index note: {}", e)))?;
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), NotesError> {
+        self.writer
+            .commit()
+            .map_err(|e| NotesError::Search(format!("Failed to commit search index: {}", e)))?;
+        self.reader
+            .reload()
+            .map_err(|e| NotesError::Search(format!("Failed to reload search index: {}", e)))
+    }
+}
+
+/// The Unix time at which `date` begins here. Where a change of clocks
+/// skips midnight, the day begins an hour later.
+fn local_midnight(date: NaiveDate) -> i64 {
+    let time = |hour| {
+        Local
+            .from_local_datetime(&date.and_hms_opt(hour, 0, 0)?)
+            .earliest()
+    };
+    time(0)
+        .or_else(|| time(1))
+        .map_or(0, |time| time.timestamp())
+}
+
+/// A query made only of exclusions matches nothing, so exclude from every
+/// note instead.
+fn with_positive_clause(mut clauses: Vec<(Occur, Box<dyn TantivyQuery>)>) -> BooleanQuery {
+    if clauses.iter().all(|(occur, _)| *occur == Occur::MustNot) {
+        clauses.push((Occur::Must, Box::new(AllQuery)));
+    }
+    BooleanQuery::new(clauses)
+}
+
+/// Returns up to `limit` of `notes` whose title or content matches the
+/// regular expression `pattern`, those with the most matches first. Title
+/// matches count double, as in `SearchIndex::search`.
+pub fn regex_search(
+    notes: &[Note],
+    pattern: &str,
+    limit: usize,
+) -> Result<Vec<SearchHit>, NotesError> {
+    let pattern = Regex::new(pattern)
+        .map_err(|e| NotesError::Invalid(format!("Invalid regular expression: {}", e)))?;
+    let mut hits: Vec<SearchHit> = notes
+        .iter()
+        .filter_map(|note| {
+            let title_matches: Vec<_> = pattern
+                .find_iter(&note.title)
+                .filter(|found| !found.is_empty())
+                .map(|found| found.range())
+                .collect();
+            let content_matches: Vec<_> = pattern
+                .find_iter(&note.content)
+                .filter(|found| !found.is_empty())
+                .map(|found| found.range())
+                .collect();
+            let score = 2 * title_matches.len() + content_matches.len();
+            if score == 0 {
+                return None;
+            }
+            let (snippet, highlights) = regex_snippet(&note.content, &content_matches);
+            Some(SearchHit {
+                id: note.id.clone(),
+                title: note.title.clone(),
+                score: score as f32,
+                snippet,
+                highlights,
+                in_attachment: false,
+                title_highlights: to_highlights(&note.title, title_matches),
+            })
+        })
+        .collect();
+    hits.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.title.cmp(&b.title))
+    });
+    hits.truncate(limit);
+    Ok(hits)
+}
+
+/// Cuts a snippet of `content` starting a little before its first match,
+/// highlighting the `matches` (byte ranges, in order) that fit in it.
+fn regex_snippet(content: &str, matches: &[std::ops::Range<usize>]) -> (String, Vec<Highlight>) {
+    let Some(first) = matches.first() else {
+        return (leading_snippet(content), Vec::new());
+    };
+    // Some context before the match, without splitting a character
+    let start = content[..first.start]
+        .char_indices()
+        .rev()
+        .nth(SNIPPET_CHARS / 4)
+        .map_or(0, |(i, _)| i);
+    let end = content[start..]
+        .char_indices()
+        .nth(SNIPPET_CHARS)
+        .map_or(content.len(), |(i, _)| start + i);
+    let snippet = &content[start..end];
+    let ranges = matches
+        .iter()
+        .filter(|range| range.start >= start && range.end <= end)
+        .map(|range| range.start - start..range.end - start);
+    (snippet.to_string(), to_highlights(snippet, ranges))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn note(id: &str, title: &str, content: &str, tags: &[&str]) -> Note {
+        Note {
+            id: id.into(),
+            title: title.into(),
+            content: content.into(),
+            created_at: 0,
+            updated_at: 0,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            trashed_at: None,
+            pinned: false,
+            archived: false,
+            favorite: false,
+            locked: false,
+            content_hash: String::new(),
+            color: None,
+            notebook_id: None,
+            attachments: Vec::new(),
+            reminders: Vec::new(),
+            expiry: None,
+            location: None,
+            meta: BTreeMap::new(),
+        }
+    }
+
+    fn ids(index: &SearchIndex, query: &str) -> Vec<String> {
+        let mut ids: Vec<String> = index
+            .search(query, 10)
+            .unwrap()
+            .into_iter()
+            .map(|hit| hit.id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn runs_advanced_queries() {
+        let mut index = SearchIndex::new().unwrap();
+        let mut roadmap = note("3", "Roadmap", "Weekly review", &["work"]);
+        roadmap.created_at = local_midnight(NaiveDate::from_ymd_opt(2024, 3, 10).unwrap()) + 60;
+        index
+            .rebuild(&[
+                note("1", "Weekly plan", "Draft the roadmap", &["Work"]),
+                note("2", "Groceries", "Plan weekly meals", &["home"]),
+                roadmap,
+            ])
+            .unwrap();
+
+        assert_eq!(ids(&index, "weekly"), ["1", "2", "3"]);
+        assert_eq!(ids(&index, "title:weekly"), ["1"]);
+        assert_eq!(ids(&index, "tag:work"), ["1", "3"]);
+        assert_eq!(ids(&index, r#""weekly plan""#), ["1"]);
+        assert_eq!(ids(&index, "tag:work -roadmap"), Vec::<String>::new());
+        assert_eq!(ids(&index, "NOT tag:work"), ["2"]);
+        assert_eq!(ids(&index, "meals OR review"), ["2", "3"]);
+        assert_eq!(ids(&index, "created:2024-03"), ["3"]);
+        assert_eq!(ids(&index, "tag:work created:<2024-03-10"), ["1"]);
+        assert!(ids(&index, "").is_empty());
+    }
+
+    #[test]
+    fn highlights_matches_in_snippets() {
+        let mut index = SearchIndex::new().unwrap();
+        let content = format!("{}Ünïcode café here", "word ".repeat(100));
+        index
+            .rebuild(&[
+                note("1", "Café notes", &content, &[]),
+                note("2", "Menu", "Nothing matches in here", &[]),
+            ])
+            .unwrap();
+
+        let hits = index.search("café", 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        let hit = &hits[0];
+        assert_eq!(hit.title_highlights, [Highlight { start: 0, end: 4 }]);
+        assert!(hit.snippet.ends_with("Ünïcode café here"));
+        let highlighted: Vec<String> = hit
+            .highlights
+            .iter()
+            .map(|h| {
+                hit.snippet
+                    .chars()
+                    .skip(h.start)
+                    .take(h.end - h.start)
+                    .collect()
+            })
+            .collect();
+        assert_eq!(highlighted, ["café"]);
+
+        let hits = index.search("title:menu", 10).unwrap();
+        assert_eq!(hits[0].snippet, "Nothing matches in here");
+        assert!(hits[0].highlights.is_empty());
+    }
+
+    #[test]
+    fn finds_text_read_from_images() {
+        let mut index = SearchIndex::new().unwrap();
+        let mut screenshot = note("1", "Screenshot", "From the standup", &[]);
+        screenshot.attachments.push(crate::note::Attachment {
+            id: "abc".into(),
+            name: "board.png".into(),
+            mime: "image/png".into(),
+            size: 3,
+            added_at: 1,
+            text: Some("Launch checklist\nInvoice due Friday".into()),
+        });
+        let mut locked = screenshot.clone();
+        locked.id = "2".into();
+        locked.locked = true;
+        index.rebuild(&[screenshot, locked]).unwrap();
+
+        let hits = index.search("invoice", 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].in_attachment);
+        assert_eq!(hits[0].snippet, "Launch checklist\nInvoice due Friday");
+        assert_eq!(ids(&index, "standup"), ["1"]);
+        assert!(!index.search("standup", 10).unwrap()[0].in_attachment);
+    }
+
+    #[test]
+    fn regex_search_ranks_by_matches() {
+        let notes = [
+            note("1", "a1", "b22", &[]),
+            note("2", "none", "3 4 5 6", &[]),
+        ];
+        let hits = regex_search(&notes, r"\d+", 10).unwrap();
+        let ids: Vec<&str> = hits.iter().map(|hit| hit.id.as_str()).collect();
+        assert_eq!(ids, ["2", "1"]);
+        assert_eq!(hits[1].title_highlights, [Highlight { start: 1, end: 2 }]);
+        assert!(regex_search(&notes, "(", 10).is_err());
+    }
+}
@@ -0,0 +1,297 @@
+//! Semantic search: finding notes by what they're about rather than the
+//! words in them. Each note is turned into an embedding, a vector that lies
+//! near those of notes with similar meaning, by
+//! [all-MiniLM-L6-v2](https://huggingface.co/sentence-transformers/all-MiniLM-L6-v2)
+//! running on the device. A query is embedded the same way and matched to
+//! the notes by cosine similarity.
+//!
+//! The vectors are kept in embeddings.json in the workspace's data
+//! directory, each with a hash of the text it was computed from, so only
+//! notes changed since are embedded again.
+
+use crate::error::NotesError;
+use crate::fsutil;
+use crate::models;
+use crate::note::Note;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use rten::{Model, Value};
+use rten_text::tokenizer::EncodeOptions;
+use rten_text::Tokenizer;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+const MODEL_URL: &str =
+    "https://huggingface.co/sentence-transformers/all-MiniLM-L6-v2/resolve/main/onnx/model.onnx";
+const TOKENIZER_URL: &str =
+    "https://huggingface.co/sentence-transformers/all-MiniLM-L6-v2/resolve/main/tokenizer.json";
+const MODEL: &str = "all-MiniLM-L6-v2.onnx";
+const TOKENIZER: &str = "all-MiniLM-L6-v2.tokenizer.json";
+/// The model was trained on this many tokens, so the rest of a long note
+/// is left out of its embedding.
+const MAX_TOKENS: usize = 256;
+
+/// The embedding model, with its tokenizer.
+pub struct Embedder {
+    model: Model,
+    tokenizer: Tokenizer,
+}
+
+impl Embedder {
+    /// Loads the model in `models_dir`, downloading it if it isn't there yet.
+    pub fn load(models_dir: &Path) -> Result<Self, NotesError> {
+        let failed = |e: &dyn std::fmt::Display| {
+            NotesError::Internal(format!("Failed to load embedding model: {}", e))
+        };
+        let model = Model::load_file(models::fetch(models_dir, MODEL, MODEL_URL)?)
+            .map_err(|e| failed(&e))?;
+        let tokenizer = Tokenizer::from_file(models::fetch(models_dir, TOKENIZER, TOKENIZER_URL)?)
+            .map_err(|e| failed(&e))?;
+        Ok(Embedder { model, tokenizer })
+    }
+
+    /// Returns the embedding of `text`, of unit length.
+    pub fn embed(&self, text: &str) -> Result<Vec<f32>, NotesError> {
+        let failed = |e: &dyn std::fmt::Display| {
+            NotesError::Internal(format!("Failed to embed text: {}", e))
+        };
+        let options = EncodeOptions {
+            max_chunk_len: Some(MAX_TOKENS),
+            overlap: 0,
+        };
+        let encoded = self
+            .tokenizer
+            .encode(text, Some(options))
+            .map_err(|e| failed(&e))?;
+        let ids: Vec<i32> = encoded.token_ids().iter().map(|&id| id as i32).collect();
+        let tokens = ids.len();
+        let tensor = |data: Vec<i32>| Value::from_shape([1, tokens], data).map_err(|e| failed(&e));
+
+        let node = |name: &str| self.model.node_id(name).map_err(|e| failed(&e));
+        let mut inputs = vec![
+            (node("input_ids")?, tensor(ids)?.into()),
+            (node("attention_mask")?, tensor(vec![1; tokens])?.into()),
+        ];
+        if let Some(types) = self.model.find_node("token_type_ids") {
+            inputs.push((types, tensor(vec![0; tokens])?.into()));
+        }
+        let [hidden] = self
+            .model
+            .run_n(inputs, [node("last_hidden_state")?], None)
+            .map_err(|e| failed(&e))?;
+        let ([_, tokens, dims], hidden) =
+            hidden.into_shape_vec::<f32, 3>().map_err(|e| failed(&e))?;
+
+        // The mean over the tokens, as the model was trained with
+        let mut vector = vec![0.0; dims];
+        for token in hidden.chunks(dims) {
+            for (sum, value) in vector.iter_mut().zip(token) {
+                *sum += value / tokens as f32;
+            }
+        }
+        normalize(&mut vector);
+        Ok(vector)
+    }
+}
+
+fn normalize(vector: &mut [f32]) {
+    let length = vector.iter().map(|value| value * value).sum::<f32>().sqrt();
+    if length > 0.0 {
+        vector.iter_mut().for_each(|value| *value /= length);
+    }
+}
+
+/// What a note's embedding is computed from.
+pub fn note_text(note: &Note) -> String {
+    format!("{}\n\n{}", note.title, note.content)
+}
+
+fn text_hash(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredVector {
+    hash: String,
+    /// The vector's little-endian floats, in base64.
+    vector: String,
+}
+
+struct Entry {
+    hash: String,
+    vector: Vec<f32>,
+}
+
+/// A note found by `Embeddings::nearest`, with its cosine similarity to
+/// the query, from -1 to 1.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct SemanticHit {
+    pub id: String,
+    pub title: String,
+    pub score: f32,
+}
+
+/// The embeddings of every note in one workspace.
+pub struct Embeddings {
+    path: PathBuf,
+    /// By note id.
+    entries: BTreeMap<String, Entry>,
+}
+
+impl Embeddings {
+    /// Loads the embeddings of the workspace in `data_dir`.
+    pub fn load(data_dir: &Path) -> Result<Self, NotesError> {
+        let path = data_dir.join("embeddings.json");
+        let stored: BTreeMap<String, StoredVector> = if path.exists() {
+            fsutil::read_with_backup(&path, |content| {
+                serde_json::from_str(content)
+                    .map_err(|e| NotesError::Serde(format!("Failed to parse embeddings: {}", e)))
+            })?
+        } else {
+            BTreeMap::new()
+        };
+        let entries = stored
+            .into_iter()
+            .filter_map(|(id, stored)| {
+                let bytes = BASE64.decode(stored.vector).ok()?;
+                let vector = bytes
+                    .chunks_exact(4)
+                    .map(|float| f32::from_le_bytes([float[0], float[1], float[2], float[3]]))
+                    .collect();
+                Some((
+                    id,
+                    Entry {
+                        hash: stored.hash,
+                        vector,
+                    },
+                ))
+            })
+            .collect();
+        Ok(Embeddings { path, entries })
+    }
+
+    pub fn save(&self) -> Result<(), NotesError> {
+        let stored: BTreeMap<&String, StoredVector> = self
+            .entries
+            .iter()
+            .map(|(id, entry)| {
+                let bytes: Vec<u8> = entry.vector.iter().flat_map(|f| f.to_le_bytes()).collect();
+                (
+                    id,
+                    StoredVector {
+                        hash: entry.hash.clone(),
+                        vector: BASE64.encode(bytes),
+                    },
+                )
+            })
+            .collect();
+        let json = serde_json::to_string(&stored)
+            .map_err(|e| NotesError::Serde(format!("Failed to serialize embeddings: {}", e)))?;
+        fsutil::write_with_backup(&self.path, json.as_bytes())
+            .map_err(|e| NotesError::Io(format!("Failed to write embeddings: {}", e)))
+    }
+
+    /// Which of `notes` have no embedding, or one of an older version.
+    /// Locked notes are left out, since their content is sealed.
+    pub fn stale<'a>(&self, notes: &'a [Note]) -> Vec<&'a Note> {
+        notes
+            .iter()
+            .filter(|note| !note.locked)
+            .filter(|note| {
+                self.entries
+                    .get(&note.id)
+                    .is_none_or(|entry| entry.hash != text_hash(&note_text(note)))
+            })
+            .collect()
+    }
+
+    /// Records `vector` as the embedding of `note`.
+    pub fn set(&mut self, note: &Note, vector: Vec<f32>) {
+        let hash = text_hash(&note_text(note));
+        self.entries.insert(note.id.clone(), Entry { hash, vector });
+    }
+
+    /// Drops the embeddings of notes other than `ids`, such as deleted ones.
+    /// Returns whether there were any.
+    pub fn retain(&mut self, ids: &HashSet<&str>) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|id, _| ids.contains(id.as_str()));
+        self.entries.len() != before
+    }
+
+    /// The embedding of note `id`, if it has one.
+    pub fn get(&self, id: &str) -> Option<&[f32]> {
+        self.entries.get(id).map(|entry| entry.vector.as_slice())
+    }
+
+    /// The `k` of `notes` nearest to `query`, nearest first. Notes without
+    /// an embedding are left out.
+    pub fn nearest(&self, query: &[f32], k: usize, notes: &[Note]) -> Vec<SemanticHit> {
+        let mut hits: Vec<SemanticHit> = notes
+            .iter()
+            .filter_map(|note| {
+                let entry = self.entries.get(&note.id)?;
+                Some(SemanticHit {
+                    id: note.id.clone(),
+                    title: note.title.clone(),
+                    // Vectors are of unit length, so this is their cosine
+                    score: entry.vector.iter().zip(query).map(|(a, b)| a * b).sum(),
+                })
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits.truncate(k);
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note;
+    use tempfile::TempDir;
+
+    #[test]
+    fn finds_the_nearest_notes() {
+        let dir = TempDir::new().unwrap();
+        let notes = [
+            note::new("Cats".into(), String::new(), Vec::new(), 1),
+            note::new("Dogs".into(), String::new(), Vec::new(), 1),
+            note::new("Taxes".into(), String::new(), Vec::new(), 1),
+        ];
+        let mut embeddings = Embeddings::load(dir.path()).unwrap();
+        assert_eq!(embeddings.stale(&notes).len(), 3);
+        for (note, mut vector) in notes.iter().zip([
+            vec![1.0, 0.2, 0.0],
+            vec![0.8, 0.6, 0.0],
+            vec![0.0, 0.1, 1.0],
+        ]) {
+            normalize(&mut vector);
+            embeddings.set(note, vector);
+        }
+        embeddings.save().unwrap();
+
+        let mut embeddings = Embeddings::load(dir.path()).unwrap();
+        assert!(embeddings.stale(&notes).is_empty());
+        let edited = Note {
+            content: "Now with birds".into(),
+            ..notes[1].clone()
+        };
+        assert_eq!(embeddings.stale(std::slice::from_ref(&edited)).len(), 1);
+
+        let found = embeddings.nearest(&[1.0, 0.0, 0.0], 2, &notes);
+        let titles: Vec<&str> = found.iter().map(|hit| hit.title.as_str()).collect();
+        assert_eq!(titles, ["Cats", "Dogs"]);
+        assert!((found[0].score - 0.98).abs() < 0.01);
+        assert_eq!(
+            embeddings.nearest(&[1.0, 0.0, 0.0], 1, &notes[1..])[0].id,
+            notes[1].id
+        );
+
+        assert!(embeddings.retain(&HashSet::from([notes[2].id.as_str()])));
+        assert_eq!(embeddings.get(&notes[0].id), None);
+        assert!(embeddings.get(&notes[2].id).is_some());
+    }
+}
@@ -0,0 +1,225 @@
+//! App preferences, persisted in settings.json in the app data directory.
+//!
+//! Every field has a default, so files written by older versions load with
+//! the missing fields filled in and unknown fields are ignored.
+
+use crate::error::NotesError;
+use crate::fsutil;
+use crate::store::{SortDirection, SortKey};
+use crate::validate::Limits;
+use chrono::format::{Item, StrftimeItems};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// The shortest autosave interval that can be set, so drafts can't turn back
+/// into a write per keystroke.
+const MIN_AUTOSAVE_INTERVAL_MS: u64 = 250;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Settings {
+    pub theme: Theme,
+    /// Order of the note list when the UI doesn't ask for one.
+    pub default_sort: SortKey,
+    pub default_direction: SortDirection,
+    /// How long autosaved drafts are held before being written.
+    pub autosave_interval_ms: u64,
+    /// Keeps notes somewhere other than the app data directory, e.g. a
+    /// synced folder. Changed through `set_notes_directory`, which moves the
+    /// notes along.
+    pub data_dir: Option<PathBuf>,
+    /// How often the notes are backed up automatically, or 0 to never.
+    pub backup_interval_hours: u64,
+    /// Older automatic and manual backups are deleted beyond this many.
+    pub backups_to_keep: usize,
+    /// strftime-style format of daily note titles, e.g. `%Y-%m-%d`.
+    pub daily_note_format: String,
+    /// Template that new daily notes start from, if any.
+    pub daily_note_template: Option<String>,
+    /// Whether the web clipper listens for clips on 127.0.0.1.
+    pub clipper_enabled: bool,
+    pub clipper_port: u16,
+    /// Longer titles are refused.
+    pub max_title_chars: usize,
+    /// Notes whose content is larger than this, in bytes, are refused.
+    pub max_note_bytes: usize,
+    /// Whether the vault can be unlocked with Touch ID, Face ID or the like,
+    /// on devices that have it.
+    pub biometric_unlock: bool,
+    /// Whether note contents are written zstd-compressed. Notes already
+    /// stored keep their form until `compact_store` rewrites them.
+    pub compress_notes: bool,
+    /// Whether the title and description of links pasted into notes are
+    /// fetched, for showing previews.
+    pub link_previews: bool,
+    /// Whether the text in image attachments is read in the background, so
+    /// search finds it.
+    pub ocr_images: bool,
+    /// Command that prints the transcript of an audio memo, e.g.
+    /// `whisper-cli -m ggml-base.bin -nt -f {file}`. `{file}` stands for the
+    /// memo, which is passed last if it's left out. When set, audio memos
+    /// are transcribed as they're attached.
+    pub transcription_command: Option<String>,
+    /// Whether notes are embedded on the device as they're saved, for
+    /// finding them by meaning with `semantic_search`.
+    pub semantic_search: bool,
+    /// Notes not edited in this many months are archived once a day, or 0 to
+    /// never. Pinned and favorite notes are left alone.
+    pub auto_archive_months: u32,
+    /// Whether notes created on a phone or tablet are tagged with where they
+    /// were written, for `load_notes_near`.
+    pub geotag_notes: bool,
+    /// Whether EXIF metadata, such as where a photo was taken, is removed
+    /// from photos taken in the app before they're attached.
+    pub strip_photo_metadata: bool,
+    /// Photos taken in the app are scaled down to fit this many pixels
+    /// across, or 0 to attach them at full size.
+    pub photo_max_dimension: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            theme: Theme::default(),
+            default_sort: SortKey::default(),
+            default_direction: SortDirection::default(),
+            autosave_interval_ms: 2000,
+            data_dir: None,
+            backup_interval_hours: 24,
+            backups_to_keep: 10,
+            daily_note_format: "%Y-%m-%d".into(),
+            daily_note_template: None,
+            clipper_enabled: false,
+            clipper_port: 27184,
+            max_title_chars: 500,
+            max_note_bytes: 10 * 1024 * 1024,
+            biometric_unlock: false,
+            compress_notes: false,
+            link_previews: false,
+            ocr_images: false,
+            transcription_command: None,
+            semantic_search: false,
+            auto_archive_months: 0,
+            geotag_notes: false,
+            strip_photo_metadata: true,
+            photo_max_dimension: 2048,
+        }
+    }
+}
+
+impl Settings {
+    pub fn limits(&self) -> Limits {
+        Limits {
+            max_title_chars: self.max_title_chars,
+            max_note_bytes: self.max_note_bytes,
+        }
+    }
+
+    /// Loads the settings at `path`, or the defaults if there are none yet.
+    pub fn load(path: &Path) -> Result<Self, NotesError> {
+        if !path.exists() {
+            return Ok(Settings::default());
+        }
+        fsutil::read_with_backup(path, |content| {
+            serde_json::from_str(content)
+                .map_err(|e| NotesError::Serde(format!("Failed to parse settings: {}", e)))
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), NotesError> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| NotesError::Serde(format!("Failed to serialize settings: {}", e)))?;
+        fsutil::write_with_backup(path, json.as_bytes())
+            .map_err(|e| NotesError::Io(format!("Failed to write settings: {}", e)))
+    }
+
+    /// Returns these settings with the fields in `patch`, a JSON object,
+    /// replaced. Fields left out of `patch` keep their current values.
+    pub fn patched(&self, patch: Value) -> Result<Settings, NotesError> {
+        let Value::Object(patch) = patch else {
+            return Err(NotesError::Invalid(
+                "Settings patch must be an object".into(),
+            ));
+        };
+        let mut fields = match serde_json::to_value(self) {
+            Ok(Value::Object(fields)) => fields,
+            _ => return Err(NotesError::Serde("Failed to serialize settings".into())),
+        };
+        for (key, value) in patch {
+            if !fields.contains_key(&key) {
+                return Err(NotesError::Invalid(format!("Unknown setting: {}", key)));
+            }
+            fields.insert(key, value);
+        }
+
+        let settings: Settings = serde_json::from_value(Value::Object(fields))
+            .map_err(|e| NotesError::Serde(format!("Invalid settings: {}", e)))?;
+        if settings.autosave_interval_ms < MIN_AUTOSAVE_INTERVAL_MS {
+            return Err(NotesError::Invalid(format!(
+                "Autosave interval must be at least {} ms",
+                MIN_AUTOSAVE_INTERVAL_MS
+            )));
+        }
+        if settings.backups_to_keep == 0 {
+            return Err(NotesError::Invalid(
+                "At least one backup must be kept".into(),
+            ));
+        }
+        if settings.clipper_port == 0 {
+            return Err(NotesError::Invalid("Clipper port cannot be 0".into()));
+        }
+        if settings.max_title_chars == 0 || settings.max_note_bytes == 0 {
+            return Err(NotesError::Invalid(
+                "Title and note size limits must be above 0".into(),
+            ));
+        }
+        if !is_valid_date_format(&settings.daily_note_format) {
+            return Err(NotesError::Invalid(format!(
+                "Invalid daily note format: {}",
+                settings.daily_note_format
+            )));
+        }
+        Ok(settings)
+    }
+}
+
+/// Whether `format` is a non-empty strftime-style format chrono can render.
+/// Rendering an invalid one panics.
+pub fn is_valid_date_format(format: &str) -> bool {
+    !format.trim().is_empty() && StrftimeItems::new(format).all(|item| item != Item::Error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn rejects_invalid_patches() {
+        let settings = Settings::default();
+        assert!(settings.patched(json!({ "nope": 1 })).is_err());
+        assert!(settings
+            .patched(json!({ "autosave_interval_ms": 10 }))
+            .is_err());
+        assert!(settings.patched(json!({ "backups_to_keep": 0 })).is_err());
+        assert!(settings
+            .patched(json!({ "daily_note_format": "%Y-%Q" }))
+            .is_err());
+
+        let patched = settings
+            .patched(json!({ "daily_note_format": "Journal %d %B %Y" }))
+            .unwrap();
+        assert_eq!(patched.daily_note_format, "Journal %d %B %Y");
+        assert_eq!(patched.backups_to_keep, settings.backups_to_keep);
+    }
+}
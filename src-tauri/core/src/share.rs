@@ -0,0 +1,478 @@
+//! Publishing a note as an unlisted paste, to send someone a link to it:
+//!
+//! - `gist`: a secret GitHub Gist, made with a personal access token that has
+//!   the `gist` scope
+//! - `0x0`: a file behind a secret URL on 0x0.st, or another instance of it
+//! - `custom`: a service of your own. The note is POSTed to its URL as
+//!   `text/markdown`, with `Authorization: Bearer <token>` if a token is set,
+//!   and it replies `{"url": "…", "id": "…"}`. `DELETE <url>/<id>` takes the
+//!   paste down.
+//!
+//! Which notes are shared, and where, is kept in shares.json in the
+//! workspace's data directory, with the service URLs. Tokens are kept in the
+//! OS keychain. Anyone with a link can read the note, vault or not.
+
+use crate::error::NotesError;
+use crate::fsutil;
+use crate::keychain::{self, Secret};
+use crate::note::{slugify, Note};
+use chrono::Utc;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::warn;
+use ureq::Agent;
+use uuid::Uuid;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+const GIST_API: &str = "https://api.github.com/gists";
+const DEFAULT_0X0_URL: &str = "https://0x0.st";
+const USER_AGENT: &str = concat!("min_notes/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum ShareProvider {
+    #[serde(rename = "gist")]
+    Gist,
+    /// 0x0.st, "The Null Pointer".
+    #[serde(rename = "0x0")]
+    NullPointer,
+    #[serde(rename = "custom")]
+    Custom,
+}
+
+/// Where a note is shared.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Share {
+    pub provider: ShareProvider,
+    pub url: String,
+    /// What the provider calls the paste: the gist id, or the custom
+    /// service's id.
+    #[serde(default)]
+    remote_id: String,
+    /// The token 0x0 hands out for taking the file down.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    delete_token: Option<String>,
+    pub shared_at: i64,
+}
+
+/// A paste just made, before it's recorded as a `Share`.
+struct Published {
+    url: String,
+    remote_id: String,
+    delete_token: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct SharesFile {
+    /// The 0x0 instance to use instead of 0x0.st.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    null_pointer_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    custom_url: Option<String>,
+    /// By note id.
+    #[serde(default)]
+    shares: BTreeMap<String, Share>,
+}
+
+/// Publishes and takes down pastes on one service.
+trait PasteService {
+    fn publish(&self, name: &str, markdown: &str) -> Result<Published, NotesError>;
+    fn take_down(&self, share: &Share) -> Result<(), NotesError>;
+}
+
+pub struct Shares {
+    path: PathBuf,
+    file: SharesFile,
+}
+
+impl Shares {
+    /// Loads the shares of the workspace in `data_dir`.
+    pub fn load(data_dir: &Path) -> Result<Self, NotesError> {
+        let path = data_dir.join("shares.json");
+        let file = if path.exists() {
+            fsutil::read_with_backup(&path, |content| {
+                serde_json::from_str(content)
+                    .map_err(|e| NotesError::Serde(format!("Failed to parse shares: {}", e)))
+            })?
+        } else {
+            SharesFile::default()
+        };
+        Ok(Shares { path, file })
+    }
+
+    fn save(&self) -> Result<(), NotesError> {
+        let json = serde_json::to_string_pretty(&self.file)
+            .map_err(|e| NotesError::Serde(format!("Failed to serialize shares: {}", e)))?;
+        fsutil::write_with_backup(&self.path, json.as_bytes())
+            .map_err(|e| NotesError::Io(format!("Failed to write shares: {}", e)))
+    }
+
+    /// Where note `id` is shared, if it is.
+    pub fn get(&self, id: &str) -> Option<&Share> {
+        self.file.shares.get(id)
+    }
+
+    /// Sets the URL of the 0x0 instance or custom service to share to.
+    /// Gists always go to GitHub.
+    pub fn set_url(
+        &mut self,
+        provider: ShareProvider,
+        url: Option<String>,
+    ) -> Result<(), NotesError> {
+        if let Some(url) = &url {
+            if !url.starts_with("https://") && !url.starts_with("http://") {
+                return Err(NotesError::Invalid(
+                    "The paste service URL must start with http:// or https://".into(),
+                ));
+            }
+        }
+        match provider {
+            ShareProvider::Gist if url.is_some() => {
+                return Err(NotesError::Invalid("Gists always go to GitHub".into()))
+            }
+            ShareProvider::Gist => {}
+            ShareProvider::NullPointer => self.file.null_pointer_url = url,
+            ShareProvider::Custom => self.file.custom_url = url,
+        }
+        self.save()
+    }
+
+    fn service(
+        &self,
+        provider: ShareProvider,
+        workspace: &str,
+    ) -> Result<Box<dyn PasteService>, NotesError> {
+        let agent = ureq::AgentBuilder::new()
+            .timeout(TIMEOUT)
+            .user_agent(USER_AGENT)
+            .build();
+        Ok(match provider {
+            ShareProvider::Gist => Box::new(Gist {
+                agent,
+                authorization: format!(
+                    "Bearer {}",
+                    keychain::require(Secret::GistToken, workspace)?
+                ),
+            }),
+            ShareProvider::NullPointer => Box::new(NullPointer {
+                agent,
+                url: self
+                    .file
+                    .null_pointer_url
+                    .clone()
+                    .unwrap_or_else(|| DEFAULT_0X0_URL.into()),
+            }),
+            ShareProvider::Custom => Box::new(Custom {
+                agent,
+                url: self.file.custom_url.clone().ok_or_else(|| {
+                    NotesError::Invalid("Set the URL of the paste service first".into())
+                })?,
+                authorization: keychain::get(Secret::PasteToken, workspace)?
+                    .map(|token| format!("Bearer {}", token)),
+            }),
+        })
+    }
+
+    /// Publishes `note` with `provider`, using the tokens of workspace
+    /// `workspace`, and returns the link to it. A note shared before is
+    /// taken down from where it was, so only the latest link works.
+    pub fn share(
+        &mut self,
+        note: &Note,
+        provider: ShareProvider,
+        workspace: &str,
+    ) -> Result<String, NotesError> {
+        let service = self.service(provider, workspace)?;
+        self.share_with(note, provider, service.as_ref(), workspace)
+    }
+
+    fn share_with(
+        &mut self,
+        note: &Note,
+        provider: ShareProvider,
+        service: &dyn PasteService,
+        workspace: &str,
+    ) -> Result<String, NotesError> {
+        if note.locked {
+            return Err(NotesError::Invalid(
+                "Remove the note's password before sharing it".into(),
+            ));
+        }
+        let markdown = format!("# {}\n\n{}", note.title, note.content);
+        let published = service.publish(&format!("{}.md", slugify(&note.title)), &markdown)?;
+        let share = Share {
+            provider,
+            url: published.url.clone(),
+            remote_id: published.remote_id,
+            delete_token: published.delete_token,
+            shared_at: Utc::now().timestamp(),
+        };
+        if let Some(previous) = self.file.shares.insert(note.id.clone(), share) {
+            let taken_down = if previous.provider == provider {
+                service.take_down(&previous)
+            } else {
+                self.service(previous.provider, workspace)
+                    .and_then(|service| service.take_down(&previous))
+            };
+            if let Err(e) = taken_down {
+                warn!("Failed to take down {}: {}", previous.url, e);
+            }
+        }
+        self.save()?;
+        Ok(published.url)
+    }
+
+    /// Takes down the paste of note `id`, returning whether it was shared.
+    pub fn unshare(&mut self, id: &str, workspace: &str) -> Result<bool, NotesError> {
+        let Some(share) = self.file.shares.get(id) else {
+            return Ok(false);
+        };
+        self.service(share.provider, workspace)?.take_down(share)?;
+        self.file.shares.remove(id);
+        self.save()?;
+        Ok(true)
+    }
+}
+
+/// Reports a failed request, singling out a rejected token.
+fn failed(action: &str, e: ureq::Error) -> NotesError {
+    match e {
+        ureq::Error::Status(401 | 403, _) => {
+            NotesError::Network("The paste service rejected the token".into())
+        }
+        e => NotesError::Network(format!("Failed to {}: {}", action, e)),
+    }
+}
+
+/// Parses the JSON reply to a request.
+fn reply<T: DeserializeOwned>(response: ureq::Response) -> Result<T, NotesError> {
+    let body = response.into_string().map_err(|e| {
+        NotesError::Network(format!("Failed to read the paste service's reply: {}", e))
+    })?;
+    serde_json::from_str(&body)
+        .map_err(|e| NotesError::Serde(format!("Failed to parse the paste service's reply: {}", e)))
+}
+
+struct Gist {
+    agent: Agent,
+    authorization: String,
+}
+
+#[derive(Deserialize)]
+struct GistReply {
+    id: String,
+    html_url: String,
+}
+
+impl PasteService for Gist {
+    fn publish(&self, name: &str, markdown: &str) -> Result<Published, NotesError> {
+        let body = serde_json::json!({
+            "public": false,
+            "files": { name: { "content": markdown } },
+        });
+        let reply: GistReply = reply(
+            self.agent
+                .post(GIST_API)
+                .set("Authorization", &self.authorization)
+                .set("Accept", "application/vnd.github+json")
+                .set("Content-Type", "application/json")
+                .send_string(&body.to_string())
+                .map_err(|e| failed("create gist", e))?,
+        )?;
+        Ok(Published {
+            url: reply.html_url,
+            remote_id: reply.id,
+            delete_token: None,
+        })
+    }
+
+    fn take_down(&self, share: &Share) -> Result<(), NotesError> {
+        match self
+            .agent
+            .delete(&format!("{}/{}", GIST_API, share.remote_id))
+            .set("Authorization", &self.authorization)
+            .call()
+        {
+            Ok(_) | Err(ureq::Error::Status(404, _)) => Ok(()),
+            Err(e) => Err(failed("delete gist", e)),
+        }
+    }
+}
+
+struct NullPointer {
+    agent: Agent,
+    url: String,
+}
+
+impl PasteService for NullPointer {
+    fn publish(&self, name: &str, markdown: &str) -> Result<Published, NotesError> {
+        let boundary = format!("min-notes-{}", Uuid::new_v4());
+        let mut body = Vec::new();
+        for (field, file, content) in [("file", Some(name), markdown), ("secret", None, "")] {
+            body.extend(format!("--{}\r\n", boundary).as_bytes());
+            match file {
+                Some(file) => body.extend(
+                    format!(
+                        "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n\
+                         Content-Type: text/markdown\r\n\r\n",
+                        field, file
+                    )
+                    .as_bytes(),
+                ),
+                None => body.extend(
+                    format!("Content-Disposition: form-data; name=\"{}\"\r\n\r\n", field)
+                        .as_bytes(),
+                ),
+            }
+            body.extend(content.as_bytes());
+            body.extend(b"\r\n");
+        }
+        body.extend(format!("--{}--\r\n", boundary).as_bytes());
+
+        let response = self
+            .agent
+            .post(&self.url)
+            .set(
+                "Content-Type",
+                &format!("multipart/form-data; boundary={}", boundary),
+            )
+            .send_bytes(&body)
+            .map_err(|e| failed("upload note", e))?;
+        let delete_token = response.header("X-Token").map(str::to_string);
+        let url = response
+            .into_string()
+            .map_err(|e| NotesError::Network(format!("Failed to read 0x0's reply: {}", e)))?
+            .trim()
+            .to_string();
+        Ok(Published {
+            url,
+            remote_id: String::new(),
+            delete_token,
+        })
+    }
+
+    fn take_down(&self, share: &Share) -> Result<(), NotesError> {
+        let Some(token) = &share.delete_token else {
+            return Err(NotesError::Invalid(
+                "0x0 didn't hand out a token for taking this note down".into(),
+            ));
+        };
+        match self
+            .agent
+            .post(&share.url)
+            .send_form(&[("token", token), ("delete", "")])
+        {
+            Ok(_) | Err(ureq::Error::Status(404, _)) => Ok(()),
+            Err(e) => Err(failed("delete upload", e)),
+        }
+    }
+}
+
+struct Custom {
+    agent: Agent,
+    url: String,
+    authorization: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CustomReply {
+    url: String,
+    id: String,
+}
+
+impl Custom {
+    fn request(&self, method: &str, url: &str) -> ureq::Request {
+        let request = self.agent.request(method, url);
+        match &self.authorization {
+            Some(authorization) => request.set("Authorization", authorization),
+            None => request,
+        }
+    }
+}
+
+impl PasteService for Custom {
+    fn publish(&self, _name: &str, markdown: &str) -> Result<Published, NotesError> {
+        let reply: CustomReply = reply(
+            self.request("POST", &self.url)
+                .set("Content-Type", "text/markdown; charset=utf-8")
+                .send_string(markdown)
+                .map_err(|e| failed("upload note", e))?,
+        )?;
+        Ok(Published {
+            url: reply.url,
+            remote_id: reply.id,
+            delete_token: None,
+        })
+    }
+
+    fn take_down(&self, share: &Share) -> Result<(), NotesError> {
+        let url = format!("{}/{}", self.url.trim_end_matches('/'), share.remote_id);
+        match self.request("DELETE", &url).call() {
+            Ok(_) | Err(ureq::Error::Status(404, _)) => Ok(()),
+            Err(e) => Err(failed("delete paste", e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note;
+    use std::cell::RefCell;
+    use tempfile::TempDir;
+
+    #[derive(Default)]
+    struct MemoryService {
+        pastes: RefCell<BTreeMap<String, String>>,
+    }
+
+    impl PasteService for MemoryService {
+        fn publish(&self, name: &str, markdown: &str) -> Result<Published, NotesError> {
+            let mut pastes = self.pastes.borrow_mut();
+            let id = format!("{}-{}", pastes.len(), name);
+            pastes.insert(id.clone(), markdown.to_string());
+            Ok(Published {
+                url: format!("https://paste.example/{}", id),
+                remote_id: id,
+                delete_token: None,
+            })
+        }
+
+        fn take_down(&self, share: &Share) -> Result<(), NotesError> {
+            self.pastes.borrow_mut().remove(&share.remote_id);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn keeps_one_paste_per_note() {
+        let dir = TempDir::new().unwrap();
+        let mut shares = Shares::load(dir.path()).unwrap();
+        let service = MemoryService::default();
+        let mut note = note::new("Trip plan".into(), "Day 1\n".into(), Vec::new(), 1);
+        let share = |shares: &mut Shares, note: &Note| {
+            shares
+                .share_with(note, ShareProvider::Custom, &service, "default")
+                .unwrap()
+        };
+
+        let first = share(&mut shares, &note);
+        assert_eq!(first, "https://paste.example/0-trip-plan.md");
+        note.content = "Day 1\nDay 2\n".into();
+        let second = share(&mut shares, &note);
+        assert_ne!(first, second);
+        let pastes: Vec<String> = service.pastes.borrow().values().cloned().collect();
+        assert_eq!(pastes, ["# Trip plan\n\nDay 1\nDay 2\n"]);
+
+        let shares = Shares::load(dir.path()).unwrap();
+        assert_eq!(shares.get(&note.id).unwrap().url, second);
+
+        note.locked = true;
+        let mut shares = shares;
+        assert!(shares
+            .share_with(&note, ShareProvider::Custom, &service, "default")
+            .is_err());
+    }
+}
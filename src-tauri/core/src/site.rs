@@ -0,0 +1,266 @@
+//! Exporting notes as a static website, for publishing them on any web
+//! host, or just browsing them from disk.
+//!
+//! The site has an index of every note, a page per note in notes/, a page
+//! per tag in tags/ listing the notes with it, and the notes' attachments in
+//! attachments/. `[[links]]` between published notes become links between
+//! their pages. Archived and locked notes are left out.
+
+use crate::error::NotesError;
+use crate::export;
+use crate::links;
+use crate::note::{slugify, Note};
+use pulldown_cmark::{html, Parser};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct SiteOptions {
+    /// The site's name, atop every page. "Notes" when unset.
+    pub title: Option<String>,
+    /// Publishes only the notes with one of these tags, or every note if
+    /// empty.
+    pub tags: Vec<String>,
+}
+
+/// What a site export wrote.
+#[derive(Serialize, PartialEq, Debug)]
+pub struct SiteReport {
+    pub notes: usize,
+    pub tags: usize,
+    pub attachments: usize,
+}
+
+/// Picks a name from `slug` that isn't in `taken` yet, and takes it.
+fn unique(slug: String, taken: &mut HashSet<String>) -> String {
+    let mut name = slug.clone();
+    let mut n = 2;
+    while !taken.insert(name.clone()) {
+        name = format!("{}-{}", slug, n);
+        n += 1;
+    }
+    name
+}
+
+/// A page of the site, `depth` folders below its root, with `body` under
+/// the site's name.
+fn page(site: &str, title: &str, depth: usize, body: &str) -> String {
+    let root = "../".repeat(depth);
+    let body = format!(
+        "<nav><a href=\"{}index.html\">{}</a></nav>\n{}",
+        root,
+        export::escape_html(site),
+        body
+    );
+    export::html_document(title, &body)
+}
+
+/// A list of links to `notes`, from a page `depth` folders below the root.
+fn note_list(notes: &[(&Note, &String)], depth: usize) -> String {
+    let root = "../".repeat(depth);
+    let mut list = String::from("<ul>\n");
+    for (note, name) in notes {
+        list.push_str(&format!(
+            "<li><a href=\"{}notes/{}.html\">{}</a></li>\n",
+            root,
+            name,
+            export::escape_html(&note.title)
+        ));
+    }
+    list.push_str("</ul>\n");
+    list
+}
+
+/// Writes the notes among `notes` that `options` asks for to a static site
+/// in `dir`, with `read_attachment` returning the content of an attachment.
+pub fn export_site(
+    notes: &[Note],
+    read_attachment: impl Fn(&str) -> Result<Vec<u8>, NotesError>,
+    dir: &Path,
+    options: &SiteOptions,
+) -> Result<SiteReport, NotesError> {
+    let failed = |e: std::io::Error| NotesError::Io(format!("Failed to write site: {}", e));
+    let site = options.title.as_deref().unwrap_or("Notes");
+    let mut published: Vec<Note> = notes
+        .iter()
+        .filter(|note| note.trashed_at.is_none() && !note.archived && !note.locked)
+        .filter(|note| {
+            options.tags.is_empty() || note.tags.iter().any(|tag| options.tags.contains(tag))
+        })
+        .cloned()
+        .collect();
+    published.sort_by_key(|note| note.title.to_lowercase());
+    if published.is_empty() {
+        return Err(NotesError::Invalid("There are no notes to publish".into()));
+    }
+    fs::create_dir_all(dir.join("notes")).map_err(failed)?;
+
+    let mut taken = HashSet::new();
+    let names: Vec<String> = published
+        .iter()
+        .map(|note| unique(slugify(&note.title), &mut taken))
+        .collect();
+    let mut by_tag: BTreeMap<&str, Vec<(&Note, &String)>> = BTreeMap::new();
+    for (note, name) in published.iter().zip(&names) {
+        for tag in &note.tags {
+            by_tag.entry(tag).or_default().push((note, name));
+        }
+    }
+    let mut taken = HashSet::new();
+    let tag_names: HashMap<&str, String> = by_tag
+        .keys()
+        .map(|tag| (*tag, unique(slugify(tag), &mut taken)))
+        .collect();
+    let attachments = export::write_attachments(&published, read_attachment, dir)?;
+
+    for (note, name) in published.iter().zip(&names) {
+        let mut content = links::rewrite_links(&note.content, |title| {
+            let index = published
+                .iter()
+                .position(|other| links::same_title(&other.title, title))?;
+            Some(format!("{}.html", names[index]))
+        });
+        for attachment in &note.attachments {
+            let path = format!("../{}", attachments[attachment.id.as_str()]);
+            content = content.replace(
+                &format!("(attachment:{})", attachment.id),
+                &format!("(<{}>)", path),
+            );
+        }
+        if !note.attachments.is_empty() {
+            content = content.trim_end().to_string();
+            content.push_str("\n\n## Attachments\n\n");
+            for attachment in &note.attachments {
+                let path = &attachments[attachment.id.as_str()];
+                content.push_str(&format!("- [{}](<../{}>)\n", attachment.name, path));
+            }
+        }
+
+        let mut body = format!("<h1>{}</h1>\n", export::escape_html(&note.title));
+        if !note.tags.is_empty() {
+            body.push_str("<p>");
+            for tag in &note.tags {
+                body.push_str(&format!(
+                    "<a href=\"../tags/{}.html\">#{}</a> ",
+                    tag_names[tag.as_str()],
+                    export::escape_html(tag)
+                ));
+            }
+            body.push_str("</p>\n");
+        }
+        html::push_html(
+            &mut body,
+            Parser::new_ext(&content, export::markdown_options()),
+        );
+        let path = dir.join("notes").join(format!("{}.html", name));
+        fs::write(path, page(site, &note.title, 1, &body)).map_err(failed)?;
+    }
+
+    if !by_tag.is_empty() {
+        fs::create_dir_all(dir.join("tags")).map_err(failed)?;
+    }
+    for (tag, tagged) in &by_tag {
+        let title = format!("#{}", tag);
+        let body = format!(
+            "<h1>{}</h1>\n{}",
+            export::escape_html(&title),
+            note_list(tagged, 1)
+        );
+        let path = dir.join("tags").join(format!("{}.html", tag_names[*tag]));
+        fs::write(path, page(site, &title, 1, &body)).map_err(failed)?;
+    }
+
+    let listed: Vec<(&Note, &String)> = published.iter().zip(&names).collect();
+    let mut body = format!(
+        "<h1>{}</h1>\n{}",
+        export::escape_html(site),
+        note_list(&listed, 0)
+    );
+    if !by_tag.is_empty() {
+        body.push_str("<h2>Tags</h2>\n<ul>\n");
+        for (tag, tagged) in &by_tag {
+            body.push_str(&format!(
+                "<li><a href=\"tags/{}.html\">#{}</a> ({})</li>\n",
+                tag_names[*tag],
+                export::escape_html(tag),
+                tagged.len()
+            ));
+        }
+        body.push_str("</ul>\n");
+    }
+    fs::write(dir.join("index.html"), page(site, site, 0, &body)).map_err(failed)?;
+
+    Ok(SiteReport {
+        notes: published.len(),
+        tags: by_tag.len(),
+        attachments: attachments.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::{self, Attachment};
+    use tempfile::TempDir;
+
+    #[test]
+    fn writes_a_browsable_site() {
+        let dir = TempDir::new().unwrap();
+        let mut rust = note::new(
+            "Rust".into(),
+            "Read [[Cargo|the Cargo notes]] and [[Unpublished]]".into(),
+            vec!["lang".into()],
+            1,
+        );
+        rust.attachments.push(Attachment {
+            id: "logo".into(),
+            name: "logo.png".into(),
+            mime: "image/png".into(),
+            size: 3,
+            added_at: 1,
+            text: None,
+        });
+        let cargo = note::new("Cargo".into(), "Builds".into(), vec!["lang".into()], 1);
+        let archived = Note {
+            archived: true,
+            ..note::new("Unpublished".into(), String::new(), Vec::new(), 1)
+        };
+        let report = export_site(
+            &[rust, cargo, archived],
+            |_| Ok(b"png".to_vec()),
+            dir.path(),
+            &SiteOptions {
+                title: Some("My notes".into()),
+                tags: Vec::new(),
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            report,
+            SiteReport {
+                notes: 2,
+                tags: 1,
+                attachments: 1
+            }
+        );
+
+        let read = |path: &str| fs::read_to_string(dir.path().join(path)).unwrap();
+        let index = read("index.html");
+        assert!(index.contains("<a href=\"notes/cargo.html\">Cargo</a>"));
+        assert!(index.contains("<a href=\"tags/lang.html\">#lang</a> (2)"));
+        assert!(!index.contains("Unpublished</a>"));
+        let page = read("notes/rust.html");
+        assert!(page.contains("<a href=\"cargo.html\">the Cargo notes</a>"));
+        assert!(page.contains("[[Unpublished]]"));
+        assert!(page.contains("href=\"../attachments/logo.png\""));
+        assert!(page.contains("<a href=\"../index.html\">My notes</a>"));
+        assert!(read("tags/lang.html").contains("<a href=\"../notes/rust.html\">Rust</a>"));
+        assert_eq!(
+            fs::read(dir.path().join("attachments/logo.png")).unwrap(),
+            b"png"
+        );
+    }
+}
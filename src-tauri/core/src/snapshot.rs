@@ -0,0 +1,281 @@
+//! Snapshots of the web pages notes link to, kept as attachments so what a
+//! note refers to survives the page moving or going away.
+//!
+//! A snapshot is a single HTML file that needs nothing else to display: the
+//! page's article, or its body if it has none, without scripts, styles, or
+//! navigation, and with its images inlined as data URLs. It opens with the
+//! page's title, where it came from, and when it was taken.
+
+use crate::error::NotesError;
+use crate::export;
+use crate::previews;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{TimeZone, Utc};
+use regex::{Captures, Regex};
+use std::io::Read;
+use std::sync::LazyLock;
+use std::time::Duration;
+use tracing::debug;
+use url::Url;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_PAGE_BYTES: u64 = 10 * 1024 * 1024;
+const MAX_IMAGE_BYTES: u64 = 5 * 1024 * 1024;
+/// Images past this many bytes in all are left out.
+const MAX_IMAGES_BYTES: usize = 20 * 1024 * 1024;
+const USER_AGENT: &str = concat!("min_notes/", env!("CARGO_PKG_VERSION"));
+
+/// Elements dropped along with what's in them.
+const DROPPED: &[&str] = &[
+    "script", "noscript", "style", "iframe", "object", "template", "svg", "nav", "header",
+    "footer", "aside", "form", "button",
+];
+
+static DROPPED_ELEMENTS: LazyLock<Vec<Regex>> = LazyLock::new(|| {
+    DROPPED
+        .iter()
+        .map(|tag| Regex::new(&format!(r"(?is)<{0}\b[^>]*>.*?</{0}\s*>", tag)).unwrap())
+        .collect()
+});
+static DROPPED_TAGS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)<(?:embed|link|meta|base|input)\b[^>]*>|<!--.*?-->").unwrap()
+});
+static HANDLERS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?is)\s(?:on[a-z]+|style|srcset)\s*=\s*(?:"[^"]*"|'[^']*'|[^\s>]+)"#).unwrap()
+});
+static CONTENT: LazyLock<[Regex; 3]> = LazyLock::new(|| {
+    ["article", "main", "body"]
+        .map(|tag| Regex::new(&format!(r"(?is)<{0}\b[^>]*>(.*?)</{0}\s*>", tag)).unwrap())
+});
+static IMAGE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?is)(<img\b[^>]*?\ssrc\s*=\s*)(?:"([^"]*)"|'([^']*)')"#).unwrap()
+});
+static LINK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?is)(<a\b[^>]*?\shref\s*=\s*)(?:"([^"]*)"|'([^']*)')"#).unwrap()
+});
+
+/// A page as saved for reading later.
+pub struct Snapshot {
+    pub title: String,
+    pub html: String,
+}
+
+/// Downloads the page at `url` and its images, and makes a snapshot of it
+/// taken at `now`.
+pub fn capture(url: &str, now: i64) -> Result<Snapshot, NotesError> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(TIMEOUT)
+        .user_agent(USER_AGENT)
+        .build();
+    let failed = |e: &dyn std::fmt::Display| {
+        NotesError::Network(format!("Failed to download {}: {}", url, e))
+    };
+    let response = agent.get(url).call().map_err(|e| failed(&e))?;
+    let content_type = response.content_type().to_ascii_lowercase();
+    if content_type != "text/html" && content_type != "application/xhtml+xml" {
+        return Err(NotesError::Invalid(format!(
+            "{} is not a web page, but {}",
+            url, content_type
+        )));
+    }
+    let base = Url::parse(response.get_url()).map_err(|e| failed(&e))?;
+    let mut page = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_PAGE_BYTES)
+        .read_to_end(&mut page)
+        .map_err(|e| failed(&e))?;
+    let page = String::from_utf8_lossy(&page);
+
+    let mut images_bytes = 0;
+    Ok(render(url, &page, &base, now, |src| {
+        if images_bytes >= MAX_IMAGES_BYTES {
+            return None;
+        }
+        let image = fetch_image(&agent, src)
+            .map_err(|e| debug!("Failed to download image {}: {}", src, e))
+            .ok()?;
+        images_bytes += image.len();
+        Some(image)
+    }))
+}
+
+/// Downloads the image at `src` as a data URL.
+fn fetch_image(agent: &ureq::Agent, src: &Url) -> Result<String, NotesError> {
+    let response = agent
+        .get(src.as_str())
+        .call()
+        .map_err(|e| NotesError::Network(e.to_string()))?;
+    let mime = response.content_type().to_ascii_lowercase();
+    if !mime.starts_with("image/") {
+        return Err(NotesError::Invalid(format!("not an image, but {}", mime)));
+    }
+    let mut image = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_IMAGE_BYTES + 1)
+        .read_to_end(&mut image)
+        .map_err(|e| NotesError::Network(e.to_string()))?;
+    if image.len() as u64 > MAX_IMAGE_BYTES {
+        return Err(NotesError::Invalid("too large".into()));
+    }
+    Ok(format!("data:{};base64,{}", mime, BASE64.encode(image)))
+}
+
+/// Makes a snapshot of `page`, downloaded from `url` and ending up at
+/// `base` after redirects, with `image` returning the data URL of each
+/// image, or `None` to leave it out.
+fn render(
+    url: &str,
+    page: &str,
+    base: &Url,
+    now: i64,
+    mut image: impl FnMut(&Url) -> Option<String>,
+) -> Snapshot {
+    let title = previews::parse_page(url, page, Some(base))
+        .title
+        .unwrap_or_else(|| base.host_str().unwrap_or(url).to_string());
+
+    let mut content = page.to_string();
+    for element in DROPPED_ELEMENTS.iter() {
+        content = element.replace_all(&content, "").into_owned();
+    }
+    content = DROPPED_TAGS.replace_all(&content, "").into_owned();
+    content = HANDLERS.replace_all(&content, "").into_owned();
+    if let Some(inner) = CONTENT.iter().find_map(|tag| tag.captures(&content)) {
+        content = inner[1].to_string();
+    }
+
+    let attribute = |captures: &Captures| {
+        captures
+            .get(2)
+            .or(captures.get(3))
+            .map_or("", |m| m.as_str())
+            .trim()
+            .replace("&amp;", "&")
+    };
+    content = IMAGE
+        .replace_all(&content, |captures: &Captures| {
+            let src = base
+                .join(&attribute(captures))
+                .ok()
+                .filter(|src| matches!(src.scheme(), "http" | "https"))
+                .and_then(|src| image(&src))
+                .unwrap_or_default();
+            format!("{}\"{}\"", &captures[1], src)
+        })
+        .into_owned();
+    content = LINK
+        .replace_all(&content, |captures: &Captures| {
+            let href = attribute(captures);
+            let href = match href.starts_with('#') {
+                true => href,
+                false => base.join(&href).map_or(href, String::from),
+            };
+            format!("{}\"{}\"", &captures[1], export::escape_html(&href))
+        })
+        .into_owned();
+
+    let taken = Utc
+        .timestamp_opt(now, 0)
+        .single()
+        .map(|time| time.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_default();
+    let link = export::escape_html(url);
+    let body = format!(
+        "<h1>{}</h1>\n<p><small>Saved from <a href=\"{}\">{}</a> on {}</small></p>\n\
+         <hr>\n{}\n",
+        export::escape_html(&title),
+        link,
+        link,
+        taken,
+        content.trim()
+    );
+    Snapshot {
+        html: export::html_document(&title, &body),
+        title,
+    }
+}
+
+/// Adds a link to `attachment_id`, a snapshot of `url` named `name`, to
+/// `content`: below the first line that mentions `url`, or at the end if
+/// none does.
+pub fn link_snapshot(content: &str, url: &str, attachment_id: &str, name: &str) -> String {
+    let link = format!("[Saved copy: {}](attachment:{})", name, attachment_id);
+    let mut lines: Vec<&str> = content.lines().collect();
+    match lines.iter().position(|line| line.contains(url)) {
+        Some(line) => lines.insert(line + 1, &link),
+        None => {
+            while lines.last().is_some_and(|line| line.trim().is_empty()) {
+                lines.pop();
+            }
+            if !lines.is_empty() {
+                lines.push("");
+            }
+            lines.push(&link);
+        }
+    }
+    let mut linked = lines.join("\n");
+    linked.push('\n');
+    linked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn makes_a_self_contained_page() {
+        let page = r#"<html><head><title>Post</title>
+            <script>track()</script><link rel="stylesheet" href="/site.css">
+            </head><body><nav><a href="/">Home</a></nav>
+            <article onclick="go()"><p style="color: red">Text with
+            <a href="/other?a=1&amp;b=2">a link</a>.</p>
+            <img src="/figure.png" srcset="/figure@2x.png 2x"><img src='data:x'></article>
+            <footer>Copyright</footer></body></html>"#;
+        let base = Url::parse("https://example.com/post").unwrap();
+        let mut requested = Vec::new();
+        let snapshot = render("https://example.com/post", page, &base, 0, |src| {
+            requested.push(src.to_string());
+            Some("data:image/png;base64,AAAA".into())
+        });
+        assert_eq!(snapshot.title, "Post");
+        assert_eq!(requested, vec!["https://example.com/figure.png"]);
+        let html = snapshot.html;
+        assert!(html.contains("Saved from <a href=\"https://example.com/post\">"));
+        assert!(html.contains("1970-01-01 00:00 UTC"));
+        assert!(html.contains("<img src=\"data:image/png;base64,AAAA\">"));
+        assert!(html.contains("<img src=\"\">"));
+        assert!(html.contains("href=\"https://example.com/other?a=1&amp;b=2\""));
+        for gone in [
+            "track()",
+            "site.css",
+            "Home",
+            "onclick",
+            "color: red",
+            "srcset",
+            "Copyright",
+        ] {
+            assert!(!html.contains(gone), "{} is still there", gone);
+        }
+    }
+
+    #[test]
+    fn links_the_snapshot_below_the_url() {
+        let url = "https://example.com/post";
+        assert_eq!(
+            link_snapshot(
+                "Read https://example.com/post\nlater\n",
+                url,
+                "abc",
+                "Post.html"
+            ),
+            "Read https://example.com/post\n[Saved copy: Post.html](attachment:abc)\nlater\n"
+        );
+        assert_eq!(
+            link_snapshot("Notes\n\n", url, "abc", "Post.html"),
+            "Notes\n\n[Saved copy: Post.html](attachment:abc)\n"
+        );
+    }
+}
@@ -0,0 +1,149 @@
+//! Word counts and other statistics, for a single note and for the whole
+//! workspace.
+
+use crate::links;
+use crate::note::Note;
+use crate::store::TagCount;
+use chrono::{DateTime, Days, NaiveDate, TimeZone};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Average silent reading speed used for reading time estimates.
+const WORDS_PER_MINUTE: usize = 200;
+/// How many days the edit heatmap covers, ending today.
+const HEATMAP_DAYS: u64 = 365;
+
+#[derive(Serialize)]
+pub struct NoteStats {
+    pub words: usize,
+    pub characters: usize,
+    /// Rounded up to whole minutes.
+    pub reading_minutes: usize,
+    pub links: usize,
+    pub attachments: usize,
+}
+
+pub fn note_stats(note: &Note) -> NoteStats {
+    let words = note.content.split_whitespace().count();
+    NoteStats {
+        words,
+        characters: note.content.chars().count(),
+        reading_minutes: words.div_ceil(WORDS_PER_MINUTE),
+        links: links::parse_links(&note.content).len(),
+        attachments: note.attachments.len(),
+    }
+}
+
+#[derive(Serialize, PartialEq, Debug)]
+pub struct HeatmapDay {
+    /// `YYYY-MM-DD`, in local time.
+    pub date: String,
+    pub edits: usize,
+}
+
+#[derive(Serialize)]
+pub struct VaultStats {
+    pub notes: usize,
+    pub words: usize,
+    pub characters: usize,
+    pub links: usize,
+    pub attachments: usize,
+    pub tags: Vec<TagCount>,
+    /// Every day of the past year, oldest first.
+    pub heatmap: Vec<HeatmapDay>,
+}
+
+/// Totals the stats of `notes`. `edit_times` are the timestamps of every saved
+/// version of them, bucketed into days in `now`'s time zone for the heatmap.
+pub fn vault_stats<Tz: TimeZone>(
+    notes: &[Note],
+    tags: Vec<TagCount>,
+    edit_times: &[i64],
+    now: DateTime<Tz>,
+) -> VaultStats {
+    let mut stats = VaultStats {
+        notes: notes.len(),
+        words: 0,
+        characters: 0,
+        links: 0,
+        attachments: 0,
+        tags,
+        heatmap: Vec::new(),
+    };
+    for note in notes {
+        let note = note_stats(note);
+        stats.words += note.words;
+        stats.characters += note.characters;
+        stats.links += note.links;
+        stats.attachments += note.attachments;
+    }
+    stats.heatmap = heatmap(edit_times, now);
+    stats
+}
+
+fn heatmap<Tz: TimeZone>(edit_times: &[i64], now: DateTime<Tz>) -> Vec<HeatmapDay> {
+    let zone = now.timezone();
+    let mut per_day: HashMap<NaiveDate, usize> = HashMap::new();
+    for &time in edit_times {
+        if let Some(time) = zone.timestamp_opt(time, 0).single() {
+            *per_day.entry(time.date_naive()).or_default() += 1;
+        }
+    }
+    let today = now.date_naive();
+    (0..HEATMAP_DAYS)
+        .rev()
+        .filter_map(|ago| today.checked_sub_days(Days::new(ago)))
+        .map(|day| HeatmapDay {
+            date: day.format("%Y-%m-%d").to_string(),
+            edits: per_day.get(&day).copied().unwrap_or(0),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note;
+    use chrono::{FixedOffset, Utc};
+
+    fn note(content: &str) -> Note {
+        note::new("A".into(), content.into(), Vec::new(), 0)
+    }
+
+    #[test]
+    fn counts_words_and_links() {
+        let stats = note_stats(&note("Héllo  wörld\nsee [[Other]] "));
+        assert_eq!(stats.words, 4);
+        assert_eq!(stats.characters, 27);
+        assert_eq!(stats.reading_minutes, 1);
+        assert_eq!(stats.links, 1);
+
+        let long = note(&"word ".repeat(401));
+        assert_eq!(note_stats(&long).reading_minutes, 3);
+        assert_eq!(note_stats(&note("")).reading_minutes, 0);
+    }
+
+    #[test]
+    fn buckets_edits_by_local_day() {
+        // 2024-03-10 01:00 at UTC+2 is still 2024-03-09 in UTC
+        let zone = FixedOffset::east_opt(2 * 3600).unwrap();
+        let now = Utc
+            .with_ymd_and_hms(2024, 3, 9, 23, 0, 0)
+            .unwrap()
+            .with_timezone(&zone);
+        let yesterday = now.timestamp() - 24 * 3600;
+        let too_old = now.timestamp() - 400 * 24 * 3600;
+        let days = heatmap(&[now.timestamp(), now.timestamp(), yesterday, too_old], now);
+
+        assert_eq!(days.len(), 365);
+        assert_eq!(
+            days.last(),
+            Some(&HeatmapDay {
+                date: "2024-03-10".into(),
+                edits: 2
+            })
+        );
+        assert_eq!(days[363].edits, 1);
+        assert_eq!(days.iter().map(|day| day.edits).sum::<usize>(), 3);
+    }
+}
@@ -0,0 +1,121 @@
+//! Content-addressed storage for attachment files under attachments/. Each
+//! blob is named after the SHA-256 of its plaintext, or a keyed hash of that
+//! while the vault is enabled, and kept in a subdirectory named after the
+//! name's first two characters.
+
+use crate::error::NotesError;
+use crate::fsutil;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct Blobs {
+    dir: PathBuf,
+}
+
+impl Blobs {
+    pub fn open(dir: &Path) -> Result<Self, NotesError> {
+        fs::create_dir_all(dir).map_err(|e| {
+            NotesError::Io(format!("Failed to create attachments directory: {}", e))
+        })?;
+        Ok(Blobs {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// Returns the path for blob `hash`, or an error if `hash` isn't a
+    /// SHA-256 hex digest, so ids from the UI can't escape the directory.
+    fn path(&self, hash: &str) -> Result<PathBuf, NotesError> {
+        if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(NotesError::Invalid("Invalid attachment id".into()));
+        }
+        Ok(self.dir.join(&hash[..2]).join(hash))
+    }
+
+    pub fn contains(&self, hash: &str) -> Result<bool, NotesError> {
+        Ok(self.path(hash)?.exists())
+    }
+
+    /// Stores `stored` as blob `hash`. Blobs are immutable, so an existing
+    /// blob is left alone.
+    pub fn write(&self, hash: &str, stored: &[u8]) -> Result<(), NotesError> {
+        let path = self.path(hash)?;
+        if path.exists() {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| {
+                NotesError::Io(format!("Failed to create attachments directory: {}", e))
+            })?;
+        }
+        fsutil::write_atomic(&path, stored)
+            .map_err(|e| NotesError::Io(format!("Failed to save attachment: {}", e)))
+    }
+
+    pub fn read(&self, hash: &str) -> Result<Vec<u8>, NotesError> {
+        fs::read(self.path(hash)?)
+            .map_err(|e| NotesError::Io(format!("Failed to read attachment: {}", e)))
+    }
+
+    /// Copies every blob that `to` doesn't have yet into it, as stored.
+    pub fn copy_into(&self, to: &Blobs) -> Result<(), NotesError> {
+        for hash in self.hashes()? {
+            if !to.contains(&hash)? {
+                to.write(&hash, &self.read(&hash)?)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn remove(&self, hash: &str) -> Result<(), NotesError> {
+        let path = self.path(hash)?;
+        fs::remove_file(&path)
+            .map_err(|e| NotesError::Io(format!("Failed to delete attachment: {}", e)))?;
+        // Only succeeds once the shard is empty
+        if let Some(shard) = path.parent() {
+            let _ = fs::remove_dir(shard);
+        }
+        Ok(())
+    }
+
+    /// Returns the hash of every stored blob.
+    pub fn hashes(&self) -> Result<Vec<String>, NotesError> {
+        let mut hashes = Vec::new();
+        let shards = fs::read_dir(&self.dir)
+            .map_err(|e| NotesError::Io(format!("Failed to read attachments directory: {}", e)))?;
+        for shard in shards.flatten() {
+            let Ok(entries) = fs::read_dir(shard.path()) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                if let Some(name) = entry.file_name().to_str() {
+                    if self.path(name).is_ok() {
+                        hashes.push(name.to_string());
+                    }
+                }
+            }
+        }
+        Ok(hashes)
+    }
+
+    /// Deletes every blob not in `referenced`, returning how many were
+    /// removed.
+    pub fn collect_garbage(&self, referenced: &HashSet<String>) -> Result<usize, NotesError> {
+        let mut removed = 0;
+        for hash in self.hashes()? {
+            if !referenced.contains(hash.as_str()) {
+                self.remove(&hash)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+pub fn hash(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
@@ -0,0 +1,64 @@
+//! zstd compression of note contents at rest, for stores bloated by pasted
+//! logs and the like. Compressed content is kept as text, so every backend
+//! can hold it: `zst1:` followed by the base64 of the zstd frame. Content is
+//! compressed before it's encrypted, since ciphertext doesn't compress.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use tracing::warn;
+
+const PREFIX: &str = "zst1:";
+/// Shorter content is stored as is, since compressing it gains little.
+const MIN_BYTES: usize = 1024;
+const LEVEL: i32 = 3;
+
+/// Returns `content` compressed, unless it's too short for that to be
+/// worth it or wouldn't shrink.
+pub fn pack(content: &str) -> String {
+    if content.len() < MIN_BYTES {
+        return content.to_string();
+    }
+    match zstd::encode_all(content.as_bytes(), LEVEL) {
+        Ok(frame) if frame.len() * 4 / 3 + PREFIX.len() < content.len() => {
+            format!("{}{}", PREFIX, BASE64.encode(frame))
+        }
+        Ok(_) => content.to_string(),
+        Err(e) => {
+            warn!("Failed to compress note content: {}", e);
+            content.to_string()
+        }
+    }
+}
+
+/// Reverses `pack`. Content that isn't compressed, including content that
+/// merely starts with the prefix, is returned unchanged.
+pub fn unpack(content: String) -> String {
+    let Some(encoded) = content.strip_prefix(PREFIX) else {
+        return content;
+    };
+    BASE64
+        .decode(encoded)
+        .ok()
+        .and_then(|frame| zstd::decode_all(frame.as_slice()).ok())
+        .and_then(|plain| String::from_utf8(plain).ok())
+        .unwrap_or(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_only_what_shrinks() {
+        let log = "2024-05-01 12:00:00 INFO request served\n".repeat(200);
+        let packed = pack(&log);
+        assert!(packed.starts_with(PREFIX) && packed.len() < log.len() / 10);
+        assert_eq!(unpack(packed), log);
+
+        assert_eq!(pack("short"), "short");
+        assert_eq!(
+            unpack("zst1: not compressed".into()),
+            "zst1: not compressed"
+        );
+    }
+}
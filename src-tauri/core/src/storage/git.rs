@@ -0,0 +1,312 @@
+//! Markdown note files kept in a local git repository, committed after every
+//! change. Each commit lists the notes it touched in `Note-Id:` trailers, so
+//! a note's log survives the file being renamed along with its title.
+//! Attachment blobs live in the repository's attachments/ folder, so they're
+//! committed, pushed, and pulled along with the notes that use them.
+
+use super::markdown::MarkdownBackend;
+use super::NotesBackend;
+use crate::error::NotesError;
+use crate::note::Note;
+use crate::vault;
+use git2::{
+    build::CheckoutBuilder, Cred, CredentialType, FetchOptions, IndexAddOption, PushOptions,
+    RemoteCallbacks, Repository, Signature,
+};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const REMOTE: &str = "origin";
+const LOG_LIMIT: usize = 200;
+/// Credential callbacks are retried by libgit2 until they fail, so give up
+/// after a few attempts instead of looping on a rejected key.
+const MAX_CREDENTIAL_ATTEMPTS: usize = 3;
+
+#[derive(Serialize)]
+pub struct GitLogEntry {
+    pub commit: String,
+    pub message: String,
+    /// Commit time as a Unix timestamp.
+    pub time: i64,
+}
+
+pub struct GitBackend {
+    dir: PathBuf,
+    notes: MarkdownBackend,
+    repo: Repository,
+}
+
+fn git_error(action: &str, e: git2::Error) -> NotesError {
+    NotesError::Git(format!("Failed to {}: {}", action, e.message()))
+}
+
+impl GitBackend {
+    /// Opens the repository at `dir`, creating it if needed.
+    pub fn open(dir: &Path) -> Result<Self, NotesError> {
+        let notes = MarkdownBackend::open(dir)?;
+        let repo = match Repository::open(dir) {
+            Ok(repo) => repo,
+            Err(_) => Repository::init(dir).map_err(|e| git_error("create git repository", e))?,
+        };
+
+        // Keep the file store's backups and temp files out of the history
+        let gitignore = dir.join(".gitignore");
+        if !gitignore.exists() {
+            fs::write(&gitignore, "*.bak\n*.tmp\n")
+                .map_err(|e| NotesError::Io(format!("Failed to write .gitignore: {}", e)))?;
+        }
+
+        Ok(GitBackend {
+            dir: dir.to_path_buf(),
+            notes,
+            repo,
+        })
+    }
+
+    /// Stages every change in the working tree and commits it, unless nothing
+    /// changed since the last commit.
+    fn commit(&self, summary: &str, ids: &[&str]) -> Result<(), NotesError> {
+        let mut index = self
+            .repo
+            .index()
+            .map_err(|e| git_error("open git index", e))?;
+        index
+            .add_all(["*"], IndexAddOption::DEFAULT, None)
+            .and_then(|_| index.update_all(["*"], None))
+            .and_then(|_| index.write())
+            .map_err(|e| git_error("stage notes", e))?;
+        let tree_id = index
+            .write_tree()
+            .map_err(|e| git_error("write git tree", e))?;
+
+        let parent = match self.repo.head() {
+            Ok(head) => Some(
+                head.peel_to_commit()
+                    .map_err(|e| git_error("read HEAD", e))?,
+            ),
+            Err(_) => None,
+        };
+        if parent
+            .as_ref()
+            .is_some_and(|parent| parent.tree_id() == tree_id)
+        {
+            return Ok(());
+        }
+
+        let tree = self
+            .repo
+            .find_tree(tree_id)
+            .map_err(|e| git_error("read git tree", e))?;
+        let signature = self
+            .repo
+            .signature()
+            .or_else(|_| Signature::now("Min Notes", "min-notes@localhost"))
+            .map_err(|e| git_error("create commit signature", e))?;
+        let mut message = format!("{}\n\n", summary);
+        for id in ids {
+            message.push_str(&format!("Note-Id: {}\n", id));
+        }
+        let parents: Vec<_> = parent.iter().collect();
+        self.repo
+            .commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                &message,
+                &tree,
+                &parents,
+            )
+            .map_err(|e| git_error("commit notes", e))?;
+        Ok(())
+    }
+
+    /// Returns the commits that touched note `id`, newest first.
+    pub fn log(&self, id: &str) -> Result<Vec<GitLogEntry>, NotesError> {
+        let mut walk = self
+            .repo
+            .revwalk()
+            .map_err(|e| git_error("read git log", e))?;
+        if walk.push_head().is_err() {
+            // No commits yet
+            return Ok(Vec::new());
+        }
+
+        let trailer = format!("Note-Id: {}", id);
+        let mut entries = Vec::new();
+        for oid in walk {
+            let oid = oid.map_err(|e| git_error("read git log", e))?;
+            let commit = self
+                .repo
+                .find_commit(oid)
+                .map_err(|e| git_error("read commit", e))?;
+            let message = commit.message().unwrap_or("");
+            if message.lines().any(|line| line == trailer) {
+                entries.push(GitLogEntry {
+                    commit: oid.to_string(),
+                    message: commit.summary().unwrap_or("").to_string(),
+                    time: commit.time().seconds(),
+                });
+                if entries.len() == LOG_LIMIT {
+                    break;
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Sets the URL of the remote that notes are pushed to and pulled from.
+    pub fn set_remote(&self, url: &str) -> Result<(), NotesError> {
+        if self.repo.find_remote(REMOTE).is_ok() {
+            self.repo
+                .remote_set_url(REMOTE, url)
+                .map_err(|e| git_error("set git remote", e))
+        } else {
+            self.repo
+                .remote(REMOTE, url)
+                .map(|_| ())
+                .map_err(|e| git_error("add git remote", e))
+        }
+    }
+
+    fn branch(&self) -> Result<String, NotesError> {
+        let head = self
+            .repo
+            .head()
+            .map_err(|_| NotesError::Invalid("There are no commits to sync yet".into()))?;
+        Ok(head.shorthand().unwrap_or("master").to_string())
+    }
+
+    pub fn push(&self) -> Result<(), NotesError> {
+        let branch = self.branch()?;
+        let mut remote = self
+            .repo
+            .find_remote(REMOTE)
+            .map_err(|_| NotesError::Invalid("No git remote has been set".into()))?;
+        let mut options = PushOptions::new();
+        options.remote_callbacks(callbacks());
+        remote
+            .push(
+                &[format!("refs/heads/{0}:refs/heads/{0}", branch)],
+                Some(&mut options),
+            )
+            .map_err(|e| git_error("push notes", e))
+    }
+
+    /// Fetches the remote branch and fast-forwards to it. Diverged histories
+    /// are left for the user to merge by hand.
+    pub fn pull(&mut self) -> Result<(), NotesError> {
+        let branch = self.branch()?;
+        let mut remote = self
+            .repo
+            .find_remote(REMOTE)
+            .map_err(|_| NotesError::Invalid("No git remote has been set".into()))?;
+        let mut options = FetchOptions::new();
+        options.remote_callbacks(callbacks());
+        remote
+            .fetch(&[branch.as_str()], Some(&mut options), None)
+            .map_err(|e| git_error("fetch notes", e))?;
+
+        let fetch_head = self
+            .repo
+            .find_reference("FETCH_HEAD")
+            .and_then(|reference| self.repo.reference_to_annotated_commit(&reference))
+            .map_err(|e| git_error("read fetched notes", e))?;
+        let (analysis, _) = self
+            .repo
+            .merge_analysis(&[&fetch_head])
+            .map_err(|e| git_error("compare with remote", e))?;
+
+        if analysis.is_up_to_date() {
+            return Ok(());
+        }
+        if !analysis.is_fast_forward() {
+            return Err(NotesError::Conflict(
+                "Local and remote notes have diverged; merge them in the notes repository".into(),
+            ));
+        }
+
+        let refname = format!("refs/heads/{}", branch);
+        self.repo
+            .find_reference(&refname)
+            .and_then(|mut reference| {
+                reference.set_target(fetch_head.id(), "Fast-forward to remote notes")
+            })
+            .and_then(|_| self.repo.set_head(&refname))
+            .and_then(|_| {
+                self.repo
+                    .checkout_head(Some(CheckoutBuilder::default().force()))
+            })
+            .map_err(|e| git_error("update notes from remote", e))?;
+
+        // Pick up added, removed, and renamed files
+        self.notes = MarkdownBackend::open(&self.dir)?;
+        Ok(())
+    }
+}
+
+fn callbacks() -> RemoteCallbacks<'static> {
+    let mut attempts = 0;
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |url, username, allowed| {
+        attempts += 1;
+        if attempts > MAX_CREDENTIAL_ATTEMPTS {
+            return Err(git2::Error::from_str("Authentication failed"));
+        }
+        if allowed.contains(CredentialType::SSH_KEY) {
+            return Cred::ssh_key_from_agent(username.unwrap_or("git"));
+        }
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            let config = git2::Config::open_default()?;
+            return Cred::credential_helper(&config, url, username);
+        }
+        Cred::default()
+    });
+    callbacks
+}
+
+/// Describes a save in the commit summary. Encrypted titles would be noise,
+/// so those notes are described by count only.
+fn save_summary(notes: &[Note]) -> String {
+    match notes {
+        [note] if !vault::is_encrypted(&note.title) => format!("Update \"{}\"", note.title),
+        [_] => "Update note".to_string(),
+        _ => format!("Update {} notes", notes.len()),
+    }
+}
+
+impl NotesBackend for GitBackend {
+    fn load_all(&self) -> Result<Vec<Note>, NotesError> {
+        self.notes.load_all()
+    }
+
+    fn list(&self) -> Result<Vec<String>, NotesError> {
+        self.notes.list()
+    }
+
+    fn save(&mut self, notes: &[Note]) -> Result<(), NotesError> {
+        if notes.is_empty() {
+            return Ok(());
+        }
+        self.notes.save(notes)?;
+        let ids: Vec<&str> = notes.iter().map(|note| note.id.as_str()).collect();
+        self.commit(&save_summary(notes), &ids)
+    }
+
+    fn delete(&mut self, ids: &[String]) -> Result<usize, NotesError> {
+        let deleted = self.notes.delete(ids)?;
+        if deleted > 0 {
+            let summary = match deleted {
+                1 => "Delete note".to_string(),
+                n => format!("Delete {} notes", n),
+            };
+            let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+            self.commit(&summary, &ids)?;
+        }
+        Ok(deleted)
+    }
+
+    fn as_git(&mut self) -> Option<&mut GitBackend> {
+        Some(self)
+    }
+}
@@ -0,0 +1,123 @@
+//! Bounded per-note revision history, kept as one JSON file per note under
+//! history/ so it works the same with every backend.
+
+use crate::error::NotesError;
+use crate::fsutil;
+use crate::note;
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Older revisions are dropped once a note has this many.
+pub const MAX_REVISIONS: usize = 50;
+
+/// A previous version of a note's title and content.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Revision {
+    /// Increases by one with every revision of the note, starting at 1.
+    pub rev: u32,
+    pub title: String,
+    pub content: String,
+    /// When this version was saved, i.e. the note's `updated_at` at the time.
+    pub saved_at: i64,
+}
+
+pub struct History {
+    dir: PathBuf,
+}
+
+impl History {
+    pub fn open(dir: &Path) -> Result<Self, NotesError> {
+        fs::create_dir_all(dir)
+            .map_err(|e| NotesError::Io(format!("Failed to create history directory: {}", e)))?;
+        Ok(History {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// Returns the history file of note `id`, or an error if `id` could
+    /// point outside the directory.
+    fn path(&self, id: &str) -> Result<PathBuf, NotesError> {
+        if !note::is_valid_id(id) {
+            return Err(NotesError::Invalid("Invalid note id".into()));
+        }
+        Ok(self.dir.join(format!("{}.json", id)))
+    }
+
+    /// Loads the revisions of note `id`, oldest first.
+    pub fn load(&self, id: &str) -> Result<Vec<Revision>, NotesError> {
+        let path = self.path(id)?;
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        fsutil::read_with_backup(&path, |content| {
+            serde_json::from_str(content)
+                .map_err(|e| NotesError::Serde(format!("Failed to parse history: {}", e)))
+        })
+    }
+
+    pub fn save(&self, id: &str, revisions: &[Revision]) -> Result<(), NotesError> {
+        let json = serde_json::to_string(revisions)
+            .map_err(|e| NotesError::Serde(format!("Failed to serialize history: {}", e)))?;
+        fsutil::write_with_backup(&self.path(id)?, json.as_bytes())
+            .map_err(|e| NotesError::Io(format!("Failed to write history: {}", e)))
+    }
+
+    /// Appends a revision to note `id`, dropping the oldest ones beyond
+    /// `MAX_REVISIONS`.
+    pub fn push(
+        &self,
+        id: &str,
+        title: String,
+        content: String,
+        saved_at: i64,
+    ) -> Result<(), NotesError> {
+        let mut revisions = self.load(id)?;
+        let rev = revisions.last().map_or(1, |last| last.rev + 1);
+        revisions.push(Revision {
+            rev,
+            title,
+            content,
+            saved_at,
+        });
+        let excess = revisions.len().saturating_sub(MAX_REVISIONS);
+        revisions.drain(..excess);
+        self.save(id, &revisions)
+    }
+
+    pub fn remove(&self, id: &str) -> Result<(), NotesError> {
+        let path = self.path(id)?;
+        if !path.exists() {
+            return Ok(());
+        }
+        fsutil::remove_with_backup(&path)
+            .map_err(|e| NotesError::Io(format!("Failed to delete history: {}", e)))
+    }
+
+    /// Returns the ids of every note with a history file.
+    pub fn note_ids(&self) -> Result<Vec<String>, NotesError> {
+        let entries = fs::read_dir(&self.dir)
+            .map_err(|e| NotesError::Io(format!("Failed to read history directory: {}", e)))?;
+        Ok(entries
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.path();
+                if path.extension().is_none_or(|ext| ext != "json") {
+                    return None;
+                }
+                path.file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .map(str::to_string)
+            })
+            .collect())
+    }
+}
+
+/// Renders the changes from `old` to `new` as a unified line diff.
+pub fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(old_label, new_label)
+        .to_string()
+}
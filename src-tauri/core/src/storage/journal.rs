@@ -0,0 +1,84 @@
+//! A journal of the writes handed to the backend, so one cut short by a
+//! crash is finished the next time the notes are opened. Each write is
+//! appended to notes.journal and flushed to disk before the backend sees it,
+//! and the journal is emptied once the backend is done.
+//!
+//! Write to the backend only while holding the exclusive store lock, which
+//! guards the journal too. Notes are journaled as sealed for the backend, so
+//! the journal holds nothing in the clear that the backend wouldn't.
+
+use crate::error::NotesError;
+use crate::note::Note;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+pub const JOURNAL_FILE: &str = "notes.journal";
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Entry<'a> {
+    /// Inserts the notes, replacing stored notes with the same ids.
+    Save { notes: Cow<'a, [Note]> },
+    /// Deletes the notes with the ids, along with their history.
+    Delete { ids: Cow<'a, [String]> },
+}
+
+pub struct Journal {
+    path: PathBuf,
+}
+
+impl Journal {
+    pub fn open(data_dir: &Path) -> Self {
+        Journal {
+            path: data_dir.join(JOURNAL_FILE),
+        }
+    }
+
+    /// Appends `entry` as a line of JSON and waits for it to reach the disk.
+    pub fn append(&self, entry: &Entry) -> Result<(), NotesError> {
+        let mut line = serde_json::to_vec(entry)
+            .map_err(|e| NotesError::Serde(format!("Failed to serialize journal entry: {}", e)))?;
+        line.push(b'\n');
+        let write = || -> io::Result<()> {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            file.write_all(&line)?;
+            file.sync_data()
+        };
+        write().map_err(|e| NotesError::Io(format!("Failed to write journal: {}", e)))
+    }
+
+    /// The entries the backend may not have finished, oldest first. A last
+    /// line cut short was never handed to the backend, so it's left out.
+    pub fn pending(&self) -> Result<Vec<Entry<'static>>, NotesError> {
+        let text = match fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(NotesError::Io(format!("Failed to read journal: {}", e))),
+        };
+        let mut entries = Vec::new();
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            match serde_json::from_str(line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => warn!("Skipping unreadable journal entry: {}", e),
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Empties the journal once the backend has every entry.
+    pub fn clear(&self) -> Result<(), NotesError> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                Err(NotesError::Io(format!("Failed to clear journal: {}", e)))
+            }
+            _ => Ok(()),
+        }
+    }
+}
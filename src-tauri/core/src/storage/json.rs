@@ -0,0 +1,78 @@
+//! Keeps every note in a single JSON file, which is simple to inspect, diff,
+//! and process with other tools. The file is read on every operation rather
+//! than cached, so it never writes back a stale copy over changes made by
+//! another process.
+
+use super::NotesBackend;
+use crate::error::NotesError;
+use crate::fsutil;
+use crate::note::Note;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+pub struct JsonBackend {
+    path: PathBuf,
+}
+
+impl JsonBackend {
+    /// Opens the notes file at `path`, which is created on the first save.
+    pub fn open(path: &Path) -> Result<Self, NotesError> {
+        let backend = JsonBackend {
+            path: path.to_path_buf(),
+        };
+        // Fail now rather than on the first read if the file is unusable
+        backend.read()?;
+        Ok(backend)
+    }
+
+    fn read(&self) -> Result<Vec<Note>, NotesError> {
+        if !self.path.exists() && !fsutil::backup_path(&self.path).exists() {
+            return Ok(Vec::new());
+        }
+        fsutil::read_with_backup(&self.path, |content| {
+            serde_json::from_str(content)
+                .map_err(|e| NotesError::Serde(format!("Failed to parse notes file: {}", e)))
+        })
+    }
+
+    fn write(&self, notes: &[Note]) -> Result<(), NotesError> {
+        let json = serde_json::to_string_pretty(notes)
+            .map_err(|e| NotesError::Serde(format!("Failed to serialize notes: {}", e)))?;
+        fsutil::write_with_backup(&self.path, json.as_bytes())
+            .map_err(|e| NotesError::Io(format!("Failed to write notes file: {}", e)))
+    }
+}
+
+impl NotesBackend for JsonBackend {
+    fn load_all(&self) -> Result<Vec<Note>, NotesError> {
+        let mut notes = self.read()?;
+        notes.sort_by_key(|note| note.created_at);
+        Ok(notes)
+    }
+
+    fn save(&mut self, notes: &[Note]) -> Result<(), NotesError> {
+        if notes.is_empty() {
+            return Ok(());
+        }
+        let mut stored = self.read()?;
+        for note in notes {
+            match stored.iter_mut().find(|stored| stored.id == note.id) {
+                Some(stored) => *stored = note.clone(),
+                None => stored.push(note.clone()),
+            }
+        }
+        self.write(&stored)
+    }
+
+    fn delete(&mut self, ids: &[String]) -> Result<usize, NotesError> {
+        let ids: HashSet<&String> = ids.iter().collect();
+        let mut stored = self.read()?;
+        let before = stored.len();
+        stored.retain(|note| !ids.contains(&note.id));
+        let deleted = before - stored.len();
+        if deleted > 0 {
+            self.write(&stored)?;
+        }
+        Ok(deleted)
+    }
+}
@@ -0,0 +1,150 @@
+//! One-time import of the notes.json file used before notes moved to SQLite,
+//! and recovery of what's readable when that file is damaged.
+
+use super::Storage;
+use crate::error::NotesError;
+use crate::fsutil;
+use crate::note::Note;
+use chrono::Utc;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Parses notes.json, filling in `updated_at` for notes saved in the old
+/// single-`timestamp` format.
+fn parse_notes(content: &str) -> Result<Vec<Note>, serde_json::Error> {
+    let mut notes: Vec<Note> = serde_json::from_str(content)?;
+    for note in notes.iter_mut().filter(|n| n.updated_at == 0) {
+        note.updated_at = note.created_at;
+    }
+    Ok(notes)
+}
+
+/// The notes that could be read out of a damaged notes.json.
+pub struct Salvaged {
+    pub notes: Vec<Note>,
+    /// Note objects that were cut off or couldn't be parsed.
+    pub unreadable: usize,
+}
+
+/// Reads whatever notes can be read from `content`, even if it isn't valid
+/// JSON as a whole. Every outermost `{...}` object is parsed on its own, so a
+/// truncated file or a broken note only loses the notes affected.
+pub fn salvage_notes(content: &str) -> Salvaged {
+    let mut salvaged = Salvaged {
+        notes: Vec::new(),
+        unreadable: 0,
+    };
+    let mut depth = 0usize;
+    let mut start = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in content.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' if depth > 0 => in_string = true,
+            '{' => {
+                if depth == 0 {
+                    start = i;
+                }
+                depth += 1;
+            }
+            '}' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    match serde_json::from_str::<Note>(&content[start..=i]) {
+                        Ok(note) => salvaged.notes.push(note),
+                        Err(_) => salvaged.unreadable += 1,
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    if depth > 0 {
+        salvaged.unreadable += 1;
+    }
+    for note in salvaged.notes.iter_mut().filter(|n| n.updated_at == 0) {
+        note.updated_at = note.created_at;
+    }
+    salvaged
+}
+
+/// Renames a damaged `notes_file` to `notes.json.corrupt-<time>`, so it's
+/// kept for reference but no longer imported, and returns the new path.
+pub fn set_aside(notes_file: &Path) -> Result<PathBuf, NotesError> {
+    let suffix = format!(".corrupt-{}", Utc::now().format("%Y%m%d-%H%M%S"));
+    let aside = fsutil::with_suffix(notes_file, &suffix);
+    fs::rename(notes_file, &aside)
+        .map_err(|e| NotesError::Io(format!("Failed to rename damaged notes file: {}", e)))?;
+    Ok(aside)
+}
+
+/// Copies every note from `notes_file` into `storage`, then renames the file to
+/// `notes.json.imported` so the import only ever happens once. Returns the
+/// number of notes imported, or `None` if the file is damaged, in which case
+/// it's left in place to be salvaged.
+pub fn import_notes_json(
+    storage: &mut Storage,
+    notes_file: &Path,
+) -> Result<Option<usize>, NotesError> {
+    if !notes_file.exists() {
+        return Ok(Some(0));
+    }
+
+    let content = fs::read_to_string(notes_file)
+        .map_err(|e| NotesError::Io(format!("Failed to read notes file: {}", e)))?;
+    let Ok(notes) = parse_notes(&content) else {
+        return Ok(None);
+    };
+
+    storage.insert_notes(&notes)?;
+
+    fs::rename(notes_file, notes_file.with_extension("json.imported"))
+        .map_err(|e| NotesError::Io(format!("Failed to rename imported notes file: {}", e)))?;
+    Ok(Some(notes.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTE_A: &str = r#"{"id":"a","title":"A {1}","content":"say \"}\"","timestamp":5}"#;
+    const NOTE_B: &str =
+        r#"{"id":"b","title":"B","content":"","created_at":1,"updated_at":2,"tags":["x"]}"#;
+
+    #[test]
+    fn salvages_intact_file() {
+        let salvaged = salvage_notes(&format!("[{},{}]", NOTE_A, NOTE_B));
+        assert_eq!(salvaged.notes.len(), 2);
+        assert_eq!(salvaged.unreadable, 0);
+        assert_eq!(salvaged.notes[0].content, "say \"}\"");
+        assert_eq!(salvaged.notes[0].updated_at, 5);
+        assert_eq!(salvaged.notes[1].tags, ["x"]);
+    }
+
+    #[test]
+    fn salvages_truncated_file() {
+        let content = format!("[{},{}", NOTE_A, &NOTE_B[..30]);
+        let salvaged = salvage_notes(&content);
+        assert_eq!(salvaged.notes.len(), 1);
+        assert_eq!(salvaged.notes[0].id, "a");
+        assert_eq!(salvaged.unreadable, 1);
+    }
+
+    #[test]
+    fn skips_broken_notes() {
+        let content = format!("[{},{{\"id\":7}},\0\0garbage,{}]", NOTE_A, NOTE_B);
+        let salvaged = salvage_notes(&content);
+        let ids: Vec<&str> = salvaged.notes.iter().map(|n| n.id.as_str()).collect();
+        assert_eq!(ids, ["a", "b"]);
+        assert_eq!(salvaged.unreadable, 1);
+    }
+}
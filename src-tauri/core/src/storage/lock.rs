@@ -0,0 +1,83 @@
+//! Advisory locks on a data directory's notes, so that two processes using
+//! it, such as the app and a script, never interleave their reads and
+//! writes. A lock is taken around each backend operation and released as
+//! soon as it's done.
+
+use crate::error::NotesError;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const LOCK_FILE: &str = "notes.lock";
+/// How long to wait for another process to finish before giving up.
+pub const WAIT: Duration = Duration::from_secs(5);
+const RETRY_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Held until dropped, when the file is closed and the OS releases it.
+pub struct StoreLock {
+    _file: File,
+}
+
+/// Waits up to `wait` to share the lock with other readers.
+pub fn shared(data_dir: &Path, wait: Duration) -> Result<StoreLock, NotesError> {
+    acquire(data_dir, false, wait)
+}
+
+/// Waits up to `wait` for the lock to be free and takes it.
+pub fn exclusive(data_dir: &Path, wait: Duration) -> Result<StoreLock, NotesError> {
+    acquire(data_dir, true, wait)
+}
+
+fn acquire(data_dir: &Path, exclusive: bool, wait: Duration) -> Result<StoreLock, NotesError> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(data_dir.join(LOCK_FILE))
+        .map_err(|e| NotesError::Io(format!("Failed to open the notes lock: {}", e)))?;
+    let deadline = Instant::now() + wait;
+    loop {
+        let attempt = if exclusive {
+            file.try_lock()
+        } else {
+            file.try_lock_shared()
+        };
+        match attempt {
+            Ok(()) => return Ok(StoreLock { _file: file }),
+            Err(TryLockError::WouldBlock) if Instant::now() < deadline => {
+                thread::sleep(RETRY_INTERVAL)
+            }
+            Err(TryLockError::WouldBlock) => return Err(NotesError::Busy),
+            Err(TryLockError::Error(e)) => {
+                return Err(NotesError::Io(format!("Failed to lock the notes: {}", e)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn readers_share_and_writers_wait() {
+        let dir = TempDir::new().unwrap();
+        let reader = shared(dir.path(), Duration::ZERO).unwrap();
+        let other_reader = shared(dir.path(), Duration::ZERO).unwrap();
+        assert_eq!(
+            exclusive(dir.path(), Duration::ZERO).err(),
+            Some(NotesError::Busy)
+        );
+        drop((reader, other_reader));
+
+        let writer = exclusive(dir.path(), Duration::ZERO).unwrap();
+        assert_eq!(
+            shared(dir.path(), Duration::from_millis(50)).err(),
+            Some(NotesError::Busy)
+        );
+        drop(writer);
+        assert!(shared(dir.path(), Duration::ZERO).is_ok());
+    }
+}
@@ -0,0 +1,271 @@
+//! Stores each note as a Markdown file with YAML frontmatter, so the notes
+//! directory stays greppable and usable outside the app.
+//!
+//! ```text
+//! ---
+//! id: 5f0c…
+//! title: Shopping list
+//! created_at: 1715900000
+//! updated_at: 1715903600
+//! tags:
+//! - errands
+//! store: corner shop
+//! ---
+//! - milk
+//! ```
+//!
+//! Fields that aren't the note's own, like `store` here, are its metadata.
+
+use super::NotesBackend;
+use crate::error::NotesError;
+use crate::fsutil;
+use crate::note::{self, slugify, Note};
+use crate::vault;
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+pub struct MarkdownBackend {
+    dir: PathBuf,
+    /// Maps note ids to the file holding them.
+    files: HashMap<String, PathBuf>,
+}
+
+impl MarkdownBackend {
+    /// Opens the notes directory at `dir`, creating it if needed. Files that
+    /// aren't valid notes are skipped rather than failing the whole store.
+    pub fn open(dir: &Path) -> Result<Self, NotesError> {
+        fs::create_dir_all(dir)
+            .map_err(|e| NotesError::Io(format!("Failed to create notes directory: {}", e)))?;
+
+        let entries = fs::read_dir(dir)
+            .map_err(|e| NotesError::Io(format!("Failed to read notes directory: {}", e)))?;
+        let mut files = HashMap::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "md") {
+                continue;
+            }
+            match read_note_file(&path) {
+                Ok(note) => {
+                    files.insert(note.id, path);
+                }
+                Err(e) => warn!("Skipping {}: {}", path.display(), e),
+            }
+        }
+
+        Ok(MarkdownBackend {
+            dir: dir.to_path_buf(),
+            files,
+        })
+    }
+
+    /// Picks the file for `note`: its current file if the name still matches
+    /// the title, otherwise the first free `<slug>.md`, `<slug>-2.md`, ….
+    fn path_for(&self, note: &Note) -> PathBuf {
+        // Encrypted titles would make meaningless file names
+        let slug = if vault::is_encrypted(&note.title) {
+            note.id.clone()
+        } else {
+            slugify(&note.title)
+        };
+
+        if let Some(current) = self.files.get(&note.id) {
+            let stem = current.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+            let suffix = stem.strip_prefix(slug.as_str());
+            let matches = suffix.is_some_and(|suffix| {
+                suffix.is_empty()
+                    || suffix
+                        .strip_prefix('-')
+                        .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+            });
+            if matches {
+                return current.clone();
+            }
+        }
+
+        let mut candidate = self.dir.join(format!("{}.md", slug));
+        let mut n = 2;
+        while candidate.exists() {
+            candidate = self.dir.join(format!("{}-{}.md", slug, n));
+            n += 1;
+        }
+        candidate
+    }
+}
+
+impl NotesBackend for MarkdownBackend {
+    fn load_all(&self) -> Result<Vec<Note>, NotesError> {
+        let mut notes = self
+            .files
+            .values()
+            .map(|path| read_note_file(path))
+            .collect::<Result<Vec<Note>, NotesError>>()?;
+        notes.sort_by_key(|n| n.created_at);
+        Ok(notes)
+    }
+
+    fn list(&self) -> Result<Vec<String>, NotesError> {
+        Ok(self.files.keys().cloned().collect())
+    }
+
+    fn save(&mut self, notes: &[Note]) -> Result<(), NotesError> {
+        for note in notes {
+            let path = self.path_for(note);
+            fsutil::write_with_backup(&path, render_note(note)?.as_bytes())
+                .map_err(|e| NotesError::Io(format!("Failed to write note file: {}", e)))?;
+
+            if let Some(old_path) = self.files.insert(note.id.clone(), path.clone()) {
+                if old_path != path {
+                    fsutil::remove_with_backup(&old_path).map_err(|e| {
+                        NotesError::Io(format!("Failed to remove renamed note file: {}", e))
+                    })?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, ids: &[String]) -> Result<usize, NotesError> {
+        let mut deleted = 0;
+        for id in ids {
+            if let Some(path) = self.files.remove(id) {
+                fsutil::remove_with_backup(&path)
+                    .map_err(|e| NotesError::Io(format!("Failed to delete note file: {}", e)))?;
+                deleted += 1;
+            }
+        }
+        Ok(deleted)
+    }
+}
+
+/// Reads a note file, falling back to its `.bak` copy if the file is damaged.
+fn read_note_file(path: &Path) -> Result<Note, NotesError> {
+    fsutil::read_with_backup(path, parse_note)
+}
+
+/// A note file's frontmatter, with the fields the note doesn't have read as
+/// its metadata.
+#[derive(Deserialize)]
+struct Frontmatter {
+    #[serde(flatten)]
+    note: Note,
+    #[serde(flatten, deserialize_with = "note::scalars")]
+    meta: BTreeMap<String, String>,
+}
+
+/// Splits a note file into its frontmatter fields and Markdown body.
+pub fn parse_note(text: &str) -> Result<Note, NotesError> {
+    let text = text.replace("\r\n", "\n");
+    let rest = text
+        .strip_prefix("---\n")
+        .ok_or_else(|| NotesError::Serde("Note file has no frontmatter".into()))?;
+    let (front, body) = match rest.find("\n---\n") {
+        Some(end) => (&rest[..end + 1], &rest[end + 5..]),
+        None => (
+            rest.strip_suffix("\n---")
+                .ok_or_else(|| NotesError::Serde("Note file frontmatter is not closed".into()))?,
+            "",
+        ),
+    };
+
+    let mut fields: Mapping = serde_yaml::from_str(front)
+        .map_err(|e| NotesError::Serde(format!("Failed to parse frontmatter: {}", e)))?;
+    fields.insert("content".into(), body.into());
+    let Frontmatter { mut note, meta } = serde_yaml::from_value(Value::Mapping(fields))
+        .map_err(|e| NotesError::Serde(format!("Failed to parse frontmatter: {}", e)))?;
+    note.meta.extend(meta);
+    Ok(note)
+}
+
+pub fn render_note(note: &Note) -> Result<String, NotesError> {
+    let mut fields = serde_yaml::to_value(note)
+        .map_err(|e| NotesError::Serde(format!("Failed to serialize note: {}", e)))?;
+    if let Value::Mapping(fields) = &mut fields {
+        fields.remove("content");
+        // Metadata goes beside the note's own fields, where other apps look
+        // for it, unless it was written by one with a field of the same name
+        if let Some(Value::Mapping(meta)) = fields.remove("meta") {
+            let mut nested = Mapping::new();
+            for (key, value) in meta {
+                match key.as_str() {
+                    Some(name) if !note::FIELDS.contains(&name) => fields.insert(key, value),
+                    _ => nested.insert(key, value),
+                };
+            }
+            if !nested.is_empty() {
+                fields.insert("meta".into(), Value::Mapping(nested));
+            }
+        }
+    }
+    let front = serde_yaml::to_string(&fields)
+        .map_err(|e| NotesError::Serde(format!("Failed to serialize note: {}", e)))?;
+    Ok(format!("---\n{}---\n{}", front, note.content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn rendered_notes_parse_back(
+            title in any::<String>(),
+            // Line endings are normalized, so only \n survives
+            content in "[^\r]*",
+            tags in proptest::collection::vec(any::<String>(), 0..4),
+            created_at in any::<i64>(),
+            trashed_at in any::<Option<i64>>(),
+            pinned in any::<bool>(),
+            color in proptest::option::of("#[0-9a-f]{6}"),
+            meta in proptest::collection::btree_map("[a-z_]{1,12}", any::<String>(), 0..3),
+        ) {
+            let note = Note {
+                id: "5f0c-ab".into(),
+                title,
+                content,
+                created_at,
+                updated_at: created_at,
+                tags,
+                trashed_at,
+                pinned,
+                archived: !pinned,
+                favorite: pinned,
+                locked: pinned,
+                content_hash: String::new(),
+                color,
+                notebook_id: None,
+                attachments: Vec::new(),
+                reminders: Vec::new(),
+                expiry: None,
+                location: None,
+                meta,
+            };
+            let parsed = parse_note(&render_note(&note).unwrap()).unwrap();
+            prop_assert!(parsed == note);
+        }
+    }
+
+    /// Metadata named like one of the note's own fields, as another app may
+    /// write it, is kept apart from them.
+    fn assert_meta_round_trips(key: &str) {
+        let mut note = note::new("Shopping".into(), "Eggs".into(), Vec::new(), 1);
+        note.meta.insert(key.into(), "Office".into());
+        let parsed = parse_note(&render_note(&note).unwrap()).unwrap();
+        assert!(parsed == note, "{}", key);
+    }
+
+    #[test]
+    fn keeps_meta_named_expiry() {
+        assert_meta_round_trips("expiry");
+    }
+
+    #[test]
+    fn keeps_meta_named_location() {
+        assert_meta_round_trips("location");
+    }
+}
@@ -0,0 +1,98 @@
+use crate::error::NotesError;
+use rusqlite::Connection;
+
+/// Schema migrations, applied in order. The database's `user_version` pragma
+/// records how many have run, so only append to this list.
+const MIGRATIONS: &[&str] = &[
+    // 1: initial notes table
+    "CREATE TABLE notes (
+        id TEXT PRIMARY KEY NOT NULL,
+        title TEXT NOT NULL,
+        content TEXT NOT NULL,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE INDEX notes_updated_at ON notes (updated_at);",
+    // 2: tags
+    "CREATE TABLE note_tags (
+        note_id TEXT NOT NULL REFERENCES notes (id) ON DELETE CASCADE,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (note_id, tag)
+    );
+    CREATE INDEX note_tags_tag ON note_tags (tag);",
+    // 3: trash
+    "ALTER TABLE notes ADD COLUMN trashed_at INTEGER;
+    CREATE INDEX notes_trashed_at ON notes (trashed_at);",
+    // 4: pinning and archiving
+    "ALTER TABLE notes ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE notes ADD COLUMN archived INTEGER NOT NULL DEFAULT 0;",
+    // 5: attachments
+    "CREATE TABLE note_attachments (
+        note_id TEXT NOT NULL REFERENCES notes (id) ON DELETE CASCADE,
+        id TEXT NOT NULL,
+        name TEXT NOT NULL,
+        mime TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        added_at INTEGER NOT NULL,
+        PRIMARY KEY (note_id, id)
+    );",
+    // 6: reminders
+    "CREATE TABLE note_reminders (
+        note_id TEXT NOT NULL REFERENCES notes (id) ON DELETE CASCADE,
+        id TEXT NOT NULL,
+        due_at INTEGER NOT NULL,
+        repeat TEXT NOT NULL,
+        PRIMARY KEY (note_id, id)
+    );",
+    // 7: favorites and color labels
+    "ALTER TABLE notes ADD COLUMN favorite INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE notes ADD COLUMN color TEXT;",
+    // 8: notebooks
+    "ALTER TABLE notes ADD COLUMN notebook_id TEXT;",
+    // 9: notes locked with a password of their own
+    "ALTER TABLE notes ADD COLUMN locked INTEGER NOT NULL DEFAULT 0;",
+    // 10: a hash of each note's content, to find duplicates by
+    "ALTER TABLE notes ADD COLUMN content_hash TEXT NOT NULL DEFAULT '';",
+    // 11: text read from image attachments
+    "ALTER TABLE note_attachments ADD COLUMN text TEXT;",
+    // 12: metadata fields
+    "CREATE TABLE note_meta (
+        note_id TEXT NOT NULL REFERENCES notes (id) ON DELETE CASCADE,
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        PRIMARY KEY (note_id, key)
+    );",
+    // 13: expiry times
+    "ALTER TABLE notes ADD COLUMN expires_at INTEGER;
+     ALTER TABLE notes ADD COLUMN expiry_action TEXT;",
+    // 14: where notes were written
+    "ALTER TABLE notes ADD COLUMN latitude REAL;
+     ALTER TABLE notes ADD COLUMN longitude REAL;",
+];
+
+pub fn run(conn: &mut Connection) -> Result<(), NotesError> {
+    let version: usize = conn
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .map_err(|e| NotesError::Database(format!("Failed to read schema version: {}", e)))?;
+
+    if version > MIGRATIONS.len() {
+        return Err(NotesError::Database(format!(
+            "Database schema version {} is newer than this app supports",
+            version
+        )));
+    }
+
+    for (index, sql) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn
+            .transaction()
+            .map_err(|e| NotesError::Database(format!("Failed to start migration: {}", e)))?;
+        tx.execute_batch(sql).map_err(|e| {
+            NotesError::Database(format!("Failed to run migration {}: {}", index + 1, e))
+        })?;
+        tx.pragma_update(None, "user_version", index + 1)
+            .map_err(|e| NotesError::Database(format!("Failed to record schema version: {}", e)))?;
+        tx.commit()
+            .map_err(|e| NotesError::Database(format!("Failed to commit migration: {}", e)))?;
+    }
+    Ok(())
+}
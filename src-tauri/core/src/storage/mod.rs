@@ -0,0 +1,1016 @@
+//! Note storage: pluggable on-disk backends plus the encryption shared by all
+//! of them.
+
+mod attachments;
+mod compress;
+mod git;
+mod history;
+mod journal;
+mod json;
+mod legacy;
+mod lock;
+mod markdown;
+mod migrations;
+mod sharded;
+mod sqlite;
+
+use crate::error::NotesError;
+use crate::fsutil;
+use crate::note::{self, Attachment, Note};
+use crate::vault::{self, Encryption};
+use attachments::Blobs;
+use history::History;
+use journal::{Entry, Journal};
+use json::JsonBackend;
+use markdown::MarkdownBackend;
+use serde::{Deserialize, Serialize};
+use sharded::ShardedBackend;
+use sqlite::SqliteBackend;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+pub use attachments::hash as attachment_hash;
+pub use git::{GitBackend, GitLogEntry};
+pub use history::{unified_diff, Revision};
+pub use journal::JOURNAL_FILE;
+pub use legacy::{import_notes_json, salvage_notes, set_aside};
+pub use markdown::{parse_note as parse_markdown_note, render_note as render_markdown_note};
+
+/// Persists notes exactly as it is handed them. Backends know nothing about
+/// encryption or trash; `Storage` layers those on top. Searching is left to
+/// the search index, since with the vault enabled a backend only ever sees
+/// ciphertext.
+///
+/// Adding a backend takes an implementation of this trait and a
+/// `BackendKind` to select it with.
+pub trait NotesBackend: Send {
+    /// Loads every stored note, including trashed ones, oldest first.
+    fn load_all(&self) -> Result<Vec<Note>, NotesError>;
+    /// Returns the ids of every stored note.
+    fn list(&self) -> Result<Vec<String>, NotesError> {
+        Ok(self.load_all()?.into_iter().map(|note| note.id).collect())
+    }
+    /// Inserts `notes`, replacing stored notes with the same ids.
+    fn save(&mut self, notes: &[Note]) -> Result<(), NotesError>;
+    /// Deletes the notes with `ids`, returning how many existed.
+    fn delete(&mut self, ids: &[String]) -> Result<usize, NotesError>;
+    /// Gives back the space left over by rewritten and deleted notes, for
+    /// backends that hold on to it.
+    fn compact(&mut self) -> Result<(), NotesError> {
+        Ok(())
+    }
+
+    /// Returns the git repository holding the notes, for backends that keep
+    /// one.
+    fn as_git(&mut self) -> Option<&mut GitBackend> {
+        None
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// A single notes.db SQLite database.
+    #[default]
+    Sqlite,
+    /// One Markdown file per note under notes/.
+    Markdown,
+    /// Markdown files in a git repository under notes-git/, committed on
+    /// every change.
+    Git,
+    /// A single notes-store.json file.
+    Json,
+    /// JSON files under notes-shards/, one for each month notes were
+    /// created in.
+    Sharded,
+}
+
+impl BackendKind {
+    fn open(self, data_dir: &Path) -> Result<Box<dyn NotesBackend>, NotesError> {
+        let location = self.location(data_dir);
+        Ok(match self {
+            BackendKind::Sqlite => Box::new(SqliteBackend::open(&location)?),
+            BackendKind::Markdown => Box::new(MarkdownBackend::open(&location)?),
+            BackendKind::Git => Box::new(GitBackend::open(&location)?),
+            BackendKind::Json => Box::new(JsonBackend::open(&location)?),
+            BackendKind::Sharded => Box::new(ShardedBackend::open(&location)?),
+        })
+    }
+
+    /// The database file or directory holding the notes.
+    pub fn location(self, data_dir: &Path) -> PathBuf {
+        data_dir.join(match self {
+            BackendKind::Sqlite => "notes.db",
+            BackendKind::Markdown => "notes",
+            BackendKind::Git => "notes-git",
+            BackendKind::Json => "notes-store.json",
+            BackendKind::Sharded => "notes-shards",
+        })
+    }
+
+    /// Where attachment blobs are kept. The git backend keeps them inside its
+    /// repository, so they're pushed and pulled along with the notes.
+    pub fn attachments_dir(self, data_dir: &Path) -> PathBuf {
+        match self {
+            BackendKind::Git => data_dir.join("notes-git").join("attachments"),
+            BackendKind::Sqlite
+            | BackendKind::Markdown
+            | BackendKind::Json
+            | BackendKind::Sharded => data_dir.join("attachments"),
+        }
+    }
+}
+
+/// Contents of storage.json, which records the backend in use.
+#[derive(Serialize, Deserialize, Default)]
+struct StorageConfig {
+    backend: BackendKind,
+}
+
+fn config_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("storage.json")
+}
+
+/// Where `verify_integrity` moves corrupted notes, one `<id>.json` each,
+/// exactly as the backend stored them.
+pub const QUARANTINE_DIR: &str = "quarantine";
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrityProblem {
+    /// The content no longer matches the hash saved with it.
+    HashMismatch,
+    /// The note couldn't be decrypted.
+    Unreadable,
+}
+
+#[derive(Serialize, Debug)]
+pub struct IntegrityIssue {
+    pub id: String,
+    /// The note's title, unless it couldn't be read.
+    pub title: Option<String>,
+    pub problem: IntegrityProblem,
+    pub error: Option<NotesError>,
+    pub quarantined: bool,
+}
+
+#[derive(Serialize, Default, Debug)]
+pub struct IntegrityReport {
+    pub checked: usize,
+    pub issues: Vec<IntegrityIssue>,
+}
+
+/// The space a note takes up as stored, compressed and encrypted if it is.
+#[derive(Serialize, Debug)]
+pub struct NoteUsage {
+    pub id: String,
+    /// `None` while the vault is locked.
+    pub title: Option<String>,
+    /// The stored title and content.
+    pub bytes: u64,
+    /// The note's attachments, some of which other notes may share.
+    pub attachment_bytes: u64,
+}
+
+/// What the notes take up on disk.
+#[derive(Serialize, Debug)]
+pub struct DiskUsage {
+    /// The backend's database or files, without the attachments.
+    pub notes_bytes: u64,
+    pub history_bytes: u64,
+    pub attachments_bytes: u64,
+    /// Every stored note, trashed ones included.
+    pub notes: Vec<NoteUsage>,
+}
+
+impl IntegrityReport {
+    pub fn quarantined(&self) -> usize {
+        self.issues.iter().filter(|issue| issue.quarantined).count()
+    }
+}
+
+pub struct Storage {
+    data_dir: PathBuf,
+    kind: BackendKind,
+    backend: Box<dyn NotesBackend>,
+    journal: Journal,
+    history: History,
+    blobs: Blobs,
+    encryption: Encryption,
+    /// Whether note contents are compressed when they're written.
+    compress: bool,
+}
+
+impl Storage {
+    /// Opens the backend recorded in `data_dir`'s storage.json, defaulting to
+    /// SQLite, and finishes any write a crash cut short.
+    pub fn open(data_dir: &Path) -> Result<Self, NotesError> {
+        let config_path = config_path(data_dir);
+        let config: StorageConfig = if config_path.exists() {
+            fsutil::read_with_backup(&config_path, |content| {
+                serde_json::from_str(content).map_err(|e| {
+                    NotesError::Serde(format!("Failed to parse storage config: {}", e))
+                })
+            })?
+        } else {
+            StorageConfig::default()
+        };
+
+        let shared = lock::shared(data_dir, lock::WAIT)?;
+        let backend = config.backend.open(data_dir)?;
+        let blobs = Blobs::open(&config.backend.attachments_dir(data_dir))?;
+        // Versions that kept the git backend's blobs outside the repository
+        let outside = BackendKind::Sqlite.attachments_dir(data_dir);
+        if config.backend == BackendKind::Git && outside.exists() {
+            Blobs::open(&outside)?.copy_into(&blobs)?;
+        }
+        drop(shared);
+
+        let mut storage = Storage {
+            data_dir: data_dir.to_path_buf(),
+            kind: config.backend,
+            backend,
+            journal: Journal::open(data_dir),
+            history: History::open(&data_dir.join("history"))?,
+            blobs,
+            encryption: Encryption::Disabled,
+            compress: false,
+        };
+        storage.replay_journal()?;
+        Ok(storage)
+    }
+
+    /// Hands the backend the writes left in the journal, by this or another
+    /// program, that it may not have finished.
+    fn replay_journal(&mut self) -> Result<(), NotesError> {
+        if self.journal.pending()?.is_empty() {
+            return Ok(());
+        }
+        let _lock = lock::exclusive(&self.data_dir, lock::WAIT)?;
+        // Read again, in case another program replayed them in the meantime
+        let pending = self.journal.pending()?;
+        for entry in &pending {
+            self.apply(entry)?;
+        }
+        if !pending.is_empty() {
+            warn!("Finished {} interrupted writes to the notes", pending.len());
+        }
+        self.journal.clear()
+    }
+
+    /// Writes `entry` to the journal, then to the backend, returning how many
+    /// notes the backend saved or deleted. The exclusive lock must be held.
+    fn journaled(&mut self, entry: Entry) -> Result<usize, NotesError> {
+        self.journal.append(&entry)?;
+        // A write that failed was reported as such, and isn't retried later
+        let applied = self.apply(&entry);
+        self.journal.clear()?;
+        applied
+    }
+
+    fn apply(&mut self, entry: &Entry) -> Result<usize, NotesError> {
+        match entry {
+            Entry::Save { notes } => {
+                self.backend.save(notes)?;
+                Ok(notes.len())
+            }
+            Entry::Delete { ids } => {
+                let deleted = self.backend.delete(ids)?;
+                for id in ids.iter() {
+                    self.history.remove(id)?;
+                }
+                Ok(deleted)
+            }
+        }
+    }
+
+    pub fn backend_kind(&self) -> BackendKind {
+        self.kind
+    }
+
+    /// Copies every note and attachment into the `kind` backend and makes it
+    /// the active one.
+    /// The previous backend's files are left in place. Returns the number of
+    /// notes copied.
+    pub fn switch_backend(&mut self, kind: BackendKind) -> Result<usize, NotesError> {
+        if kind == self.kind {
+            return Ok(0);
+        }
+        let _lock = lock::exclusive(&self.data_dir, lock::WAIT)?;
+        let blobs = Blobs::open(&kind.attachments_dir(&self.data_dir))?;
+        self.blobs.copy_into(&blobs)?;
+        let mut backend = kind.open(&self.data_dir)?;
+        let notes = self.backend.load_all()?;
+        backend.save(&notes)?;
+
+        let json = serde_json::to_string_pretty(&StorageConfig { backend: kind })
+            .map_err(|e| NotesError::Serde(format!("Failed to serialize storage config: {}", e)))?;
+        fsutil::write_with_backup(&config_path(&self.data_dir), json.as_bytes())
+            .map_err(|e| NotesError::Io(format!("Failed to write storage config: {}", e)))?;
+
+        self.backend = backend;
+        self.blobs = blobs;
+        self.kind = kind;
+        Ok(notes.len())
+    }
+
+    /// Opens the backend again, so it sees notes another program added to
+    /// its files.
+    pub fn reopen(&mut self) -> Result<(), NotesError> {
+        {
+            let _lock = lock::shared(&self.data_dir, lock::WAIT)?;
+            self.backend = self.kind.open(&self.data_dir)?;
+        }
+        self.replay_journal()
+    }
+
+    pub fn git(&mut self) -> Result<&mut GitBackend, NotesError> {
+        self.backend
+            .as_git()
+            .ok_or_else(|| NotesError::Invalid("Notes aren't stored in git".into()))
+    }
+
+    /// Sets how note titles, contents, tags, and attachments are encrypted.
+    /// Ids, timestamps, and flags are always stored in plaintext so trash and
+    /// pins work while the vault is locked.
+    pub fn set_encryption(&mut self, encryption: Encryption) {
+        self.encryption = encryption;
+    }
+
+    /// Sets whether note contents, in the notes and their history, are
+    /// compressed from now on. Compressed contents are read either way.
+    pub fn set_compression(&mut self, compress: bool) {
+        self.compress = compress;
+    }
+
+    pub fn cipher(&self) -> Option<&vault::Cipher> {
+        match &self.encryption {
+            Encryption::Unlocked(cipher) => Some(cipher),
+            _ => None,
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        matches!(self.encryption, Encryption::Locked)
+    }
+
+    /// Loads and decrypts every stored note, including trashed ones, oldest
+    /// first. Notes that can't be decrypted are left out rather than failing
+    /// the whole load, until `verify_integrity` reports or quarantines them.
+    pub fn load_all(&self) -> Result<Vec<Note>, NotesError> {
+        let _lock = lock::shared(&self.data_dir, lock::WAIT)?;
+        if self.is_locked() {
+            return self.load_locked();
+        }
+        let mut notes = Vec::new();
+        for stored in self.backend.load_all()? {
+            let id = stored.id.clone();
+            match open_note(&self.encryption, stored) {
+                Ok(note) => notes.push(note),
+                Err(e) => warn!("Skipping unreadable note {}: {}", id, e),
+            }
+        }
+        Ok(notes)
+    }
+
+    fn load_locked(&self) -> Result<Vec<Note>, NotesError> {
+        self.backend
+            .load_all()?
+            .into_iter()
+            .map(|note| open_note(&self.encryption, note))
+            .collect()
+    }
+
+    /// Inserts `notes`, replacing stored notes with the same ids.
+    pub fn save_notes(&mut self, notes: &[Note]) -> Result<(), NotesError> {
+        let sealed = notes
+            .iter()
+            .map(|note| seal_note(&self.encryption, self.compress, note))
+            .collect::<Result<Vec<Note>, NotesError>>()?;
+        if sealed.is_empty() {
+            return Ok(());
+        }
+        let _lock = lock::exclusive(&self.data_dir, lock::WAIT)?;
+        self.journaled(Entry::Save {
+            notes: Cow::Owned(sealed),
+        })?;
+        Ok(())
+    }
+
+    /// Deletes the notes with `ids` along with their history, returning how
+    /// many existed.
+    pub fn delete_notes(&mut self, ids: &[String]) -> Result<usize, NotesError> {
+        let _lock = lock::exclusive(&self.data_dir, lock::WAIT)?;
+        self.delete_locked(ids)
+    }
+
+    fn delete_locked(&mut self, ids: &[String]) -> Result<usize, NotesError> {
+        let deleted = self.journaled(Entry::Delete {
+            ids: Cow::Borrowed(ids),
+        })?;
+        // The notes are gone either way, so don't report a failed cleanup
+        if let Err(e) = self.collect_garbage_locked() {
+            warn!("Failed to clean up attachments: {}", e);
+        }
+        Ok(deleted)
+    }
+
+    /// Returns the name of the blob holding attachment `id`. With the vault
+    /// enabled that's a keyed hash, so blob names don't reveal the SHA-256 of
+    /// their content.
+    fn blob_name(&self, id: &str) -> Result<String, NotesError> {
+        match &self.encryption {
+            Encryption::Disabled => Ok(id.to_string()),
+            Encryption::Locked => Err(NotesError::Locked),
+            Encryption::Unlocked(cipher) => Ok(cipher.keyed_hash(id.as_bytes())),
+        }
+    }
+
+    /// Stores `content` as an attachment blob and returns its id, the SHA-256
+    /// of the content.
+    pub fn put_attachment(&self, content: &[u8]) -> Result<String, NotesError> {
+        let id = attachments::hash(content);
+        let name = self.blob_name(&id)?;
+        if !self.blobs.contains(&name)? {
+            self.blobs
+                .write(&name, &seal_bytes(&self.encryption, content)?)?;
+        }
+        Ok(id)
+    }
+
+    pub fn read_attachment(&self, id: &str) -> Result<Vec<u8>, NotesError> {
+        open_bytes(&self.encryption, self.blobs.read(&self.blob_name(id)?)?)
+    }
+
+    pub fn has_attachment(&self, id: &str) -> Result<bool, NotesError> {
+        self.blobs.contains(&self.blob_name(id)?)
+    }
+
+    /// Deletes attachment blobs that no stored note refers to, returning how
+    /// many were removed. Blob names and attachment ids are encrypted while
+    /// the vault is locked, so nothing is removed then.
+    pub fn collect_garbage(&self) -> Result<usize, NotesError> {
+        let _lock = lock::shared(&self.data_dir, lock::WAIT)?;
+        self.collect_garbage_locked()
+    }
+
+    fn collect_garbage_locked(&self) -> Result<usize, NotesError> {
+        if self.is_locked() {
+            return Ok(0);
+        }
+        let notes = self.load_locked()?;
+        let referenced = notes
+            .iter()
+            .flat_map(|note| &note.attachments)
+            .map(|attachment| self.blob_name(&attachment.id))
+            .collect::<Result<HashSet<String>, NotesError>>()?;
+        self.blobs.collect_garbage(&referenced)
+    }
+
+    /// Encrypts the attachment blobs of `notes` and renames them after their
+    /// keyed hash, for blobs stored before the vault was enabled or by
+    /// versions that named them by id regardless.
+    pub fn reseal_attachments(&self, notes: &[Note]) -> Result<(), NotesError> {
+        for attachment in notes.iter().flat_map(|note| &note.attachments) {
+            let name = self.blob_name(&attachment.id)?;
+            if name == attachment.id
+                || self.blobs.contains(&name)?
+                || !self.blobs.contains(&attachment.id)?
+            {
+                continue;
+            }
+            let stored = self.blobs.read(&attachment.id)?;
+            let sealed = if vault::is_encrypted_bytes(&stored) {
+                stored
+            } else {
+                seal_bytes(&self.encryption, &stored)?
+            };
+            self.blobs.write(&name, &sealed)?;
+            self.blobs.remove(&attachment.id)?;
+        }
+        Ok(())
+    }
+
+    /// Re-saves notes whose tags or attachment details are still in
+    /// plaintext, as versions that only encrypted titles and contents left
+    /// them.
+    pub fn seal_legacy_fields(&mut self) -> Result<(), NotesError> {
+        if self.cipher().is_none() {
+            return Ok(());
+        }
+        let stale = self
+            .backend
+            .load_all()?
+            .into_iter()
+            .filter(|note| {
+                note.tags.iter().any(|tag| !vault::is_encrypted(tag))
+                    || note.attachments.iter().any(|attachment| {
+                        [&attachment.id, &attachment.name, &attachment.mime]
+                            .iter()
+                            .any(|field| !vault::is_encrypted(field))
+                    })
+            })
+            .map(|note| open_note(&self.encryption, note))
+            .collect::<Result<Vec<Note>, NotesError>>()?;
+        self.save_notes(&stale)
+    }
+
+    /// Rewrites every note and revision to match the compression setting,
+    /// then has the backend give back the space freed. Returns how many notes
+    /// were rewritten.
+    pub fn compact(&mut self) -> Result<usize, NotesError> {
+        if self.is_locked() {
+            return Err(NotesError::Locked);
+        }
+        let notes = self.load_all()?;
+        self.save_notes(&notes)?;
+        self.reseal_history()?;
+        let _lock = lock::exclusive(&self.data_dir, lock::WAIT)?;
+        self.backend.compact()?;
+        Ok(notes.len())
+    }
+
+    /// Measures what the notes, their history and their attachments take up
+    /// on disk. This works while the vault is locked, without the titles.
+    pub fn disk_usage(&self) -> Result<DiskUsage, NotesError> {
+        let _lock = lock::shared(&self.data_dir, lock::WAIT)?;
+        let location = self.kind.location(&self.data_dir);
+        let attachments_bytes = fsutil::size_on_disk(&self.kind.attachments_dir(&self.data_dir));
+        let mut notes_bytes = fsutil::size_on_disk(&location);
+        match self.kind {
+            BackendKind::Sqlite => {
+                notes_bytes += fsutil::size_on_disk(&fsutil::with_suffix(&location, "-wal"))
+            }
+            BackendKind::Git => notes_bytes = notes_bytes.saturating_sub(attachments_bytes),
+            BackendKind::Markdown | BackendKind::Json | BackendKind::Sharded => {}
+        }
+        let notes = self
+            .backend
+            .load_all()?
+            .into_iter()
+            .map(|stored| NoteUsage {
+                bytes: (stored.title.len() + stored.content.len()) as u64,
+                attachment_bytes: stored.attachments.iter().map(|a| a.size).sum(),
+                title: open_text(&self.encryption, stored.title).ok(),
+                id: stored.id,
+            })
+            .collect();
+        Ok(DiskUsage {
+            notes_bytes,
+            history_bytes: fsutil::size_on_disk(&self.data_dir.join("history")),
+            attachments_bytes,
+            notes,
+        })
+    }
+
+    /// Re-hashes the content of every stored note, reporting notes whose
+    /// content doesn't match its hash and notes that can't be decrypted. With
+    /// `quarantine`, those are also moved out of the backend into
+    /// `QUARANTINE_DIR`, keeping their history.
+    pub fn verify_integrity(&mut self, quarantine: bool) -> Result<IntegrityReport, NotesError> {
+        if self.is_locked() {
+            return Err(NotesError::Locked);
+        }
+        let _lock = lock::exclusive(&self.data_dir, lock::WAIT)?;
+        let mut report = IntegrityReport::default();
+        let mut corrupted = Vec::new();
+        for stored in self.backend.load_all()? {
+            report.checked += 1;
+            let (problem, title, error) = match open_note(&self.encryption, stored.clone()) {
+                Ok(note)
+                    if note.content_hash.is_empty()
+                        || note.content_hash == note::content_hash(&note.content) =>
+                {
+                    continue
+                }
+                Ok(note) => (IntegrityProblem::HashMismatch, Some(note.title), None),
+                Err(e) => (IntegrityProblem::Unreadable, None, Some(e)),
+            };
+            if quarantine {
+                self.quarantine(&stored)?;
+                corrupted.push(stored.id.clone());
+            }
+            report.issues.push(IntegrityIssue {
+                id: stored.id,
+                title,
+                problem,
+                error,
+                quarantined: quarantine,
+            });
+        }
+        self.backend.delete(&corrupted)?;
+        Ok(report)
+    }
+
+    /// Saves a copy of `stored`, as the backend holds it, to the quarantine
+    /// directory.
+    fn quarantine(&self, stored: &Note) -> Result<(), NotesError> {
+        let failed = |e: io::Error| NotesError::Io(format!("Failed to quarantine note: {}", e));
+        let dir = self.data_dir.join(QUARANTINE_DIR);
+        fs::create_dir_all(&dir).map_err(failed)?;
+        let json = serde_json::to_vec_pretty(stored)
+            .map_err(|e| NotesError::Serde(format!("Failed to serialize note: {}", e)))?;
+        fsutil::write_atomic(&dir.join(format!("{}.json", stored.id)), &json).map_err(failed)
+    }
+
+    /// Adds the current title and content of `note` to its history.
+    pub fn record_revision(&self, note: &Note) -> Result<(), NotesError> {
+        self.history.push(
+            &note.id,
+            seal_text(&self.encryption, &note.title)?,
+            seal_text(&self.encryption, &self.pack(&note.content))?,
+            note.updated_at,
+        )
+    }
+
+    /// Forgets every revision of note `id`.
+    pub fn clear_history(&self, id: &str) -> Result<(), NotesError> {
+        self.history.remove(id)
+    }
+
+    /// Returns when each revision of note `id` was saved, without decrypting
+    /// them.
+    pub fn revision_times(&self, id: &str) -> Result<Vec<i64>, NotesError> {
+        Ok(self
+            .history
+            .load(id)?
+            .into_iter()
+            .map(|revision| revision.saved_at)
+            .collect())
+    }
+
+    /// Loads and decrypts the history of note `id`, oldest first.
+    pub fn revisions(&self, id: &str) -> Result<Vec<Revision>, NotesError> {
+        self.history
+            .load(id)?
+            .into_iter()
+            .map(|revision| {
+                Ok(Revision {
+                    title: open_text(&self.encryption, revision.title)?,
+                    content: compress::unpack(open_text(&self.encryption, revision.content)?),
+                    ..revision
+                })
+            })
+            .collect()
+    }
+
+    fn pack(&self, content: &str) -> String {
+        if self.compress {
+            compress::pack(content)
+        } else {
+            content.to_string()
+        }
+    }
+
+    /// Rewrites every note's history so that it matches the current encryption
+    /// and compression settings.
+    pub fn reseal_history(&self) -> Result<(), NotesError> {
+        for id in self.history.note_ids()? {
+            let sealed = self
+                .revisions(&id)?
+                .into_iter()
+                .map(|revision| {
+                    Ok(Revision {
+                        title: seal_text(&self.encryption, &revision.title)?,
+                        content: seal_text(&self.encryption, &self.pack(&revision.content))?,
+                        ..revision
+                    })
+                })
+                .collect::<Result<Vec<Revision>, NotesError>>()?;
+            self.history.save(&id, &sealed)?;
+        }
+        Ok(())
+    }
+
+    /// Inserts many notes at once. Notes whose id is already stored are left
+    /// untouched.
+    pub fn insert_notes(&mut self, notes: &[Note]) -> Result<(), NotesError> {
+        let _lock = lock::exclusive(&self.data_dir, lock::WAIT)?;
+        let existing: HashSet<String> = self.backend.list()?.into_iter().collect();
+        let sealed = notes
+            .iter()
+            .filter(|note| !existing.contains(&note.id))
+            .map(|note| seal_note(&self.encryption, self.compress, note))
+            .collect::<Result<Vec<Note>, NotesError>>()?;
+        if sealed.is_empty() {
+            return Ok(());
+        }
+        self.journaled(Entry::Save {
+            notes: Cow::Owned(sealed),
+        })?;
+        Ok(())
+    }
+
+    /// Gives notes saved with only a creation time, by versions that didn't
+    /// track edits separately, that as their update time, returning how many
+    /// there were. This only touches plaintext fields, so it works while the
+    /// vault is locked.
+    pub fn fill_updated_at(&mut self) -> Result<usize, NotesError> {
+        let _lock = lock::exclusive(&self.data_dir, lock::WAIT)?;
+        let stale: Vec<Note> = self
+            .backend
+            .load_all()?
+            .into_iter()
+            .filter(|note| note.updated_at == 0)
+            .map(|note| Note {
+                updated_at: note.created_at,
+                ..note
+            })
+            .collect();
+        if stale.is_empty() {
+            return Ok(0);
+        }
+        self.journaled(Entry::Save {
+            notes: Cow::Owned(stale),
+        })
+    }
+
+    /// Permanently deletes notes trashed before `cutoff` (a Unix timestamp),
+    /// returning how many were removed. This only reads plaintext fields, so it
+    /// works while the vault is locked.
+    pub fn purge_trashed_before(&mut self, cutoff: i64) -> Result<usize, NotesError> {
+        let _lock = lock::exclusive(&self.data_dir, lock::WAIT)?;
+        let ids: Vec<String> = self
+            .backend
+            .load_all()?
+            .into_iter()
+            .filter(|note| note.trashed_at.is_some_and(|at| at < cutoff))
+            .map(|note| note.id)
+            .collect();
+        self.delete_locked(&ids)
+    }
+}
+
+/// Returns the copy of `note` that should be handed to the backend. The
+/// sealed content of locked notes wouldn't compress, so it's never tried.
+fn seal_note(encryption: &Encryption, compress: bool, note: &Note) -> Result<Note, NotesError> {
+    let content = if compress && !note.locked {
+        compress::pack(&note.content)
+    } else {
+        note.content.clone()
+    };
+    Ok(Note {
+        title: seal_text(encryption, &note.title)?,
+        content: seal_text(encryption, &content)?,
+        content_hash: seal_text(encryption, &note.content_hash)?,
+        tags: note
+            .tags
+            .iter()
+            .map(|tag| seal_text(encryption, tag))
+            .collect::<Result<_, _>>()?,
+        attachments: note
+            .attachments
+            .iter()
+            .map(|attachment| {
+                Ok(Attachment {
+                    id: seal_text(encryption, &attachment.id)?,
+                    name: seal_text(encryption, &attachment.name)?,
+                    mime: seal_text(encryption, &attachment.mime)?,
+                    text: attachment
+                        .text
+                        .as_deref()
+                        .map(|text| seal_text(encryption, text))
+                        .transpose()?,
+                    ..attachment.clone()
+                })
+            })
+            .collect::<Result<_, NotesError>>()?,
+        meta: note
+            .meta
+            .iter()
+            .map(|(key, value)| Ok((seal_text(encryption, key)?, seal_text(encryption, value)?)))
+            .collect::<Result<_, NotesError>>()?,
+        ..note.clone()
+    })
+}
+
+/// Decrypts a note read from the backend. Plaintext fields, such as those
+/// written before encryption was enabled, pass through unchanged.
+fn open_note(encryption: &Encryption, mut note: Note) -> Result<Note, NotesError> {
+    note.title = open_text(encryption, note.title)?;
+    note.content = compress::unpack(open_text(encryption, note.content)?);
+    note.content_hash = open_text(encryption, note.content_hash)?;
+    note.tags = note
+        .tags
+        .into_iter()
+        .map(|tag| open_text(encryption, tag))
+        .collect::<Result<_, _>>()?;
+    // Sealed tags sort by their ciphertext
+    note.tags.sort();
+    for attachment in &mut note.attachments {
+        attachment.id = open_text(encryption, std::mem::take(&mut attachment.id))?;
+        attachment.name = open_text(encryption, std::mem::take(&mut attachment.name))?;
+        attachment.mime = open_text(encryption, std::mem::take(&mut attachment.mime))?;
+        attachment.text = attachment
+            .text
+            .take()
+            .map(|text| open_text(encryption, text))
+            .transpose()?;
+    }
+    note.meta = std::mem::take(&mut note.meta)
+        .into_iter()
+        .map(|(key, value)| Ok((open_text(encryption, key)?, open_text(encryption, value)?)))
+        .collect::<Result<_, NotesError>>()?;
+    Ok(note)
+}
+
+fn seal_text(encryption: &Encryption, text: &str) -> Result<String, NotesError> {
+    match encryption {
+        Encryption::Disabled => Ok(text.to_string()),
+        Encryption::Locked => Err(NotesError::Locked),
+        Encryption::Unlocked(cipher) => Ok(cipher.encrypt_str(text)),
+    }
+}
+
+fn open_text(encryption: &Encryption, text: String) -> Result<String, NotesError> {
+    match encryption {
+        Encryption::Unlocked(cipher) => cipher.decrypt_str(&text),
+        _ if vault::is_encrypted(&text) => Err(NotesError::Locked),
+        _ => Ok(text),
+    }
+}
+
+fn seal_bytes(encryption: &Encryption, content: &[u8]) -> Result<Vec<u8>, NotesError> {
+    match encryption {
+        Encryption::Disabled => Ok(content.to_vec()),
+        Encryption::Locked => Err(NotesError::Locked),
+        Encryption::Unlocked(cipher) => Ok(cipher.encrypt_bytes(content)),
+    }
+}
+
+fn open_bytes(encryption: &Encryption, stored: Vec<u8>) -> Result<Vec<u8>, NotesError> {
+    match encryption {
+        Encryption::Unlocked(cipher) => cipher.decrypt_bytes(&stored),
+        _ if vault::is_encrypted_bytes(&stored) => Err(NotesError::Locked),
+        _ => Ok(stored),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expiry::{Expiry, ExpiryAction};
+    use crate::geo::Location;
+    use std::collections::BTreeMap;
+    use tempfile::TempDir;
+
+    fn note(id: &str, created_at: i64) -> Note {
+        Note {
+            id: id.into(),
+            title: format!("Note {}", id),
+            content: "Body".into(),
+            created_at,
+            updated_at: created_at,
+            tags: vec!["tag".into()],
+            trashed_at: None,
+            pinned: false,
+            archived: false,
+            favorite: true,
+            locked: false,
+            content_hash: String::new(),
+            color: Some("#3366cc".into()),
+            notebook_id: None,
+            attachments: Vec::new(),
+            reminders: Vec::new(),
+            expiry: None,
+            location: None,
+            meta: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn every_backend_stores_notes() {
+        for kind in [
+            BackendKind::Sqlite,
+            BackendKind::Markdown,
+            BackendKind::Git,
+            BackendKind::Json,
+            BackendKind::Sharded,
+        ] {
+            let dir = TempDir::new().unwrap();
+            let mut backend = kind.open(dir.path()).unwrap();
+            backend.save(&[note("b", 2), note("a", 1)]).unwrap();
+            let mut edited = note("b", 2);
+            edited.content = "Edited".into();
+            edited.expiry = Some(Expiry {
+                at: 10,
+                action: ExpiryAction::Delete,
+            });
+            edited.location = Some(Location {
+                latitude: 51.5,
+                longitude: -0.125,
+            });
+            backend.save(&[edited.clone()]).unwrap();
+
+            let mut ids = backend.list().unwrap();
+            ids.sort();
+            assert_eq!(ids, ["a", "b"]);
+            // Reopened, as another process would see them
+            let backend = &mut kind.open(dir.path()).unwrap();
+            assert!(backend.load_all().unwrap() == [note("a", 1), edited]);
+            assert_eq!(backend.delete(&["a".into(), "c".into()]).unwrap(), 1);
+            assert_eq!(backend.list().unwrap(), ["b"]);
+        }
+    }
+
+    #[test]
+    fn compacts_contents_at_rest() {
+        let dir = TempDir::new().unwrap();
+        let mut storage = Storage::open(dir.path()).unwrap();
+        let mut log = note("a", 1);
+        log.content = "GET /notes 200\n".repeat(500);
+        storage.save_notes(std::slice::from_ref(&log)).unwrap();
+        storage.record_revision(&log).unwrap();
+
+        storage.set_compression(true);
+        assert_eq!(storage.compact().unwrap(), 1);
+        let stored = storage.backend.load_all().unwrap();
+        assert!(stored[0].content.len() < log.content.len() / 10);
+        assert!(storage.load_all().unwrap() == [log.clone()]);
+        assert_eq!(storage.revisions("a").unwrap()[0].content, log.content);
+    }
+
+    #[test]
+    fn finishes_interrupted_writes() {
+        let dir = TempDir::new().unwrap();
+        let mut storage = Storage::open(dir.path()).unwrap();
+        storage.save_notes(&[note("a", 1), note("b", 2)]).unwrap();
+        assert!(!dir.path().join(JOURNAL_FILE).exists());
+
+        // A crash after journaling these but before the backend saw them
+        let journal = Journal::open(dir.path());
+        journal
+            .append(&Entry::Save {
+                notes: Cow::Owned(vec![note("c", 3)]),
+            })
+            .unwrap();
+        journal
+            .append(&Entry::Delete {
+                ids: Cow::Owned(vec!["a".into()]),
+            })
+            .unwrap();
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(dir.path().join(JOURNAL_FILE))
+            .unwrap();
+        io::Write::write_all(&mut file, b"{\"op\":\"save\",\"no").unwrap();
+
+        let storage = Storage::open(dir.path()).unwrap();
+        let ids: Vec<String> = storage
+            .load_all()
+            .unwrap()
+            .into_iter()
+            .map(|n| n.id)
+            .collect();
+        assert_eq!(ids, ["b", "c"]);
+        assert!(!dir.path().join(JOURNAL_FILE).exists());
+    }
+
+    #[test]
+    fn reports_and_quarantines_corrupted_notes() {
+        let dir = TempDir::new().unwrap();
+        let mut storage = Storage::open(dir.path()).unwrap();
+        let mut intact = note("a", 1);
+        intact.content_hash = note::content_hash(&intact.content);
+        let mut changed = note("b", 2);
+        changed.content_hash = note::content_hash("Before");
+        let mut sealed = note("c", 3);
+        sealed.title = "enc1:damaged".into();
+        storage.save_notes(&[intact, changed, sealed]).unwrap();
+        // Loading passes over the note it can't read
+        let ids = |storage: &Storage| -> Vec<String> {
+            storage
+                .load_all()
+                .unwrap()
+                .into_iter()
+                .map(|note| note.id)
+                .collect()
+        };
+        assert_eq!(ids(&storage), ["a", "b"]);
+
+        let report = storage.verify_integrity(false).unwrap();
+        assert_eq!(report.checked, 3);
+        let problems: Vec<(&str, IntegrityProblem)> = report
+            .issues
+            .iter()
+            .map(|issue| (issue.id.as_str(), issue.problem))
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                ("b", IntegrityProblem::HashMismatch),
+                ("c", IntegrityProblem::Unreadable)
+            ]
+        );
+        assert_eq!(report.quarantined(), 0);
+
+        assert_eq!(storage.verify_integrity(true).unwrap().quarantined(), 2);
+        assert_eq!(ids(&storage), ["a"]);
+        assert!(dir.path().join(QUARANTINE_DIR).join("c.json").exists());
+        assert!(storage.verify_integrity(false).unwrap().issues.is_empty());
+    }
+}
@@ -0,0 +1,232 @@
+//! Keeps the notes in JSON files under notes-shards/, one for each month
+//! notes were created in, so saving a note rewrites only the notes of its
+//! month rather than all of them. index.json records which shard holds each
+//! note, so notes are listed and found without reading the shards, and is
+//! rebuilt from them if it goes missing.
+//!
+//! Like the single JSON file, the files are read on every operation rather
+//! than cached, so changes made by another process are never written over.
+
+use super::NotesBackend;
+use crate::error::NotesError;
+use crate::fsutil;
+use crate::note::Note;
+use chrono::DateTime;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const INDEX_FILE: &str = "index.json";
+
+/// The shard holding each note, by id.
+type Index = BTreeMap<String, String>;
+
+pub struct ShardedBackend {
+    dir: PathBuf,
+}
+
+impl ShardedBackend {
+    pub fn open(dir: &Path) -> Result<Self, NotesError> {
+        fs::create_dir_all(dir)
+            .map_err(|e| NotesError::Io(format!("Failed to create notes directory: {}", e)))?;
+        let backend = ShardedBackend {
+            dir: dir.to_path_buf(),
+        };
+        // Fail now rather than on the first read if the index is unusable
+        backend.index()?;
+        Ok(backend)
+    }
+
+    fn index(&self) -> Result<Index, NotesError> {
+        let path = self.dir.join(INDEX_FILE);
+        if path.exists() || fsutil::backup_path(&path).exists() {
+            return fsutil::read_with_backup(&path, |content| {
+                serde_json::from_str(content)
+                    .map_err(|e| NotesError::Serde(format!("Failed to parse notes index: {}", e)))
+            });
+        }
+        let mut index = Index::new();
+        for shard in self.shards()? {
+            for note in self.read(&shard)? {
+                index.insert(note.id, shard.clone());
+            }
+        }
+        Ok(index)
+    }
+
+    fn write_index(&self, index: &Index) -> Result<(), NotesError> {
+        let json = serde_json::to_string_pretty(index)
+            .map_err(|e| NotesError::Serde(format!("Failed to serialize notes index: {}", e)))?;
+        fsutil::write_with_backup(&self.dir.join(INDEX_FILE), json.as_bytes())
+            .map_err(|e| NotesError::Io(format!("Failed to write notes index: {}", e)))
+    }
+
+    /// The names of the shards there are, such as `2024-05`.
+    fn shards(&self) -> Result<Vec<String>, NotesError> {
+        let entries = fs::read_dir(&self.dir)
+            .map_err(|e| NotesError::Io(format!("Failed to read notes directory: {}", e)))?;
+        let mut shards: Vec<String> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                let shard = name.strip_suffix(".json")?;
+                (name != INDEX_FILE).then(|| shard.to_string())
+            })
+            .collect();
+        shards.sort();
+        Ok(shards)
+    }
+
+    fn shard_path(&self, shard: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", shard))
+    }
+
+    fn read(&self, shard: &str) -> Result<Vec<Note>, NotesError> {
+        let path = self.shard_path(shard);
+        if !path.exists() && !fsutil::backup_path(&path).exists() {
+            return Ok(Vec::new());
+        }
+        fsutil::read_with_backup(&path, |content| {
+            serde_json::from_str(content).map_err(|e| {
+                NotesError::Serde(format!("Failed to parse notes shard {}: {}", shard, e))
+            })
+        })
+    }
+
+    /// Writes `notes` as the whole of `shard`, removing it if that's nothing.
+    fn write(&self, shard: &str, notes: &[Note]) -> Result<(), NotesError> {
+        let path = self.shard_path(shard);
+        if notes.is_empty() {
+            if path.exists() {
+                fsutil::remove_with_backup(&path)
+                    .map_err(|e| NotesError::Io(format!("Failed to remove notes shard: {}", e)))?;
+            }
+            return Ok(());
+        }
+        let json = serde_json::to_string_pretty(notes)
+            .map_err(|e| NotesError::Serde(format!("Failed to serialize notes: {}", e)))?;
+        fsutil::write_with_backup(&path, json.as_bytes())
+            .map_err(|e| NotesError::Io(format!("Failed to write notes shard: {}", e)))
+    }
+}
+
+/// Notes are sharded by the month they were created in, which doesn't
+/// change as they're edited.
+fn shard_of(note: &Note) -> String {
+    DateTime::from_timestamp(note.created_at, 0).map_or_else(
+        || "undated".to_string(),
+        |at| at.format("%Y-%m").to_string(),
+    )
+}
+
+impl NotesBackend for ShardedBackend {
+    fn load_all(&self) -> Result<Vec<Note>, NotesError> {
+        let mut notes = Vec::new();
+        for shard in self.shards()? {
+            notes.extend(self.read(&shard)?);
+        }
+        notes.sort_by_key(|note| note.created_at);
+        Ok(notes)
+    }
+
+    fn list(&self) -> Result<Vec<String>, NotesError> {
+        Ok(self.index()?.into_keys().collect())
+    }
+
+    fn save(&mut self, notes: &[Note]) -> Result<(), NotesError> {
+        if notes.is_empty() {
+            return Ok(());
+        }
+        let mut index = self.index()?;
+        let mut saved: BTreeMap<String, Vec<&Note>> = BTreeMap::new();
+        // Notes whose creation time was changed, out of the shard they were in
+        let mut moved: BTreeMap<String, HashSet<&str>> = BTreeMap::new();
+        let mut reindexed = false;
+        for note in notes {
+            let shard = shard_of(note);
+            match index.insert(note.id.clone(), shard.clone()) {
+                Some(old) if old == shard => {}
+                Some(old) => {
+                    moved.entry(old).or_default().insert(&note.id);
+                    reindexed = true;
+                }
+                None => reindexed = true,
+            }
+            saved.entry(shard).or_default().push(note);
+        }
+
+        let shards: HashSet<&String> = saved.keys().chain(moved.keys()).collect();
+        for shard in shards {
+            let mut stored = self.read(shard)?;
+            if let Some(ids) = moved.get(shard) {
+                stored.retain(|note| !ids.contains(note.id.as_str()));
+            }
+            for &note in saved.get(shard).into_iter().flatten() {
+                match stored.iter_mut().find(|stored| stored.id == note.id) {
+                    Some(stored) => *stored = note.clone(),
+                    None => stored.push(note.clone()),
+                }
+            }
+            self.write(shard, &stored)?;
+        }
+        // Edits to notes already indexed leave the index as it was
+        if reindexed {
+            self.write_index(&index)?;
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, ids: &[String]) -> Result<usize, NotesError> {
+        let mut index = self.index()?;
+        let mut by_shard: BTreeMap<String, HashSet<&str>> = BTreeMap::new();
+        for id in ids {
+            if let Some(shard) = index.remove(id) {
+                by_shard.entry(shard).or_default().insert(id);
+            }
+        }
+        let mut deleted = 0;
+        for (shard, ids) in &by_shard {
+            let mut stored = self.read(shard)?;
+            let before = stored.len();
+            stored.retain(|note| !ids.contains(note.id.as_str()));
+            deleted += before - stored.len();
+            self.write(shard, &stored)?;
+        }
+        if !by_shard.is_empty() {
+            self.write_index(&index)?;
+        }
+        Ok(deleted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note;
+    use tempfile::TempDir;
+
+    #[test]
+    fn keeps_notes_in_monthly_shards() {
+        let dir = TempDir::new().unwrap();
+        let mut backend = ShardedBackend::open(dir.path()).unwrap();
+        let new = |title: &str, created_at: i64| {
+            note::new(title.into(), String::new(), Vec::new(), created_at)
+        };
+        // May and June 2024
+        let (may, mut june) = (new("May", 1_715_000_000), new("June", 1_718_000_000));
+        backend.save(&[may.clone(), june.clone()]).unwrap();
+        assert_eq!(backend.shards().unwrap(), ["2024-05", "2024-06"]);
+
+        june.created_at = may.created_at;
+        backend.save(std::slice::from_ref(&june)).unwrap();
+        assert_eq!(backend.shards().unwrap(), ["2024-05"]);
+        assert_eq!(backend.load_all().unwrap().len(), 2);
+
+        fs::remove_file(dir.path().join(INDEX_FILE)).unwrap();
+        fs::remove_file(fsutil::backup_path(&dir.path().join(INDEX_FILE))).unwrap();
+        let mut backend = ShardedBackend::open(dir.path()).unwrap();
+        assert_eq!(backend.list().unwrap().len(), 2);
+        assert_eq!(backend.delete(&[june.id.clone()]).unwrap(), 1);
+        assert_eq!(backend.load_all().unwrap(), [may]);
+    }
+}
@@ -0,0 +1,369 @@
+//! SQLite storage backend.
+
+use super::{migrations, NotesBackend};
+use crate::error::NotesError;
+use crate::expiry::{Expiry, ExpiryAction};
+use crate::geo::Location;
+use crate::note::{Attachment, Note};
+use crate::reminders::{Reminder, Repeat};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, Row};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+pub struct SqliteBackend {
+    conn: Connection,
+}
+
+const NOTE_COLUMNS: &str = "id, title, content, created_at, updated_at, trashed_at, pinned, \
+    archived, favorite, color, notebook_id, locked, content_hash, expires_at, expiry_action, \
+    latitude, longitude";
+
+fn note_from_row(row: &Row) -> rusqlite::Result<Note> {
+    Ok(Note {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        content: row.get(2)?,
+        created_at: row.get(3)?,
+        updated_at: row.get(4)?,
+        tags: Vec::new(),
+        trashed_at: row.get(5)?,
+        pinned: row.get(6)?,
+        archived: row.get(7)?,
+        favorite: row.get(8)?,
+        color: row.get(9)?,
+        notebook_id: row.get(10)?,
+        locked: row.get(11)?,
+        content_hash: row.get(12)?,
+        attachments: Vec::new(),
+        reminders: Vec::new(),
+        expiry: expiry_from_row(row)?,
+        location: match (row.get(15)?, row.get(16)?) {
+            (Some(latitude), Some(longitude)) => Some(Location {
+                latitude,
+                longitude,
+            }),
+            _ => None,
+        },
+        meta: BTreeMap::new(),
+    })
+}
+
+fn expiry_from_row(row: &Row) -> rusqlite::Result<Option<Expiry>> {
+    let Some(at) = row.get(13)? else {
+        return Ok(None);
+    };
+    let action = match row.get::<_, Option<String>>(14)? {
+        Some(action) => serde_json::from_value(action.into())
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(14, Type::Text, Box::new(e)))?,
+        None => ExpiryAction::default(),
+    };
+    Ok(Some(Expiry { at, action }))
+}
+
+impl SqliteBackend {
+    /// Opens (creating if needed) the database at `path` and brings its schema
+    /// up to date.
+    pub fn open(path: &Path) -> Result<Self, NotesError> {
+        let mut conn = Connection::open(path)
+            .map_err(|e| NotesError::Database(format!("Failed to open notes database: {}", e)))?;
+        conn.pragma_update(None, "journal_mode", "WAL")
+            .and_then(|_| conn.pragma_update(None, "foreign_keys", "ON"))
+            .map_err(|e| {
+                NotesError::Database(format!("Failed to configure notes database: {}", e))
+            })?;
+        migrations::run(&mut conn)?;
+        Ok(SqliteBackend { conn })
+    }
+
+    fn tags_by_note(&self) -> Result<HashMap<String, Vec<String>>, NotesError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT note_id, tag FROM note_tags ORDER BY tag")
+            .map_err(|e| NotesError::Database(format!("Failed to query tags: {}", e)))?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get(1)?)))
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<(String, String)>>>())
+            .map_err(|e| NotesError::Database(format!("Failed to load tags: {}", e)))?;
+
+        let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+        for (note_id, tag) in rows {
+            tags.entry(note_id).or_default().push(tag);
+        }
+        Ok(tags)
+    }
+
+    fn attachments_by_note(&self) -> Result<HashMap<String, Vec<Attachment>>, NotesError> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT note_id, id, name, mime, size, added_at, text FROM note_attachments
+                 ORDER BY added_at, rowid",
+            )
+            .map_err(|e| NotesError::Database(format!("Failed to query attachments: {}", e)))?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    Attachment {
+                        id: row.get(1)?,
+                        name: row.get(2)?,
+                        mime: row.get(3)?,
+                        size: row.get(4)?,
+                        added_at: row.get(5)?,
+                        text: row.get(6)?,
+                    },
+                ))
+            })
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<(String, Attachment)>>>())
+            .map_err(|e| NotesError::Database(format!("Failed to load attachments: {}", e)))?;
+
+        let mut attachments: HashMap<String, Vec<Attachment>> = HashMap::new();
+        for (note_id, attachment) in rows {
+            attachments.entry(note_id).or_default().push(attachment);
+        }
+        Ok(attachments)
+    }
+
+    fn reminders_by_note(&self) -> Result<HashMap<String, Vec<Reminder>>, NotesError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT note_id, id, due_at, repeat FROM note_reminders ORDER BY due_at, id")
+            .map_err(|e| NotesError::Database(format!("Failed to query reminders: {}", e)))?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>())
+            .map_err(|e| NotesError::Database(format!("Failed to load reminders: {}", e)))?;
+
+        let mut reminders: HashMap<String, Vec<Reminder>> = HashMap::new();
+        for (note_id, id, due_at, repeat) in rows {
+            let repeat: Repeat = serde_json::from_value(repeat.into()).map_err(|e| {
+                NotesError::Database(format!("Invalid repeat for reminder {}: {}", id, e))
+            })?;
+            reminders
+                .entry(note_id)
+                .or_default()
+                .push(Reminder { id, due_at, repeat });
+        }
+        Ok(reminders)
+    }
+
+    fn meta_by_note(&self) -> Result<HashMap<String, BTreeMap<String, String>>, NotesError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT note_id, key, value FROM note_meta")
+            .map_err(|e| NotesError::Database(format!("Failed to query metadata: {}", e)))?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get(1)?, row.get(2)?))
+            })
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<(String, String, String)>>>())
+            .map_err(|e| NotesError::Database(format!("Failed to load metadata: {}", e)))?;
+
+        let mut meta: HashMap<String, BTreeMap<String, String>> = HashMap::new();
+        for (note_id, key, value) in rows {
+            meta.entry(note_id).or_default().insert(key, value);
+        }
+        Ok(meta)
+    }
+}
+
+impl NotesBackend for SqliteBackend {
+    fn load_all(&self) -> Result<Vec<Note>, NotesError> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!(
+                "SELECT {} FROM notes ORDER BY created_at, rowid",
+                NOTE_COLUMNS
+            ))
+            .map_err(|e| NotesError::Database(format!("Failed to query notes: {}", e)))?;
+        let mut notes = stmt
+            .query_map([], note_from_row)
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<Note>>>())
+            .map_err(|e| NotesError::Database(format!("Failed to load notes: {}", e)))?;
+
+        let mut tags = self.tags_by_note()?;
+        let mut attachments = self.attachments_by_note()?;
+        let mut reminders = self.reminders_by_note()?;
+        let mut meta = self.meta_by_note()?;
+        for note in &mut notes {
+            note.tags = tags.remove(&note.id).unwrap_or_default();
+            note.attachments = attachments.remove(&note.id).unwrap_or_default();
+            note.reminders = reminders.remove(&note.id).unwrap_or_default();
+            note.meta = meta.remove(&note.id).unwrap_or_default();
+        }
+        Ok(notes)
+    }
+
+    fn list(&self) -> Result<Vec<String>, NotesError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id FROM notes")
+            .map_err(|e| NotesError::Database(format!("Failed to query notes: {}", e)))?;
+        stmt.query_map([], |row| row.get(0))
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<String>>>())
+            .map_err(|e| NotesError::Database(format!("Failed to list notes: {}", e)))
+    }
+
+    fn save(&mut self, notes: &[Note]) -> Result<(), NotesError> {
+        let tx = self
+            .conn
+            .transaction()
+            .map_err(|e| NotesError::Database(format!("Failed to start transaction: {}", e)))?;
+        for note in notes {
+            let expiry_action = note
+                .expiry
+                .map(|expiry| serde_json::to_value(expiry.action))
+                .transpose()
+                .map_err(|e| NotesError::Serde(format!("Failed to serialize expiry: {}", e)))?;
+            tx.execute(
+                &format!(
+                    "INSERT INTO notes ({})
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15,
+                             ?16, ?17)
+                     ON CONFLICT (id) DO UPDATE SET
+                        title = excluded.title,
+                        content = excluded.content,
+                        created_at = excluded.created_at,
+                        updated_at = excluded.updated_at,
+                        trashed_at = excluded.trashed_at,
+                        pinned = excluded.pinned,
+                        archived = excluded.archived,
+                        favorite = excluded.favorite,
+                        color = excluded.color,
+                        notebook_id = excluded.notebook_id,
+                        locked = excluded.locked,
+                        content_hash = excluded.content_hash,
+                        expires_at = excluded.expires_at,
+                        expiry_action = excluded.expiry_action,
+                        latitude = excluded.latitude,
+                        longitude = excluded.longitude",
+                    NOTE_COLUMNS
+                ),
+                params![
+                    note.id,
+                    note.title,
+                    note.content,
+                    note.created_at,
+                    note.updated_at,
+                    note.trashed_at,
+                    note.pinned,
+                    note.archived,
+                    note.favorite,
+                    note.color,
+                    note.notebook_id,
+                    note.locked,
+                    note.content_hash,
+                    note.expiry.map(|expiry| expiry.at),
+                    expiry_action.as_ref().and_then(|action| action.as_str()),
+                    note.location.map(|location| location.latitude),
+                    note.location.map(|location| location.longitude)
+                ],
+            )
+            .map_err(|e| NotesError::Database(format!("Failed to save note: {}", e)))?;
+            write_tags(&tx, note)?;
+            write_attachments(&tx, note)?;
+            write_reminders(&tx, note)?;
+            write_meta(&tx, note)?;
+        }
+        tx.commit()
+            .map_err(|e| NotesError::Database(format!("Failed to commit notes: {}", e)))
+    }
+
+    fn delete(&mut self, ids: &[String]) -> Result<usize, NotesError> {
+        let tx = self
+            .conn
+            .transaction()
+            .map_err(|e| NotesError::Database(format!("Failed to start transaction: {}", e)))?;
+        let mut deleted = 0;
+        for id in ids {
+            deleted += tx
+                .execute("DELETE FROM notes WHERE id = ?1", [id])
+                .map_err(|e| NotesError::Database(format!("Failed to delete note: {}", e)))?;
+        }
+        tx.commit()
+            .map_err(|e| NotesError::Database(format!("Failed to commit deletion: {}", e)))?;
+        Ok(deleted)
+    }
+
+    fn compact(&mut self) -> Result<(), NotesError> {
+        self.conn
+            .execute_batch("VACUUM; PRAGMA wal_checkpoint(TRUNCATE);")
+            .map_err(|e| NotesError::Database(format!("Failed to compact notes database: {}", e)))
+    }
+}
+
+fn write_tags(conn: &Connection, note: &Note) -> Result<(), NotesError> {
+    conn.execute("DELETE FROM note_tags WHERE note_id = ?1", [&note.id])
+        .map_err(|e| NotesError::Database(format!("Failed to save tags: {}", e)))?;
+    for tag in &note.tags {
+        conn.execute(
+            "INSERT OR IGNORE INTO note_tags (note_id, tag) VALUES (?1, ?2)",
+            [&note.id, tag],
+        )
+        .map_err(|e| NotesError::Database(format!("Failed to save tags: {}", e)))?;
+    }
+    Ok(())
+}
+
+fn write_attachments(conn: &Connection, note: &Note) -> Result<(), NotesError> {
+    conn.execute(
+        "DELETE FROM note_attachments WHERE note_id = ?1",
+        [&note.id],
+    )
+    .map_err(|e| NotesError::Database(format!("Failed to save attachments: {}", e)))?;
+    for attachment in &note.attachments {
+        conn.execute(
+            "INSERT OR IGNORE INTO note_attachments
+                 (note_id, id, name, mime, size, added_at, text)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                note.id,
+                attachment.id,
+                attachment.name,
+                attachment.mime,
+                attachment.size,
+                attachment.added_at,
+                attachment.text
+            ],
+        )
+        .map_err(|e| NotesError::Database(format!("Failed to save attachments: {}", e)))?;
+    }
+    Ok(())
+}
+
+fn write_reminders(conn: &Connection, note: &Note) -> Result<(), NotesError> {
+    conn.execute("DELETE FROM note_reminders WHERE note_id = ?1", [&note.id])
+        .map_err(|e| NotesError::Database(format!("Failed to save reminders: {}", e)))?;
+    for reminder in &note.reminders {
+        let repeat = serde_json::to_value(reminder.repeat)
+            .map_err(|e| NotesError::Serde(format!("Failed to serialize reminder: {}", e)))?;
+        conn.execute(
+            "INSERT OR IGNORE INTO note_reminders (note_id, id, due_at, repeat)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![note.id, reminder.id, reminder.due_at, repeat.as_str()],
+        )
+        .map_err(|e| NotesError::Database(format!("Failed to save reminders: {}", e)))?;
+    }
+    Ok(())
+}
+
+fn write_meta(conn: &Connection, note: &Note) -> Result<(), NotesError> {
+    conn.execute("DELETE FROM note_meta WHERE note_id = ?1", [&note.id])
+        .map_err(|e| NotesError::Database(format!("Failed to save metadata: {}", e)))?;
+    for (key, value) in &note.meta {
+        conn.execute(
+            "INSERT INTO note_meta (note_id, key, value) VALUES (?1, ?2, ?3)",
+            [&note.id, key, value],
+        )
+        .map_err(|e| NotesError::Database(format!("Failed to save metadata: {}", e)))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,1018 @@
+//! The in-memory copy of every note, kept in step with `Storage`.
+//!
+//! Notes are loaded once at startup (or when the vault is unlocked) and every
+//! read is served from memory. Mutations are written through to the backend
+//! before the cached copy changes, so a failed save leaves both untouched.
+//! Mutations made on the user's behalf are also recorded, to undo.
+
+use crate::error::NotesError;
+use crate::note::{self, Note};
+use crate::storage::{BackendKind, DiskUsage, GitBackend, IntegrityReport, Revision, Storage};
+use crate::undo::{Change, UndoLog};
+use crate::vault::{self, Cipher, Encryption};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// An autosaved draft replacing a version saved less than this many seconds
+/// earlier doesn't add to the history, so a typing session leaves one
+/// revision rather than one per flush.
+const DRAFT_REVISION_WINDOW_SECS: i64 = 300;
+
+#[derive(Serialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    #[default]
+    Created,
+    Updated,
+    Title,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Which page of the note list to return, and in what order. Every field is
+/// optional.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct NoteQuery {
+    pub offset: usize,
+    /// Returns every note from `offset` on when unset.
+    pub limit: Option<usize>,
+    pub sort: SortKey,
+    pub direction: SortDirection,
+    pub exclude_archived: bool,
+}
+
+#[derive(Serialize)]
+pub struct NotePage {
+    pub notes: Vec<Note>,
+    /// How many notes match the query across all pages.
+    pub total: usize,
+}
+
+pub struct NotesStore {
+    storage: Storage,
+    /// Every note, trashed ones included, decrypted and oldest first. Empty
+    /// while the vault is locked.
+    notes: Vec<Note>,
+    /// The index in `notes` of each note, by id.
+    positions: HashMap<String, usize>,
+    undo: UndoLog,
+}
+
+impl NotesStore {
+    pub fn new(storage: Storage) -> Result<Self, NotesError> {
+        let mut store = NotesStore {
+            storage,
+            notes: Vec::new(),
+            positions: HashMap::new(),
+            undo: UndoLog::default(),
+        };
+        store.reload()?;
+        Ok(store)
+    }
+
+    /// Replaces the cached notes with what the backend currently holds,
+    /// which can't be undone past.
+    pub fn reload(&mut self) -> Result<(), NotesError> {
+        let notes = if self.storage.is_locked() {
+            Vec::new()
+        } else {
+            self.storage.load_all()?
+        };
+        self.set_notes(notes);
+        self.undo.clear();
+        Ok(())
+    }
+
+    fn set_notes(&mut self, mut notes: Vec<Note>) {
+        // Notes saved before hashes were kept are hashed here, and saved with
+        // it the next time they change
+        for note in notes.iter_mut().filter(|note| note.content_hash.is_empty()) {
+            stamp(note);
+        }
+        self.positions = notes
+            .iter()
+            .enumerate()
+            .map(|(index, note)| (note.id.clone(), index))
+            .collect();
+        self.notes = notes;
+    }
+
+    /// Picks up changes another program, such as a file sync tool, made to
+    /// the stored notes. Where both copies of a note changed, the one updated
+    /// last is kept, and written back if it's the cached one. Returns whether
+    /// any cached notes changed; nothing does while the vault is locked.
+    pub fn merge_external(&mut self) -> Result<bool, NotesError> {
+        if self.storage.is_locked() {
+            return Ok(false);
+        }
+        self.storage.reopen()?;
+        let mut stored: HashMap<String, Note> = self
+            .storage
+            .load_all()?
+            .into_iter()
+            .map(|note| (note.id.clone(), note))
+            .collect();
+
+        let mut changed = false;
+        let mut newer = Vec::new();
+        let mut merged = Vec::with_capacity(stored.len());
+        for note in &self.notes {
+            match stored.remove(&note.id) {
+                // Deleted by the other program
+                None => changed = true,
+                Some(theirs) if theirs.updated_at < note.updated_at => {
+                    newer.push(note.clone());
+                    merged.push(note.clone());
+                }
+                Some(theirs) => {
+                    changed |= theirs != *note;
+                    merged.push(theirs);
+                }
+            }
+        }
+        changed |= !stored.is_empty();
+        merged.extend(stored.into_values());
+        merged.sort_by_key(|note| note.created_at);
+
+        self.storage.save_notes(&newer)?;
+        self.set_notes(merged);
+        Ok(changed)
+    }
+
+    /// Checks every stored note against its content hash, optionally
+    /// quarantining the corrupted ones. See `Storage::verify_integrity`.
+    pub fn verify_integrity(&mut self, quarantine: bool) -> Result<IntegrityReport, NotesError> {
+        let report = self.storage.verify_integrity(quarantine)?;
+        if report.quarantined() > 0 {
+            self.reload()?;
+        }
+        Ok(report)
+    }
+
+    pub fn set_compression(&mut self, compress: bool) {
+        self.storage.set_compression(compress);
+    }
+
+    /// Rewrites every stored note to match the compression setting. See
+    /// `Storage::compact`.
+    pub fn compact(&mut self) -> Result<usize, NotesError> {
+        self.storage.compact()
+    }
+
+    pub fn disk_usage(&self) -> Result<DiskUsage, NotesError> {
+        self.storage.disk_usage()
+    }
+
+    pub fn backend_kind(&self) -> BackendKind {
+        self.storage.backend_kind()
+    }
+
+    pub fn switch_backend(&mut self, kind: BackendKind) -> Result<usize, NotesError> {
+        self.storage.switch_backend(kind)
+    }
+
+    pub fn git(&mut self) -> Result<&mut GitBackend, NotesError> {
+        self.storage.git()
+    }
+
+    pub fn cipher(&self) -> Option<&Cipher> {
+        self.storage.cipher()
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.storage.is_locked()
+    }
+
+    /// Changes the encryption setting, loading the notes when the vault is
+    /// unlocked and dropping them from memory when it's locked.
+    pub fn set_encryption(&mut self, encryption: Encryption) -> Result<(), NotesError> {
+        self.storage.set_encryption(encryption);
+        self.reload()?;
+        if self.storage.cipher().is_some() {
+            self.storage.seal_legacy_fields()?;
+            self.storage.reseal_attachments(&self.notes)?;
+        }
+        Ok(())
+    }
+
+    /// Rewrites every note so that all of them match the current encryption
+    /// setting.
+    pub fn reseal_all(&mut self) -> Result<(), NotesError> {
+        self.unlocked()?;
+        self.storage.save_notes(&self.notes)?;
+        self.storage.reseal_history()?;
+        self.storage.reseal_attachments(&self.notes)
+    }
+
+    fn unlocked(&self) -> Result<&[Note], NotesError> {
+        if self.storage.is_locked() {
+            return Err(NotesError::Locked);
+        }
+        Ok(&self.notes)
+    }
+
+    /// Returns every note, trashed ones included, oldest first.
+    pub fn all_notes(&self) -> Result<&[Note], NotesError> {
+        self.unlocked()
+    }
+
+    fn position(&self, id: &str) -> Result<usize, NotesError> {
+        self.unlocked()?;
+        self.positions
+            .get(id)
+            .copied()
+            .ok_or_else(|| NotesError::NotFound("Note not found".into()))
+    }
+
+    /// Returns every note that isn't in the trash.
+    pub fn notes(&self) -> Result<Vec<Note>, NotesError> {
+        Ok(self
+            .unlocked()?
+            .iter()
+            .filter(|note| note.trashed_at.is_none())
+            .cloned()
+            .collect())
+    }
+
+    /// Returns up to `limit` notes outside the trash and archive, most
+    /// recently updated first.
+    pub fn recent(&self, limit: usize) -> Result<Vec<Note>, NotesError> {
+        let mut notes: Vec<&Note> = self
+            .unlocked()?
+            .iter()
+            .filter(|note| note.trashed_at.is_none() && !note.archived)
+            .collect();
+        notes.sort_by_key(|note| std::cmp::Reverse(note.updated_at));
+        Ok(notes.into_iter().take(limit).cloned().collect())
+    }
+
+    /// Returns a page of the notes outside the trash, pinned ones first and
+    /// each group sorted as `query` asks.
+    pub fn query(&self, query: &NoteQuery) -> Result<NotePage, NotesError> {
+        let mut notes: Vec<&Note> = self
+            .unlocked()?
+            .iter()
+            .filter(|note| note.trashed_at.is_none())
+            .filter(|note| !(query.exclude_archived && note.archived))
+            .collect();
+        notes.sort_by(|a, b| {
+            let order = match query.sort {
+                SortKey::Created => a.created_at.cmp(&b.created_at),
+                SortKey::Updated => a.updated_at.cmp(&b.updated_at),
+                SortKey::Title => a.title.to_lowercase().cmp(&b.title.to_lowercase()),
+            };
+            let order = match query.direction {
+                SortDirection::Asc => order,
+                SortDirection::Desc => order.reverse(),
+            };
+            b.pinned.cmp(&a.pinned).then(order)
+        });
+
+        let total = notes.len();
+        let notes = notes
+            .into_iter()
+            .skip(query.offset)
+            .take(query.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect();
+        Ok(NotePage { notes, total })
+    }
+
+    /// Returns the notes in the trash, most recently trashed first.
+    pub fn trashed_notes(&self) -> Result<Vec<Note>, NotesError> {
+        let mut notes: Vec<Note> = self
+            .unlocked()?
+            .iter()
+            .filter(|note| note.trashed_at.is_some())
+            .cloned()
+            .collect();
+        notes.sort_by_key(|note| std::cmp::Reverse(note.trashed_at));
+        Ok(notes)
+    }
+
+    pub fn notes_by_tag(&self, tag: &str) -> Result<Vec<Note>, NotesError> {
+        Ok(self
+            .unlocked()?
+            .iter()
+            .filter(|note| note.trashed_at.is_none() && note.tags.iter().any(|t| t == tag))
+            .cloned()
+            .collect())
+    }
+
+    /// Returns the notes outside the trash in any of `notebooks`, oldest
+    /// first.
+    pub fn notes_in(&self, notebooks: &HashSet<String>) -> Result<Vec<Note>, NotesError> {
+        Ok(self
+            .unlocked()?
+            .iter()
+            .filter(|note| note.trashed_at.is_none())
+            .filter(|note| {
+                note.notebook_id
+                    .as_ref()
+                    .is_some_and(|id| notebooks.contains(id))
+            })
+            .cloned()
+            .collect())
+    }
+
+    /// Returns the favorite notes outside the trash, oldest first.
+    pub fn favorites(&self) -> Result<Vec<Note>, NotesError> {
+        Ok(self
+            .unlocked()?
+            .iter()
+            .filter(|note| note.trashed_at.is_none() && note.favorite)
+            .cloned()
+            .collect())
+    }
+
+    /// The notes that have metadata field `key`, set to `value` if given.
+    pub fn with_meta(&self, key: &str, value: Option<&str>) -> Result<Vec<Note>, NotesError> {
+        Ok(self
+            .unlocked()?
+            .iter()
+            .filter(|note| note.trashed_at.is_none())
+            .filter(|note| match (note.meta.get(key), value) {
+                (Some(found), Some(value)) => found == value,
+                (found, None) => found.is_some(),
+                (None, Some(_)) => false,
+            })
+            .cloned()
+            .collect())
+    }
+
+    pub fn get(&self, id: &str) -> Result<Note, NotesError> {
+        let index = self.position(id)?;
+        Ok(self.notes[index].clone())
+    }
+
+    /// Returns every tag on a note outside the trash with the number of such
+    /// notes carrying it, sorted by tag name.
+    pub fn list_tags(&self) -> Result<Vec<TagCount>, NotesError> {
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for note in self.unlocked()? {
+            if note.trashed_at.is_none() {
+                for tag in &note.tags {
+                    *counts.entry(tag).or_default() += 1;
+                }
+            }
+        }
+        Ok(counts
+            .into_iter()
+            .map(|(tag, count)| TagCount {
+                tag: tag.to_string(),
+                count,
+            })
+            .collect())
+    }
+
+    pub fn insert(&mut self, mut note: Note) -> Result<(), NotesError> {
+        self.unlocked()?;
+        stamp(&mut note);
+        self.storage.save_notes(std::slice::from_ref(&note))?;
+        self.undo.record(
+            vec![Change {
+                before: None,
+                after: Some(note.clone()),
+            }],
+            false,
+        );
+        self.positions.insert(note.id.clone(), self.notes.len());
+        self.notes.push(note);
+        Ok(())
+    }
+
+    /// Inserts `note`, or replaces the note with the same id. Unless inserted,
+    /// it can't be undone, since the note comes from elsewhere.
+    pub fn upsert(&mut self, mut note: Note) -> Result<(), NotesError> {
+        let Ok(index) = self.position(&note.id) else {
+            return self.insert(note);
+        };
+        stamp(&mut note);
+        // Unlike `update`, this may replace sealed content, which came from
+        // elsewhere rather than from an edit
+        let previous = &self.notes[index];
+        if note.title != previous.title || note.content != previous.content {
+            self.storage.record_revision(previous)?;
+        }
+        self.storage.save_notes(std::slice::from_ref(&note))?;
+        self.notes[index] = note;
+        Ok(())
+    }
+
+    /// Applies `change` to the note with `id` and saves it, returning the
+    /// updated note. If the title or content changed, the previous version is
+    /// kept in the note's history.
+    pub fn update(
+        &mut self,
+        id: &str,
+        change: impl FnOnce(&mut Note),
+    ) -> Result<&Note, NotesError> {
+        self.apply(id, change, true)
+    }
+
+    /// Like `update`, for a change the app makes on its own, such as marking
+    /// a reminder fired, which isn't offered to undo.
+    pub fn update_without_undo(
+        &mut self,
+        id: &str,
+        change: impl FnOnce(&mut Note),
+    ) -> Result<&Note, NotesError> {
+        let index = self.position(id)?;
+        self.write(index, change, false)?;
+        Ok(&self.notes[index])
+    }
+
+    /// Like `update_without_undo`, for a change the app makes on its own to
+    /// finish one the user made, such as tagging a new note with where it was
+    /// written. The change joins the last step that changed the note, so
+    /// undoing that step still can.
+    pub fn amend(&mut self, id: &str, change: impl FnOnce(&mut Note)) -> Result<&Note, NotesError> {
+        let index = self.position(id)?;
+        let previous = self.write(index, change, false)?;
+        self.undo.amend(&previous, &self.notes[index]);
+        Ok(&self.notes[index])
+    }
+
+    /// Like `update`, for an edit to the version of note `id` saved at
+    /// `seen_at`. Refuses it with `NotesError::Stale` if the note has been
+    /// saved since, so one window's edit can't silently replace another's.
+    pub fn update_seen(
+        &mut self,
+        id: &str,
+        seen_at: i64,
+        change: impl FnOnce(&mut Note),
+    ) -> Result<&Note, NotesError> {
+        let current = &self.unlocked()?[self.position(id)?];
+        if current.updated_at > seen_at {
+            return Err(NotesError::Stale(Box::new(current.clone())));
+        }
+        self.update(id, change)
+    }
+
+    /// Replaces the content of note `id` with an autosaved draft, written at
+    /// `now`. The previous version goes into the history, and is a step of
+    /// its own to undo, only if it's older than `DRAFT_REVISION_WINDOW_SECS`.
+    pub fn save_draft(&mut self, id: &str, content: String, now: i64) -> Result<&Note, NotesError> {
+        let previous = &self.notes[self.position(id)?];
+        let keep_revision = now - previous.updated_at >= DRAFT_REVISION_WINDOW_SECS;
+        self.apply(
+            id,
+            |note| {
+                note.content = content;
+                note.updated_at = now;
+            },
+            keep_revision,
+        )
+    }
+
+    /// Like `write`, recording the change to undo. One kept out of the
+    /// history joins the last step if that changed the same note.
+    fn apply(
+        &mut self,
+        id: &str,
+        change: impl FnOnce(&mut Note),
+        keep_revision: bool,
+    ) -> Result<&Note, NotesError> {
+        let index = self.position(id)?;
+        let previous = self.write(index, change, keep_revision)?;
+        let change = Change {
+            before: Some(previous),
+            after: Some(self.notes[index].clone()),
+        };
+        self.undo.record(vec![change], !keep_revision);
+        Ok(&self.notes[index])
+    }
+
+    /// Applies `change` to the note at `index` and saves it, returning the
+    /// note as it was.
+    fn write(
+        &mut self,
+        index: usize,
+        change: impl FnOnce(&mut Note),
+        keep_revision: bool,
+    ) -> Result<Note, NotesError> {
+        let previous = &self.notes[index];
+        let mut note = previous.clone();
+        change(&mut note);
+        check_unlocked_content(previous, &note)?;
+        stamp(&mut note);
+        if keep_revision && (note.title != previous.title || note.content != previous.content) {
+            self.storage.record_revision(previous)?;
+        }
+        self.storage.save_notes(std::slice::from_ref(&note))?;
+        Ok(std::mem::replace(&mut self.notes[index], note))
+    }
+
+    /// Applies `change` to each of the notes with `ids` and saves them in one
+    /// write, returning the updated notes. Nothing changes if any of them is
+    /// missing.
+    pub fn update_many(
+        &mut self,
+        ids: &[String],
+        change: impl Fn(&mut Note),
+    ) -> Result<Vec<Note>, NotesError> {
+        let mut indices = ids
+            .iter()
+            .map(|id| self.position(id))
+            .collect::<Result<Vec<usize>, NotesError>>()?;
+        let mut seen = HashSet::new();
+        indices.retain(|&index| seen.insert(index));
+        let mut updated = Vec::with_capacity(indices.len());
+        for &index in &indices {
+            let previous = &self.notes[index];
+            let mut note = previous.clone();
+            change(&mut note);
+            check_unlocked_content(previous, &note)?;
+            stamp(&mut note);
+            if note.title != previous.title || note.content != previous.content {
+                self.storage.record_revision(previous)?;
+            }
+            updated.push(note);
+        }
+        self.storage.save_notes(&updated)?;
+        let mut changes = Vec::with_capacity(updated.len());
+        for (&index, note) in indices.iter().zip(&updated) {
+            let previous = std::mem::replace(&mut self.notes[index], note.clone());
+            changes.push(Change {
+                before: Some(previous),
+                after: Some(note.clone()),
+            });
+        }
+        self.undo.record(changes, false);
+        Ok(updated)
+    }
+
+    /// Seals note `id`'s content with `password`, saved at `now`. Its history
+    /// is forgotten, since it holds the content in the clear.
+    pub fn lock_note(&mut self, id: &str, password: &str, now: i64) -> Result<&Note, NotesError> {
+        let note = &self.notes[self.position(id)?];
+        if note.locked {
+            return Err(NotesError::Invalid("Note is already locked".into()));
+        }
+        let sealed = vault::lock_content(&note.content, password)?;
+        self.storage.clear_history(id)?;
+        // Undoing would need the content in the clear too
+        self.undo.forget(id);
+        self.update_without_undo(id, |note| {
+            note.content = sealed;
+            note.locked = true;
+            note.updated_at = now;
+        })
+    }
+
+    /// Returns the content of locked note `id`, which stays locked.
+    pub fn unlock_note(&self, id: &str, password: &str) -> Result<String, NotesError> {
+        let note = &self.notes[self.position(id)?];
+        if !note.locked {
+            return Err(NotesError::Invalid("Note isn't locked".into()));
+        }
+        vault::unlock_content(&note.content, password)
+    }
+
+    /// Takes the password off locked note `id`, saved at `now`, leaving its
+    /// content in the clear again.
+    pub fn remove_note_lock(
+        &mut self,
+        id: &str,
+        password: &str,
+        now: i64,
+    ) -> Result<&Note, NotesError> {
+        let content = self.unlock_note(id, password)?;
+        self.update_without_undo(id, |note| {
+            note.content = content;
+            note.locked = false;
+            note.updated_at = now;
+        })
+    }
+
+    /// Stores `content` as an attachment blob and returns its id. The blob is
+    /// deleted again by the next cleanup unless a note refers to it.
+    pub fn put_attachment(&self, content: &[u8]) -> Result<String, NotesError> {
+        self.unlocked()?;
+        self.storage.put_attachment(content)
+    }
+
+    pub fn read_attachment(&self, id: &str) -> Result<Vec<u8>, NotesError> {
+        self.unlocked()?;
+        self.storage.read_attachment(id)
+    }
+
+    pub fn has_attachment(&self, id: &str) -> Result<bool, NotesError> {
+        self.storage.has_attachment(id)
+    }
+
+    pub fn collect_garbage(&self) -> Result<usize, NotesError> {
+        self.storage.collect_garbage()
+    }
+
+    /// Returns the previous versions of note `id`, oldest first.
+    pub fn history(&self, id: &str) -> Result<Vec<Revision>, NotesError> {
+        self.position(id)?;
+        self.storage.revisions(id)
+    }
+
+    /// Returns when every note that isn't trashed was saved: the time of its
+    /// current version and of each revision in its history.
+    pub fn edit_times(&self) -> Result<Vec<i64>, NotesError> {
+        let mut times = Vec::new();
+        for note in self.unlocked()? {
+            if note.trashed_at.is_none() {
+                times.push(note.updated_at);
+                times.extend(self.storage.revision_times(&note.id)?);
+            }
+        }
+        Ok(times)
+    }
+
+    pub fn revision(&self, id: &str, rev: u32) -> Result<Revision, NotesError> {
+        self.history(id)?
+            .into_iter()
+            .find(|revision| revision.rev == rev)
+            .ok_or_else(|| NotesError::NotFound("Revision not found".into()))
+    }
+
+    /// Permanently deletes the note with `id` and its history, returning
+    /// whether it existed.
+    pub fn delete(&mut self, id: &str) -> Result<bool, NotesError> {
+        Ok(self.delete_where(|note| note.id == id)? > 0)
+    }
+
+    /// Permanently deletes the notes with `ids` and their history, returning
+    /// how many existed.
+    pub fn delete_many(&mut self, ids: &[String]) -> Result<usize, NotesError> {
+        let ids: HashSet<&String> = ids.iter().collect();
+        self.delete_where(|note| ids.contains(&note.id))
+    }
+
+    /// Like `delete_many`, but for notes the app deletes on its own, such as
+    /// expired ones. The deletion can't be undone, and every earlier change
+    /// to the notes is forgotten, so nothing of them is kept.
+    pub fn delete_without_undo(&mut self, ids: &[String]) -> Result<usize, NotesError> {
+        if ids.is_empty() {
+            return Ok(0);
+        }
+        let ids: HashSet<&String> = ids.iter().collect();
+        let (deleted, removed) = self.delete_where_without_undo(|note| ids.contains(&note.id))?;
+        for note in &removed {
+            self.undo.forget(&note.id);
+        }
+        Ok(deleted)
+    }
+
+    /// Permanently deletes every trashed note, returning how many were removed.
+    pub fn empty_trash(&mut self) -> Result<usize, NotesError> {
+        self.delete_where(|note| note.trashed_at.is_some())
+    }
+
+    fn delete_where(&mut self, filter: impl Fn(&Note) -> bool) -> Result<usize, NotesError> {
+        let (deleted, removed) = self.delete_where_without_undo(filter)?;
+        let changes = removed
+            .into_iter()
+            .map(|note| Change {
+                before: Some(note),
+                after: None,
+            })
+            .collect();
+        self.undo.record(changes, false);
+        Ok(deleted)
+    }
+
+    /// Deletes the notes matching `filter`, returning how many were stored
+    /// and the notes as they were.
+    fn delete_where_without_undo(
+        &mut self,
+        filter: impl Fn(&Note) -> bool,
+    ) -> Result<(usize, Vec<Note>), NotesError> {
+        let ids: Vec<String> = self
+            .unlocked()?
+            .iter()
+            .filter(|note| filter(note))
+            .map(|note| note.id.clone())
+            .collect();
+        let deleted = self.storage.delete_notes(&ids)?;
+        let (removed, kept): (Vec<Note>, Vec<Note>) = std::mem::take(&mut self.notes)
+            .into_iter()
+            .partition(|note| filter(note));
+        self.set_notes(kept);
+        Ok((deleted, removed))
+    }
+
+    /// Undoes the last change made to the notes, returning the ids of the
+    /// notes it touched, or nothing if there's no change left to undo.
+    pub fn undo(&mut self) -> Result<Vec<String>, NotesError> {
+        self.unlocked()?;
+        let Some(changes) = self.undo.pop_undo() else {
+            return Ok(Vec::new());
+        };
+        let ids = self.restore(&changes, false)?;
+        self.undo.undone(changes);
+        Ok(ids)
+    }
+
+    /// Redoes the last change undone, returning the ids of the notes it
+    /// touched, or nothing if there's no change left to redo.
+    pub fn redo(&mut self) -> Result<Vec<String>, NotesError> {
+        self.unlocked()?;
+        let Some(changes) = self.undo.pop_redo() else {
+            return Ok(Vec::new());
+        };
+        let ids = self.restore(&changes, true)?;
+        self.undo.redone(changes);
+        Ok(ids)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.undo.can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.undo.can_redo()
+    }
+
+    /// Puts the notes `changes` touched back as they were before them, or
+    /// as they were after them if `forward`. Refuses with
+    /// `NotesError::Conflict`, dropping the step, if any of them has been
+    /// edited elsewhere since.
+    fn restore(&mut self, changes: &[Change], forward: bool) -> Result<Vec<String>, NotesError> {
+        let mut saves = Vec::new();
+        let mut deletes = Vec::new();
+        for change in changes {
+            let (expected, target) = match forward {
+                true => (&change.before, &change.after),
+                false => (&change.after, &change.before),
+            };
+            let current = self
+                .position(change.id())
+                .ok()
+                .map(|index| &self.notes[index]);
+            if current != expected.as_ref() {
+                return Err(NotesError::Conflict(
+                    "The note has changed since, so this can't be undone".into(),
+                ));
+            }
+            match (current, target) {
+                (Some(current), Some(target)) => {
+                    if target.title != current.title || target.content != current.content {
+                        self.storage.record_revision(current)?;
+                    }
+                    saves.push(target.clone());
+                }
+                (None, Some(target)) => saves.push(target.clone()),
+                (_, None) => deletes.push(change.id().to_string()),
+            }
+        }
+        self.storage.save_notes(&saves)?;
+        self.storage.delete_notes(&deletes)?;
+
+        let ids: Vec<String> = changes
+            .iter()
+            .map(|change| change.id().to_string())
+            .collect();
+        let mut notes = std::mem::take(&mut self.notes);
+        notes.retain(|note| !ids.contains(&note.id));
+        notes.extend(saves);
+        notes.sort_by_key(|note| note.created_at);
+        self.set_notes(notes);
+        Ok(ids)
+    }
+}
+
+/// Brings `note`'s content hash up to date.
+fn stamp(note: &mut Note) {
+    note.content_hash = note::content_hash(&note.content);
+}
+
+/// Refuses changes to the sealed content of a locked note, which can only be
+/// edited once its password is removed.
+fn check_unlocked_content(previous: &Note, note: &Note) -> Result<(), NotesError> {
+    if previous.locked && note.locked && note.content != previous.content {
+        return Err(NotesError::Invalid(
+            "Note is locked; remove its password to edit it".into(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo::Location;
+    use tempfile::TempDir;
+
+    fn note(id: &str, updated_at: i64) -> Note {
+        Note {
+            id: id.into(),
+            title: id.into(),
+            content: String::new(),
+            created_at: 1,
+            updated_at,
+            tags: Vec::new(),
+            trashed_at: None,
+            pinned: false,
+            archived: false,
+            favorite: false,
+            locked: false,
+            content_hash: String::new(),
+            color: None,
+            notebook_id: None,
+            attachments: Vec::new(),
+            reminders: Vec::new(),
+            expiry: None,
+            location: None,
+            meta: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn merges_external_changes_by_update_time() {
+        let dir = TempDir::new().unwrap();
+        let mut store = NotesStore::new(Storage::open(dir.path()).unwrap()).unwrap();
+        for (id, updated_at) in [("edited", 1), ("kept", 5), ("deleted", 1)] {
+            store.insert(note(id, updated_at)).unwrap();
+        }
+        assert!(!store.merge_external().unwrap());
+
+        // Another copy of the app writing to the same files
+        let mut other = Storage::open(dir.path()).unwrap();
+        other
+            .save_notes(&[note("edited", 2), note("kept", 3), note("added", 1)])
+            .unwrap();
+        other.delete_notes(&["deleted".to_string()]).unwrap();
+
+        assert!(store.merge_external().unwrap());
+        let times = |notes: &[Note]| {
+            let mut times: Vec<(String, i64)> = notes
+                .iter()
+                .map(|note| (note.id.clone(), note.updated_at))
+                .collect();
+            times.sort();
+            times
+        };
+        let expected = [("added", 1), ("edited", 2), ("kept", 5)]
+            .map(|(id, updated_at)| (id.to_string(), updated_at));
+        assert_eq!(times(store.all_notes().unwrap()), expected);
+        assert_eq!(times(&other.load_all().unwrap()), expected);
+    }
+
+    #[test]
+    fn finds_notes_by_id_after_deletes() {
+        let dir = TempDir::new().unwrap();
+        let mut store = NotesStore::new(Storage::open(dir.path()).unwrap()).unwrap();
+        for id in ["a", "b", "c"] {
+            store.insert(note(id, 1)).unwrap();
+        }
+        assert!(store.delete("a").unwrap());
+        assert_eq!(store.get("c").unwrap().id, "c");
+        store.update("c", |note| note.updated_at = 2).unwrap();
+        store.upsert(note("b", 3)).unwrap();
+        assert!(matches!(store.get("a"), Err(NotesError::NotFound(_))));
+
+        store.reload().unwrap();
+        assert_eq!(store.get("b").unwrap().updated_at, 3);
+        assert_eq!(store.get("c").unwrap().updated_at, 2);
+    }
+
+    #[test]
+    fn updates_and_deletes_many_notes() {
+        let dir = TempDir::new().unwrap();
+        let mut store = NotesStore::new(Storage::open(dir.path()).unwrap()).unwrap();
+        for id in ["a", "b", "c"] {
+            store.insert(note(id, 1)).unwrap();
+        }
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        assert!(store
+            .update_many(&ids(&["a", "missing"]), |note| note.archived = true)
+            .is_err());
+        assert!(!store.get("a").unwrap().archived);
+
+        let updated = store
+            .update_many(&ids(&["a", "b", "a"]), |note| note.archived = true)
+            .unwrap();
+        assert_eq!(updated.len(), 2);
+        store.reload().unwrap();
+        assert!(store.get("a").unwrap().archived && store.get("b").unwrap().archived);
+        assert!(!store.get("c").unwrap().archived);
+
+        assert_eq!(store.delete_many(&ids(&["a", "c", "missing"])).unwrap(), 2);
+        assert_eq!(store.notes().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn refuses_edits_to_outdated_versions() {
+        let dir = TempDir::new().unwrap();
+        let mut store = NotesStore::new(Storage::open(dir.path()).unwrap()).unwrap();
+        store.insert(note("a", 1)).unwrap();
+        let edit = |text: &'static str, at: i64| {
+            move |note: &mut Note| {
+                note.content = text.into();
+                note.updated_at = at;
+            }
+        };
+        store.update_seen("a", 1, edit("first", 5)).unwrap();
+
+        let refused = store.update_seen("a", 1, edit("second", 6)).unwrap_err();
+        let NotesError::Stale(current) = refused else {
+            panic!("expected a stale write, got {:?}", refused);
+        };
+        assert_eq!((current.content.as_str(), current.updated_at), ("first", 5));
+        assert_eq!(store.get("a").unwrap().content, "first");
+        store.update_seen("a", 5, edit("second", 6)).unwrap();
+    }
+
+    #[test]
+    fn locks_notes_with_their_own_password() {
+        let dir = TempDir::new().unwrap();
+        let mut store = NotesStore::new(Storage::open(dir.path()).unwrap()).unwrap();
+        store.insert(note("a", 1)).unwrap();
+        store
+            .update("a", |note| note.content = "secret".into())
+            .unwrap();
+        store.lock_note("a", "hunter2", 2).unwrap();
+        store.reload().unwrap();
+
+        let locked = store.get("a").unwrap();
+        assert!(locked.locked && !locked.content.contains("secret"));
+        assert!(store.history("a").unwrap().is_empty());
+        assert!(locked.redact().content.is_empty());
+        assert!(matches!(
+            store.unlock_note("a", "wrong"),
+            Err(NotesError::Crypto(_))
+        ));
+        assert_eq!(store.unlock_note("a", "hunter2").unwrap(), "secret");
+        assert!(store
+            .update("a", |note| note.content = "edited".into())
+            .is_err());
+
+        store.remove_note_lock("a", "hunter2", 3).unwrap();
+        let note = store.get("a").unwrap();
+        assert!(!note.locked);
+        assert_eq!(note.content, "secret");
+    }
+
+    #[test]
+    fn undoes_and_redoes_changes() {
+        let dir = TempDir::new().unwrap();
+        let mut store = NotesStore::new(Storage::open(dir.path()).unwrap()).unwrap();
+        store.insert(note("a", 1)).unwrap();
+        store.insert(note("b", 1)).unwrap();
+        // Autosaves in a row are one step
+        store.save_draft("a", "one".into(), 2).unwrap();
+        store.save_draft("a", "two".into(), 3).unwrap();
+        store.delete_many(&["b".to_string()]).unwrap();
+
+        assert_eq!(store.undo().unwrap(), ["b"]);
+        assert!(store.get("b").is_ok());
+        assert_eq!(store.undo().unwrap(), ["a"]);
+        assert_eq!(store.get("a").unwrap().content, "");
+        store.reload().unwrap();
+        assert_eq!(store.get("a").unwrap().content, "");
+        assert!(!store.can_undo() && !store.can_redo());
+
+        store
+            .update("a", |note| note.content = "three".into())
+            .unwrap();
+        store.undo().unwrap();
+        assert_eq!(store.redo().unwrap(), ["a"]);
+        assert_eq!(store.get("a").unwrap().content, "three");
+        assert!(store.redo().unwrap().is_empty());
+
+        // Not over a change made since
+        store.undo().unwrap();
+        store
+            .update_without_undo("a", |note| note.pinned = true)
+            .unwrap();
+        assert!(matches!(store.redo(), Err(NotesError::Conflict(_))));
+        assert!(store.get("a").unwrap().pinned);
+    }
+
+    #[test]
+    fn undoes_amended_changes_in_one_step() {
+        let dir = TempDir::new().unwrap();
+        let mut store = NotesStore::new(Storage::open(dir.path()).unwrap()).unwrap();
+        store.insert(note("a", 1)).unwrap();
+        store
+            .amend("a", |note| {
+                note.location = Some(Location {
+                    latitude: 51.5,
+                    longitude: -0.125,
+                })
+            })
+            .unwrap();
+        assert_eq!(store.undo().unwrap(), ["a"]);
+        assert!(store.get("a").is_err());
+        assert!(!store.can_undo());
+    }
+}
@@ -0,0 +1,318 @@
+//! Notes kept as a CRDT (a Yjs document, through yrs), for transports that
+//! exchange changes rather than files. Edits made on two devices since they
+//! last exchanged changes merge by themselves instead of becoming conflicted
+//! copies.
+//!
+//! Each note's content is a root text named after its id, and the rest of it
+//! is its JSON in the `notes` map, where the last write wins. Locked notes
+//! keep their sealed content in the map too, since ciphertext can't be
+//! merged. The store stays the source of truth: before changes are
+//! exchanged, every note is diffed against the document to record what
+//! changed locally.
+//!
+//! crdt.bin is encrypted with the vault, but the changes handed to the
+//! transport are not.
+
+use crate::error::NotesError;
+use crate::note::Note;
+use crate::store::NotesStore;
+use crate::vault::{self, Cipher};
+use crate::{fsutil, note};
+use serde::Serialize;
+use similar::{DiffOp, TextDiff};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::Encode;
+use yrs::{Any, Doc, GetString, Map, MapRef, Out, ReadTxn, StateVector, Text, Transact, Update};
+
+const FILE: &str = "crdt.bin";
+const NOTES: &str = "notes";
+
+/// Changes that another device hasn't seen yet.
+#[derive(Serialize)]
+pub struct LocalChanges {
+    /// A Yjs update to pass to the other device's `apply_remote_changes`.
+    pub changes: Vec<u8>,
+    /// What this device has seen, for the other device's `local_changes`.
+    pub state_vector: Vec<u8>,
+}
+
+/// How applying another device's changes affected the notes here.
+#[derive(Serialize, Default, Debug, PartialEq)]
+pub struct CrdtReport {
+    pub updated: usize,
+    pub deleted: usize,
+}
+
+struct NoteDoc {
+    path: PathBuf,
+    doc: Doc,
+    notes: MapRef,
+}
+
+impl NoteDoc {
+    fn open(data_dir: &Path, cipher: Option<&Cipher>) -> Result<Self, NotesError> {
+        let path = data_dir.join(FILE);
+        let doc = Doc::new();
+        let notes = doc.get_or_insert_map(NOTES);
+        if path.exists() {
+            let stored = fs::read(&path)
+                .map_err(|e| NotesError::Io(format!("Failed to read {}: {}", FILE, e)))?;
+            let state = match cipher {
+                Some(cipher) => cipher.decrypt_bytes(&stored)?,
+                None if vault::is_encrypted_bytes(&stored) => return Err(NotesError::Locked),
+                None => stored,
+            };
+            apply_update(&doc, &state)?;
+        }
+        Ok(NoteDoc { path, doc, notes })
+    }
+
+    fn save(&self, cipher: Option<&Cipher>) -> Result<(), NotesError> {
+        let state = self
+            .doc
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default());
+        let stored = match cipher {
+            Some(cipher) => cipher.encrypt_bytes(&state),
+            None => state,
+        };
+        fsutil::write_atomic(&self.path, &stored)
+            .map_err(|e| NotesError::Io(format!("Failed to write {}: {}", FILE, e)))
+    }
+
+    /// Records how `notes` differ from the document: new and changed notes,
+    /// and those deleted since.
+    fn record(&self, notes: &[Note]) -> Result<(), NotesError> {
+        let ids: HashSet<&str> = notes.iter().map(|note| note.id.as_str()).collect();
+        let gone: Vec<String> = {
+            let txn = self.doc.transact();
+            self.notes
+                .keys(&txn)
+                .filter(|id| !ids.contains(id))
+                .map(str::to_string)
+                .collect()
+        };
+        for id in gone {
+            let text = self.doc.get_or_insert_text(id.as_str());
+            let mut txn = self.doc.transact_mut();
+            self.notes.remove(&mut txn, &id);
+            let len = text.len(&txn);
+            text.remove_range(&mut txn, 0, len);
+        }
+
+        for note in notes {
+            let meta = meta_json(note)?;
+            let text = self.doc.get_or_insert_text(note.id.as_str());
+            let mut txn = self.doc.transact_mut();
+            if self.meta(&txn, &note.id).as_deref() != Some(meta.as_str()) {
+                self.notes.insert(&mut txn, note.id.as_str(), meta);
+            }
+            let content = if note.locked { "" } else { &note.content };
+            let old = text.get_string(&txn);
+            if old != content {
+                // Applied from the end, so earlier offsets stay valid
+                let diff = TextDiff::from_chars(old.as_str(), content);
+                let (old_offsets, new_offsets) = (byte_offsets(&old), byte_offsets(content));
+                for op in diff.ops().iter().rev() {
+                    let (old_range, new_range) = match *op {
+                        DiffOp::Equal { .. } => continue,
+                        DiffOp::Delete {
+                            old_index, old_len, ..
+                        } => (old_index..old_index + old_len, 0..0),
+                        DiffOp::Insert {
+                            old_index,
+                            new_index,
+                            new_len,
+                        } => (old_index..old_index, new_index..new_index + new_len),
+                        DiffOp::Replace {
+                            old_index,
+                            old_len,
+                            new_index,
+                            new_len,
+                        } => (
+                            old_index..old_index + old_len,
+                            new_index..new_index + new_len,
+                        ),
+                    };
+                    let start = old_offsets[old_range.start];
+                    let removed = old_offsets[old_range.end] - start;
+                    if removed > 0 {
+                        text.remove_range(&mut txn, start, removed);
+                    }
+                    if !new_range.is_empty() {
+                        let from = new_offsets[new_range.start] as usize;
+                        let to = new_offsets[new_range.end] as usize;
+                        text.insert(&mut txn, start, &content[from..to]);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn meta<T: ReadTxn>(&self, txn: &T, id: &str) -> Option<String> {
+        match self.notes.get(txn, id) {
+            Some(Out::Any(Any::String(json))) => Some(json.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Every note the document holds. Entries that don't parse are skipped.
+    fn notes(&self) -> Vec<Note> {
+        let ids: Vec<String> = {
+            let txn = self.doc.transact();
+            self.notes.keys(&txn).map(str::to_string).collect()
+        };
+        ids.into_iter()
+            .filter(|id| note::is_valid_id(id))
+            .filter_map(|id| {
+                let text = self.doc.get_or_insert_text(id.as_str());
+                let txn = self.doc.transact();
+                let mut note: Note = serde_json::from_str(&self.meta(&txn, &id)?).ok()?;
+                if note.id != id {
+                    return None;
+                }
+                if !note.locked {
+                    note.content = text.get_string(&txn);
+                }
+                Some(note)
+            })
+            .collect()
+    }
+}
+
+/// The note as kept in the `notes` map: without its content, unless that's
+/// sealed.
+fn meta_json(note: &Note) -> Result<String, NotesError> {
+    let mut meta = note.clone();
+    if !meta.locked {
+        meta.content.clear();
+    }
+    serde_json::to_string(&meta)
+        .map_err(|e| NotesError::Serde(format!("Failed to serialize note: {}", e)))
+}
+
+/// The byte offset of each character of `text`, and of its end.
+fn byte_offsets(text: &str) -> Vec<u32> {
+    text.char_indices()
+        .map(|(offset, _)| offset as u32)
+        .chain([text.len() as u32])
+        .collect()
+}
+
+fn apply_update(doc: &Doc, update: &[u8]) -> Result<(), NotesError> {
+    let update = Update::decode_v1(update)
+        .map_err(|e| NotesError::Invalid(format!("Failed to read changes: {}", e)))?;
+    doc.transact_mut()
+        .apply_update(update)
+        .map_err(|e| NotesError::Invalid(format!("Failed to apply changes: {}", e)))
+}
+
+/// Returns the changes to `store`, the notes in `data_dir`, that a device
+/// with state vector `since` hasn't seen, or all of them if `since` is empty.
+pub fn local_changes(
+    data_dir: &Path,
+    store: &NotesStore,
+    since: &[u8],
+) -> Result<LocalChanges, NotesError> {
+    let since = if since.is_empty() {
+        StateVector::default()
+    } else {
+        StateVector::decode_v1(since)
+            .map_err(|e| NotesError::Invalid(format!("Failed to read state vector: {}", e)))?
+    };
+    let doc = NoteDoc::open(data_dir, store.cipher())?;
+    doc.record(store.all_notes()?)?;
+    doc.save(store.cipher())?;
+    let txn = doc.doc.transact();
+    Ok(LocalChanges {
+        changes: txn.encode_state_as_update_v1(&since),
+        state_vector: txn.state_vector().encode_v1(),
+    })
+}
+
+/// Merges `changes` from another device's `local_changes` into `store`, the
+/// notes in `data_dir`.
+pub fn apply_remote_changes(
+    data_dir: &Path,
+    store: &mut NotesStore,
+    changes: &[u8],
+) -> Result<CrdtReport, NotesError> {
+    let cipher = store.cipher().cloned();
+    let doc = NoteDoc::open(data_dir, cipher.as_ref())?;
+    doc.record(store.all_notes()?)?;
+    apply_update(&doc.doc, changes)?;
+
+    let merged = doc.notes();
+    let mut report = CrdtReport::default();
+    let kept: HashSet<&str> = merged.iter().map(|note| note.id.as_str()).collect();
+    let gone: Vec<String> = store
+        .all_notes()?
+        .iter()
+        .filter(|note| !kept.contains(note.id.as_str()))
+        .map(|note| note.id.clone())
+        .collect();
+    report.deleted = store.delete_many(&gone)?;
+    for note in merged {
+        if store.get(&note.id).ok().as_ref() != Some(&note) {
+            store.upsert(note)?;
+            report.updated += 1;
+        }
+    }
+    doc.save(cipher.as_ref())?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+    use tempfile::TempDir;
+
+    fn device() -> (TempDir, NotesStore) {
+        let dir = TempDir::new().unwrap();
+        let store = NotesStore::new(Storage::open(dir.path()).unwrap()).unwrap();
+        (dir, store)
+    }
+
+    fn exchange(from: &(TempDir, NotesStore), to: &mut (TempDir, NotesStore)) -> CrdtReport {
+        let since = local_changes(to.0.path(), &to.1, &[]).unwrap().state_vector;
+        let changes = local_changes(from.0.path(), &from.1, &since).unwrap();
+        apply_remote_changes(to.0.path(), &mut to.1, &changes.changes).unwrap()
+    }
+
+    fn edit(store: &mut NotesStore, id: &str, content: &str) {
+        store
+            .update(id, |note| note.content = content.into())
+            .unwrap();
+    }
+
+    #[test]
+    fn merges_concurrent_edits() {
+        let (mut a, mut b) = (device(), device());
+        let note = note::new("List".into(), "milk\neggs\n".into(), Vec::new(), 1);
+        let id = note.id.clone();
+        a.1.insert(note).unwrap();
+        assert_eq!(
+            exchange(&a, &mut b),
+            CrdtReport {
+                updated: 1,
+                deleted: 0
+            }
+        );
+
+        edit(&mut a.1, &id, "oat milk\neggs\n");
+        edit(&mut b.1, &id, "milk\neggs\nbread 🍞\n");
+        exchange(&a, &mut b);
+        exchange(&b, &mut a);
+        assert_eq!(a.1.get(&id).unwrap().content, "oat milk\neggs\nbread 🍞\n");
+        assert_eq!(b.1.get(&id).unwrap().content, "oat milk\neggs\nbread 🍞\n");
+
+        a.1.delete(&id).unwrap();
+        assert_eq!(exchange(&a, &mut b).deleted, 1);
+        assert!(b.1.get(&id).is_err());
+    }
+}
@@ -0,0 +1,352 @@
+//! A plain folder of Markdown files, such as an Obsidian vault or a folder
+//! synced by another tool, kept in step with the notes.
+//!
+//! Notes are written like the Markdown storage backend writes them, as
+//! `<title>.md` with the note's fields in YAML frontmatter, and trashed ones
+//! go in .trash/. Files written by other apps, without that frontmatter, are
+//! read the way `import_directory` reads them and given an id derived from
+//! their path. A file's ETag is the hash of its contents.
+
+use super::{BlobStore, Remote};
+use crate::error::NotesError;
+use crate::fsutil;
+use crate::import;
+use crate::note::{self, Note};
+use crate::storage;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tracing::warn;
+
+const TRASH_DIR: &str = ".trash";
+const ATTACHMENTS_DIR: &str = ".attachments";
+
+/// Folder sync settings, persisted in folder_sync.json.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FolderConfig {
+    pub path: PathBuf,
+}
+
+/// The file holding a note, and the title it had when last read or written.
+struct NoteFile {
+    path: PathBuf,
+    title: String,
+}
+
+pub struct Folder {
+    dir: PathBuf,
+    /// Which file holds each note, as of the last `list`.
+    files: RefCell<HashMap<String, NoteFile>>,
+}
+
+fn hash(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+impl Folder {
+    pub fn new(config: &FolderConfig) -> Result<Self, NotesError> {
+        fs::create_dir_all(&config.path)
+            .map_err(|e| NotesError::Io(format!("Failed to create sync folder: {}", e)))?;
+        Ok(Folder {
+            dir: config.path.clone(),
+            files: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Every .md file in the folder and its subfolders, skipping hidden
+    /// ones other than the trash.
+    fn note_paths(&self) -> Result<Vec<PathBuf>, NotesError> {
+        let mut paths = Vec::new();
+        let mut pending = vec![self.dir.clone()];
+        while let Some(folder) = pending.pop() {
+            let entries = fs::read_dir(&folder).map_err(|e| {
+                NotesError::Io(format!("Failed to read {}: {}", folder.display(), e))
+            })?;
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let name = entry.file_name();
+                let hidden = name.to_string_lossy().starts_with('.');
+                let Ok(file_type) = entry.file_type() else {
+                    continue;
+                };
+                if file_type.is_dir() {
+                    if !hidden || (folder == self.dir && name == TRASH_DIR) {
+                        pending.push(path);
+                    }
+                } else if file_type.is_file()
+                    && !hidden
+                    && path.extension().is_some_and(|ext| ext == "md")
+                {
+                    paths.push(path);
+                }
+            }
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// Reads the note in the file at `path`, returning it with the file's
+    /// ETag.
+    fn read(&self, path: &Path) -> Result<(Note, String), NotesError> {
+        let bytes = fs::read(path)
+            .map_err(|e| NotesError::Io(format!("Failed to read {}: {}", path.display(), e)))?;
+        let text = String::from_utf8(bytes)
+            .map_err(|_| NotesError::Invalid(format!("{} is not UTF-8 text", path.display())))?;
+        let etag = hash(text.as_bytes());
+        if let Ok(note) = storage::parse_markdown_note(&text) {
+            if note::is_valid_id(&note.id) {
+                return Ok((note, etag));
+            }
+        }
+        Ok((self.read_foreign(path, &text)?, etag))
+    }
+
+    /// Reads a file written by another app.
+    fn read_foreign(&self, path: &Path, text: &str) -> Result<Note, NotesError> {
+        let relative = path.strip_prefix(&self.dir).unwrap_or(path);
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let metadata = fs::metadata(path)
+            .map_err(|e| NotesError::Io(format!("Failed to read {}: {}", path.display(), e)))?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|since| since.as_secs() as i64);
+
+        let mut imported = import::folder::parse(&stem, text);
+        imported.created_at = modified;
+        imported.updated_at = modified;
+        let mut note = imported.into_note();
+        // Stays the same until the file is moved, and then it's a new note
+        let path_hash = hash(relative.to_string_lossy().replace('\\', "/").as_bytes());
+        note.id = format!("file-{}", &path_hash[..32]);
+        if relative.starts_with(TRASH_DIR) {
+            note.trashed_at = Some(note.updated_at);
+        }
+        Ok(note)
+    }
+
+    /// Picks the file for `note`: the one it's in, unless it was renamed or
+    /// moved in or out of the trash, otherwise the first free `<slug>.md`,
+    /// `<slug>-2.md`, ….
+    fn path_for(&self, note: &Note) -> PathBuf {
+        let folder = if note.trashed_at.is_some() {
+            self.dir.join(TRASH_DIR)
+        } else {
+            self.dir.clone()
+        };
+        if let Some(current) = self.files.borrow().get(&note.id) {
+            let in_trash = current.path.starts_with(self.dir.join(TRASH_DIR));
+            if current.title == note.title && in_trash == note.trashed_at.is_some() {
+                return current.path.clone();
+            }
+        }
+        let slug = note::slugify(&note.title);
+        let mut candidate = folder.join(format!("{}.md", slug));
+        let mut n = 2;
+        while candidate.exists() {
+            candidate = folder.join(format!("{}-{}.md", slug, n));
+            n += 1;
+        }
+        candidate
+    }
+
+    fn path_of(&self, id: &str) -> Result<PathBuf, NotesError> {
+        self.files
+            .borrow()
+            .get(id)
+            .map(|file| file.path.clone())
+            .ok_or_else(|| NotesError::NotFound(format!("No file holds note {}", id)))
+    }
+}
+
+impl Remote for Folder {
+    fn list(&self) -> Result<HashMap<String, String>, NotesError> {
+        let mut files = HashMap::new();
+        let mut etags = HashMap::new();
+        for path in self.note_paths()? {
+            let (note, etag) = match self.read(&path) {
+                Ok(read) => read,
+                Err(e) => {
+                    warn!("Skipping {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            if files.contains_key(&note.id) {
+                warn!(
+                    "Skipping {}, which holds the same note as another file",
+                    path.display()
+                );
+                continue;
+            }
+            etags.insert(note.id.clone(), etag);
+            files.insert(
+                note.id,
+                NoteFile {
+                    path,
+                    title: note.title,
+                },
+            );
+        }
+        *self.files.borrow_mut() = files;
+        Ok(etags)
+    }
+
+    fn get(&self, id: &str) -> Result<(Note, String), NotesError> {
+        self.read(&self.path_of(id)?)
+    }
+
+    fn put(&self, note: &Note) -> Result<String, NotesError> {
+        let path = self.path_for(note);
+        let text = storage::render_markdown_note(note)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| NotesError::Io(format!("Failed to create sync folder: {}", e)))?;
+        }
+        fsutil::write_atomic(&path, text.as_bytes())
+            .map_err(|e| NotesError::Io(format!("Failed to write {}: {}", path.display(), e)))?;
+
+        let previous = self.files.borrow_mut().insert(
+            note.id.clone(),
+            NoteFile {
+                path: path.clone(),
+                title: note.title.clone(),
+            },
+        );
+        if let Some(previous) = previous.filter(|previous| previous.path != path) {
+            fs::remove_file(&previous.path).map_err(|e| {
+                NotesError::Io(format!(
+                    "Failed to remove {}: {}",
+                    previous.path.display(),
+                    e
+                ))
+            })?;
+        }
+        Ok(hash(text.as_bytes()))
+    }
+
+    fn delete(&self, id: &str) -> Result<(), NotesError> {
+        let path = self.path_of(id)?;
+        fs::remove_file(&path)
+            .map_err(|e| NotesError::Io(format!("Failed to remove {}: {}", path.display(), e)))?;
+        self.files.borrow_mut().remove(id);
+        Ok(())
+    }
+}
+
+impl BlobStore for Folder {
+    fn list_blobs(&self) -> Result<HashSet<String>, NotesError> {
+        let dir = self.dir.join(ATTACHMENTS_DIR);
+        if !dir.exists() {
+            return Ok(HashSet::new());
+        }
+        let entries = fs::read_dir(&dir)
+            .map_err(|e| NotesError::Io(format!("Failed to list synced attachments: {}", e)))?;
+        Ok(entries
+            .flatten()
+            .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+            .filter(|name| note::is_valid_id(name))
+            .collect())
+    }
+
+    fn get_blob(&self, hash: &str) -> Result<Vec<u8>, NotesError> {
+        fs::read(self.dir.join(ATTACHMENTS_DIR).join(hash))
+            .map_err(|e| NotesError::Io(format!("Failed to read synced attachment: {}", e)))
+    }
+
+    fn put_blob(&self, hash: &str, content: &[u8]) -> Result<(), NotesError> {
+        let dir = self.dir.join(ATTACHMENTS_DIR);
+        fs::create_dir_all(&dir)
+            .and_then(|_| fsutil::write_atomic(&dir.join(hash), content))
+            .map_err(|e| NotesError::Io(format!("Failed to write synced attachment: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use tempfile::TempDir;
+
+    fn note(id: &str, title: &str) -> Note {
+        Note {
+            id: id.into(),
+            title: title.into(),
+            content: "Body\n".into(),
+            created_at: 1,
+            updated_at: 1,
+            tags: Vec::new(),
+            trashed_at: None,
+            pinned: false,
+            archived: false,
+            favorite: false,
+            locked: false,
+            content_hash: String::new(),
+            color: None,
+            notebook_id: None,
+            attachments: Vec::new(),
+            reminders: Vec::new(),
+            expiry: None,
+            location: None,
+            meta: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn reads_own_and_foreign_files() {
+        let dir = TempDir::new().unwrap();
+        let folder = Folder::new(&FolderConfig {
+            path: dir.path().to_path_buf(),
+        })
+        .unwrap();
+        fs::create_dir_all(dir.path().join("projects/.git")).unwrap();
+        fs::write(dir.path().join("projects/idea.md"), "# Big idea\n\nText").unwrap();
+        fs::write(dir.path().join("projects/.git/x.md"), "").unwrap();
+        let etag = folder.put(&note("a", "Plan")).unwrap();
+
+        let etags = folder.list().unwrap();
+        assert_eq!(etags.len(), 2);
+        assert_eq!(etags["a"], etag);
+        let foreign = etags.keys().find(|id| *id != "a").unwrap();
+        assert!(note::is_valid_id(foreign));
+        let (idea, _) = folder.get(foreign).unwrap();
+        assert_eq!(
+            (idea.title.as_str(), idea.content.as_str()),
+            ("Big idea", "Text")
+        );
+        // Listing again gives the file the same id
+        assert!(folder.list().unwrap().contains_key(foreign));
+    }
+
+    #[test]
+    fn moves_renamed_and_trashed_notes() {
+        let dir = TempDir::new().unwrap();
+        let folder = Folder::new(&FolderConfig {
+            path: dir.path().to_path_buf(),
+        })
+        .unwrap();
+        let mut plan = note("a", "Plan");
+        folder.put(&plan).unwrap();
+        plan.content = "Edited".into();
+        folder.put(&plan).unwrap();
+        assert!(dir.path().join("plan.md").exists());
+
+        plan.title = "New plan".into();
+        folder.put(&plan).unwrap();
+        assert!(!dir.path().join("plan.md").exists());
+
+        plan.trashed_at = Some(2);
+        folder.put(&plan).unwrap();
+        assert!(dir.path().join(".trash/new-plan.md").exists());
+        assert_eq!(folder.list().unwrap().len(), 1);
+        folder.delete("a").unwrap();
+        assert!(folder.list().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,868 @@
+//! Two-way sync of the note store with a remote copy.
+//!
+//! Every note is stored remotely as its own file, and every attachment as a
+//! file named after its hash. After each sync the state
+//! file records a fingerprint of every note and the remote file's ETag, so
+//! the next sync can tell which side changed a note. When both did, the
+//! local version wins and the remote one is kept as a "conflicted copy" note
+//! instead of being overwritten. Transports that exchange changes rather
+//! than files use `crdt` instead, which merges concurrent edits, and a
+//! self-hosted `server` hands out the changes since the last sync.
+//!
+//! WebDAV, folder and server sync write notes decrypted, so they're refused while
+//! the vault is enabled. S3 payloads are encrypted client-side with a key
+//! derived from the sync passphrase.
+
+mod crdt;
+mod folder;
+mod s3;
+mod server;
+mod webdav;
+
+use crate::error::NotesError;
+use crate::fsutil;
+use crate::keychain::{self, Secret};
+use crate::note::{self, Note};
+use crate::store::NotesStore;
+use chrono::Utc;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+pub use crdt::{apply_remote_changes, local_changes, CrdtReport, LocalChanges};
+pub use folder::{Folder, FolderConfig};
+pub use s3::{S3Config, S3Secrets, S3};
+pub use server::{ChangeFeed, ChangeSet, Server, ServerConfig, Tombstone};
+pub use webdav::WebDav;
+
+fn not_configured() -> NotesError {
+    NotesError::Invalid("Sync has not been configured".into())
+}
+
+/// Fails if notes can't be synced with `target` given whether the vault is
+/// enabled.
+pub fn check_target(target: SyncTarget, vault_enabled: bool) -> Result<(), NotesError> {
+    let name = match target {
+        SyncTarget::WebDav => "WebDAV",
+        SyncTarget::Folder => "Folder",
+        SyncTarget::Server => "Server",
+        SyncTarget::S3 => return Ok(()),
+    };
+    if vault_enabled {
+        return Err(NotesError::Invalid(format!(
+            "{} sync writes notes unencrypted, so it's unavailable while the vault is \
+             enabled. Use S3 sync instead.",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Somewhere notes can be synced to, addressed by note id.
+pub trait Remote: BlobStore {
+    /// Returns the id and ETag of every note stored remotely.
+    fn list(&self) -> Result<HashMap<String, String>, NotesError>;
+    /// Downloads note `id` along with the ETag of the version downloaded.
+    fn get(&self, id: &str) -> Result<(Note, String), NotesError>;
+    /// Uploads `note` and returns the ETag of the new version.
+    fn put(&self, note: &Note) -> Result<String, NotesError>;
+    fn delete(&self, id: &str) -> Result<(), NotesError>;
+}
+
+/// Where synced attachments are kept, addressed by the hash of their content.
+pub trait BlobStore {
+    /// Returns the hash of every attachment blob stored remotely.
+    fn list_blobs(&self) -> Result<HashSet<String>, NotesError>;
+    fn get_blob(&self, hash: &str) -> Result<Vec<u8>, NotesError>;
+    fn put_blob(&self, hash: &str, content: &[u8]) -> Result<(), NotesError>;
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncTarget {
+    #[default]
+    WebDav,
+    S3,
+    /// A folder of Markdown files on this computer.
+    Folder,
+    /// A self-hosted sync server.
+    Server,
+}
+
+impl SyncTarget {
+    fn config_path(self, data_dir: &Path) -> PathBuf {
+        data_dir.join(match self {
+            SyncTarget::WebDav => "sync.json",
+            SyncTarget::S3 => "s3_sync.json",
+            SyncTarget::Folder => "folder_sync.json",
+            SyncTarget::Server => "server_sync.json",
+        })
+    }
+
+    fn state_path(self, data_dir: &Path) -> PathBuf {
+        data_dir.join(match self {
+            SyncTarget::WebDav => "sync_state.json",
+            SyncTarget::S3 => "s3_sync_state.json",
+            SyncTarget::Folder => "folder_sync_state.json",
+            SyncTarget::Server => "server_sync_state.json",
+        })
+    }
+}
+
+/// WebDAV settings, persisted in sync.json. The password is kept in the OS
+/// keychain.
+#[derive(Serialize, Deserialize)]
+pub struct SyncConfig {
+    /// URL of the WebDAV folder holding the notes.
+    pub url: String,
+    pub username: String,
+    /// Only set in files written before the password moved to the keychain.
+    #[serde(default, skip_serializing)]
+    password: Option<String>,
+}
+
+impl SyncConfig {
+    pub fn new(url: String, username: String) -> Self {
+        SyncConfig {
+            url,
+            username,
+            password: None,
+        }
+    }
+}
+
+/// Returns the settings for `target`, or `None` if it hasn't been configured.
+pub fn load_config<T: DeserializeOwned>(
+    data_dir: &Path,
+    target: SyncTarget,
+) -> Result<Option<T>, NotesError> {
+    let path = target.config_path(data_dir);
+    if !path.exists() {
+        return Ok(None);
+    }
+    fsutil::read_with_backup(&path, |content| {
+        serde_json::from_str(content)
+            .map_err(|e| NotesError::Serde(format!("Failed to parse sync config: {}", e)))
+    })
+    .map(Some)
+}
+
+/// Saves new settings for `target` and forgets what was synced with it
+/// before, since the remote may be a different one.
+pub fn save_config<T: Serialize>(
+    data_dir: &Path,
+    target: SyncTarget,
+    config: &T,
+) -> Result<(), NotesError> {
+    write_config(data_dir, target, config)?;
+    let state = target.state_path(data_dir);
+    if state.exists() {
+        fsutil::remove_with_backup(&state)
+            .map_err(|e| NotesError::Io(format!("Failed to reset sync state: {}", e)))?;
+    }
+    Ok(())
+}
+
+fn write_config<T: Serialize>(
+    data_dir: &Path,
+    target: SyncTarget,
+    config: &T,
+) -> Result<(), NotesError> {
+    let json = serde_json::to_string_pretty(config)
+        .map_err(|e| NotesError::Serde(format!("Failed to serialize sync config: {}", e)))?;
+    fsutil::write_with_backup(&target.config_path(data_dir), json.as_bytes())
+        .map_err(|e| NotesError::Io(format!("Failed to write sync config: {}", e)))
+}
+
+/// Stores the WebDAV password in workspace `workspace`'s keychain entry and
+/// creates the folder if needed before saving the settings.
+pub fn configure_webdav(
+    data_dir: &Path,
+    workspace: &str,
+    config: &SyncConfig,
+    password: &str,
+) -> Result<(), NotesError> {
+    keychain::set(Secret::WebDavPassword, workspace, password)?;
+    WebDav::new(config, password).ensure_collection()?;
+    save_config(data_dir, SyncTarget::WebDav, config)
+}
+
+/// Stores the S3 credentials in workspace `workspace`'s keychain entry and
+/// checks that they, and the passphrase, work before saving the settings.
+pub fn configure_s3(
+    data_dir: &Path,
+    workspace: &str,
+    config: &S3Config,
+    secrets: S3Secrets,
+) -> Result<(), NotesError> {
+    secrets.save(workspace)?;
+    S3::connect(config, secrets)?.list()?;
+    save_config(data_dir, SyncTarget::S3, config)
+}
+
+/// Creates the folder if needed before saving the settings.
+pub fn configure_folder(data_dir: &Path, config: &FolderConfig) -> Result<(), NotesError> {
+    Folder::new(config)?;
+    save_config(data_dir, SyncTarget::Folder, config)
+}
+
+/// Stores the token in workspace `workspace`'s keychain entry and checks
+/// that the server accepts it before saving the settings.
+pub fn configure_server(
+    data_dir: &Path,
+    workspace: &str,
+    config: &ServerConfig,
+    token: &str,
+) -> Result<(), NotesError> {
+    keychain::set(Secret::ServerToken, workspace, token)?;
+    Server::new(config, token).list_blobs()?;
+    save_config(data_dir, SyncTarget::Server, config)
+}
+
+/// Stops syncing with `target`, forgetting its settings and what was synced.
+/// What was synced stays where it is on both sides.
+pub fn remove_config(data_dir: &Path, target: SyncTarget) -> Result<(), NotesError> {
+    for path in [target.config_path(data_dir), target.state_path(data_dir)] {
+        if path.exists() {
+            fsutil::remove_with_backup(&path)
+                .map_err(|e| NotesError::Io(format!("Failed to remove sync config: {}", e)))?;
+        }
+    }
+    Ok(())
+}
+
+/// What a note looked like on both sides after it was last synced.
+#[derive(Serialize, Deserialize)]
+struct SyncedNote {
+    fingerprint: String,
+    etag: String,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct SyncState {
+    notes: HashMap<String, SyncedNote>,
+    /// Where the next pull from a sync server picks up.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cursor: Option<String>,
+    #[serde(default)]
+    last_synced_at: Option<i64>,
+    /// Why the most recent sync failed, if it did.
+    #[serde(default)]
+    last_error: Option<String>,
+}
+
+impl SyncState {
+    fn load(data_dir: &Path, target: SyncTarget) -> Result<Self, NotesError> {
+        let path = target.state_path(data_dir);
+        if !path.exists() {
+            return Ok(SyncState::default());
+        }
+        fsutil::read_with_backup(&path, |content| {
+            serde_json::from_str(content)
+                .map_err(|e| NotesError::Serde(format!("Failed to parse sync state: {}", e)))
+        })
+    }
+
+    fn save(&self, data_dir: &Path, target: SyncTarget) -> Result<(), NotesError> {
+        let json = serde_json::to_string(self)
+            .map_err(|e| NotesError::Serde(format!("Failed to serialize sync state: {}", e)))?;
+        fsutil::write_with_backup(&target.state_path(data_dir), json.as_bytes())
+            .map_err(|e| NotesError::Io(format!("Failed to write sync state: {}", e)))
+    }
+}
+
+#[derive(Serialize, Default)]
+pub struct SyncReport {
+    pub pushed: usize,
+    pub pulled: usize,
+    pub blobs_pushed: usize,
+    pub blobs_pulled: usize,
+    pub deleted_local: usize,
+    pub deleted_remote: usize,
+    /// Remote versions kept as conflicted copies.
+    pub conflicts: usize,
+}
+
+impl SyncReport {
+    /// Whether the sync changed any local notes.
+    pub fn changed_local(&self) -> bool {
+        self.pulled > 0 || self.deleted_local > 0 || self.conflicts > 0 || self.blobs_pulled > 0
+    }
+}
+
+#[derive(Serialize)]
+pub struct SyncStatus {
+    pub configured: bool,
+    pub last_synced_at: Option<i64>,
+    pub last_error: Option<String>,
+    /// How many notes were in sync after the last successful sync.
+    pub synced_notes: usize,
+}
+
+pub fn status(data_dir: &Path, target: SyncTarget) -> Result<SyncStatus, NotesError> {
+    let state = SyncState::load(data_dir, target)?;
+    Ok(SyncStatus {
+        configured: target.config_path(data_dir).exists(),
+        last_synced_at: state.last_synced_at,
+        last_error: state.last_error,
+        synced_notes: state.notes.len(),
+    })
+}
+
+/// A sync target, connected to.
+enum Connection {
+    /// One holding a file per note.
+    Files(Box<dyn Remote>),
+    /// One handing out changes.
+    Changes(Server),
+}
+
+fn connect(data_dir: &Path, workspace: &str, target: SyncTarget) -> Result<Connection, NotesError> {
+    let remote: Box<dyn Remote> = match target {
+        SyncTarget::WebDav => {
+            let mut config: SyncConfig =
+                load_config(data_dir, target)?.ok_or_else(not_configured)?;
+            // Move a password saved by an older version into the keychain
+            let password = match config.password.take() {
+                Some(password) => {
+                    keychain::set(Secret::WebDavPassword, workspace, &password)?;
+                    write_config(data_dir, target, &config)?;
+                    password
+                }
+                None => keychain::require(Secret::WebDavPassword, workspace)?,
+            };
+            Box::new(WebDav::new(&config, &password))
+        }
+        SyncTarget::S3 => {
+            let config: S3Config = load_config(data_dir, target)?.ok_or_else(not_configured)?;
+            Box::new(S3::connect(&config, S3Secrets::load(workspace)?)?)
+        }
+        SyncTarget::Folder => {
+            let config: FolderConfig = load_config(data_dir, target)?.ok_or_else(not_configured)?;
+            Box::new(Folder::new(&config)?)
+        }
+        SyncTarget::Server => {
+            let config: ServerConfig = load_config(data_dir, target)?.ok_or_else(not_configured)?;
+            let token = keychain::require(Secret::ServerToken, workspace)?;
+            return Ok(Connection::Changes(Server::new(&config, &token)));
+        }
+    };
+    Ok(Connection::Files(remote))
+}
+
+/// Syncs `store`, workspace `workspace`'s notes, with `target` and records
+/// the outcome for `status`.
+pub fn run(
+    data_dir: &Path,
+    workspace: &str,
+    target: SyncTarget,
+    store: &mut NotesStore,
+) -> Result<SyncReport, NotesError> {
+    let mut state = SyncState::load(data_dir, target)?;
+    let vault_enabled = store.is_locked() || store.cipher().is_some();
+    let result = check_target(target, vault_enabled)
+        .and_then(|_| connect(data_dir, workspace, target))
+        .and_then(|connection| match connection {
+            Connection::Files(remote) => sync(store, remote.as_ref(), &mut state),
+            Connection::Changes(server) => server::sync(store, &server, &mut state),
+        });
+    match &result {
+        Ok(_) => {
+            state.last_synced_at = Some(Utc::now().timestamp());
+            state.last_error = None;
+        }
+        Err(e) => {
+            warn!("Sync failed: {}", e);
+            state.last_error = Some(e.to_string());
+        }
+    }
+    state.save(data_dir, target)?;
+    result
+}
+
+/// Syncs every note, trashed ones included, between `store` and `remote`.
+/// `state` must be saved afterwards for the next sync to see what changed.
+fn sync(
+    store: &mut NotesStore,
+    remote: &dyn Remote,
+    state: &mut SyncState,
+) -> Result<SyncReport, NotesError> {
+    let mut remote_etags = remote.list()?;
+    remote_etags.retain(|id, _| {
+        let valid = note::is_valid_id(id);
+        if !valid {
+            warn!("Skipping synced file with invalid note id {:?}", id);
+        }
+        valid
+    });
+    let local: HashMap<String, Note> = store
+        .all_notes()?
+        .iter()
+        .map(|note| (note.id.clone(), note.clone()))
+        .collect();
+    let ids: BTreeSet<&String> = local
+        .keys()
+        .chain(remote_etags.keys())
+        .chain(state.notes.keys())
+        .collect();
+
+    // What each note's remote file will look like once this sync is done:
+    // untouched files keep the ETag they were listed with, transferred ones
+    // take the ETag of the version sent or received. Listing again afterwards
+    // could pick up another device's upload that this sync never merged.
+    let mut etags = remote_etags.clone();
+    let mut report = SyncReport::default();
+    for id in ids {
+        let base = state.notes.get(id);
+        let local_changed = match (local.get(id), base) {
+            (Some(note), Some(base)) => fingerprint(note) != base.fingerprint,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        let remote_changed = match (remote_etags.get(id), base) {
+            (Some(etag), Some(base)) => *etag != base.etag,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+
+        match (local.get(id), remote_etags.contains_key(id)) {
+            (Some(note), true) => match (local_changed, remote_changed) {
+                (false, false) => {}
+                (true, false) => {
+                    etags.insert(id.clone(), remote.put(note)?);
+                    report.pushed += 1;
+                }
+                (false, true) => {
+                    let (theirs, etag) = fetch(remote, id)?;
+                    store.upsert(theirs)?;
+                    etags.insert(id.clone(), etag);
+                    report.pulled += 1;
+                }
+                (true, true) => {
+                    let (theirs, _) = fetch(remote, id)?;
+                    if fingerprint(&theirs) != fingerprint(note) {
+                        let copy = conflicted_copy(theirs);
+                        etags.insert(copy.id.clone(), remote.put(&copy)?);
+                        store.insert(copy)?;
+                        report.conflicts += 1;
+                    }
+                    etags.insert(id.clone(), remote.put(note)?);
+                    report.pushed += 1;
+                }
+            },
+            // Deleted remotely since the last sync, unless it's new or was
+            // edited here since
+            (Some(note), false) => {
+                if base.is_some() && !local_changed {
+                    store.delete(id)?;
+                    report.deleted_local += 1;
+                } else {
+                    etags.insert(id.clone(), remote.put(note)?);
+                    report.pushed += 1;
+                }
+            }
+            (None, true) => {
+                if base.is_some() && !remote_changed {
+                    remote.delete(id)?;
+                    etags.remove(id);
+                    report.deleted_remote += 1;
+                } else {
+                    let (theirs, etag) = fetch(remote, id)?;
+                    store.insert(theirs)?;
+                    etags.insert(id.clone(), etag);
+                    report.pulled += 1;
+                }
+            }
+            (None, false) => {}
+        }
+    }
+
+    sync_blobs(store, remote, &mut report)?;
+
+    state.notes = store
+        .all_notes()?
+        .iter()
+        .filter_map(|note| {
+            let etag = etags.get(&note.id)?.clone();
+            Some((
+                note.id.clone(),
+                SyncedNote {
+                    fingerprint: fingerprint(note),
+                    etag,
+                },
+            ))
+        })
+        .collect();
+    Ok(report)
+}
+
+/// Uploads the attachments of local notes that the remote lacks, and
+/// downloads those it has that are missing here. Blobs are immutable and
+/// named after their content, so there's nothing to merge.
+fn sync_blobs<R: BlobStore + ?Sized>(
+    store: &mut NotesStore,
+    remote: &R,
+    report: &mut SyncReport,
+) -> Result<(), NotesError> {
+    let referenced: BTreeSet<String> = store
+        .all_notes()?
+        .iter()
+        .flat_map(|note| &note.attachments)
+        .map(|attachment| attachment.id.clone())
+        .collect();
+    if referenced.is_empty() {
+        return Ok(());
+    }
+
+    let remote_blobs = remote.list_blobs()?;
+    for hash in referenced {
+        let local = store.has_attachment(&hash)?;
+        match (local, remote_blobs.contains(&hash)) {
+            (true, false) => {
+                remote.put_blob(&hash, &store.read_attachment(&hash)?)?;
+                report.blobs_pushed += 1;
+            }
+            (false, true) => {
+                let content = remote.get_blob(&hash)?;
+                if store.put_attachment(&content)? != hash {
+                    return Err(NotesError::Invalid(format!(
+                        "Synced attachment {} doesn't match its hash",
+                        hash
+                    )));
+                }
+                report.blobs_pulled += 1;
+            }
+            (false, false) => warn!("Attachment {} is missing on both sides", hash),
+            (true, true) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Downloads note `id`, making sure the file really holds that note so it
+/// can't overwrite a different one.
+fn fetch(remote: &dyn Remote, id: &str) -> Result<(Note, String), NotesError> {
+    let (note, etag) = remote.get(id)?;
+    if note.id != id {
+        return Err(NotesError::Invalid(format!(
+            "Synced note {} contains note {} instead",
+            id, note.id
+        )));
+    }
+    Ok((note, etag))
+}
+
+/// Turns the remote side of a conflict into a separate note.
+fn conflicted_copy(note: Note) -> Note {
+    let now = Utc::now();
+    Note {
+        id: note::new_id(),
+        title: format!(
+            "{} (conflicted copy {})",
+            note.title,
+            now.format("%Y-%m-%d %H:%M")
+        ),
+        updated_at: now.timestamp(),
+        ..note
+    }
+}
+
+/// Hashes every field of `note` with 64-bit FNV-1a. Unlike `std`'s hasher
+/// this is stable across builds, so fingerprints can be saved. The content
+/// hash is left out, since notes read from files written elsewhere lack it.
+fn fingerprint(note: &Note) -> String {
+    let note = Note {
+        content_hash: String::new(),
+        ..note.clone()
+    };
+    let json = serde_json::to_vec(&note).unwrap_or_default();
+    let hash = json.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+    use std::cell::{Cell, RefCell};
+    use tempfile::TempDir;
+
+    /// A remote kept in memory, handing out a fresh ETag on every upload.
+    #[derive(Default)]
+    struct MemoryRemote {
+        notes: RefCell<HashMap<String, (Note, String)>>,
+        blobs: RefCell<HashMap<String, Vec<u8>>>,
+        next_etag: Cell<u32>,
+    }
+
+    impl MemoryRemote {
+        fn store(&self, note: Note) -> String {
+            self.next_etag.set(self.next_etag.get() + 1);
+            let etag = format!("\"{}\"", self.next_etag.get());
+            self.notes
+                .borrow_mut()
+                .insert(note.id.clone(), (note, etag.clone()));
+            etag
+        }
+
+        fn note(&self, id: &str) -> Option<Note> {
+            self.notes.borrow().get(id).map(|(note, _)| note.clone())
+        }
+    }
+
+    impl Remote for MemoryRemote {
+        fn list(&self) -> Result<HashMap<String, String>, NotesError> {
+            Ok(self
+                .notes
+                .borrow()
+                .iter()
+                .map(|(id, (_, etag))| (id.clone(), etag.clone()))
+                .collect())
+        }
+
+        fn get(&self, id: &str) -> Result<(Note, String), NotesError> {
+            self.notes
+                .borrow()
+                .get(id)
+                .cloned()
+                .ok_or_else(|| NotesError::NotFound(id.to_string()))
+        }
+
+        fn put(&self, note: &Note) -> Result<String, NotesError> {
+            Ok(self.store(note.clone()))
+        }
+
+        fn delete(&self, id: &str) -> Result<(), NotesError> {
+            self.notes.borrow_mut().remove(id);
+            Ok(())
+        }
+    }
+
+    impl BlobStore for MemoryRemote {
+        fn list_blobs(&self) -> Result<HashSet<String>, NotesError> {
+            Ok(self.blobs.borrow().keys().cloned().collect())
+        }
+
+        fn get_blob(&self, hash: &str) -> Result<Vec<u8>, NotesError> {
+            self.blobs
+                .borrow()
+                .get(hash)
+                .cloned()
+                .ok_or_else(|| NotesError::NotFound(hash.to_string()))
+        }
+
+        fn put_blob(&self, hash: &str, content: &[u8]) -> Result<(), NotesError> {
+            self.blobs
+                .borrow_mut()
+                .insert(hash.to_string(), content.to_vec());
+            Ok(())
+        }
+    }
+
+    /// A store in a fresh data directory, which lives as long as the
+    /// returned `TempDir`.
+    fn empty_store() -> (TempDir, NotesStore) {
+        let dir = TempDir::new().unwrap();
+        let store = NotesStore::new(Storage::open(dir.path()).unwrap()).unwrap();
+        (dir, store)
+    }
+
+    fn note(id: &str, title: &str) -> Note {
+        let mut note = note::new(title.to_string(), String::new(), Vec::new(), 1);
+        note.id = id.to_string();
+        note
+    }
+
+    fn titles(store: &NotesStore) -> Vec<String> {
+        let mut titles: Vec<String> = store
+            .all_notes()
+            .unwrap()
+            .iter()
+            .map(|note| note.title.clone())
+            .collect();
+        titles.sort();
+        titles
+    }
+
+    /// Syncs a store holding note "a" so that both sides start out in sync.
+    fn synced() -> (TempDir, NotesStore, MemoryRemote, SyncState) {
+        let (dir, mut store) = empty_store();
+        let remote = MemoryRemote::default();
+        let mut state = SyncState::default();
+        store.insert(note("a", "A")).unwrap();
+        sync(&mut store, &remote, &mut state).unwrap();
+        (dir, store, remote, state)
+    }
+
+    #[test]
+    fn pushes_new_local_note() {
+        let (_dir, mut store, remote, mut state) = synced();
+        assert_eq!(remote.note("a").unwrap().title, "A");
+        assert!(state.notes.contains_key("a"));
+
+        store.insert(note("b", "B")).unwrap();
+        let report = sync(&mut store, &remote, &mut state).unwrap();
+        assert_eq!(report.pushed, 1);
+        assert_eq!(remote.note("b").unwrap().title, "B");
+    }
+
+    #[test]
+    fn pulls_new_remote_note() {
+        let (_dir, mut store, remote, mut state) = synced();
+        remote.store(note("b", "B"));
+        let report = sync(&mut store, &remote, &mut state).unwrap();
+        assert_eq!(report.pulled, 1);
+        assert_eq!(titles(&store), ["A", "B"]);
+    }
+
+    #[test]
+    fn unchanged_notes_are_left_alone() {
+        let (_dir, mut store, remote, mut state) = synced();
+        let report = sync(&mut store, &remote, &mut state).unwrap();
+        assert_eq!(report.pushed + report.pulled, 0);
+        assert_eq!(remote.next_etag.get(), 1);
+    }
+
+    #[test]
+    fn pushes_local_edit() {
+        let (_dir, mut store, remote, mut state) = synced();
+        store.update("a", |note| note.title = "A2".into()).unwrap();
+        let report = sync(&mut store, &remote, &mut state).unwrap();
+        assert_eq!(report.pushed, 1);
+        assert_eq!(remote.note("a").unwrap().title, "A2");
+    }
+
+    #[test]
+    fn pulls_remote_edit() {
+        let (_dir, mut store, remote, mut state) = synced();
+        remote.store(note("a", "A2"));
+        let report = sync(&mut store, &remote, &mut state).unwrap();
+        assert_eq!(report.pulled, 1);
+        assert_eq!(store.get("a").unwrap().title, "A2");
+
+        // The pulled version is the new base, so it isn't pushed back
+        let report = sync(&mut store, &remote, &mut state).unwrap();
+        assert_eq!(report.pushed + report.pulled, 0);
+    }
+
+    #[test]
+    fn keeps_remote_side_of_conflict_as_copy() {
+        let (_dir, mut store, remote, mut state) = synced();
+        store
+            .update("a", |note| note.title = "Mine".into())
+            .unwrap();
+        remote.store(note("a", "Theirs"));
+        let report = sync(&mut store, &remote, &mut state).unwrap();
+        assert_eq!(report.conflicts, 1);
+        assert_eq!(remote.note("a").unwrap().title, "Mine");
+
+        let titles = titles(&store);
+        assert_eq!(titles.len(), 2);
+        assert_eq!(titles[0], "Mine");
+        assert!(titles[1].starts_with("Theirs (conflicted copy "));
+        assert_eq!(remote.notes.borrow().len(), 2);
+    }
+
+    #[test]
+    fn identical_edits_are_not_a_conflict() {
+        let (_dir, mut store, remote, mut state) = synced();
+        store
+            .update("a", |note| note.title = "Same".into())
+            .unwrap();
+        remote.store(store.get("a").unwrap());
+        let report = sync(&mut store, &remote, &mut state).unwrap();
+        assert_eq!(report.conflicts, 0);
+        assert_eq!(titles(&store), ["Same"]);
+    }
+
+    #[test]
+    fn applies_remote_delete() {
+        let (_dir, mut store, remote, mut state) = synced();
+        remote.delete("a").unwrap();
+        let report = sync(&mut store, &remote, &mut state).unwrap();
+        assert_eq!(report.deleted_local, 1);
+        assert!(titles(&store).is_empty());
+        assert!(state.notes.is_empty());
+    }
+
+    #[test]
+    fn local_edit_survives_remote_delete() {
+        let (_dir, mut store, remote, mut state) = synced();
+        store.update("a", |note| note.title = "A2".into()).unwrap();
+        remote.delete("a").unwrap();
+        let report = sync(&mut store, &remote, &mut state).unwrap();
+        assert_eq!(report.pushed, 1);
+        assert_eq!(remote.note("a").unwrap().title, "A2");
+    }
+
+    #[test]
+    fn applies_local_delete() {
+        let (_dir, mut store, remote, mut state) = synced();
+        store.delete("a").unwrap();
+        let report = sync(&mut store, &remote, &mut state).unwrap();
+        assert_eq!(report.deleted_remote, 1);
+        assert!(remote.note("a").is_none());
+    }
+
+    #[test]
+    fn remote_edit_survives_local_delete() {
+        let (_dir, mut store, remote, mut state) = synced();
+        store.delete("a").unwrap();
+        remote.store(note("a", "A2"));
+        let report = sync(&mut store, &remote, &mut state).unwrap();
+        assert_eq!(report.pulled, 1);
+        assert_eq!(titles(&store), ["A2"]);
+    }
+
+    #[test]
+    fn rejects_file_holding_another_note() {
+        let (_dir, mut store, remote, mut state) = synced();
+        remote
+            .notes
+            .borrow_mut()
+            .insert("b".into(), (note("c", "C"), "\"b\"".into()));
+        let result = sync(&mut store, &remote, &mut state);
+        assert!(matches!(result, Err(NotesError::Invalid(_))));
+        assert_eq!(titles(&store), ["A"]);
+    }
+
+    #[test]
+    fn skips_invalid_remote_ids() {
+        let (_dir, mut store, remote, mut state) = synced();
+        remote.store(note("../escape", "Bad"));
+        let report = sync(&mut store, &remote, &mut state).unwrap();
+        assert_eq!(report.pulled, 0);
+        assert_eq!(titles(&store), ["A"]);
+    }
+
+    #[test]
+    fn syncs_attachment_blobs() {
+        let (_dir, mut store, remote, mut state) = synced();
+        let hash = store.put_attachment(b"hello").unwrap();
+        store
+            .update("a", |note| {
+                note.attachments.push(note::Attachment {
+                    id: hash.clone(),
+                    name: "hello.txt".into(),
+                    mime: "text/plain".into(),
+                    size: 5,
+                    added_at: 1,
+                    text: None,
+                })
+            })
+            .unwrap();
+        let report = sync(&mut store, &remote, &mut state).unwrap();
+        assert_eq!(report.blobs_pushed, 1);
+        assert_eq!(remote.get_blob(&hash).unwrap(), b"hello");
+
+        let (_other_dir, mut other) = empty_store();
+        let mut other_state = SyncState::default();
+        let report = sync(&mut other, &remote, &mut other_state).unwrap();
+        assert_eq!(report.blobs_pulled, 1);
+        assert_eq!(other.read_attachment(&hash).unwrap(), b"hello");
+    }
+}
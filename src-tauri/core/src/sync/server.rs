@@ -0,0 +1,407 @@
+//! A self-hosted sync server, which keeps the notes and hands out what
+//! changed since a cursor. Every request is authorized with
+//! `Authorization: Bearer <token>`, and URLs are relative to the configured
+//! one:
+//!
+//! - `GET changes?since=<cursor>` returns every note changed or deleted
+//!   since `cursor`, or all of them without one, as
+//!   `{"cursor": "…", "notes": [<note>…], "deleted": [<tombstone>…]}`, where
+//!   a tombstone is `{"id": "…", "deleted_at": <unix seconds>}`. The cursor
+//!   is opaque to the app, which passes it back next time.
+//! - `POST changes` stores `{"notes": […], "deleted": […]}`, in the same form.
+//! - `GET blobs` returns the hashes of the attachments stored, as a JSON
+//!   array, and `GET blobs/<hash>` and `PUT blobs/<hash>` transfer one.
+//!
+//! The server sees the notes unencrypted, so use HTTPS anywhere but on a
+//! trusted network.
+
+use super::{
+    conflicted_copy, fingerprint, sync_blobs, BlobStore, SyncReport, SyncState, SyncedNote,
+};
+use crate::error::NotesError;
+use crate::note::{self, Note};
+use crate::store::NotesStore;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::Read;
+use std::time::Duration;
+use tracing::warn;
+use ureq::Agent;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Sync server settings, persisted in server_sync.json. The token is kept in
+/// the OS keychain.
+#[derive(Serialize, Deserialize)]
+pub struct ServerConfig {
+    pub url: String,
+}
+
+/// A note deleted from the server, or to be.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Tombstone {
+    pub id: String,
+    pub deleted_at: i64,
+}
+
+/// What changed on the server since a cursor.
+#[derive(Deserialize, Default)]
+pub struct ChangeSet {
+    /// Where the next pull picks up.
+    pub cursor: String,
+    #[serde(default)]
+    pub notes: Vec<Note>,
+    #[serde(default)]
+    pub deleted: Vec<Tombstone>,
+}
+
+#[derive(Serialize)]
+struct Push<'a> {
+    notes: &'a [Note],
+    deleted: &'a [Tombstone],
+}
+
+/// Somewhere that keeps the notes and hands out changes since a cursor.
+pub trait ChangeFeed: BlobStore {
+    /// Returns what changed since `cursor`, or everything without one.
+    fn pull(&self, cursor: Option<&str>) -> Result<ChangeSet, NotesError>;
+    fn push(&self, notes: &[Note], deleted: &[Tombstone]) -> Result<(), NotesError>;
+}
+
+pub struct Server {
+    agent: Agent,
+    /// Server URL, always ending in a slash.
+    base: String,
+    authorization: String,
+}
+
+impl Server {
+    pub fn new(config: &ServerConfig, token: &str) -> Self {
+        let mut base = config.url.clone();
+        if !base.ends_with('/') {
+            base.push('/');
+        }
+        Server {
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+            base,
+            authorization: format!("Bearer {}", token),
+        }
+    }
+
+    fn request(&self, method: &str, path: &str) -> ureq::Request {
+        self.agent
+            .request(method, &format!("{}{}", self.base, path))
+            .set("Authorization", &self.authorization)
+    }
+}
+
+/// Reports a failed request, singling out a rejected token.
+fn failed(action: &str, e: ureq::Error) -> NotesError {
+    match e {
+        ureq::Error::Status(401 | 403, _) => {
+            NotesError::Network("The sync server rejected the token".into())
+        }
+        e => NotesError::Network(format!("Failed to {}: {}", action, e)),
+    }
+}
+
+impl ChangeFeed for Server {
+    fn pull(&self, cursor: Option<&str>) -> Result<ChangeSet, NotesError> {
+        let mut request = self.request("GET", "changes");
+        if let Some(cursor) = cursor {
+            request = request.query("since", cursor);
+        }
+        let body = request
+            .call()
+            .map_err(|e| failed("download changes", e))?
+            .into_string()
+            .map_err(|e| NotesError::Network(format!("Failed to download changes: {}", e)))?;
+        serde_json::from_str(&body)
+            .map_err(|e| NotesError::Serde(format!("Failed to parse synced changes: {}", e)))
+    }
+
+    fn push(&self, notes: &[Note], deleted: &[Tombstone]) -> Result<(), NotesError> {
+        let json = serde_json::to_string(&Push { notes, deleted })
+            .map_err(|e| NotesError::Serde(format!("Failed to serialize changes: {}", e)))?;
+        self.request("POST", "changes")
+            .set("Content-Type", "application/json")
+            .send_string(&json)
+            .map_err(|e| failed("upload changes", e))?;
+        Ok(())
+    }
+}
+
+impl BlobStore for Server {
+    fn list_blobs(&self) -> Result<HashSet<String>, NotesError> {
+        let body = self
+            .request("GET", "blobs")
+            .call()
+            .map_err(|e| failed("list synced attachments", e))?
+            .into_string()
+            .map_err(|e| {
+                NotesError::Network(format!("Failed to list synced attachments: {}", e))
+            })?;
+        serde_json::from_str(&body)
+            .map_err(|e| NotesError::Serde(format!("Failed to parse synced attachments: {}", e)))
+    }
+
+    fn get_blob(&self, hash: &str) -> Result<Vec<u8>, NotesError> {
+        let action = format!("download attachment {}", hash);
+        let response = self
+            .request("GET", &format!("blobs/{}", hash))
+            .call()
+            .map_err(|e| failed(&action, e))?;
+        let mut content = Vec::new();
+        response
+            .into_reader()
+            .read_to_end(&mut content)
+            .map_err(|e| NotesError::Network(format!("Failed to {}: {}", action, e)))?;
+        Ok(content)
+    }
+
+    fn put_blob(&self, hash: &str, content: &[u8]) -> Result<(), NotesError> {
+        self.request("PUT", &format!("blobs/{}", hash))
+            .set("Content-Type", "application/octet-stream")
+            .send_bytes(content)
+            .map_err(|e| failed(&format!("upload attachment {}", hash), e))?;
+        Ok(())
+    }
+}
+
+/// Syncs every note, trashed ones included, between `store` and `server`.
+/// `state` must be saved afterwards for the next sync to see what changed.
+///
+/// A note is changed here if it differs from the version last synced, and
+/// pulled notes that match that version are this device's own uploads coming
+/// back. When both sides changed a note, the local version wins and the
+/// server's is kept as a conflicted copy.
+pub(super) fn sync(
+    store: &mut NotesStore,
+    server: &dyn ChangeFeed,
+    state: &mut SyncState,
+) -> Result<SyncReport, NotesError> {
+    let pulled = server.pull(state.cursor.as_deref())?;
+    let synced = |id: &str, note: &Note| {
+        state
+            .notes
+            .get(id)
+            .is_some_and(|base| base.fingerprint == fingerprint(note))
+    };
+    let changed_here: Vec<String> = store
+        .all_notes()?
+        .iter()
+        .filter(|note| !synced(&note.id, note))
+        .map(|note| note.id.clone())
+        .collect();
+    let local_ids: HashSet<String> = store
+        .all_notes()?
+        .iter()
+        .map(|note| note.id.clone())
+        .collect();
+    let deleted_here: Vec<String> = state
+        .notes
+        .keys()
+        .filter(|id| !local_ids.contains(*id))
+        .cloned()
+        .collect();
+
+    let mut report = SyncReport::default();
+    let mut copies = Vec::new();
+    for theirs in pulled.notes {
+        if !note::is_valid_id(&theirs.id) {
+            warn!("Skipping synced note with invalid id {:?}", theirs.id);
+            continue;
+        }
+        if synced(&theirs.id, &theirs) {
+            continue;
+        }
+        match store.get(&theirs.id).ok() {
+            Some(ours) if fingerprint(&ours) == fingerprint(&theirs) => {}
+            Some(_) if changed_here.contains(&theirs.id) => {
+                let copy = conflicted_copy(theirs);
+                store.insert(copy.clone())?;
+                copies.push(copy);
+                report.conflicts += 1;
+            }
+            // Edited on the server, or there since it was deleted here
+            _ => {
+                store.upsert(theirs)?;
+                report.pulled += 1;
+            }
+        }
+    }
+    for tombstone in &pulled.deleted {
+        // A note edited here since survives, and is pushed again below
+        if local_ids.contains(&tombstone.id) && !changed_here.contains(&tombstone.id) {
+            store.delete(&tombstone.id)?;
+            report.deleted_local += 1;
+        }
+    }
+
+    let mut notes = copies;
+    for id in &changed_here {
+        notes.push(store.get(id)?);
+    }
+    let now = Utc::now().timestamp();
+    let deleted: Vec<Tombstone> = deleted_here
+        .into_iter()
+        // Unless the server's edit just brought it back
+        .filter(|id| store.get(id).is_err())
+        .map(|id| Tombstone {
+            id,
+            deleted_at: now,
+        })
+        .collect();
+    if !notes.is_empty() || !deleted.is_empty() {
+        server.push(&notes, &deleted)?;
+    }
+    report.pushed = notes.len() - report.conflicts;
+    report.deleted_remote = deleted.len();
+
+    sync_blobs(store, server, &mut report)?;
+
+    state.cursor = Some(pulled.cursor);
+    state.notes = store
+        .all_notes()?
+        .iter()
+        .map(|note| {
+            let synced = SyncedNote {
+                fingerprint: fingerprint(note),
+                etag: String::new(),
+            };
+            (note.id.clone(), synced)
+        })
+        .collect();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    /// A server kept in memory, whose cursor counts the pushes it has seen.
+    #[derive(Default)]
+    struct MemoryServer {
+        /// Each push, in order.
+        log: RefCell<Vec<(Vec<Note>, Vec<Tombstone>)>>,
+        blobs: RefCell<HashMap<String, Vec<u8>>>,
+    }
+
+    impl ChangeFeed for MemoryServer {
+        fn pull(&self, cursor: Option<&str>) -> Result<ChangeSet, NotesError> {
+            let log = self.log.borrow();
+            let since: usize = cursor.map_or(0, |cursor| cursor.parse().unwrap());
+            let mut changes = ChangeSet {
+                cursor: log.len().to_string(),
+                ..ChangeSet::default()
+            };
+            for (notes, deleted) in &log[since..] {
+                changes.notes.extend(notes.iter().cloned());
+                changes.deleted.extend(deleted.iter().cloned());
+            }
+            Ok(changes)
+        }
+
+        fn push(&self, notes: &[Note], deleted: &[Tombstone]) -> Result<(), NotesError> {
+            self.log
+                .borrow_mut()
+                .push((notes.to_vec(), deleted.to_vec()));
+            Ok(())
+        }
+    }
+
+    impl BlobStore for MemoryServer {
+        fn list_blobs(&self) -> Result<HashSet<String>, NotesError> {
+            Ok(self.blobs.borrow().keys().cloned().collect())
+        }
+
+        fn get_blob(&self, hash: &str) -> Result<Vec<u8>, NotesError> {
+            self.blobs
+                .borrow()
+                .get(hash)
+                .cloned()
+                .ok_or_else(|| NotesError::NotFound(hash.to_string()))
+        }
+
+        fn put_blob(&self, hash: &str, content: &[u8]) -> Result<(), NotesError> {
+            self.blobs
+                .borrow_mut()
+                .insert(hash.to_string(), content.to_vec());
+            Ok(())
+        }
+    }
+
+    struct Device {
+        _dir: TempDir,
+        store: NotesStore,
+        state: SyncState,
+    }
+
+    impl Device {
+        fn new() -> Self {
+            let dir = TempDir::new().unwrap();
+            let store = NotesStore::new(Storage::open(dir.path()).unwrap()).unwrap();
+            Device {
+                _dir: dir,
+                store,
+                state: SyncState::default(),
+            }
+        }
+
+        fn sync(&mut self, server: &MemoryServer) -> SyncReport {
+            sync(&mut self.store, server, &mut self.state).unwrap()
+        }
+
+        fn titles(&self) -> Vec<String> {
+            let mut titles: Vec<String> = self
+                .store
+                .all_notes()
+                .unwrap()
+                .iter()
+                .map(|note| note.title.clone())
+                .collect();
+            titles.sort();
+            titles
+        }
+    }
+
+    #[test]
+    fn syncs_changes_through_a_server() {
+        let server = MemoryServer::default();
+        let (mut a, mut b) = (Device::new(), Device::new());
+        let note = note::new("A".into(), String::new(), Vec::new(), 1);
+        let id = note.id.clone();
+        a.store.insert(note).unwrap();
+        assert_eq!(a.sync(&server).pushed, 1);
+        assert_eq!(b.sync(&server).pulled, 1);
+
+        // A's own upload coming back isn't a change
+        let report = a.sync(&server);
+        assert_eq!(report.pushed + report.pulled, 0);
+
+        b.store.update(&id, |note| note.title = "B".into()).unwrap();
+        b.sync(&server);
+        a.store
+            .update(&id, |note| note.title = "A2".into())
+            .unwrap();
+        a.sync(&server);
+        // The edit B made first is kept as a copy on both devices
+        b.sync(&server);
+        for device in [&a, &b] {
+            let titles = device.titles();
+            assert_eq!(titles.len(), 2);
+            assert_eq!(titles[0], "A2");
+            assert!(titles[1].starts_with("B (conflicted copy "));
+        }
+
+        a.store.delete(&id).unwrap();
+        assert_eq!(a.sync(&server).deleted_remote, 1);
+        assert_eq!(b.sync(&server).deleted_local, 1);
+        assert!(b.store.get(&id).is_err());
+    }
+}
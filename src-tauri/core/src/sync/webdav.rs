@@ -0,0 +1,349 @@
+//! A WebDAV collection, such as a Nextcloud folder, holding one `<id>.json`
+//! file per note and the attachments under attachments/.
+
+use super::{BlobStore, Remote, SyncConfig};
+use crate::error::NotesError;
+use crate::note::Note;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use ureq::Agent;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:"><d:prop><d:getetag/><d:resourcetype/></d:prop></d:propfind>"#;
+
+pub struct WebDav {
+    agent: Agent,
+    /// Collection URL, always ending in a slash.
+    base: String,
+    authorization: String,
+}
+
+impl WebDav {
+    pub fn new(config: &SyncConfig, password: &str) -> Self {
+        let mut base = config.url.clone();
+        if !base.ends_with('/') {
+            base.push('/');
+        }
+        let credentials = format!("{}:{}", config.username, password);
+        WebDav {
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+            base,
+            authorization: format!("Basic {}", STANDARD.encode(credentials)),
+        }
+    }
+
+    fn request(&self, method: &str, url: &str) -> ureq::Request {
+        self.agent
+            .request(method, url)
+            .set("Authorization", &self.authorization)
+    }
+
+    fn note_url(&self, id: &str) -> String {
+        format!("{}{}.json", self.base, id)
+    }
+
+    fn blobs_url(&self) -> String {
+        format!("{}attachments/", self.base)
+    }
+
+    /// Creates the collection and its attachments folder if they don't exist
+    /// yet.
+    pub fn ensure_collection(&self) -> Result<(), NotesError> {
+        self.ensure_folder(&self.base)?;
+        self.ensure_folder(&self.blobs_url())
+    }
+
+    fn ensure_folder(&self, url: &str) -> Result<(), NotesError> {
+        match self.propfind(url, "0") {
+            Ok(_) => Ok(()),
+            Err(e) if matches!(*e, ureq::Error::Status(404, _)) => {
+                self.request("MKCOL", url).call().map_err(|e| {
+                    NotesError::Network(format!("Failed to create sync folder: {}", e))
+                })?;
+                Ok(())
+            }
+            Err(e) => Err(NotesError::Network(format!(
+                "Failed to reach sync server: {}",
+                e
+            ))),
+        }
+    }
+
+    fn propfind(&self, url: &str, depth: &str) -> Result<String, Box<ureq::Error>> {
+        let response = self
+            .request("PROPFIND", url)
+            .set("Depth", depth)
+            .set("Content-Type", "application/xml; charset=utf-8")
+            .send_string(PROPFIND_BODY)?;
+        Ok(response.into_string().map_err(ureq::Error::from)?)
+    }
+
+    /// Returns the ETag a GET or PUT of note `id` responded with. Servers that
+    /// leave it out of PUT responses are asked for it separately.
+    fn etag_of(&self, id: &str, response: &ureq::Response) -> Result<String, NotesError> {
+        if let Some(etag) = response.header("ETag") {
+            return Ok(etag.trim().to_string());
+        }
+        let xml = self.propfind(&self.note_url(id), "0").map_err(|e| {
+            NotesError::Network(format!("Failed to check synced note {}: {}", id, e))
+        })?;
+        parse_multistatus(&xml)?
+            .remove(&format!("{}.json", id))
+            .ok_or_else(|| NotesError::Network(format!("Synced note {} has no ETag", id)))
+    }
+}
+
+impl Remote for WebDav {
+    fn list(&self) -> Result<HashMap<String, String>, NotesError> {
+        let xml = self
+            .propfind(&self.base, "1")
+            .map_err(|e| NotesError::Network(format!("Failed to list synced notes: {}", e)))?;
+        Ok(parse_multistatus(&xml)?
+            .into_iter()
+            .filter_map(|(name, etag)| Some((name.strip_suffix(".json")?.to_string(), etag)))
+            .collect())
+    }
+
+    fn get(&self, id: &str) -> Result<(Note, String), NotesError> {
+        let response = self
+            .request("GET", &self.note_url(id))
+            .call()
+            .map_err(|e| NotesError::Network(format!("Failed to download note {}: {}", id, e)))?;
+        let etag = self.etag_of(id, &response)?;
+        let body = response
+            .into_string()
+            .map_err(|e| NotesError::Network(format!("Failed to download note {}: {}", id, e)))?;
+        let note = serde_json::from_str(&body)
+            .map_err(|e| NotesError::Serde(format!("Failed to parse synced note {}: {}", id, e)))?;
+        Ok((note, etag))
+    }
+
+    fn put(&self, note: &Note) -> Result<String, NotesError> {
+        let json = serde_json::to_string(note)
+            .map_err(|e| NotesError::Serde(format!("Failed to serialize note: {}", e)))?;
+        let response = self
+            .request("PUT", &self.note_url(&note.id))
+            .set("Content-Type", "application/json")
+            .send_string(&json)
+            .map_err(|e| {
+                NotesError::Network(format!("Failed to upload note {}: {}", note.id, e))
+            })?;
+        self.etag_of(&note.id, &response)
+    }
+
+    fn delete(&self, id: &str) -> Result<(), NotesError> {
+        match self.request("DELETE", &self.note_url(id)).call() {
+            Ok(_) | Err(ureq::Error::Status(404, _)) => Ok(()),
+            Err(e) => Err(NotesError::Network(format!(
+                "Failed to delete synced note {}: {}",
+                id, e
+            ))),
+        }
+    }
+}
+
+impl BlobStore for WebDav {
+    fn list_blobs(&self) -> Result<HashSet<String>, NotesError> {
+        let xml = match self.propfind(&self.blobs_url(), "1") {
+            Ok(xml) => xml,
+            // Folders set up before attachments were synced
+            Err(e) if matches!(*e, ureq::Error::Status(404, _)) => {
+                self.ensure_folder(&self.blobs_url())?;
+                return Ok(HashSet::new());
+            }
+            Err(e) => {
+                return Err(NotesError::Network(format!(
+                    "Failed to list synced attachments: {}",
+                    e
+                )))
+            }
+        };
+        Ok(parse_multistatus(&xml)?.into_keys().collect())
+    }
+
+    fn get_blob(&self, hash: &str) -> Result<Vec<u8>, NotesError> {
+        let response = self
+            .request("GET", &format!("{}{}", self.blobs_url(), hash))
+            .call()
+            .map_err(|e| {
+                NotesError::Network(format!("Failed to download attachment {}: {}", hash, e))
+            })?;
+        let mut content = Vec::new();
+        response
+            .into_reader()
+            .read_to_end(&mut content)
+            .map_err(|e| {
+                NotesError::Network(format!("Failed to download attachment {}: {}", hash, e))
+            })?;
+        Ok(content)
+    }
+
+    fn put_blob(&self, hash: &str, content: &[u8]) -> Result<(), NotesError> {
+        self.request("PUT", &format!("{}{}", self.blobs_url(), hash))
+            .set("Content-Type", "application/octet-stream")
+            .send_bytes(content)
+            .map_err(|e| {
+                NotesError::Network(format!("Failed to upload attachment {}: {}", hash, e))
+            })?;
+        Ok(())
+    }
+}
+
+/// Pulls the file names and ETags out of a PROPFIND response. Collections,
+/// such as the one listed, are skipped.
+fn parse_multistatus(xml: &str) -> Result<HashMap<String, String>, NotesError> {
+    let mut reader = Reader::from_str(xml);
+    let mut entries = HashMap::new();
+    let mut href = String::new();
+    let mut etag = String::new();
+    let mut is_collection = false;
+    let mut field: Option<&'static str> = None;
+
+    loop {
+        let event = reader.read_event().map_err(|e| {
+            NotesError::Serde(format!("Failed to parse sync server response: {}", e))
+        })?;
+        match event {
+            // Servers pick their own namespace prefixes, so match local names
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"response" => {
+                    href.clear();
+                    etag.clear();
+                    is_collection = false;
+                }
+                b"href" => field = Some("href"),
+                b"getetag" => field = Some("getetag"),
+                b"collection" => is_collection = true,
+                _ => {}
+            },
+            Event::Empty(e) if e.local_name().as_ref() == b"collection" => is_collection = true,
+            Event::Text(text) => {
+                let text = text.unescape().map_err(|e| {
+                    NotesError::Serde(format!("Failed to parse sync server response: {}", e))
+                })?;
+                match field {
+                    Some("href") => href.push_str(&text),
+                    Some("getetag") => etag.push_str(&text),
+                    _ => {}
+                }
+            }
+            Event::End(e) => match e.local_name().as_ref() {
+                b"response" => {
+                    let href = href.trim();
+                    if !is_collection && !href.ends_with('/') {
+                        let name = href.rsplit('/').next().unwrap_or("");
+                        entries.insert(name.to_string(), etag.trim().to_string());
+                    }
+                }
+                b"href" | b"getetag" => field = None,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A Depth: 1 PROPFIND response as Nextcloud sends it.
+    const NEXTCLOUD_LISTING: &str = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:" xmlns:s="http://sabredav.org/ns" xmlns:oc="http://owncloud.org/ns" xmlns:nc="http://nextcloud.org/ns">
+ <d:response>
+  <d:href>/remote.php/dav/files/joe/Notes/</d:href>
+  <d:propstat>
+   <d:prop>
+    <d:getetag>&quot;6523a1f3c2b9e&quot;</d:getetag>
+    <d:resourcetype><d:collection/></d:resourcetype>
+   </d:prop>
+   <d:status>HTTP/1.1 200 OK</d:status>
+  </d:propstat>
+ </d:response>
+ <d:response>
+  <d:href>/remote.php/dav/files/joe/Notes/attachments/</d:href>
+  <d:propstat>
+   <d:prop>
+    <d:getetag>&quot;6523a1f3d0a4f&quot;</d:getetag>
+    <d:resourcetype><d:collection/></d:resourcetype>
+   </d:prop>
+   <d:status>HTTP/1.1 200 OK</d:status>
+  </d:propstat>
+ </d:response>
+ <d:response>
+  <d:href>/remote.php/dav/files/joe/Notes/0f8e4c1a-3b7d-4e2a-9c61-5d2f8a7b9e10.json</d:href>
+  <d:propstat>
+   <d:prop>
+    <d:getetag>&quot;8f14e45fceea167a5a36dedd4bea2543&quot;</d:getetag>
+    <d:resourcetype/>
+   </d:prop>
+   <d:status>HTTP/1.1 200 OK</d:status>
+  </d:propstat>
+ </d:response>
+ <d:response>
+  <d:href>/remote.php/dav/files/joe/Notes/7c9e6679-7425-40de-944b-e07fc1f90ae7.json</d:href>
+  <d:propstat>
+   <d:prop>
+    <d:getetag>&quot;c9f0f895fb98ab9159f51fd0297e236d&quot;</d:getetag>
+    <d:resourcetype/>
+   </d:prop>
+   <d:status>HTTP/1.1 200 OK</d:status>
+  </d:propstat>
+ </d:response>
+</d:multistatus>"#;
+
+    #[test]
+    fn parses_nextcloud_listing() {
+        let entries = parse_multistatus(NEXTCLOUD_LISTING).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries["0f8e4c1a-3b7d-4e2a-9c61-5d2f8a7b9e10.json"],
+            "\"8f14e45fceea167a5a36dedd4bea2543\""
+        );
+        assert_eq!(
+            entries["7c9e6679-7425-40de-944b-e07fc1f90ae7.json"],
+            "\"c9f0f895fb98ab9159f51fd0297e236d\""
+        );
+    }
+
+    #[test]
+    fn parses_single_file_with_other_prefix() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<D:multistatus xmlns:D="DAV:">
+  <D:response>
+    <D:href>https://dav.example.com/notes/a.json</D:href>
+    <D:propstat>
+      <D:prop><D:getetag>W/"42"</D:getetag><D:resourcetype></D:resourcetype></D:prop>
+      <D:status>HTTP/1.1 200 OK</D:status>
+    </D:propstat>
+  </D:response>
+</D:multistatus>"#;
+        let entries = parse_multistatus(xml).unwrap();
+        assert_eq!(entries["a.json"], "W/\"42\"");
+    }
+
+    #[test]
+    fn lists_empty_collection_as_empty() {
+        let xml = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:">
+ <d:response>
+  <d:href>/remote.php/dav/files/joe/Notes/attachments/</d:href>
+  <d:propstat><d:prop><d:resourcetype><d:collection/></d:resourcetype></d:prop></d:propstat>
+ </d:response>
+</d:multistatus>"#;
+        assert!(parse_multistatus(xml).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_xml() {
+        assert!(parse_multistatus("<d:multistatus><d:response></d:multistatus>").is_err());
+    }
+}
@@ -0,0 +1,258 @@
+//! `- [ ]` checklist items in note contents.
+//!
+//! Like the link graph, the tasks of every note are kept in memory alongside
+//! the search index, and re-parsed whenever a note is saved.
+
+use crate::note::Note;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A checklist item and where it's written.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct Task {
+    pub note_id: String,
+    pub note_title: String,
+    /// The item's line in the note's content, counting from 1.
+    pub line: usize,
+    pub text: String,
+    pub done: bool,
+}
+
+/// Which tasks `list_tasks` returns.
+#[derive(Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskFilter {
+    #[default]
+    Open,
+    Done,
+    All,
+}
+
+impl TaskFilter {
+    fn matches(self, done: bool) -> bool {
+        match self {
+            TaskFilter::Open => !done,
+            TaskFilter::Done => done,
+            TaskFilter::All => true,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+struct Item {
+    line: usize,
+    text: String,
+    done: bool,
+}
+
+struct Entry {
+    title: String,
+    items: Vec<Item>,
+}
+
+/// The tasks of every note given to it, which should only be notes that
+/// aren't trashed.
+#[derive(Default)]
+pub struct TaskList {
+    notes: HashMap<String, Entry>,
+}
+
+impl TaskList {
+    pub fn rebuild(&mut self, notes: &[Note]) {
+        self.notes.clear();
+        for note in notes {
+            self.upsert(note);
+        }
+    }
+
+    pub fn upsert(&mut self, note: &Note) {
+        let items = parse_tasks(&note.content);
+        if items.is_empty() {
+            self.notes.remove(&note.id);
+        } else {
+            self.notes.insert(
+                note.id.clone(),
+                Entry {
+                    title: note.title.clone(),
+                    items,
+                },
+            );
+        }
+    }
+
+    pub fn remove(&mut self, id: &str) {
+        self.notes.remove(id);
+    }
+
+    /// Returns the tasks that match `filter`, grouped by note in title order
+    /// and then in the order they're written.
+    pub fn list(&self, filter: TaskFilter) -> Vec<Task> {
+        let mut tasks: Vec<Task> = self
+            .notes
+            .iter()
+            .flat_map(|(id, entry)| {
+                entry
+                    .items
+                    .iter()
+                    .filter(|item| filter.matches(item.done))
+                    .map(|item| Task {
+                        note_id: id.clone(),
+                        note_title: entry.title.clone(),
+                        line: item.line,
+                        text: item.text.clone(),
+                        done: item.done,
+                    })
+            })
+            .collect();
+        tasks.sort_by(|a, b| {
+            a.note_title
+                .cmp(&b.note_title)
+                .then_with(|| a.note_id.cmp(&b.note_id))
+                .then_with(|| a.line.cmp(&b.line))
+        });
+        tasks
+    }
+}
+
+/// If `line` is a checklist item, returns the byte offset of the character
+/// inside its brackets.
+fn checkbox(line: &str) -> Option<usize> {
+    let indent = line.len() - line.trim_start().len();
+    let rest = &line[indent..];
+    let rest = rest
+        .strip_prefix("- ")
+        .or_else(|| rest.strip_prefix("* "))
+        .or_else(|| rest.strip_prefix("+ "))?;
+    let mark = rest.strip_prefix('[')?.chars().next()?;
+    let after = &rest[1 + mark.len_utf8()..];
+    let is_item = matches!(mark, ' ' | 'x' | 'X')
+        && after.starts_with(']')
+        && (after.len() == 1 || after[1..].starts_with([' ', '\t', '\r', '\n']));
+    is_item.then(|| line.len() - rest.len() + 1)
+}
+
+/// Finds the checklist items in `content`, skipping fenced code blocks.
+fn parse_tasks(content: &str) -> Vec<Item> {
+    let mut items = Vec::new();
+    let mut in_code = false;
+    for (i, line) in content.lines().enumerate() {
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            continue;
+        }
+        if let Some(mark) = checkbox(line) {
+            let text = line[mark + 2..].trim();
+            items.push(Item {
+                line: i + 1,
+                text: text.to_string(),
+                done: line.as_bytes()[mark] != b' ',
+            });
+        }
+    }
+    items
+}
+
+/// Checks or unchecks the item on `line` (counting from 1) of `content`.
+/// Returns `None` if that line isn't one.
+pub fn toggle(content: &str, line: usize) -> Option<String> {
+    if !parse_tasks(content).iter().any(|item| item.line == line) {
+        return None;
+    }
+    let mut result = String::with_capacity(content.len());
+    for (i, text) in content.split_inclusive('\n').enumerate() {
+        match checkbox(text).filter(|_| i + 1 == line) {
+            Some(mark) => {
+                let done = text.as_bytes()[mark] != b' ';
+                result.push_str(&text[..mark]);
+                result.push(if done { ' ' } else { 'x' });
+                result.push_str(&text[mark + 1..]);
+            }
+            None => result.push_str(text),
+        }
+    }
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    const CONTENT: &str = "# Plan\n\
+        - [ ] Write tests\n  \
+        * [x] Fix bug\n\
+        - [] not a task\n\
+        -[ ] nor this\n\
+        ```\n\
+        - [ ] in code\n\
+        ```\n\
+        + [X] Ship\r\n";
+
+    #[test]
+    fn parses_tasks() {
+        let items: Vec<(usize, String, bool)> = parse_tasks(CONTENT)
+            .into_iter()
+            .map(|item| (item.line, item.text, item.done))
+            .collect();
+        assert_eq!(
+            items,
+            [
+                (2, "Write tests".to_string(), false),
+                (3, "Fix bug".to_string(), true),
+                (9, "Ship".to_string(), true)
+            ]
+        );
+    }
+
+    #[test]
+    fn lists_tasks_by_filter() {
+        let note = Note {
+            id: "a".into(),
+            title: "Plan".into(),
+            content: CONTENT.into(),
+            created_at: 0,
+            updated_at: 0,
+            tags: Vec::new(),
+            trashed_at: None,
+            pinned: false,
+            archived: false,
+            favorite: false,
+            locked: false,
+            content_hash: String::new(),
+            color: None,
+            notebook_id: None,
+            attachments: Vec::new(),
+            reminders: Vec::new(),
+            expiry: None,
+            location: None,
+            meta: BTreeMap::new(),
+        };
+        let mut tasks = TaskList::default();
+        tasks.rebuild(std::slice::from_ref(&note));
+        let open = tasks.list(TaskFilter::Open);
+        assert_eq!(open.len(), 1);
+        assert_eq!((open[0].note_id.as_str(), open[0].line), ("a", 2));
+        assert_eq!(tasks.list(TaskFilter::All).len(), 3);
+
+        tasks.upsert(&Note {
+            content: "No tasks left".into(),
+            ..note
+        });
+        assert!(tasks.list(TaskFilter::All).is_empty());
+    }
+
+    #[test]
+    fn toggles_only_task_lines() {
+        let toggled = toggle(CONTENT, 2).unwrap();
+        assert!(toggled.contains("- [x] Write tests\n"));
+        let toggled = toggle(&toggled, 9).unwrap();
+        assert!(toggled.ends_with("+ [ ] Ship\r\n"));
+        assert_eq!(toggled.len(), CONTENT.len());
+        assert!(toggle(CONTENT, 1).is_none());
+        assert!(toggle(CONTENT, 7).is_none());
+        assert!(toggle(CONTENT, 42).is_none());
+    }
+}
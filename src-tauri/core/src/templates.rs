@@ -0,0 +1,217 @@
+//! Note templates, kept in templates.json in the workspace's data directory.
+//!
+//! A template's title and content may contain `{{placeholders}}`, filled in
+//! when a note is created from it: `{{date}}`, `{{time}}`, `{{datetime}}`,
+//! `{{title}}` (the new note's title), and any variables passed in.
+
+use crate::error::NotesError;
+use crate::fsutil;
+use crate::note;
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Template {
+    pub id: String,
+    pub name: String,
+    pub title: String,
+    pub content: String,
+    /// Given to every note created from the template.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// What the UI sends when creating or editing a template.
+#[derive(Deserialize)]
+pub struct TemplateFields {
+    pub name: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub content: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+pub struct Templates {
+    path: PathBuf,
+    templates: Vec<Template>,
+}
+
+impl Templates {
+    /// Loads the templates of the workspace in `data_dir`.
+    pub fn load(data_dir: &Path) -> Result<Self, NotesError> {
+        let path = data_dir.join("templates.json");
+        let templates = if path.exists() {
+            fsutil::read_with_backup(&path, |content| {
+                serde_json::from_str(content)
+                    .map_err(|e| NotesError::Serde(format!("Failed to parse templates: {}", e)))
+            })?
+        } else {
+            Vec::new()
+        };
+        Ok(Templates { path, templates })
+    }
+
+    fn save(&self) -> Result<(), NotesError> {
+        let json = serde_json::to_string_pretty(&self.templates)
+            .map_err(|e| NotesError::Serde(format!("Failed to serialize templates: {}", e)))?;
+        fsutil::write_with_backup(&self.path, json.as_bytes())
+            .map_err(|e| NotesError::Io(format!("Failed to write templates: {}", e)))
+    }
+
+    /// Every template, in the order created.
+    pub fn all(&self) -> &[Template] {
+        &self.templates
+    }
+
+    pub fn get(&self, id: &str) -> Result<&Template, NotesError> {
+        self.templates
+            .iter()
+            .find(|t| t.id == id)
+            .ok_or_else(|| NotesError::NotFound("Template not found".into()))
+    }
+
+    pub fn create(&mut self, fields: TemplateFields) -> Result<Template, NotesError> {
+        let name = checked_name(&fields.name)?;
+        let now = Utc::now().timestamp();
+        let template = Template {
+            id: Uuid::new_v4().to_string(),
+            name,
+            title: fields.title,
+            content: fields.content,
+            tags: note::normalize_tags(fields.tags),
+            created_at: now,
+            updated_at: now,
+        };
+        self.templates.push(template.clone());
+        self.save()?;
+        Ok(template)
+    }
+
+    pub fn update(&mut self, id: &str, fields: TemplateFields) -> Result<Template, NotesError> {
+        let name = checked_name(&fields.name)?;
+        let template = self
+            .templates
+            .iter_mut()
+            .find(|t| t.id == id)
+            .ok_or_else(|| NotesError::NotFound("Template not found".into()))?;
+        template.name = name;
+        template.title = fields.title;
+        template.content = fields.content;
+        template.tags = note::normalize_tags(fields.tags);
+        template.updated_at = Utc::now().timestamp();
+        let template = template.clone();
+        self.save()?;
+        Ok(template)
+    }
+
+    pub fn delete(&mut self, id: &str) -> Result<(), NotesError> {
+        let before = self.templates.len();
+        self.templates.retain(|t| t.id != id);
+        if self.templates.len() == before {
+            return Err(NotesError::NotFound("Template not found".into()));
+        }
+        self.save()
+    }
+}
+
+fn checked_name(name: &str) -> Result<String, NotesError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(NotesError::Invalid("Template name cannot be empty".into()));
+    }
+    Ok(name.to_string())
+}
+
+impl Template {
+    /// Returns the title and content of a note created from this template at
+    /// `now`. A `title` variable overrides the template's title.
+    pub fn render(&self, vars: &HashMap<String, String>, now: DateTime<Local>) -> (String, String) {
+        let mut values: HashMap<&str, String> = HashMap::from([
+            ("date", now.format("%Y-%m-%d").to_string()),
+            ("time", now.format("%H:%M").to_string()),
+            ("datetime", now.format("%Y-%m-%d %H:%M").to_string()),
+        ]);
+        for (name, value) in vars {
+            values.insert(name.as_str(), value.clone());
+        }
+        let title = match values.get("title") {
+            Some(title) => title.clone(),
+            None => substitute(&self.title, &values),
+        };
+        values.insert("title", title.clone());
+        (title, substitute(&self.content, &values))
+    }
+}
+
+/// Replaces every `{{name}}` in `text` that has a value, ignoring spaces
+/// inside the braces. Unknown placeholders are left as they are.
+fn substitute(text: &str, values: &HashMap<&str, String>) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+        match values.get(after[..end].trim()) {
+            Some(value) => result.push_str(value),
+            None => result.push_str(&rest[start..start + 2 + end + 2]),
+        }
+        rest = &after[end + 2..];
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn template(title: &str, content: &str) -> Template {
+        Template {
+            id: "t".into(),
+            name: "Daily".into(),
+            title: title.into(),
+            content: content.into(),
+            tags: Vec::new(),
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    fn now() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 3, 9, 8, 5, 0).unwrap()
+    }
+
+    #[test]
+    fn fills_in_placeholders() {
+        let vars = HashMap::from([("project".to_string(), "min_notes".to_string())]);
+        let (title, content) = template(
+            "Standup {{date}}",
+            "# {{ title }}\n{{time}} on {{project}}, {{unknown}} {{",
+        )
+        .render(&vars, now());
+        assert_eq!(title, "Standup 2024-03-09");
+        assert_eq!(
+            content,
+            "# Standup 2024-03-09\n08:05 on min_notes, {{unknown}} {{"
+        );
+    }
+
+    #[test]
+    fn title_variable_overrides_template_title() {
+        let vars = HashMap::from([("title".to_string(), "Retro".to_string())]);
+        let (title, content) = template("Standup {{date}}", "{{title}}").render(&vars, now());
+        assert_eq!((title.as_str(), content.as_str()), ("Retro", "Retro"));
+    }
+}
@@ -0,0 +1,140 @@
+//! Transcribing audio memos with a speech-to-text program of your choice,
+//! such as whisper.cpp's `whisper-cli`, set as a command line in the
+//! settings. The program is handed the memo as a file and prints the
+//! transcript, which is added to the note the memo is attached to.
+
+use crate::error::NotesError;
+use std::fs;
+use std::process::Command;
+use tracing::info;
+use uuid::Uuid;
+
+/// Stands for the audio file in the transcription command.
+const FILE_PLACEHOLDER: &str = "{file}";
+
+/// Whether attachments of type `mime` are audio that can be transcribed.
+pub fn is_audio(mime: &str) -> bool {
+    mime.starts_with("audio/")
+}
+
+/// Splits `command` into the program and its arguments at whitespace,
+/// keeping what's in single or double quotes together.
+fn split_command(command: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = None::<String>;
+    let mut quote = None;
+    for c in command.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+/// Runs `command` on `audio`, written to a temporary file ending in
+/// `.<extension>`, and returns the transcript it prints. `{file}` in the
+/// command is replaced with the file's path, which is passed last if the
+/// command has no `{file}`.
+pub fn transcribe(command: &str, audio: &[u8], extension: &str) -> Result<String, NotesError> {
+    let mut words = split_command(command);
+    if words.is_empty() {
+        return Err(NotesError::Invalid(
+            "The transcription command is empty".into(),
+        ));
+    }
+    let path = std::env::temp_dir().join(format!("min_notes-{}.{}", Uuid::new_v4(), extension));
+    fs::write(&path, audio)
+        .map_err(|e| NotesError::Io(format!("Failed to write audio for transcription: {}", e)))?;
+    let file = path.to_string_lossy();
+    if !words.iter().any(|word| word.contains(FILE_PLACEHOLDER)) {
+        words.push(FILE_PLACEHOLDER.into());
+    }
+    let args: Vec<String> = words[1..]
+        .iter()
+        .map(|word| word.replace(FILE_PLACEHOLDER, &file))
+        .collect();
+
+    info!(
+        "Transcribing {} bytes of audio with {}",
+        audio.len(),
+        words[0]
+    );
+    let output = Command::new(&words[0]).args(&args).output();
+    let _ = fs::remove_file(&path);
+    let output =
+        output.map_err(|e| NotesError::Internal(format!("Failed to run {}: {}", words[0], e)))?;
+    if !output.status.success() {
+        return Err(NotesError::Internal(format!(
+            "{} failed ({}): {}",
+            words[0],
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    let transcript = String::from_utf8_lossy(&output.stdout);
+    let transcript = transcript
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    if transcript.is_empty() {
+        return Err(NotesError::Invalid("No speech was recognized".into()));
+    }
+    Ok(transcript)
+}
+
+/// Adds `transcript`, of the audio memo named `name`, to the end of
+/// `content`.
+pub fn append_transcript(content: &str, name: &str, transcript: &str) -> String {
+    let content = content.trim_end();
+    let separator = if content.is_empty() { "" } else { "\n\n" };
+    format!(
+        "{}{}**Transcript of {}**\n\n{}\n",
+        content, separator, name, transcript
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_quoted_arguments() {
+        assert_eq!(
+            split_command(r#"whisper-cli -m "/models/ggml base.bin" -f {file} -l ''"#),
+            [
+                "whisper-cli",
+                "-m",
+                "/models/ggml base.bin",
+                "-f",
+                "{file}",
+                "-l",
+                ""
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn transcribes_with_the_command() {
+        let transcript = transcribe("cat", b"  hello\n\nworld \n", "wav").unwrap();
+        assert_eq!(transcript, "hello\nworld");
+        assert!(matches!(
+            transcribe("sh -c 'exit 3'", b"", "wav"),
+            Err(NotesError::Internal(_))
+        ));
+        assert_eq!(
+            append_transcript("Standup\n", "memo.webm", &transcript),
+            "Standup\n\n**Transcript of memo.webm**\n\nhello\nworld\n"
+        );
+    }
+}
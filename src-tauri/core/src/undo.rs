@@ -0,0 +1,120 @@
+//! Undoing and redoing changes to notes, for as long as the app runs, so a
+//! deleted note or a botched bulk edit can be brought back after the window
+//! that made it has moved on.
+//!
+//! The store records each change it makes on the user's behalf as the notes
+//! it touched, before and after. Changes the app makes on its own, like a
+//! reminder firing, aren't recorded, and neither are those picked up from
+//! elsewhere.
+
+use crate::note::Note;
+use std::collections::VecDeque;
+
+/// How many changes can be undone.
+const MAX_ENTRIES: usize = 100;
+
+/// One note as it was before and after a change, `None` where it didn't
+/// exist.
+#[derive(Clone, Debug)]
+pub struct Change {
+    pub before: Option<Note>,
+    pub after: Option<Note>,
+}
+
+impl Change {
+    pub fn id(&self) -> &str {
+        self.before
+            .as_ref()
+            .or(self.after.as_ref())
+            .map_or("", |note| note.id.as_str())
+    }
+}
+
+#[derive(Default)]
+pub struct UndoLog {
+    /// Oldest first.
+    undo: VecDeque<Vec<Change>>,
+    redo: Vec<Vec<Change>>,
+}
+
+impl UndoLog {
+    /// Records `changes` as one step, which can no longer be redone past. With
+    /// `merge`, a change to the note the last step changed, such as another
+    /// autosave of it, joins that step instead.
+    pub fn record(&mut self, changes: Vec<Change>, merge: bool) {
+        if changes.is_empty() {
+            return;
+        }
+        self.redo.clear();
+        if let (true, [change], Some([last])) = (
+            merge,
+            changes.as_slice(),
+            self.undo.back_mut().map(Vec::as_mut_slice),
+        ) {
+            if last.after.is_some() && last.after == change.before {
+                last.after = change.after.clone();
+                return;
+            }
+        }
+        self.undo.push_back(changes);
+        if self.undo.len() > MAX_ENTRIES {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Makes the last step that left a note as `before` leave it as `after`
+    /// instead, for a change that finishes that step. Nothing changes if the
+    /// note was changed since without being recorded.
+    pub fn amend(&mut self, before: &Note, after: &Note) {
+        let last = self
+            .undo
+            .iter_mut()
+            .rev()
+            .flat_map(|changes| changes.iter_mut())
+            .find(|change| change.id() == before.id);
+        if let Some(change) = last.filter(|change| change.after.as_ref() == Some(before)) {
+            change.after = Some(after.clone());
+        }
+    }
+
+    /// Drops every step that touched note `id`, such as when it's locked and
+    /// its content in the clear must not stay around.
+    pub fn forget(&mut self, id: &str) {
+        let touches = |changes: &Vec<Change>| changes.iter().any(|change| change.id() == id);
+        self.undo.retain(|changes| !touches(changes));
+        self.redo.retain(|changes| !touches(changes));
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    /// Takes the last step off to undo it.
+    pub fn pop_undo(&mut self) -> Option<Vec<Change>> {
+        self.undo.pop_back()
+    }
+
+    /// Takes the last undone step off to redo it.
+    pub fn pop_redo(&mut self) -> Option<Vec<Change>> {
+        self.redo.pop()
+    }
+
+    /// Keeps a step just undone, to redo.
+    pub fn undone(&mut self, changes: Vec<Change>) {
+        self.redo.push(changes);
+    }
+
+    /// Keeps a step just redone, to undo again.
+    pub fn redone(&mut self, changes: Vec<Change>) {
+        self.undo.push_back(changes);
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
@@ -0,0 +1,168 @@
+//! Upgrading the data older versions left in a workspace's data directory,
+//! when it's opened. schema.json records how many of `MIGRATIONS` the data
+//! has had, and data from a newer version, which may hold what this one
+//! would lose, isn't opened at all.
+//!
+//! A migration may be run again on data it already upgraded, if the app
+//! stopped before recording it, so each must leave such data as it is. The
+//! SQLite backend's own tables are upgraded by `storage`.
+
+use crate::backup;
+use crate::datadir;
+use crate::error::NotesError;
+use crate::fsutil;
+use crate::storage::{self, Storage};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+struct Migration {
+    /// What it does, for the log.
+    description: &'static str,
+    run: fn(&mut Storage, &Path) -> Result<(), NotesError>,
+}
+
+/// Applied in order. Only append to this list.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        description: "import the notes.json kept before notes moved to SQLite",
+        run: |storage, data_dir| {
+            // A damaged file is left in place, for the app to offer to salvage.
+            // Other failures stop the upgrade, so it's tried again next time
+            if storage::import_notes_json(storage, &data_dir.join("notes.json"))?.is_none() {
+                warn!("notes.json is damaged; leaving it to be salvaged");
+            }
+            Ok(())
+        },
+    },
+    Migration {
+        description: "give notes saved with only a timestamp an update time",
+        run: |storage, _| storage.fill_updated_at().map(|_| ()),
+    },
+];
+
+/// The version of the data this version of the app writes.
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+#[derive(Serialize, Deserialize)]
+struct Schema {
+    version: u32,
+}
+
+fn schema_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("schema.json")
+}
+
+/// The version of the data in `data_dir`, 0 for data from before versions
+/// were recorded.
+pub fn version(data_dir: &Path) -> Result<u32, NotesError> {
+    let path = schema_path(data_dir);
+    if !path.exists() {
+        return Ok(0);
+    }
+    fsutil::read_with_backup(&path, |content| {
+        serde_json::from_str::<Schema>(content)
+            .map(|schema| schema.version)
+            .map_err(|e| NotesError::Serde(format!("Failed to parse schema version: {}", e)))
+    })
+}
+
+fn set_version(data_dir: &Path, version: u32) -> Result<(), NotesError> {
+    let json = serde_json::to_string_pretty(&Schema { version })
+        .map_err(|e| NotesError::Serde(format!("Failed to serialize schema version: {}", e)))?;
+    fsutil::write_with_backup(&schema_path(data_dir), json.as_bytes())
+        .map_err(|e| NotesError::Io(format!("Failed to write schema version: {}", e)))
+}
+
+/// Checks that the data in `data_dir` can be opened, and backs it up if it's
+/// about to be upgraded, before the notes are opened. Returns its version,
+/// for `migrate`.
+pub fn prepare(data_dir: &Path) -> Result<u32, NotesError> {
+    let version = version(data_dir)?;
+    if version > SCHEMA_VERSION {
+        return Err(NotesError::Invalid(
+            "These notes were saved by a newer version of min_notes; update to open them".into(),
+        ));
+    }
+    if version < SCHEMA_VERSION && !datadir::entries(data_dir).is_empty() {
+        // Kept with the other backups, none of which are pruned to make room
+        let backup = backup::create(data_dir, usize::MAX)?;
+        info!(
+            "Backed up the notes to {} before upgrading them",
+            backup.path.display()
+        );
+    }
+    Ok(version)
+}
+
+/// Runs the migrations the data in `data_dir` hasn't had since `version` on
+/// `storage`, oldest first, recording each once it's done.
+pub fn migrate(data_dir: &Path, storage: &mut Storage, version: u32) -> Result<(), NotesError> {
+    for (done, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        info!("Upgrading the notes: {}", migration.description);
+        (migration.run)(storage, data_dir)?;
+        set_version(data_dir, done as u32 + 1)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note;
+    use crate::vault::Encryption;
+    use tempfile::TempDir;
+
+    #[test]
+    fn upgrades_old_data_once() {
+        let dir = TempDir::new().unwrap();
+        let mut old = note::new("Old".into(), String::new(), Vec::new(), 5);
+        old.updated_at = 0;
+        Storage::open(dir.path())
+            .unwrap()
+            .save_notes(std::slice::from_ref(&old))
+            .unwrap();
+
+        let version = prepare(dir.path()).unwrap();
+        assert_eq!(version, 0);
+        assert_eq!(backup::list(dir.path()).unwrap().len(), 1);
+        let mut storage = Storage::open(dir.path()).unwrap();
+        migrate(dir.path(), &mut storage, version).unwrap();
+        assert_eq!(storage.load_all().unwrap()[0].updated_at, 5);
+
+        assert_eq!(prepare(dir.path()).unwrap(), SCHEMA_VERSION);
+        assert_eq!(backup::list(dir.path()).unwrap().len(), 1);
+
+        set_version(dir.path(), SCHEMA_VERSION + 1).unwrap();
+        assert!(matches!(prepare(dir.path()), Err(NotesError::Invalid(_))));
+    }
+
+    #[test]
+    fn retries_imports_that_fail() {
+        let dir = TempDir::new().unwrap();
+        let notes_file = dir.path().join("notes.json");
+        std::fs::write(
+            &notes_file,
+            r#"[{"id":"a","title":"A","content":"","timestamp":5}]"#,
+        )
+        .unwrap();
+
+        let mut storage = Storage::open(dir.path()).unwrap();
+        storage.set_encryption(Encryption::Locked);
+        assert!(matches!(
+            migrate(dir.path(), &mut storage, 0),
+            Err(NotesError::Locked)
+        ));
+        assert_eq!(version(dir.path()).unwrap(), 0);
+
+        storage.set_encryption(Encryption::Disabled);
+        migrate(dir.path(), &mut storage, 0).unwrap();
+        assert_eq!(storage.load_all().unwrap()[0].id, "a");
+        assert!(!notes_file.exists());
+
+        std::fs::write(&notes_file, "[{\"id\":").unwrap();
+        migrate(dir.path(), &mut storage, 0).unwrap();
+        assert!(notes_file.exists());
+        assert_eq!(version(dir.path()).unwrap(), SCHEMA_VERSION);
+    }
+}
@@ -0,0 +1,94 @@
+//! What a workspace's data directory spends its disk space on.
+
+use crate::error::NotesError;
+use crate::storage::NoteUsage;
+use crate::store::NotesStore;
+use crate::{backup, datadir, fsutil};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Debug)]
+pub struct StorageReport {
+    pub data_dir: PathBuf,
+    /// Everything in the data directory, backups included.
+    pub total_bytes: u64,
+    /// The storage backend's database or files, without the attachments.
+    pub notes_bytes: u64,
+    pub history_bytes: u64,
+    pub attachments_bytes: u64,
+    pub backups_bytes: u64,
+    /// The largest notes by their stored size with their attachments,
+    /// largest first.
+    pub largest_notes: Vec<NoteUsage>,
+}
+
+/// Measures the workspace in `data_dir`, whose notes are in `store`, listing
+/// its `top` largest notes.
+pub fn report(
+    data_dir: &Path,
+    store: &NotesStore,
+    top: usize,
+) -> Result<StorageReport, NotesError> {
+    let usage = store.disk_usage()?;
+    let mut notes = usage.notes;
+    notes.sort_by_key(|note| std::cmp::Reverse(note.bytes + note.attachment_bytes));
+    notes.truncate(top);
+    Ok(StorageReport {
+        data_dir: data_dir.to_path_buf(),
+        total_bytes: datadir::entries(data_dir)
+            .iter()
+            .map(|path| fsutil::size_on_disk(path))
+            .sum(),
+        notes_bytes: usage.notes_bytes,
+        history_bytes: usage.history_bytes,
+        attachments_bytes: usage.attachments_bytes,
+        backups_bytes: fsutil::size_on_disk(&data_dir.join(backup::BACKUPS_DIR)),
+        largest_notes: notes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note;
+    use crate::storage::Storage;
+    use tempfile::TempDir;
+
+    #[test]
+    fn finds_the_largest_notes() {
+        let dir = TempDir::new().unwrap();
+        let mut store = NotesStore::new(Storage::open(dir.path()).unwrap()).unwrap();
+        let mut pictured = note::new("Trip".into(), "Photos".into(), Vec::new(), 1);
+        let photo = vec![7; 4096];
+        pictured.attachments.push(note::Attachment {
+            id: store.put_attachment(&photo).unwrap(),
+            name: "beach.jpg".into(),
+            mime: "image/jpeg".into(),
+            size: photo.len() as u64,
+            added_at: 1,
+            text: None,
+        });
+        store.insert(pictured).unwrap();
+        store
+            .insert(note::new("Log".into(), "ok\n".repeat(1000), Vec::new(), 2))
+            .unwrap();
+        store
+            .insert(note::new("Small".into(), String::new(), Vec::new(), 3))
+            .unwrap();
+
+        let report = report(dir.path(), &store, 2).unwrap();
+        let largest: Vec<Option<&str>> = report
+            .largest_notes
+            .iter()
+            .map(|note| note.title.as_deref())
+            .collect();
+        assert_eq!(largest, [Some("Trip"), Some("Log")]);
+        assert_eq!(report.attachments_bytes, 4096);
+        assert!(report.notes_bytes > 3000);
+        assert!(
+            report.total_bytes
+                >= report.notes_bytes + report.history_bytes + report.attachments_bytes
+        );
+        assert_eq!(report.backups_bytes, 0);
+    }
+}
@@ -0,0 +1,159 @@
+//! Limits on what goes into a note, checked before anything typed, pasted,
+//! or sent from another app is saved, so that a stray 50 MB paste is refused
+//! instead of bloating the store, the search index, and every sync.
+
+use crate::error::NotesError;
+use crate::note;
+
+const MAX_META_KEY_CHARS: usize = 64;
+
+/// The largest title and content a note may have, from the settings.
+#[derive(Clone, Copy)]
+pub struct Limits {
+    pub max_title_chars: usize,
+    pub max_note_bytes: usize,
+}
+
+fn invalid(field: &'static str, message: String) -> NotesError {
+    NotesError::Validation { field, message }
+}
+
+/// Describes a size in whole KB or MB.
+fn size(bytes: usize) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{} MB", bytes / (1024 * 1024))
+    } else {
+        format!("{} KB", bytes.div_ceil(1024))
+    }
+}
+
+impl Limits {
+    /// Returns `title` with line breaks and tabs turned into spaces and other
+    /// control characters removed.
+    pub fn title(&self, title: &str) -> Result<String, NotesError> {
+        if title.contains('\0') {
+            return Err(invalid(
+                "title",
+                "Titles can't contain NUL characters".into(),
+            ));
+        }
+        let title: String = title
+            .chars()
+            .filter_map(|c| match c {
+                '\n' | '\r' | '\t' => Some(' '),
+                c if c.is_control() => None,
+                c => Some(c),
+            })
+            .collect();
+        if title.chars().count() > self.max_title_chars {
+            return Err(invalid(
+                "title",
+                format!(
+                    "Titles can be at most {} characters long",
+                    self.max_title_chars
+                ),
+            ));
+        }
+        Ok(title)
+    }
+
+    /// Returns `content` with control characters other than line breaks and
+    /// tabs removed.
+    pub fn content(&self, content: &str) -> Result<String, NotesError> {
+        if content.contains('\0') {
+            return Err(invalid(
+                "content",
+                "Notes can't contain NUL characters".into(),
+            ));
+        }
+        if content.len() > self.max_note_bytes {
+            return Err(invalid(
+                "content",
+                format!(
+                    "This note is {}, over the limit of {}",
+                    size(content.len()),
+                    size(self.max_note_bytes)
+                ),
+            ));
+        }
+        Ok(content
+            .chars()
+            .filter(|&c| matches!(c, '\n' | '\r' | '\t') || !c.is_control())
+            .collect())
+    }
+}
+
+/// Returns the metadata field name `key`, trimmed, if a note may have a
+/// field by that name.
+pub fn meta_key(key: &str) -> Result<String, NotesError> {
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(invalid("key", "Field names can't be empty".into()));
+    }
+    if key.chars().count() > MAX_META_KEY_CHARS {
+        return Err(invalid(
+            "key",
+            format!(
+                "Field names can be at most {} characters long",
+                MAX_META_KEY_CHARS
+            ),
+        ));
+    }
+    if key.chars().any(|c| c.is_control() || c == ':') {
+        return Err(invalid(
+            "key",
+            "Field names can't contain colons or control characters".into(),
+        ));
+    }
+    if note::FIELDS.contains(&key) {
+        return Err(invalid(
+            "key",
+            format!("{} is one of the note's own fields", key),
+        ));
+    }
+    Ok(key.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: Limits = Limits {
+        max_title_chars: 5,
+        max_note_bytes: 2048,
+    };
+
+    #[test]
+    fn cleans_titles_and_content() {
+        assert_eq!(LIMITS.title("a\tb\u{7}\nc").unwrap(), "a b c");
+        assert_eq!(LIMITS.title("ééééé").unwrap(), "ééééé");
+        assert_eq!(
+            LIMITS.content("line\r\n\u{1b}[1mbold\ttab").unwrap(),
+            "line\r\n[1mbold\ttab"
+        );
+    }
+
+    #[test]
+    fn rejects_nul_and_oversized_input() {
+        let field = |result: Result<String, NotesError>| match result {
+            Err(NotesError::Validation { field, .. }) => field,
+            _ => panic!("expected a validation error"),
+        };
+        assert_eq!(field(LIMITS.title("a\0")), "title");
+        assert_eq!(field(LIMITS.title("sixsix")), "title");
+        assert_eq!(field(LIMITS.content("\0")), "content");
+        let error = LIMITS.content(&"x".repeat(3000)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "This note is 3 KB, over the limit of 2 KB"
+        );
+    }
+
+    #[test]
+    fn checks_metadata_field_names() {
+        assert_eq!(meta_key(" status ").unwrap(), "status");
+        for key in ["", "a: b", "title", "created_at", &"k".repeat(65)] {
+            assert!(meta_key(key).is_err(), "{:?} was allowed", key);
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! Optional at-rest encryption of note titles, contents, tags, and
+//! attachments.
+//!
+//! Notes are encrypted with a random 256-bit data key. The data key itself is
+//! stored in vault.json, wrapped with a key derived from the master password
+//! via Argon2id, so changing the password never re-encrypts the notes.
+
+use crate::error::NotesError;
+use crate::fsutil;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::Argon2;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::path::{Path, PathBuf};
+
+/// Marks a stored string as ciphertext rather than plaintext.
+const ENCRYPTED_PREFIX: &str = "enc1:";
+/// Marks note content sealed with the note's own password.
+const NOTE_LOCK_PREFIX: &str = "lock1:";
+const NONCE_LEN: usize = 12;
+const SALT_LEN: usize = 16;
+
+#[derive(Serialize, Deserialize)]
+struct VaultFile {
+    salt: String,
+    wrapped_key: String,
+}
+
+/// A data key kept in the keychain, with the wrapped key of the vault it was
+/// taken from.
+#[derive(Serialize, Deserialize)]
+struct RememberedKey {
+    wrapped_key: String,
+    key: String,
+}
+
+/// Encrypts and decrypts note fields with the vault's data key.
+#[derive(Clone)]
+pub struct Cipher {
+    key: [u8; 32],
+}
+
+/// How storage should treat note fields.
+pub enum Encryption {
+    /// No master password is set; notes are stored in plaintext.
+    Disabled,
+    /// A master password is set but the vault hasn't been unlocked.
+    Locked,
+    Unlocked(Cipher),
+}
+
+impl Cipher {
+    fn from_key(key: [u8; 32]) -> Self {
+        Cipher { key }
+    }
+
+    fn aead(&self) -> Aes256Gcm {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.key))
+    }
+
+    fn seal(&self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let mut sealed = nonce.to_vec();
+        sealed.extend(
+            self.aead()
+                .encrypt(&nonce, plaintext)
+                .expect("AES-GCM encryption cannot fail for in-memory buffers"),
+        );
+        sealed
+    }
+
+    fn open(&self, sealed: &[u8]) -> Option<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.aead()
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .ok()
+    }
+
+    pub fn encrypt_str(&self, plaintext: &str) -> String {
+        format!(
+            "{}{}",
+            ENCRYPTED_PREFIX,
+            BASE64.encode(self.seal(plaintext.as_bytes()))
+        )
+    }
+
+    /// Like `encrypt_str`, for binary data.
+    pub fn encrypt_bytes(&self, plaintext: &[u8]) -> Vec<u8> {
+        let mut sealed = ENCRYPTED_PREFIX.as_bytes().to_vec();
+        sealed.extend(self.seal(plaintext));
+        sealed
+    }
+
+    /// Decrypts data produced by `encrypt_bytes`. Data without the encryption
+    /// prefix is plaintext and is returned unchanged.
+    pub fn decrypt_bytes(&self, stored: &[u8]) -> Result<Vec<u8>, NotesError> {
+        match stored.strip_prefix(ENCRYPTED_PREFIX.as_bytes()) {
+            Some(sealed) => self
+                .open(sealed)
+                .ok_or_else(|| NotesError::Crypto("Failed to decrypt attachment".into())),
+            None => Ok(stored.to_vec()),
+        }
+    }
+
+    /// Returns a hex HMAC-SHA256 of `data` under the data key, for names that
+    /// mustn't reveal what they name without the key.
+    pub fn keyed_hash(&self, data: &[u8]) -> String {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.key)
+            .expect("HMAC accepts keys of any length");
+        // Keeps these hashes apart from any other use of the key
+        mac.update(b"min-notes blob name\0");
+        mac.update(data);
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// Decrypts a string produced by `encrypt_str`. Strings without the
+    /// encryption prefix are plaintext and are returned unchanged.
+    pub fn decrypt_str(&self, stored: &str) -> Result<String, NotesError> {
+        let Some(encoded) = stored.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(stored.to_string());
+        };
+        BASE64
+            .decode(encoded)
+            .ok()
+            .and_then(|sealed| self.open(&sealed))
+            .and_then(|plaintext| String::from_utf8(plaintext).ok())
+            .ok_or_else(|| NotesError::Crypto("Failed to decrypt note".into()))
+    }
+}
+
+/// Derives a cipher from a passphrase, for data shared with devices that don't
+/// share this vault. `salt` should come from `random_salt`.
+pub fn passphrase_cipher(passphrase: &str, salt: &str) -> Result<Cipher, NotesError> {
+    let salt = BASE64
+        .decode(salt)
+        .map_err(|e| NotesError::Crypto(format!("Failed to read key salt: {}", e)))?;
+    derive_key(passphrase, &salt)
+}
+
+/// Returns a new base64-encoded salt for `passphrase_cipher`.
+pub fn random_salt() -> String {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    BASE64.encode(salt)
+}
+
+/// Seals `content` with a key derived from `password`, for a note locked on
+/// its own. The result carries its salt, so only the password is needed to
+/// open it.
+pub fn lock_content(content: &str, password: &str) -> Result<String, NotesError> {
+    let salt = random_salt();
+    let sealed = passphrase_cipher(password, &salt)?.encrypt_str(content);
+    Ok(format!("{}{}:{}", NOTE_LOCK_PREFIX, salt, sealed))
+}
+
+/// Opens content sealed by `lock_content`.
+pub fn unlock_content(stored: &str, password: &str) -> Result<String, NotesError> {
+    let (salt, sealed) = stored
+        .strip_prefix(NOTE_LOCK_PREFIX)
+        .and_then(|rest| rest.split_once(':'))
+        .filter(|(_, sealed)| is_encrypted(sealed))
+        .ok_or_else(|| NotesError::Crypto("Failed to read locked note".into()))?;
+    passphrase_cipher(password, salt)?
+        .decrypt_str(sealed)
+        .map_err(|_| NotesError::Crypto("Incorrect note password".into()))
+}
+
+pub fn is_encrypted(stored: &str) -> bool {
+    stored.starts_with(ENCRYPTED_PREFIX)
+}
+
+pub fn is_encrypted_bytes(stored: &[u8]) -> bool {
+    stored.starts_with(ENCRYPTED_PREFIX.as_bytes())
+}
+
+/// The master-password configuration persisted in vault.json.
+pub struct Vault {
+    path: PathBuf,
+    file: Option<VaultFile>,
+}
+
+impl Vault {
+    pub fn load(path: &Path) -> Result<Self, NotesError> {
+        let file = if path.exists() {
+            Some(fsutil::read_with_backup(path, |content| {
+                serde_json::from_str(content)
+                    .map_err(|e| NotesError::Serde(format!("Failed to parse vault file: {}", e)))
+            })?)
+        } else {
+            None
+        };
+        Ok(Vault {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    /// Whether a master password has been set.
+    pub fn is_enabled(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// Returns the data key cipher if `password` is correct.
+    pub fn unlock(&self, password: &str) -> Result<Cipher, NotesError> {
+        let file = self
+            .file
+            .as_ref()
+            .ok_or_else(|| NotesError::Invalid("No master password has been set".into()))?;
+        let salt = BASE64
+            .decode(&file.salt)
+            .map_err(|e| NotesError::Crypto(format!("Failed to read vault salt: {}", e)))?;
+        let wrapped_key = BASE64
+            .decode(&file.wrapped_key)
+            .map_err(|e| NotesError::Crypto(format!("Failed to read vault key: {}", e)))?;
+
+        let data_key: [u8; 32] = derive_key(password, &salt)?
+            .open(&wrapped_key)
+            .and_then(|key| key.try_into().ok())
+            .ok_or_else(|| NotesError::Crypto("Incorrect master password".into()))?;
+        Ok(Cipher::from_key(data_key))
+    }
+
+    /// Returns `cipher`'s data key as text to keep in the keychain. It unlocks
+    /// this vault until the master password changes.
+    pub fn remembered_key(&self, cipher: &Cipher) -> Result<String, NotesError> {
+        let file = self.file.as_ref().ok_or(NotesError::Locked)?;
+        serde_json::to_string(&RememberedKey {
+            wrapped_key: file.wrapped_key.clone(),
+            key: BASE64.encode(cipher.key),
+        })
+        .map_err(|e| NotesError::Serde(format!("Failed to serialize vault key: {}", e)))
+    }
+
+    /// Returns the cipher for a key from `remembered_key`, or `None` if it was
+    /// taken from another vault or under an older password.
+    pub fn unlock_remembered(&self, remembered: &str) -> Option<Cipher> {
+        let remembered: RememberedKey = serde_json::from_str(remembered).ok()?;
+        if self.file.as_ref()?.wrapped_key != remembered.wrapped_key {
+            return None;
+        }
+        let key = BASE64.decode(remembered.key).ok()?.try_into().ok()?;
+        Some(Cipher::from_key(key))
+    }
+
+    /// Sets a new master password. When the vault already exists, `current`
+    /// must be its unlocked cipher so the data key can be re-wrapped;
+    /// otherwise a new data key is generated. Returns the data key cipher.
+    pub fn set_password(
+        &mut self,
+        password: &str,
+        current: Option<&Cipher>,
+    ) -> Result<Cipher, NotesError> {
+        if self.file.is_some() && current.is_none() {
+            return Err(NotesError::Locked);
+        }
+        let data_key = match current {
+            Some(cipher) => cipher.key,
+            None => {
+                let mut key = [0u8; 32];
+                OsRng.fill_bytes(&mut key);
+                key
+            }
+        };
+
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let file = VaultFile {
+            salt: BASE64.encode(salt),
+            wrapped_key: BASE64.encode(derive_key(password, &salt)?.seal(&data_key)),
+        };
+
+        let json = serde_json::to_string_pretty(&file)
+            .map_err(|e| NotesError::Serde(format!("Failed to serialize vault file: {}", e)))?;
+        fsutil::write_with_backup(&self.path, json.as_bytes())
+            .map_err(|e| NotesError::Io(format!("Failed to write vault file: {}", e)))?;
+
+        self.file = Some(file);
+        Ok(Cipher::from_key(data_key))
+    }
+}
+
+fn derive_key(password: &str, salt: &[u8]) -> Result<Cipher, NotesError> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|e| NotesError::Crypto(format!("Failed to derive key from password: {}", e)))?;
+    Ok(Cipher::from_key(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn remembered_keys_stop_working_when_the_password_changes() {
+        let dir = TempDir::new().unwrap();
+        let mut vault = Vault::load(&dir.path().join("vault.json")).unwrap();
+        let cipher = vault.set_password("first", None).unwrap();
+        let remembered = vault.remembered_key(&cipher).unwrap();
+        let unlocked = vault.unlock_remembered(&remembered).unwrap();
+        assert_eq!(unlocked.key, cipher.key);
+
+        vault.set_password("second", Some(&cipher)).unwrap();
+        assert!(vault.unlock_remembered(&remembered).is_none());
+        assert!(vault.unlock_remembered("not a key").is_none());
+    }
+}
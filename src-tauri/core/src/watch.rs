@@ -0,0 +1,108 @@
+//! File watchers that wake a background thread, which waits for a burst of
+//! changes to end before handling them.
+
+use crate::error::NotesError;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+
+pub struct Watch {
+    requests: Sender<()>,
+    /// Replaced whenever what's watched changes.
+    watcher: Mutex<Option<RecommendedWatcher>>,
+}
+
+/// The other end of a `Watch`, for the thread handling its changes.
+pub struct Requests(Receiver<()>);
+
+impl Requests {
+    /// Waits for a change, then for `quiet` to pass without another one.
+    /// Returns `false` once the `Watch` is gone.
+    pub fn wait(&self, quiet: Duration) -> bool {
+        if self.0.recv().is_err() {
+            return false;
+        }
+        loop {
+            match self.0.recv_timeout(quiet) {
+                Ok(()) => {}
+                Err(RecvTimeoutError::Timeout) => return true,
+                Err(RecvTimeoutError::Disconnected) => return false,
+            }
+        }
+    }
+}
+
+impl Watch {
+    pub fn new() -> (Self, Requests) {
+        let (requests, received) = mpsc::channel();
+        let watch = Watch {
+            requests,
+            watcher: Mutex::new(None),
+        };
+        (watch, Requests(received))
+    }
+
+    /// Wakes the thread as if something changed.
+    pub fn request(&self) {
+        // Only fails once the thread has stopped
+        let _ = self.requests.send(());
+    }
+
+    /// Watches `path`, and everything under it if `recursive`, in place of
+    /// what was watched before. Only changes to paths for which `relevant`
+    /// returns true wake the thread.
+    pub fn watch(
+        &self,
+        path: &Path,
+        recursive: bool,
+        relevant: impl Fn(&Path) -> bool + Send + 'static,
+    ) -> Result<(), NotesError> {
+        let mut watcher = self.watcher.lock().unwrap();
+        *watcher = None;
+        let requests = self.requests.clone();
+        let handler = move |event: notify::Result<notify::Event>| {
+            let Ok(event) = event else {
+                return;
+            };
+            if !event.kind.is_access() && event.paths.iter().any(|path| relevant(path)) {
+                let _ = requests.send(());
+            }
+        };
+        let mode = if recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        let mut watching = notify::recommended_watcher(handler)
+            .map_err(|e| NotesError::Io(format!("Failed to watch {}: {}", path.display(), e)))?;
+        watching
+            .watch(path, mode)
+            .map_err(|e| NotesError::Io(format!("Failed to watch {}: {}", path.display(), e)))?;
+        *watcher = Some(watching);
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        *self.watcher.lock().unwrap() = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn waits_for_requests_to_stop() {
+        let dir = TempDir::new().unwrap();
+        let (watch, requests) = Watch::new();
+        watch.watch(dir.path(), true, |_| true).unwrap();
+        watch.request();
+        watch.request();
+        assert!(requests.wait(Duration::from_millis(10)));
+        drop(watch);
+        assert!(!requests.wait(Duration::from_millis(10)));
+    }
+}
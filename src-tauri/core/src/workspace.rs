@@ -0,0 +1,241 @@
+//! Independent note collections, each in its own data directory with its own
+//! notes, vault, and sync settings. The default workspace lives in the
+//! directory from the settings; the others are listed in workspaces.json in
+//! the app data directory along with which one is active.
+
+use crate::error::NotesError;
+use crate::fsutil;
+use crate::keychain::{self, Secret};
+use crate::storage::Storage;
+use crate::store::NotesStore;
+use crate::upgrade;
+use crate::vault::{Encryption, Vault};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+use uuid::Uuid;
+
+/// Trashed notes older than this are purged when a workspace is opened.
+const TRASH_RETENTION_DAYS: i64 = 30;
+
+/// Id of the workspace that exists before any others are created.
+pub const DEFAULT_ID: &str = "default";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Workspace {
+    pub id: String,
+    pub name: String,
+    pub path: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct WorkspacesFile {
+    /// `None` while the default workspace is active.
+    #[serde(default)]
+    active: Option<String>,
+    #[serde(default)]
+    workspaces: Vec<Workspace>,
+}
+
+pub struct Workspaces {
+    path: PathBuf,
+    file: WorkspacesFile,
+}
+
+impl Workspaces {
+    pub fn load(path: &Path) -> Result<Self, NotesError> {
+        let file = if path.exists() {
+            fsutil::read_with_backup(path, |content| {
+                serde_json::from_str(content)
+                    .map_err(|e| NotesError::Serde(format!("Failed to parse workspaces: {}", e)))
+            })?
+        } else {
+            WorkspacesFile::default()
+        };
+        Ok(Workspaces {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    fn save(&self) -> Result<(), NotesError> {
+        let json = serde_json::to_string_pretty(&self.file)
+            .map_err(|e| NotesError::Serde(format!("Failed to serialize workspaces: {}", e)))?;
+        fsutil::write_with_backup(&self.path, json.as_bytes())
+            .map_err(|e| NotesError::Io(format!("Failed to write workspaces: {}", e)))
+    }
+
+    /// Every workspace besides the default one, in the order created.
+    pub fn all(&self) -> &[Workspace] {
+        &self.file.workspaces
+    }
+
+    pub fn active_id(&self) -> &str {
+        self.file.active.as_deref().unwrap_or(DEFAULT_ID)
+    }
+
+    /// Returns the active workspace, or `None` if it's the default one.
+    pub fn active(&self) -> Option<&Workspace> {
+        let id = self.file.active.as_deref()?;
+        self.file.workspaces.iter().find(|w| w.id == id)
+    }
+
+    /// Returns the active workspace's directory, or `default` if it's the
+    /// default workspace.
+    pub fn active_dir(
+        &self,
+        default: impl FnOnce() -> Result<PathBuf, NotesError>,
+    ) -> Result<PathBuf, NotesError> {
+        match self.active() {
+            Some(workspace) => Ok(workspace.path.clone()),
+            None => default(),
+        }
+    }
+
+    /// Adds a workspace stored in `path`, creating it if needed. It may
+    /// already hold notes, but mustn't be, contain, or sit inside another
+    /// workspace's directory. `taken` lists directories already in use, such
+    /// as the default workspace's.
+    pub fn create(
+        &mut self,
+        name: &str,
+        path: &Path,
+        taken: &[&Path],
+    ) -> Result<Workspace, NotesError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(NotesError::Invalid("Workspace name cannot be empty".into()));
+        }
+        if !path.is_absolute() {
+            return Err(NotesError::Invalid(
+                "Workspace directory must be an absolute path".into(),
+            ));
+        }
+        fs::create_dir_all(path)
+            .map_err(|e| NotesError::Io(format!("Failed to create workspace directory: {}", e)))?;
+        let path = fs::canonicalize(path)
+            .map_err(|e| NotesError::Io(format!("Failed to resolve workspace directory: {}", e)))?;
+        let overlaps = |other: &Path| {
+            // Directories that are gone can't overlap with an existing one
+            let other = fs::canonicalize(other).unwrap_or_else(|_| other.to_path_buf());
+            path.starts_with(&other) || other.starts_with(&path)
+        };
+        let in_use = taken.iter().copied().any(overlaps)
+            || self.file.workspaces.iter().any(|w| overlaps(&w.path));
+        if in_use {
+            return Err(NotesError::Invalid(
+                "Another workspace already uses that directory, or one inside or around it".into(),
+            ));
+        }
+
+        let workspace = Workspace {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            path,
+        };
+        self.file.workspaces.push(workspace.clone());
+        self.save()?;
+        Ok(workspace)
+    }
+
+    /// Returns the directory of workspace `id`, with `None` standing for the
+    /// default workspace.
+    pub fn path_of(&self, id: &str) -> Result<Option<&Path>, NotesError> {
+        if id == DEFAULT_ID {
+            return Ok(None);
+        }
+        self.file
+            .workspaces
+            .iter()
+            .find(|w| w.id == id)
+            .map(|w| Some(w.path.as_path()))
+            .ok_or_else(|| NotesError::NotFound("Workspace not found".into()))
+    }
+
+    pub fn set_active(&mut self, id: &str) -> Result<(), NotesError> {
+        self.path_of(id)?;
+        self.file.active = (id != DEFAULT_ID).then(|| id.to_string());
+        self.save()
+    }
+
+    /// Records that the active workspace, which isn't the default one, moved
+    /// to `path`.
+    pub fn set_active_path(&mut self, path: &Path) -> Result<(), NotesError> {
+        let id = self
+            .file
+            .active
+            .clone()
+            .ok_or_else(|| NotesError::Internal("The default workspace isn't listed".into()))?;
+        let workspace = self
+            .file
+            .workspaces
+            .iter_mut()
+            .find(|w| w.id == id)
+            .ok_or_else(|| NotesError::NotFound("Workspace not found".into()))?;
+        workspace.path = path.to_path_buf();
+        self.save()
+    }
+}
+
+/// Opens the vault and notes of the workspace in `dir`, creating it if needed.
+pub fn open(dir: &Path) -> Result<(Vault, NotesStore), NotesError> {
+    fs::create_dir_all(dir)
+        .map_err(|e| NotesError::Io(format!("Failed to create data directory: {}", e)))?;
+    let vault = Vault::load(&dir.join("vault.json"))?;
+    let store = open_store(dir, &vault)?;
+    Ok((vault, store))
+}
+
+/// Opens the notes in `data_dir`, locked if `vault` is enabled, upgrading
+/// them first if an older version saved them.
+pub fn open_store(data_dir: &Path, vault: &Vault) -> Result<NotesStore, NotesError> {
+    let version = upgrade::prepare(data_dir)?;
+    let mut storage = Storage::open(data_dir)?;
+    if vault.is_enabled() {
+        storage.set_encryption(Encryption::Locked);
+    }
+    upgrade::migrate(data_dir, &mut storage, version)?;
+
+    let cutoff = (Utc::now() - Duration::days(TRASH_RETENTION_DAYS)).timestamp();
+    if let Err(e) = storage.purge_trashed_before(cutoff) {
+        warn!("Failed to purge old trashed notes: {}", e);
+    }
+    NotesStore::new(storage)
+}
+
+/// Unlocks `store` with the data key remembered in workspace `id`'s keychain
+/// entry, returning whether it did. A key that no longer fits `vault` is
+/// forgotten.
+pub fn unlock_remembered(store: &mut NotesStore, vault: &Vault, id: &str) -> bool {
+    if !store.is_locked() {
+        return false;
+    }
+    let remembered = match keychain::get(Secret::VaultKey, id) {
+        Ok(Some(remembered)) => remembered,
+        Ok(None) => return false,
+        Err(e) => {
+            warn!("Failed to read remembered vault key: {}", e);
+            return false;
+        }
+    };
+    let unlocked = vault
+        .unlock_remembered(&remembered)
+        .ok_or_else(|| NotesError::Crypto("The vault key no longer fits".into()))
+        .and_then(|cipher| store.set_encryption(Encryption::Unlocked(cipher)));
+    match unlocked {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("Forgetting remembered vault key: {}", e);
+            // Decrypting may have failed partway
+            if let Err(e) = store.set_encryption(Encryption::Locked) {
+                warn!("Failed to lock the vault again: {}", e);
+            }
+            if let Err(e) = keychain::clear(Secret::VaultKey, id) {
+                warn!("Failed to forget vault key: {}", e);
+            }
+            false
+        }
+    }
+}
@@ -0,0 +1,33 @@
+package com.min_notes.app
+
+import android.app.Activity
+import android.content.Intent
+import android.net.Uri
+import android.os.Bundle
+
+/**
+ * Receives text and links shared from other apps and passes them on to
+ * MainActivity as a min-notes://share link, which the deep link plugin
+ * hands to Rust: as the launch link if the app wasn't running, otherwise as
+ * an opened link.
+ */
+class ShareActivity : Activity() {
+    override fun onCreate(savedInstanceState: Bundle?) {
+        super.onCreate(savedInstanceState)
+        val shared = intent
+        if (shared?.action == Intent.ACTION_SEND) {
+            // Uri.Builder escapes every reserved character, `+` included
+            val link = Uri.Builder().scheme("min-notes").authority("share")
+            shared.getStringExtra(Intent.EXTRA_SUBJECT)?.let {
+                link.appendQueryParameter("subject", it)
+            }
+            shared.getCharSequenceExtra(Intent.EXTRA_TEXT)?.let {
+                link.appendQueryParameter("text", it.toString())
+            }
+            val view = Intent(Intent.ACTION_VIEW, link.build(), this, MainActivity::class.java)
+                .addFlags(Intent.FLAG_ACTIVITY_NEW_TASK or Intent.FLAG_ACTIVITY_SINGLE_TOP)
+            startActivity(view)
+        }
+        finish()
+    }
+}
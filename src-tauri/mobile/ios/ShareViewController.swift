@@ -0,0 +1,67 @@
+import UIKit
+import UniformTypeIdentifiers
+
+/// The principal class of the share extension. Reads the text and link
+/// another app shared and opens min_notes with them as a min-notes://share
+/// link, which the deep link plugin hands to Rust: as the launch link if the
+/// app wasn't running, otherwise as an opened link.
+class ShareViewController: UIViewController {
+    override func viewDidAppear(_ animated: Bool) {
+        super.viewDidAppear(animated)
+        Task {
+            if let link = await shareLink() {
+                open(link)
+            }
+            extensionContext?.completeRequest(returningItems: nil)
+        }
+    }
+
+    private func shareLink() async -> URL? {
+        guard let item = extensionContext?.inputItems.first as? NSExtensionItem else {
+            return nil
+        }
+        var query = [URLQueryItem]()
+        if let subject = item.attributedTitle?.string, !subject.isEmpty {
+            query.append(URLQueryItem(name: "subject", value: subject))
+        }
+        var text = item.attributedContentText?.string ?? ""
+        for provider in item.attachments ?? [] {
+            if text.isEmpty, provider.hasItemConformingToTypeIdentifier(UTType.plainText.identifier),
+               let shared = try? await provider.loadItem(forTypeIdentifier: UTType.plainText.identifier) as? String {
+                text = shared
+            }
+            if provider.hasItemConformingToTypeIdentifier(UTType.url.identifier),
+               let shared = try? await provider.loadItem(forTypeIdentifier: UTType.url.identifier) as? URL {
+                query.append(URLQueryItem(name: "url", value: shared.absoluteString))
+            }
+        }
+        if !text.isEmpty {
+            query.append(URLQueryItem(name: "text", value: text))
+        }
+        if query.isEmpty {
+            return nil
+        }
+
+        var link = URLComponents()
+        link.scheme = "min-notes"
+        link.host = "share"
+        link.queryItems = query
+        // URLComponents leaves `+` as it is, which would be read as a space
+        link.percentEncodedQuery = link.percentEncodedQuery?
+            .replacingOccurrences(of: "+", with: "%2B")
+        return link.url
+    }
+
+    /// Extensions can't reach UIApplication.shared, but the app is still in
+    /// the responder chain.
+    private func open(_ link: URL) {
+        var responder: UIResponder? = self
+        while let next = responder {
+            if let application = next as? UIApplication {
+                application.open(link, options: [:], completionHandler: nil)
+                return
+            }
+            responder = next.next
+        }
+    }
+}
@@ -0,0 +1,287 @@
+//! Subcommands for scripts, run in place of the app when the binary is
+//! started with one:
+//!
+//! - `min_notes add <title> [--content <text>|-] [--tag <tag>]...` adds a
+//!   note, reading its content from stdin for `-`, and prints its id
+//! - `min_notes list [--json]` prints each note's id and title, or every
+//!   note as JSON
+//! - `min_notes export --format md|html|pdf --out <dir>` exports every note
+//!
+//! They use the active workspace's notes, even while the app is running: the
+//! store lock keeps the two from interleaving writes, and the app reloads
+//! what changed. An encrypted workspace is unlocked with the data key the app
+//! remembered in the keychain, if it did, or else with the password in
+//! `MIN_NOTES_PASSWORD`.
+
+use crate::error::NotesError;
+use crate::export::{self, ExportFormat};
+use crate::note;
+use crate::settings::Settings;
+use crate::store::NotesStore;
+use crate::vault::Encryption;
+use crate::workspace::{self, Workspaces};
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+/// Must match `identifier` in tauri.conf.json, which names the app data
+/// directory.
+const IDENTIFIER: &str = "com.min_notes.app";
+const PASSWORD_VAR: &str = "MIN_NOTES_PASSWORD";
+
+const USAGE: &str = "\
+Usage:
+  min_notes add <title> [--content <text>|-] [--tag <tag>]...
+  min_notes list [--json]
+  min_notes export --format md|html|pdf --out <dir>
+  min_notes help
+
+Without a command, starts the app.
+Set MIN_NOTES_PASSWORD to use notes protected by a master password.";
+
+#[derive(PartialEq, Debug)]
+enum Content {
+    Text(String),
+    Stdin,
+}
+
+#[derive(PartialEq, Debug)]
+enum Command {
+    Add {
+        title: String,
+        content: Option<Content>,
+        tags: Vec<String>,
+    },
+    List {
+        json: bool,
+    },
+    Export {
+        format: ExportFormat,
+        out: PathBuf,
+    },
+    Help,
+}
+
+/// Parses the command line, without the program name. Returns `None` if it
+/// doesn't start with a subcommand.
+fn parse(args: &[String]) -> Option<Result<Command, NotesError>> {
+    let (name, rest) = args.split_first()?;
+    let command = match name.as_str() {
+        "add" => parse_add(rest),
+        "list" => parse_list(rest),
+        "export" => parse_export(rest),
+        "help" | "--help" | "-h" => Ok(Command::Help),
+        _ => return None,
+    };
+    Some(command)
+}
+
+fn usage_error(message: impl Into<String>) -> NotesError {
+    NotesError::Invalid(message.into())
+}
+
+/// Returns the value following the option `name`.
+fn value<'a>(
+    name: &str,
+    args: &mut impl Iterator<Item = &'a String>,
+) -> Result<&'a String, NotesError> {
+    args.next()
+        .ok_or_else(|| usage_error(format!("{} needs a value", name)))
+}
+
+fn parse_add(args: &[String]) -> Result<Command, NotesError> {
+    let mut title = None;
+    let mut content = None;
+    let mut tags = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--content" => {
+                content = Some(match value(arg, &mut args)?.as_str() {
+                    "-" => Content::Stdin,
+                    text => Content::Text(text.to_string()),
+                })
+            }
+            "--tag" => tags.push(value(arg, &mut args)?.clone()),
+            option if option.starts_with("--") => {
+                return Err(usage_error(format!("Unknown option {}", option)))
+            }
+            _ if title.is_none() => title = Some(arg.clone()),
+            _ => return Err(usage_error(format!("Unexpected argument {}", arg))),
+        }
+    }
+    Ok(Command::Add {
+        title: title.ok_or_else(|| usage_error("add needs a title"))?,
+        content,
+        tags: note::normalize_tags(tags),
+    })
+}
+
+fn parse_list(args: &[String]) -> Result<Command, NotesError> {
+    match args {
+        [] => Ok(Command::List { json: false }),
+        [flag] if flag == "--json" => Ok(Command::List { json: true }),
+        [arg, ..] => Err(usage_error(format!("Unexpected argument {}", arg))),
+    }
+}
+
+fn parse_export(args: &[String]) -> Result<Command, NotesError> {
+    let mut format = None;
+    let mut out = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                format = Some(match value(arg, &mut args)?.as_str() {
+                    "md" | "markdown" => ExportFormat::Markdown,
+                    "html" => ExportFormat::Html,
+                    "pdf" => ExportFormat::Pdf,
+                    other => return Err(usage_error(format!("Unknown format {}", other))),
+                })
+            }
+            "--out" => out = Some(PathBuf::from(value(arg, &mut args)?)),
+            _ => return Err(usage_error(format!("Unexpected argument {}", arg))),
+        }
+    }
+    Ok(Command::Export {
+        format: format.unwrap_or(ExportFormat::Markdown),
+        out: out.ok_or_else(|| usage_error("export needs --out <dir>"))?,
+    })
+}
+
+/// Opens the active workspace's notes the way the app does at startup.
+fn open_store() -> Result<NotesStore, NotesError> {
+    let app_data = dirs::data_dir()
+        .ok_or_else(|| NotesError::Io("Failed to get app data directory".into()))?
+        .join(IDENTIFIER);
+    let settings = Settings::load(&app_data.join("settings.json"))?;
+    let workspaces = Workspaces::load(&app_data.join("workspaces.json"))?;
+    let data_dir = workspaces.active_dir(|| {
+        Ok(settings
+            .data_dir
+            .clone()
+            .unwrap_or_else(|| app_data.clone()))
+    })?;
+    let (vault, mut store) = workspace::open(&data_dir)?;
+    workspace::unlock_remembered(&mut store, &vault, workspaces.active_id());
+    if store.is_locked() {
+        let password = std::env::var(PASSWORD_VAR).map_err(|_| {
+            NotesError::Invalid(format!(
+                "These notes are encrypted; set {} to unlock them",
+                PASSWORD_VAR
+            ))
+        })?;
+        store.set_encryption(Encryption::Unlocked(vault.unlock(&password)?))?;
+    }
+    Ok(store)
+}
+
+fn execute(command: Command) -> Result<(), NotesError> {
+    match command {
+        Command::Help => println!("{}", USAGE),
+        Command::Add {
+            title,
+            content,
+            tags,
+        } => {
+            let content = match content {
+                Some(Content::Text(text)) => text,
+                Some(Content::Stdin) => {
+                    let mut text = String::new();
+                    io::stdin()
+                        .read_to_string(&mut text)
+                        .map_err(|e| NotesError::Io(format!("Failed to read stdin: {}", e)))?;
+                    text
+                }
+                None => String::new(),
+            };
+            let note = note::new(title, content, tags, chrono::Utc::now().timestamp());
+            open_store()?.insert(note.clone())?;
+            println!("{}", note.id);
+        }
+        Command::List { json } => {
+            let notes = open_store()?.notes()?;
+            if json {
+                let json = serde_json::to_string_pretty(&notes)
+                    .map_err(|e| NotesError::Serde(format!("Failed to serialize notes: {}", e)))?;
+                println!("{}", json);
+            } else {
+                for note in &notes {
+                    println!("{}\t{}", note.id, note.title);
+                }
+            }
+        }
+        Command::Export { format, out } => {
+            let notes = open_store()?.notes()?;
+            let paths = export::export_notes(&notes, format, &out)?;
+            println!("Exported {} notes to {}", paths.len(), out.display());
+        }
+    }
+    Ok(())
+}
+
+/// Runs the subcommand in `args` and returns the exit code: 0 on success, 1
+/// if it failed, and 2 if it was used wrongly. Returns `None` if there's no
+/// subcommand.
+pub fn main(args: &[String]) -> Option<i32> {
+    let code = match parse(args)? {
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            2
+        }
+        Ok(command) => match execute(command) {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("{}", e);
+                1
+            }
+        },
+    };
+    Some(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(parse(&args("")), None);
+        assert_eq!(parse(&args("min-notes://note/a")), None);
+        assert_eq!(
+            parse(&args("add Groceries --content - --tag home --tag home")).unwrap(),
+            Ok(Command::Add {
+                title: "Groceries".into(),
+                content: Some(Content::Stdin),
+                tags: vec!["home".into()],
+            })
+        );
+        assert_eq!(
+            parse(&args("list --json")).unwrap(),
+            Ok(Command::List { json: true })
+        );
+        assert_eq!(
+            parse(&args("export --format html --out dir/")).unwrap(),
+            Ok(Command::Export {
+                format: ExportFormat::Html,
+                out: PathBuf::from("dir/"),
+            })
+        );
+        for line in [
+            "add",
+            "add a b",
+            "add a --tag",
+            "list x",
+            "export --format doc --out d",
+        ] {
+            assert!(
+                matches!(parse(&args(line)), Some(Err(NotesError::Invalid(_)))),
+                "{}",
+                line
+            );
+        }
+    }
+}
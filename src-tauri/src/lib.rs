@@ -1,148 +1,5014 @@
+mod cli;
+#[cfg(desktop)]
+mod tray;
+
+use min_notes_core::{
+    anki, archive, assistant, auto_archive, backup, board, calendar, clipboard, clipper, datadir,
+    deeplink, drafts, email, error, expiry, export, geo, graph, import, keychain, links, logging,
+    markdown, note, notebooks, ocr, photo, previews, query, recent, related, reminders, replace,
+    saved_searches, search, semantic, settings, share, site, snapshot, stats, storage, store, sync,
+    tasks, templates, transcribe, upgrade, usage, validate, vault, watch, workspace,
+};
+
+use archive::{ArchiveReport, ConflictStrategy};
+use assistant::{Assistant, AssistantConfig};
+use backup::BackupInfo;
+use board::Board;
+use calendar::CalendarDay;
 use chrono::Utc;
+use clipboard::ClipboardWatch;
+use clipper::Clipper;
+use deeplink::DeepLink;
+use drafts::Drafts;
+use email::EmailConfig;
+use error::NotesError;
+use expiry::{Expiry, ExpiryAction};
+use export::ExportFormat;
+use geo::Location;
+use graph::NotesGraph;
+use import::{ImportFormat, ImportReport, ImportStrategy, Imported, ImportedItem, Importer};
+use keychain::Secret;
+use links::{LinkedNote, OutgoingLink};
+use logging::{LoggedError, Logging};
+use note::{Attachment, MergeStrategy, Note, NotePatch, NoteSummary};
+use notebooks::{Notebook, Notebooks};
+use ocr::Ocr;
+use photo::PhotoOptions;
+use previews::{LinkPreview, LinkPreviews};
+use query::DateField;
+use recent::{Recent, RecentNote, SwitchHit};
+use related::RelatedNote;
+use reminders::{Reminder, ReminderInfo, Repeat};
+use replace::{NoteMatches, Replacer};
+use saved_searches::{SavedSearch, SavedSearches};
+use search::{SearchHit, SearchIndex};
+use semantic::{Embedder, Embeddings, SemanticHit};
 use serde::{Deserialize, Serialize};
+use settings::Settings;
+use share::{ShareProvider, Shares};
+use site::{SiteOptions, SiteReport};
+use stats::{NoteStats, VaultStats};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use tauri::{AppHandle, Manager};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use storage::{BackendKind, GitLogEntry, IntegrityReport, Revision, Storage};
+use store::{NotePage, NoteQuery, NotesStore, TagCount};
+use sync::{
+    CrdtReport, FolderConfig, LocalChanges, S3Config, S3Secrets, ServerConfig, SyncConfig,
+    SyncReport, SyncStatus, SyncTarget,
+};
+use tasks::{Task, TaskFilter};
+use tauri::ipc::Response;
+use tauri::{AppHandle, Emitter, Manager, WebviewUrl, WebviewWindowBuilder};
+use templates::{Template, TemplateFields, Templates};
+use tracing::{error, info, warn};
+use usage::StorageReport;
 use uuid::Uuid;
+use validate::Limits;
+use vault::{Encryption, Vault};
+use watch::{Requests, Watch};
+use workspace::{Workspace, Workspaces};
 
-#[derive(Serialize, Deserialize, Clone)]
-struct Note {
-    id: String,
-    title: String,
-    content: String,
-    timestamp: i64,
-}
+/// Opens the quick-capture window from any app.
+#[cfg(desktop)]
+const CAPTURE_SHORTCUT: &str = "CommandOrControl+Shift+Space";
+const CAPTURE_WINDOW: &str = "capture";
 
-#[tauri::command]
-fn create_note(app: AppHandle, title: String, content: String) -> Result<String, String> {
+/// How often the backup thread checks whether a scheduled backup is due.
+const BACKUP_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+
+/// How often the reminder thread looks for reminders that are due.
+const REMINDER_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// How long to wait for the device to find where it is, in milliseconds.
+#[cfg(mobile)]
+const GEOLOCATION_TIMEOUT_MS: u32 = 15_000;
+/// How old a location the device already knows may be, in milliseconds, to
+/// tag a note with it.
+#[cfg(mobile)]
+const GEOLOCATION_MAX_AGE_MS: u32 = 60_000;
+
+/// How often clipboard capture looks at what's on the clipboard.
+const CLIPBOARD_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// How often the expiry thread looks for notes that have expired.
+const EXPIRY_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// How often the auto-archive thread checks whether a new day has started.
+const AUTO_ARCHIVE_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// How long to wait for another program to finish changing the stored notes
+/// before reloading them.
+const STORAGE_RELOAD_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// How long folder sync waits for changes to stop before syncing, so a burst
+/// of edits or file events is synced once.
+const FOLDER_SYNC_DELAY: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// How often the notes are synced with a sync server.
+const SERVER_SYNC_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+fn app_data_dir(app: &AppHandle) -> Result<PathBuf, NotesError> {
     let app_data_dir = app
         .path()
         .app_data_dir()
-        .map_err(|e| format!("Failed to get app data directory: {}", e))?;
+        .map_err(|e| NotesError::Io(format!("Failed to get app data directory: {}", e)))?;
 
     // Create app data directory if it doesn't exist
     if !app_data_dir.exists() {
         fs::create_dir_all(&app_data_dir)
-            .map_err(|e| format!("Failed to create app data directory: {}", e))?;
+            .map_err(|e| NotesError::Io(format!("Failed to create app data directory: {}", e)))?;
     }
+    Ok(app_data_dir)
+}
 
-    let notes_file = app_data_dir.join("notes.json");
+fn settings_path(app: &AppHandle) -> Result<PathBuf, NotesError> {
+    Ok(app_data_dir(app)?.join("settings.json"))
+}
 
-    // Load existing notes or create new list
-    let mut notes: Vec<Note> = if notes_file.exists() {
-        let content = fs::read_to_string(&notes_file)
-            .map_err(|e| format!("Failed to read notes file: {}", e))?;
-        serde_json::from_str(&content).unwrap_or_default()
-    } else {
-        Vec::new()
+fn workspaces_path(app: &AppHandle) -> Result<PathBuf, NotesError> {
+    Ok(app_data_dir(app)?.join("workspaces.json"))
+}
+
+fn clipper_token_path(app: &AppHandle) -> Result<PathBuf, NotesError> {
+    Ok(app_data_dir(app)?.join("clipper.json"))
+}
+
+/// Returns the default workspace's directory: the app data directory unless
+/// another one was picked with `set_notes_directory`.
+fn default_data_dir(app: &AppHandle, settings: &Settings) -> Result<PathBuf, NotesError> {
+    match &settings.data_dir {
+        Some(dir) => Ok(dir.clone()),
+        None => app_data_dir(app),
+    }
+}
+
+/// Where the active workspace keeps its notes, vault, and sync state.
+struct DataDir(Mutex<PathBuf>);
+
+fn data_dir(app: &AppHandle) -> PathBuf {
+    app.state::<DataDir>().inner().0.lock().unwrap().clone()
+}
+
+fn active_workspace(app: &AppHandle) -> String {
+    let workspaces = app.state::<Mutex<Workspaces>>().inner();
+    workspaces.lock().unwrap().active_id().to_string()
+}
+
+/// Search index updates are best-effort: the note itself is already saved, so a
+/// failure here shouldn't be reported as a failed save.
+fn reindex_note(index: &Mutex<SearchIndex>, note: &Note) {
+    if let Err(e) = index.lock().unwrap().upsert(note) {
+        warn!("Failed to index note {}: {}", note.id, e);
+    }
+}
+
+/// Emitted to every window after notes change, so each can refresh what it
+/// shows without polling.
+const NOTES_CHANGED: &str = "notes://changed";
+/// Asks the main window to select a note, with the note id as payload.
+const OPEN_NOTE: &str = "notes://open";
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum NoteOperation {
+    Created,
+    Updated,
+    Deleted,
+    /// Any number of notes may have changed, e.g. after a sync or unlocking
+    /// the vault, so the whole list should be reloaded.
+    Reloaded,
+}
+
+#[derive(Serialize, Clone)]
+struct NoteChanged {
+    /// The changed note, or `None` for `Reloaded`.
+    id: Option<String>,
+    operation: NoteOperation,
+}
+
+/// Must be called after releasing every state lock: Tauri runs Rust listeners,
+/// such as the tray menu's, on the emitting thread, and they lock the store
+/// themselves.
+fn emit_change(app: &AppHandle, id: Option<&str>, operation: NoteOperation) {
+    let payload = NoteChanged {
+        id: id.map(str::to_string),
+        operation,
     };
+    if let Err(e) = app.emit(NOTES_CHANGED, payload) {
+        warn!("Failed to emit note change: {}", e);
+    }
+    request_folder_sync(app);
+}
+
+/// Brings the main window to the front, even if it's hidden in the tray.
+fn show_main_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let shown = window
+            .show()
+            .and_then(|_| window.unminimize())
+            .and_then(|_| window.set_focus());
+        if let Err(e) = shown {
+            warn!("Failed to show main window: {}", e);
+        }
+    }
+}
+
+/// Brings the main window to the front and has it select note `id`.
+fn open_note(app: &AppHandle, id: &str) {
+    show_main_window(app);
+    if let Err(e) = app.emit_to("main", OPEN_NOTE, id) {
+        warn!("Failed to open note {}: {}", id, e);
+    }
+}
 
-    // Create new note
-    let note = Note {
-        id: Uuid::new_v4().to_string(),
-        title,
-        content,
-        timestamp: Utc::now().timestamp(),
+/// Writes every pending draft to the store. Drafts of notes that were deleted
+/// in the meantime are dropped. A draft that fails to save doesn't stop the
+/// others; it's logged and kept for the next flush, and the first failure is
+/// returned.
+fn flush_drafts(app: &AppHandle) -> Result<(), NotesError> {
+    let drafts = app.state::<Mutex<Drafts>>().inner();
+    let store = app.state::<Mutex<NotesStore>>().inner();
+    let index = app.state::<Mutex<SearchIndex>>().inner();
+
+    let pending = drafts.lock().unwrap().take();
+    let mut saved = Vec::new();
+    let mut failed = HashMap::new();
+    let mut first_error = None;
+    {
+        let mut store = store.lock().unwrap();
+        let now = Utc::now().timestamp();
+        for (id, content) in pending {
+            match store.get(&id) {
+                Ok(note) if note.content == content => continue,
+                Ok(note) if note.locked => {
+                    warn!("Dropping draft of locked note {}", id);
+                    continue;
+                }
+                Ok(_) => {}
+                Err(_) => {
+                    warn!("Dropping draft of missing note {}", id);
+                    continue;
+                }
+            }
+            match store.save_draft(&id, content.clone(), now) {
+                Ok(note) => {
+                    reindex_note(index, note);
+                    saved.push(id);
+                }
+                Err(e) => {
+                    error!("Failed to save draft of note {}: {}", id, e);
+                    failed.insert(id, content);
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+    }
+    if !failed.is_empty() {
+        drafts.lock().unwrap().restore(failed);
+    }
+    for id in &saved {
+        emit_change(app, Some(id), NoteOperation::Updated);
+        request_link_previews(app, id);
+    }
+    if !saved.is_empty() {
+        request_embeddings(app);
+    }
+    first_error.map_or(Ok(()), Err)
+}
+
+/// Emitted after the link previews of a note change, with the note id as
+/// payload.
+const LINK_PREVIEWS_CHANGED: &str = "notes://link-previews";
+
+/// Notes whose link previews are being fetched in the background, and
+/// whether each was saved again since, so it needs another look.
+#[derive(Default)]
+struct PendingPreviews(Mutex<HashMap<String, bool>>);
+
+/// Fetches previews of the links in note `id` that don't have one yet, or of
+/// every link with `refresh`, and returns the note's previews.
+fn update_link_previews(
+    app: &AppHandle,
+    id: &str,
+    refresh: bool,
+) -> Result<Vec<LinkPreview>, NotesError> {
+    let note = {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        store.lock().unwrap().get(id)?
+    };
+    // A locked note's links are sealed along with the rest of its content
+    let urls = if note.locked {
+        Vec::new()
+    } else {
+        previews::find_urls(&note.content)
     };
-    notes.push(note.clone());
-    // Save notes back to file
-    let notes_json = serde_json::to_string_pretty(&notes)
-        .map_err(|e| format!("Failed to serialize notes: {}", e))?;
-    fs::write(&notes_file, notes_json).map_err(|e| format!("Failed to write notes file: {}", e))?;
+    let missing = if refresh {
+        urls.clone()
+    } else {
+        LinkPreviews::load(&data_dir(app))?.missing(id, &urls)
+    };
+    let fetched = previews::fetch(&missing);
+    let (previews, changed) = {
+        let dir = app.state::<DataDir>().inner();
+        let dir = dir.0.lock().unwrap();
+        let mut previews = LinkPreviews::load(&dir)?;
+        let changed = previews.set(id, &urls, fetched)?;
+        (previews.get(id).to_vec(), changed)
+    };
+    if changed {
+        if let Err(e) = app.emit(LINK_PREVIEWS_CHANGED, id) {
+            warn!("Failed to emit link preview change: {}", e);
+        }
+    }
+    Ok(previews)
+}
+
+/// Fetches previews of the new links in note `id` in the background, if
+/// link previews are turned on.
+fn request_link_previews(app: &AppHandle, id: &str) {
+    let settings = app.state::<Mutex<Settings>>().inner();
+    if !settings.lock().unwrap().link_previews {
+        return;
+    }
+    {
+        let pending = app.state::<PendingPreviews>().inner();
+        let mut pending = pending.0.lock().unwrap();
+        if let Some(again) = pending.get_mut(id) {
+            *again = true;
+            return;
+        }
+        pending.insert(id.to_string(), false);
+    }
+    let (app, id) = (app.clone(), id.to_string());
+    std::thread::spawn(move || loop {
+        if let Err(e) = update_link_previews(&app, &id, false) {
+            warn!("Failed to update link previews of note {}: {}", id, e);
+        }
+        let pending = app.state::<PendingPreviews>().inner();
+        let mut pending = pending.0.lock().unwrap();
+        if pending.get(&id) != Some(&true) {
+            pending.remove(&id);
+            break;
+        }
+        pending.insert(id.clone(), false);
+    });
+}
+
+/// Runs a command's body on the blocking thread pool. Commands lock shared
+/// state and touch the disk, so running them on the IPC thread would freeze the
+/// window while a large store is saved or loaded.
+async fn blocking<T: Send + 'static>(
+    app: AppHandle,
+    f: impl FnOnce(&AppHandle) -> Result<T, NotesError> + Send + 'static,
+) -> Result<T, NotesError> {
+    tauri::async_runtime::spawn_blocking(move || f(&app))
+        .await
+        .map_err(|e| NotesError::Internal(format!("Background task failed: {}", e)))?
+}
+
+/// The limits from the settings on what notes may contain.
+fn limits(app: &AppHandle) -> Limits {
+    let settings = app.state::<Mutex<Settings>>().inner();
+    settings.lock().unwrap().limits()
+}
+
+/// Adds a new note and returns its id.
+fn insert_new_note(
+    app: &AppHandle,
+    title: String,
+    content: String,
+    tags: Vec<String>,
+) -> Result<String, NotesError> {
+    let limits = limits(app);
+    let (title, content) = (limits.title(&title)?, limits.content(&content)?);
+    let store = app.state::<Mutex<NotesStore>>().inner();
+    let index = app.state::<Mutex<SearchIndex>>().inner();
+    let note = note::new(title, content, tags, Utc::now().timestamp());
+    store.lock().unwrap().insert(note.clone())?;
+    reindex_note(index, &note);
+    emit_change(app, Some(&note.id), NoteOperation::Created);
+    geotag(app, &note.id);
     Ok(note.id)
 }
 
-#[tauri::command]
-fn update_note(app: AppHandle, id: String, title: String, content: String) -> Result<(), String> {
-    let app_data_dir = app
-        .path()
-        .app_data_dir()
-        .map_err(|e| format!("Failed to get app data directory: {}", e))?;
+/// Tags note `id` with where the device is, if new notes are to be tagged.
+/// Finding the device can take a while, so it's done in the background,
+/// once the note is saved.
+fn geotag(app: &AppHandle, id: &str) {
+    let settings = app.state::<Mutex<Settings>>().inner();
+    if !settings.lock().unwrap().geotag_notes {
+        return;
+    }
+    let (app, id) = (app.clone(), id.to_string());
+    std::thread::spawn(move || {
+        let Some(location) = current_location(&app) else {
+            return;
+        };
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        // Joins the note's creation, so undoing that removes the note
+        let tagged = store
+            .lock()
+            .unwrap()
+            .amend(&id, |note| note.location = Some(location))
+            .map(|_| ());
+        match tagged {
+            Ok(()) => emit_change(&app, Some(&id), NoteOperation::Updated),
+            Err(e) => warn!("Failed to tag note {} with its location: {}", id, e),
+        }
+    });
+}
 
-    let notes_file = app_data_dir.join("notes.json");
+/// Where the device is, asking the user's permission the first time. `None`
+/// if it can't be found or the user won't say.
+#[cfg(mobile)]
+fn current_location(app: &AppHandle) -> Option<Location> {
+    use tauri::plugin::PermissionState;
+    use tauri_plugin_geolocation::{GeolocationExt, PermissionType, PositionOptions};
 
-    if !notes_file.exists() {
-        return Err("Notes file does not exist".into());
+    let geolocation = app.geolocation();
+    let permitted = match geolocation.check_permissions() {
+        Ok(status) if matches!(status.location, PermissionState::Granted) => true,
+        Ok(status) if matches!(status.location, PermissionState::Denied) => false,
+        _ => geolocation
+            .request_permissions(Some(vec![PermissionType::Location]))
+            .is_ok_and(|status| matches!(status.location, PermissionState::Granted)),
+    };
+    if !permitted {
+        return None;
+    }
+    let options = PositionOptions {
+        enable_high_accuracy: false,
+        timeout: GEOLOCATION_TIMEOUT_MS,
+        maximum_age: GEOLOCATION_MAX_AGE_MS,
+    };
+    match geolocation.get_current_position(Some(options)) {
+        Ok(position) => Location::new(position.coords.latitude, position.coords.longitude).ok(),
+        Err(e) => {
+            warn!("Failed to find the device's location: {}", e);
+            None
+        }
     }
+}
+
+#[cfg(desktop)]
+fn current_location(_app: &AppHandle) -> Option<Location> {
+    None
+}
 
-    let file_content =
-        fs::read_to_string(&notes_file).map_err(|e| format!("Failed to read notes file: {}", e))?;
+#[tauri::command]
+async fn create_note(app: AppHandle, title: String, content: String) -> Result<String, NotesError> {
+    blocking(app, move |app| {
+        insert_new_note(app, title, content, Vec::new())
+    })
+    .await
+}
+
+/// Copies note `id` into a new note, returning the new note's id.
+#[tauri::command]
+async fn duplicate_note(app: AppHandle, id: String) -> Result<String, NotesError> {
+    blocking(app, move |app| {
+        flush_drafts(app)?;
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        let mut store = store.lock().unwrap();
+        let copy = note::duplicate(&store.get(&id)?, Utc::now().timestamp());
+        store.insert(copy.clone())?;
+        reindex_note(index, &copy);
+        drop(store);
+        emit_change(app, Some(&copy.id), NoteOperation::Created);
+        Ok(copy.id)
+    })
+    .await
+}
 
-    let mut notes: Vec<Note> = serde_json::from_str(&file_content)
-        .map_err(|e| format!("Failed to parse notes file: {}", e))?;
+/// Joins notes `ids` into a new note and moves them to the trash. Returns
+/// the new note's id.
+#[tauri::command]
+async fn merge_notes(
+    app: AppHandle,
+    ids: Vec<String>,
+    strategy: Option<MergeStrategy>,
+) -> Result<String, NotesError> {
+    blocking(app, move |app| {
+        let mut unique: Vec<&String> = Vec::new();
+        for id in &ids {
+            if !unique.contains(&id) {
+                unique.push(id);
+            }
+        }
+        if unique.len() < 2 {
+            return Err(NotesError::Invalid(
+                "Pick at least two notes to merge".into(),
+            ));
+        }
+        flush_drafts(app)?;
 
-    if let Some(note) = notes.iter_mut().find(|n| n.id == id) {
-        note.title = title;
-        note.content = content;
-        note.timestamp = Utc::now().timestamp();
-    } else {
-        return Err("Note not found".into());
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        let mut store = store.lock().unwrap();
+        let notes = unique
+            .iter()
+            .map(|id| store.get(id))
+            .collect::<Result<Vec<Note>, NotesError>>()?;
+        if notes.iter().any(|note| note.trashed_at.is_some()) {
+            return Err(NotesError::Invalid("Trashed notes can't be merged".into()));
+        }
+        if notes.iter().any(|note| note.locked) {
+            return Err(NotesError::Invalid("Locked notes can't be merged".into()));
+        }
+
+        let now = Utc::now().timestamp();
+        let merged = note::merge(&notes, strategy.unwrap_or_default(), now);
+        store.insert(merged.clone())?;
+        reindex_note(index, &merged);
+        for id in unique {
+            store.update(id, |note| note.trashed_at = Some(now))?;
+            if let Err(e) = index.lock().unwrap().remove(id) {
+                warn!("Failed to remove note {} from search index: {}", id, e);
+            }
+        }
+        drop(store);
+        emit_change(app, None, NoteOperation::Reloaded);
+        Ok(merged.id)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn list_templates(app: AppHandle) -> Result<Vec<Template>, NotesError> {
+    blocking(app, move |app| {
+        let dir = app.state::<DataDir>().inner();
+        let dir = dir.0.lock().unwrap();
+        Ok(Templates::load(&dir)?.all().to_vec())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn create_template(app: AppHandle, template: TemplateFields) -> Result<Template, NotesError> {
+    blocking(app, move |app| {
+        // Held while the file is rewritten, so concurrent edits aren't lost
+        let dir = app.state::<DataDir>().inner();
+        let dir = dir.0.lock().unwrap();
+        Templates::load(&dir)?.create(template)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn update_template(
+    app: AppHandle,
+    id: String,
+    template: TemplateFields,
+) -> Result<Template, NotesError> {
+    blocking(app, move |app| {
+        let dir = app.state::<DataDir>().inner();
+        let dir = dir.0.lock().unwrap();
+        Templates::load(&dir)?.update(&id, template)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn delete_template(app: AppHandle, id: String) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let dir = app.state::<DataDir>().inner();
+        let dir = dir.0.lock().unwrap();
+        Templates::load(&dir)?.delete(&id)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn list_notebooks(app: AppHandle) -> Result<Vec<Notebook>, NotesError> {
+    blocking(app, move |app| {
+        Ok(Notebooks::load(&data_dir(app))?.all().to_vec())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn create_notebook(
+    app: AppHandle,
+    name: String,
+    parent_id: Option<String>,
+) -> Result<Notebook, NotesError> {
+    blocking(app, move |app| {
+        // Held while the file is rewritten, so concurrent edits aren't lost
+        let dir = app.state::<DataDir>().inner();
+        let dir = dir.0.lock().unwrap();
+        Notebooks::load(&dir)?.create(&name, parent_id)
+    })
+    .await
+}
+
+/// Renames notebook `id` and moves it into `parent_id`, or to the top level.
+#[tauri::command]
+async fn update_notebook(
+    app: AppHandle,
+    id: String,
+    name: String,
+    parent_id: Option<String>,
+) -> Result<Notebook, NotesError> {
+    blocking(app, move |app| {
+        let dir = app.state::<DataDir>().inner();
+        let dir = dir.0.lock().unwrap();
+        Notebooks::load(&dir)?.update(&id, &name, parent_id)
+    })
+    .await
+}
+
+/// Deletes notebook `id`. The notes and notebooks in it move up into its
+/// parent.
+#[tauri::command]
+async fn delete_notebook(app: AppHandle, id: String) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let mut store = store.lock().unwrap();
+        let parent_id = {
+            let dir = app.state::<DataDir>().inner();
+            let dir = dir.0.lock().unwrap();
+            Notebooks::load(&dir)?.delete(&id)?
+        };
+        let moved: Vec<String> = store
+            .all_notes()?
+            .iter()
+            .filter(|note| note.notebook_id.as_deref() == Some(id.as_str()))
+            .map(|note| note.id.clone())
+            .collect();
+        store.update_many(&moved, |note| note.notebook_id = parent_id.clone())?;
+        drop(store);
+        if !moved.is_empty() {
+            emit_change(app, None, NoteOperation::Reloaded);
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// Moves note `id` into notebook `notebook_id`, or out of any notebook.
+#[tauri::command]
+async fn move_note(
+    app: AppHandle,
+    id: String,
+    notebook_id: Option<String>,
+) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        if let Some(notebook_id) = &notebook_id {
+            Notebooks::load(&data_dir(app))?.get(notebook_id)?;
+        }
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        store
+            .lock()
+            .unwrap()
+            .update(&id, |note| note.notebook_id = notebook_id)?;
+        emit_change(app, Some(&id), NoteOperation::Updated);
+        Ok(())
+    })
+    .await
+}
+
+/// Lists the notes in notebook `id`, and with `recursive` those in the
+/// notebooks inside it too.
+#[tauri::command]
+async fn load_notes_in_notebook(
+    app: AppHandle,
+    id: String,
+    recursive: bool,
+) -> Result<Vec<Note>, NotesError> {
+    blocking(app, move |app| {
+        let notebooks = Notebooks::load(&data_dir(app))?;
+        let ids = if recursive {
+            notebooks.descendants(&id)?
+        } else {
+            notebooks.get(&id)?;
+            HashSet::from([id])
+        };
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        Ok(redacted(store.lock().unwrap().notes_in(&ids)?))
+    })
+    .await
+}
+
+/// Creates a note from template `template_id`, filling in its placeholders
+/// with `vars` along with the date and time. Returns the new note's id.
+#[tauri::command]
+async fn create_note_from_template(
+    app: AppHandle,
+    template_id: String,
+    vars: Option<HashMap<String, String>>,
+) -> Result<String, NotesError> {
+    blocking(app, move |app| {
+        let template = Templates::load(&data_dir(app))?.get(&template_id)?.clone();
+        let (title, content) = template.render(&vars.unwrap_or_default(), chrono::Local::now());
+        insert_new_note(app, title, content, template.tags)
+    })
+    .await
+}
+
+/// Returns the id of today's daily note, titled after `daily_note_format`,
+/// creating it from `daily_note_template` if there isn't one yet.
+#[tauri::command]
+async fn open_daily_note(app: AppHandle) -> Result<String, NotesError> {
+    blocking(app, move |app| {
+        let (format, template_id) = {
+            let settings = app.state::<Mutex<Settings>>().inner();
+            let settings = settings.lock().unwrap();
+            (
+                settings.daily_note_format.clone(),
+                settings.daily_note_template.clone(),
+            )
+        };
+        // settings.json may have been edited by hand
+        if !settings::is_valid_date_format(&format) {
+            return Err(NotesError::Invalid(format!(
+                "Invalid daily note format: {}",
+                format
+            )));
+        }
+        let now = chrono::Local::now();
+        let title = now.format(&format).to_string();
+
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        let mut store = store.lock().unwrap();
+        if let Some(note) = store.notes()?.into_iter().find(|note| note.title == title) {
+            return Ok(note.id);
+        }
+
+        let (content, tags) = match &template_id {
+            Some(template_id) => match Templates::load(&data_dir(app))?.get(template_id) {
+                Ok(template) => {
+                    let vars = HashMap::from([("title".to_string(), title.clone())]);
+                    (template.render(&vars, now).1, template.tags.clone())
+                }
+                Err(e) => {
+                    warn!("Daily note template {} is unavailable: {}", template_id, e);
+                    (String::new(), Vec::new())
+                }
+            },
+            None => (String::new(), Vec::new()),
+        };
+        // Made here rather than with `insert_new_note`, so the store stays
+        // locked until it's in and it isn't made twice
+        let limits = limits(app);
+        let (title, content) = (limits.title(&title)?, limits.content(&content)?);
+        let note = note::new(title, content, tags, now.timestamp());
+        store.insert(note.clone())?;
+        reindex_note(index, &note);
+        drop(store);
+        emit_change(app, Some(&note.id), NoteOperation::Created);
+        geotag(app, &note.id);
+        Ok(note.id)
+    })
+    .await
+}
+
+/// Saves text typed into the quick-capture window as a new note, titled with
+/// its first line.
+#[tauri::command]
+async fn quick_capture(app: AppHandle, content: String) -> Result<String, NotesError> {
+    blocking(app, move |app| capture(app, &content)).await
+}
+
+/// Saves `content` as a new note titled with its first line, returning its
+/// id. Shares from other apps are captured the same way.
+fn capture(app: &AppHandle, content: &str) -> Result<String, NotesError> {
+    let content = content.trim();
+    if content.is_empty() {
+        return Err(NotesError::Invalid("Nothing to capture".into()));
     }
+    let (title, body) = match content.split_once('\n') {
+        Some((title, body)) => (title.trim(), body.trim_start()),
+        None => (content, ""),
+    };
+    insert_new_note(app, title.to_string(), body.to_string(), Vec::new())
+}
 
-    let notes_json = serde_json::to_string_pretty(&notes)
-        .map_err(|e| format!("Failed to serialize notes: {}", e))?;
-    fs::write(&notes_file, notes_json).map_err(|e| format!("Failed to write notes file: {}", e))?;
+/// Shows the quick-capture window, creating it the first time.
+fn open_capture_window(app: &AppHandle) -> Result<(), NotesError> {
+    if let Some(window) = app.get_webview_window(CAPTURE_WINDOW) {
+        return window
+            .show()
+            .and_then(|_| window.set_focus())
+            .map_err(|e| NotesError::Internal(format!("Failed to show capture window: {}", e)));
+    }
+    WebviewWindowBuilder::new(
+        app,
+        CAPTURE_WINDOW,
+        WebviewUrl::App("index.html#capture".into()),
+    )
+    .title("Quick capture")
+    .inner_size(480.0, 180.0)
+    .decorations(false)
+    .always_on_top(true)
+    .resizable(false)
+    .skip_taskbar(true)
+    .center()
+    .focused(true)
+    .build()
+    .map_err(|e| NotesError::Internal(format!("Failed to open capture window: {}", e)))?;
     Ok(())
 }
 
+/// Saves the title and content of note `id`, returning the saved note. Given
+/// `updated_at`, the note's `updated_at` when it was read, the write is
+/// refused with a conflict carrying the stored copy if the note has been
+/// saved since.
 #[tauri::command]
-fn delete_note(app: AppHandle, id: String) -> Result<(), String> {
-    let app_data_dir = app
-        .path()
-        .app_data_dir()
-        .map_err(|e| format!("Failed to get app data directory: {}", e))?;
+async fn update_note(
+    app: AppHandle,
+    id: String,
+    title: String,
+    content: String,
+    updated_at: Option<i64>,
+) -> Result<Note, NotesError> {
+    blocking(app, move |app| {
+        let limits = limits(app);
+        let (title, content) = (limits.title(&title)?, limits.content(&content)?);
+        let drafts = app.state::<Mutex<Drafts>>().inner();
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        drafts.lock().unwrap().discard(&id);
+        let saved = {
+            let mut store = store.lock().unwrap();
+            let change = |note: &mut Note| {
+                note.title = title;
+                note.content = content;
+                note.updated_at = Utc::now().timestamp();
+            };
+            let note = match updated_at {
+                Some(seen_at) => store.update_seen(&id, seen_at, change)?,
+                None => store.update(&id, change)?,
+            };
+            reindex_note(index, note);
+            note.clone()
+        };
+        emit_change(app, Some(&id), NoteOperation::Updated);
+        request_link_previews(app, &id);
+        request_embeddings(app);
+        Ok(saved)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn trash_note(app: AppHandle, id: String) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        store.lock().unwrap().update(&id, |note| {
+            note.trashed_at = Some(Utc::now().timestamp());
+        })?;
+        if let Err(e) = index.lock().unwrap().remove(&id) {
+            warn!("Failed to remove note {} from search index: {}", id, e);
+        }
+        emit_change(app, Some(&id), NoteOperation::Updated);
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn restore_note(app: AppHandle, id: String) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        {
+            let mut store = store.lock().unwrap();
+            let note = store.update(&id, |note| note.trashed_at = None)?;
+            reindex_note(index, note);
+        }
+        emit_change(app, Some(&id), NoteOperation::Updated);
+        Ok(())
+    })
+    .await
+}
 
-    let notes_file = app_data_dir.join("notes.json");
+#[tauri::command]
+async fn empty_trash(app: AppHandle) -> Result<usize, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let deleted = store.lock().unwrap().empty_trash()?;
+        if deleted > 0 {
+            emit_change(app, None, NoteOperation::Reloaded);
+        }
+        Ok(deleted)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn load_trashed_notes(app: AppHandle) -> Result<Vec<Note>, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        Ok(redacted(store.lock().unwrap().trashed_notes()?))
+    })
+    .await
+}
+
+/// Permanently deletes a note, bypassing the trash.
+#[tauri::command]
+async fn delete_note(app: AppHandle, id: String) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let drafts = app.state::<Mutex<Drafts>>().inner();
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        drafts.lock().unwrap().discard(&id);
+        if !store.lock().unwrap().delete(&id)? {
+            return Err(NotesError::NotFound("Note not found".into()));
+        }
+        if let Err(e) = index.lock().unwrap().remove(&id) {
+            warn!("Failed to remove note {} from search index: {}", id, e);
+        }
+        emit_change(app, Some(&id), NoteOperation::Deleted);
+        Ok(())
+    })
+    .await
+}
+
+/// Remembers that note `id` was just opened, for `get_recent_notes` and the
+/// quick switcher.
+#[tauri::command]
+async fn record_note_opened(app: AppHandle, id: String) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let store = store.lock().unwrap();
+        store.get(&id)?;
+        let dir = app.state::<DataDir>().inner();
+        let dir = dir.0.lock().unwrap();
+        Recent::load(&dir)?.record(&id, Utc::now().timestamp())
+    })
+    .await
+}
+
+/// Returns up to `limit` of the notes opened most recently, the latest
+/// first.
+#[tauri::command]
+async fn get_recent_notes(
+    app: AppHandle,
+    limit: Option<usize>,
+) -> Result<Vec<RecentNote>, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let notes = store.lock().unwrap().notes()?;
+        let recent = Recent::load(&data_dir(app))?;
+        Ok(recent::recent_notes(&notes, &recent, limit.unwrap_or(10)))
+    })
+    .await
+}
+
+/// Returns up to `limit` of the notes whose titles fuzzily match `query`,
+/// best first, or the recently opened notes if `query` is blank.
+#[tauri::command]
+async fn quick_switch(
+    app: AppHandle,
+    query: String,
+    limit: Option<usize>,
+) -> Result<Vec<SwitchHit>, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let notes = store.lock().unwrap().notes()?;
+        let recent = Recent::load(&data_dir(app))?;
+        Ok(recent::quick_switch(
+            &notes,
+            &recent,
+            &query,
+            limit.unwrap_or(20),
+        ))
+    })
+    .await
+}
+
+/// Undoes the last change made to the notes, returning the ids of the notes
+/// it touched. Nothing happens if there's no change left to undo.
+#[tauri::command]
+async fn undo(app: AppHandle) -> Result<Vec<String>, NotesError> {
+    blocking(app, move |app| {
+        flush_drafts(app)?;
+        let ids = app
+            .state::<Mutex<NotesStore>>()
+            .inner()
+            .lock()
+            .unwrap()
+            .undo()?;
+        restored(app, &ids);
+        Ok(ids)
+    })
+    .await
+}
+
+/// Redoes the last change undone, returning the ids of the notes it touched.
+#[tauri::command]
+async fn redo(app: AppHandle) -> Result<Vec<String>, NotesError> {
+    blocking(app, move |app| {
+        flush_drafts(app)?;
+        let ids = app
+            .state::<Mutex<NotesStore>>()
+            .inner()
+            .lock()
+            .unwrap()
+            .redo()?;
+        restored(app, &ids);
+        Ok(ids)
+    })
+    .await
+}
+
+/// Brings the search index up to date with the notes `ids` an undo or redo
+/// put back, and tells the UI about them.
+fn restored(app: &AppHandle, ids: &[String]) {
+    let mut changes = Vec::with_capacity(ids.len());
+    {
+        let store = app.state::<Mutex<NotesStore>>().inner().lock().unwrap();
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        for id in ids {
+            match store.get(id) {
+                Ok(note) => {
+                    reindex_note(index, &note);
+                    changes.push((id, NoteOperation::Updated));
+                }
+                Err(_) => {
+                    if let Err(e) = index.lock().unwrap().remove(id) {
+                        warn!("Failed to remove note {} from search index: {}", id, e);
+                    }
+                    changes.push((id, NoteOperation::Deleted));
+                }
+            }
+        }
+    }
+    for (id, operation) in changes {
+        emit_change(app, Some(id), operation);
+    }
+}
+
+/// Passes `notes` on to the UI without the sealed content of locked ones.
+fn redacted(notes: Vec<Note>) -> Vec<Note> {
+    notes.into_iter().map(Note::redact).collect()
+}
 
-    if !notes_file.exists() {
-        return Err("Notes file does not exist".into());
+fn check_note_password(password: &str) -> Result<(), NotesError> {
+    if password.is_empty() {
+        return Err(NotesError::Invalid("Note password cannot be empty".into()));
     }
+    Ok(())
+}
+
+/// Locks note `id` with `password`: its content is sealed in storage and
+/// left out of the note list until `unlock_note` is given the password.
+#[tauri::command]
+async fn lock_note(app: AppHandle, id: String, password: String) -> Result<(), NotesError> {
+    check_note_password(&password)?;
+    blocking(app, move |app| {
+        flush_drafts(app)?;
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        {
+            let mut store = store.lock().unwrap();
+            let note = store.lock_note(&id, &password, Utc::now().timestamp())?;
+            reindex_note(index, note);
+        }
+        emit_change(app, Some(&id), NoteOperation::Updated);
+        Ok(())
+    })
+    .await
+}
+
+/// Returns the content of locked note `id`. The note stays locked.
+#[tauri::command]
+async fn unlock_note(app: AppHandle, id: String, password: String) -> Result<String, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        store.lock().unwrap().unlock_note(&id, &password)
+    })
+    .await
+}
+
+/// Takes the password off locked note `id`, so it can be edited again.
+#[tauri::command]
+async fn remove_note_lock(app: AppHandle, id: String, password: String) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        {
+            let mut store = store.lock().unwrap();
+            let note = store.remove_note_lock(&id, &password, Utc::now().timestamp())?;
+            reindex_note(index, note);
+        }
+        emit_change(app, Some(&id), NoteOperation::Updated);
+        Ok(())
+    })
+    .await
+}
 
-    let file_content =
-        fs::read_to_string(&notes_file).map_err(|e| format!("Failed to read notes file: {}", e))?;
+/// Applies `patch` to every note in `ids` in a single write, returning the
+/// updated notes. Nothing changes if any of them is missing.
+#[tauri::command]
+async fn bulk_update(
+    app: AppHandle,
+    ids: Vec<String>,
+    patch: NotePatch,
+) -> Result<Vec<Note>, NotesError> {
+    blocking(app, move |app| {
+        if let Some(Some(notebook_id)) = &patch.notebook_id {
+            Notebooks::load(&data_dir(app))?.get(notebook_id)?;
+        }
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        let now = Utc::now().timestamp();
+        let updated = store
+            .lock()
+            .unwrap()
+            .update_many(&ids, |note| patch.apply(note, now))?;
+        for note in &updated {
+            if note.trashed_at.is_some() {
+                if let Err(e) = index.lock().unwrap().remove(&note.id) {
+                    warn!("Failed to remove note {} from search index: {}", note.id, e);
+                }
+            } else {
+                reindex_note(index, note);
+            }
+        }
+        emit_change(app, None, NoteOperation::Reloaded);
+        Ok(redacted(updated))
+    })
+    .await
+}
 
-    let mut notes: Vec<Note> = serde_json::from_str(&file_content)
-        .map_err(|e| format!("Failed to parse notes file: {}", e))?;
+/// Permanently deletes every note in `ids`, bypassing the trash, and returns
+/// how many there were.
+#[tauri::command]
+async fn bulk_delete(app: AppHandle, ids: Vec<String>) -> Result<usize, NotesError> {
+    blocking(app, move |app| {
+        let drafts = app.state::<Mutex<Drafts>>().inner();
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        for id in &ids {
+            drafts.lock().unwrap().discard(id);
+        }
+        let deleted = store.lock().unwrap().delete_many(&ids)?;
+        for id in &ids {
+            if let Err(e) = index.lock().unwrap().remove(id) {
+                warn!("Failed to remove note {} from search index: {}", id, e);
+            }
+        }
+        emit_change(app, None, NoteOperation::Reloaded);
+        Ok(deleted)
+    })
+    .await
+}
 
-    notes.retain(|n| n.id != id);
-    let notes_json = serde_json::to_string_pretty(&notes)
-        .map_err(|e| format!("Failed to serialize notes: {}", e))?;
-    fs::write(&notes_file, notes_json).map_err(|e| format!("Failed to write notes file: {}", e))?;
+/// Saves `content` as note `id`'s content once typing pauses. Calls within
+/// the autosave interval of each other are coalesced into one write.
+#[tauri::command]
+fn autosave_draft(app: AppHandle, id: String, content: String) -> Result<(), NotesError> {
+    let content = limits(&app).content(&content)?;
+    let drafts = app.state::<Mutex<Drafts>>().inner();
+    if drafts.lock().unwrap().stage(id, content) {
+        let settings = app.state::<Mutex<Settings>>().inner();
+        let interval =
+            std::time::Duration::from_millis(settings.lock().unwrap().autosave_interval_ms);
+        std::thread::spawn(move || {
+            std::thread::sleep(interval);
+            // Failures are logged per note and retried with the next flush
+            let _ = flush_drafts(&app);
+        });
+    }
     Ok(())
 }
 
+/// The previews of the links in note `id`.
 #[tauri::command]
-fn load_notes(app: AppHandle) -> Result<Vec<Note>, String> {
-    let app_data_dir = app
-        .path()
-        .app_data_dir()
-        .map_err(|e| format!("Failed to get app data directory: {}", e))?;
+async fn get_link_previews(app: AppHandle, id: String) -> Result<Vec<LinkPreview>, NotesError> {
+    blocking(app, move |app| {
+        Ok(LinkPreviews::load(&data_dir(app))?.get(&id).to_vec())
+    })
+    .await
+}
 
-    let notes_file = app_data_dir.join("notes.json");
+/// Fetches the previews of every link in note `id` again, even with link
+/// previews turned off, and returns them.
+#[tauri::command]
+async fn refresh_link_previews(app: AppHandle, id: String) -> Result<Vec<LinkPreview>, NotesError> {
+    blocking(app, move |app| {
+        flush_drafts(app)?;
+        update_link_previews(app, &id, true)
+    })
+    .await
+}
 
-    if !notes_file.exists() {
-        return Ok(Vec::new());
+/// Every note in the order picked in the settings.
+fn default_query(app: &AppHandle) -> NoteQuery {
+    let settings = app.state::<Mutex<Settings>>().inner();
+    let settings = settings.lock().unwrap();
+    NoteQuery {
+        sort: settings.default_sort,
+        direction: settings.default_direction,
+        ..NoteQuery::default()
     }
+}
+
+/// How the commands that return many notes send them to the UI.
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+enum Encoding {
+    /// Parsed for the UI as usual.
+    #[default]
+    Json,
+    /// As MessagePack in an `ArrayBuffer`, for the UI to decode, which is
+    /// smaller and much quicker to pass for thousands of notes.
+    Msgpack,
+}
 
-    let content =
-        fs::read_to_string(&notes_file).map_err(|e| format!("Failed to read notes file: {}", e))?;
+fn encoded<T: Serialize>(value: &T, encoding: Option<Encoding>) -> Result<Response, NotesError> {
+    match encoding.unwrap_or_default() {
+        Encoding::Json => serde_json::to_string(value)
+            .map(Response::new)
+            .map_err(|e| NotesError::Serde(format!("Failed to serialize response: {}", e))),
+        Encoding::Msgpack => rmp_serde::to_vec_named(value)
+            .map(Response::new)
+            .map_err(|e| NotesError::Serde(format!("Failed to serialize response: {}", e))),
+    }
+}
 
-    let notes: Vec<Note> =
-        serde_json::from_str(&content).map_err(|e| format!("Failed to parse notes file: {}", e))?;
+/// Returns the notes outside the trash written within `radius` metres of
+/// `latitude`, `longitude`, nearest first.
+#[tauri::command]
+async fn load_notes_near(
+    app: AppHandle,
+    latitude: f64,
+    longitude: f64,
+    radius: f64,
+) -> Result<Vec<Note>, NotesError> {
+    blocking(app, move |app| {
+        if radius.is_nan() || radius <= 0.0 {
+            return Err(NotesError::Invalid(
+                "Radius must be a positive number of metres".into(),
+            ));
+        }
+        let center = Location::new(latitude, longitude)?;
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let store = store.lock().unwrap();
+        let near = geo::near(store.all_notes()?, &center, radius);
+        Ok(redacted(near.into_iter().cloned().collect()))
+    })
+    .await
+}
 
-    Ok(notes)
+/// Lists the notes outside the trash, pinned ones first.
+#[tauri::command]
+async fn load_notes(
+    app: AppHandle,
+    exclude_archived: Option<bool>,
+    encoding: Option<Encoding>,
+) -> Result<Response, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let query = NoteQuery {
+            exclude_archived: exclude_archived.unwrap_or(false),
+            ..default_query(app)
+        };
+        let notes = redacted(store.lock().unwrap().query(&query)?.notes);
+        encoded(&notes, encoding)
+    })
+    .await
 }
 
-#[cfg_attr(mobile, tauri::mobile_entry_point)]
-pub fn run() {
-    tauri::Builder::default()
-        .plugin(tauri_plugin_opener::init())
-        .plugin(tauri_plugin_fs::init())
+/// Like `load_notes`, but with only the start of each note's content, so
+/// listing a large vault stays quick. `get_note` has the rest.
+#[tauri::command]
+async fn load_note_summaries(
+    app: AppHandle,
+    exclude_archived: Option<bool>,
+    encoding: Option<Encoding>,
+) -> Result<Response, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let query = NoteQuery {
+            exclude_archived: exclude_archived.unwrap_or(false),
+            ..default_query(app)
+        };
+        let notes = store.lock().unwrap().query(&query)?.notes;
+        let summaries: Vec<NoteSummary> = notes.iter().map(Note::summary).collect();
+        encoded(&summaries, encoding)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_note(app: AppHandle, id: String) -> Result<Note, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        Ok(store.lock().unwrap().get(&id)?.redact())
+    })
+    .await
+}
+
+/// Returns one page of the note list along with the total number of notes, so
+/// the UI can paginate instead of loading everything at once.
+#[tauri::command]
+async fn query_notes(
+    app: AppHandle,
+    query: Option<NoteQuery>,
+    encoding: Option<Encoding>,
+) -> Result<Response, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let query = query.unwrap_or_else(|| default_query(app));
+        let page = store.lock().unwrap().query(&query)?;
+        let page = NotePage {
+            notes: redacted(page.notes),
+            ..page
+        };
+        encoded(&page, encoding)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn pin_note(app: AppHandle, id: String, pinned: bool) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        store
+            .lock()
+            .unwrap()
+            .update(&id, |note| note.pinned = pinned)?;
+        emit_change(app, Some(&id), NoteOperation::Updated);
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn archive_note(app: AppHandle, id: String, archived: bool) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        store
+            .lock()
+            .unwrap()
+            .update(&id, |note| note.archived = archived)?;
+        emit_change(app, Some(&id), NoteOperation::Updated);
+        Ok(())
+    })
+    .await
+}
+
+/// The notes auto-archiving would archive if notes left unedited for
+/// `months` months were archived now, oldest first. `months` defaults to the
+/// `auto_archive_months` setting.
+#[tauri::command]
+async fn preview_auto_archive(
+    app: AppHandle,
+    months: Option<u32>,
+) -> Result<Vec<NoteSummary>, NotesError> {
+    blocking(app, move |app| {
+        let months = months.unwrap_or_else(|| {
+            let settings = app.state::<Mutex<Settings>>().inner();
+            settings.lock().unwrap().auto_archive_months
+        });
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let store = store.lock().unwrap();
+        let notes = store.all_notes()?;
+        let stale = auto_archive::stale(notes, months, Utc::now().timestamp());
+        Ok(stale.into_iter().map(Note::summary).collect())
+    })
+    .await
+}
+
+/// Flips whether note `id` is a favorite, returning whether it now is.
+#[tauri::command]
+async fn toggle_favorite(app: AppHandle, id: String) -> Result<bool, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let favorite = store
+            .lock()
+            .unwrap()
+            .update(&id, |note| note.favorite = !note.favorite)?
+            .favorite;
+        emit_change(app, Some(&id), NoteOperation::Updated);
+        Ok(favorite)
+    })
+    .await
+}
+
+/// Sets note `id`'s color label, as `#rrggbb`, or removes it.
+#[tauri::command]
+async fn set_note_color(
+    app: AppHandle,
+    id: String,
+    color: Option<String>,
+) -> Result<(), NotesError> {
+    let color = color.map(|color| color.to_lowercase());
+    if let Some(color) = color
+        .as_deref()
+        .filter(|color| !note::is_valid_color(color))
+    {
+        return Err(NotesError::Invalid(format!(
+            "Invalid color {}; use #rrggbb",
+            color
+        )));
+    }
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        store
+            .lock()
+            .unwrap()
+            .update(&id, |note| note.color = color)?;
+        emit_change(app, Some(&id), NoteOperation::Updated);
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn load_favorites(app: AppHandle) -> Result<Vec<Note>, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        Ok(redacted(store.lock().unwrap().favorites()?))
+    })
+    .await
+}
+
+#[tauri::command]
+async fn set_note_tags(
+    app: AppHandle,
+    id: String,
+    tags: Vec<String>,
+) -> Result<Vec<String>, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let tags = store
+            .lock()
+            .unwrap()
+            .update(&id, |note| {
+                note.tags = note::normalize_tags(tags);
+                note.updated_at = Utc::now().timestamp();
+            })?
+            .tags
+            .clone();
+        emit_change(app, Some(&id), NoteOperation::Updated);
+        Ok(tags)
+    })
+    .await
+}
+
+/// Sets metadata field `key` of note `id` to `value`, or removes the field
+/// if `value` is `None` or blank, and returns the note's metadata.
+#[tauri::command]
+async fn set_note_meta(
+    app: AppHandle,
+    id: String,
+    key: String,
+    value: Option<String>,
+) -> Result<BTreeMap<String, String>, NotesError> {
+    blocking(app, move |app| set_meta(app, &id, &key, value)).await
+}
+
+fn set_meta(
+    app: &AppHandle,
+    id: &str,
+    key: &str,
+    value: Option<String>,
+) -> Result<BTreeMap<String, String>, NotesError> {
+    let key = validate::meta_key(key)?;
+    let value = value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty());
+    let store = app.state::<Mutex<NotesStore>>().inner();
+    let meta = store
+        .lock()
+        .unwrap()
+        .update(id, |note| {
+            match value {
+                Some(value) => note.meta.insert(key, value),
+                None => note.meta.remove(&key),
+            };
+            note.updated_at = Utc::now().timestamp();
+        })?
+        .meta
+        .clone();
+    emit_change(app, Some(id), NoteOperation::Updated);
+    Ok(meta)
+}
+
+/// Loads the notes with metadata field `key`, or with it set to `value` if
+/// given.
+#[tauri::command]
+async fn query_notes_by_meta(
+    app: AppHandle,
+    key: String,
+    value: Option<String>,
+) -> Result<Vec<Note>, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let notes = store
+            .lock()
+            .unwrap()
+            .with_meta(key.trim(), value.as_deref().map(str::trim))?;
+        Ok(redacted(notes))
+    })
+    .await
+}
+
+/// Loads a kanban board of the notes, with a column for each value of
+/// metadata field `key`, and the values in `columns` first in that order.
+#[tauri::command]
+async fn get_board(
+    app: AppHandle,
+    key: String,
+    columns: Option<Vec<String>>,
+) -> Result<Board, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let notes = store.lock().unwrap().notes()?;
+        Ok(board::board(
+            &notes,
+            key.trim(),
+            &columns.unwrap_or_default(),
+        ))
+    })
+    .await
+}
+
+/// Moves note `id` to the column of `value` on the board of metadata field
+/// `key`, or to that of notes without the field if `value` is `None`.
+#[tauri::command]
+async fn move_card(
+    app: AppHandle,
+    id: String,
+    key: String,
+    value: Option<String>,
+) -> Result<(), NotesError> {
+    blocking(app, move |app| set_meta(app, &id, &key, value).map(|_| ())).await
+}
+
+/// Attaches a file to note `note_id`, read from `path` or given as `bytes`,
+/// such as an audio memo just recorded. `mime` is guessed from the name if
+/// not given. Attaching the same content twice returns the existing
+/// attachment.
+#[tauri::command]
+async fn add_attachment(
+    app: AppHandle,
+    note_id: String,
+    path: Option<String>,
+    bytes: Option<Vec<u8>>,
+    name: Option<String>,
+    mime: Option<String>,
+) -> Result<Attachment, NotesError> {
+    blocking(app, move |app| {
+        let (content, name) = match (path, bytes) {
+            (Some(path), _) => {
+                let path = std::path::PathBuf::from(path);
+                let content = fs::read(&path)
+                    .map_err(|e| NotesError::Io(format!("Failed to read attachment: {}", e)))?;
+                let name = name.unwrap_or_else(|| {
+                    path.file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_else(|| "attachment".to_string())
+                });
+                (content, name)
+            }
+            (None, Some(bytes)) => (bytes, name.unwrap_or_else(|| "attachment".to_string())),
+            (None, None) => {
+                return Err(NotesError::Invalid(
+                    "Either a path or bytes are required".into(),
+                ))
+            }
+        };
+
+        attach(app, note_id, content, name, mime)
+    })
+    .await
+}
+
+/// Attaches a photo just taken with the camera to note `note_id`, with its
+/// metadata stripped and scaled down as the settings say.
+#[tauri::command]
+async fn add_photo(
+    app: AppHandle,
+    note_id: String,
+    bytes: Vec<u8>,
+    name: Option<String>,
+) -> Result<Attachment, NotesError> {
+    blocking(app, move |app| {
+        let options = {
+            let settings = app.state::<Mutex<Settings>>().inner();
+            let settings = settings.lock().unwrap();
+            PhotoOptions {
+                strip_metadata: settings.strip_photo_metadata,
+                max_dimension: settings.photo_max_dimension,
+            }
+        };
+        let (content, mime) = photo::prepare(bytes, options)?;
+        let extension = if mime == "image/jpeg" { "jpg" } else { "png" };
+        let name = match name {
+            Some(name) => std::path::Path::new(&name)
+                .with_extension(extension)
+                .to_string_lossy()
+                .into_owned(),
+            None => chrono::Local::now()
+                .format(&format!("Photo %Y-%m-%d %H.%M.%S.{}", extension))
+                .to_string(),
+        };
+        attach(app, note_id, content, name, Some(mime))
+    })
+    .await
+}
+
+/// Stores `content` and attaches it to note `note_id` as `name`, then reads
+/// or transcribes it in the background if it's an image or audio memo.
+fn attach(
+    app: &AppHandle,
+    note_id: String,
+    content: Vec<u8>,
+    name: String,
+    mime: Option<String>,
+) -> Result<Attachment, NotesError> {
+    let store = app.state::<Mutex<NotesStore>>().inner();
+    let mut store = store.lock().unwrap();
+    store.get(&note_id)?;
+    let attachment = Attachment {
+        id: store.put_attachment(&content)?,
+        mime: mime.unwrap_or_else(|| {
+            mime_guess::from_path(&name)
+                .first_or_octet_stream()
+                .essence_str()
+                .to_string()
+        }),
+        name,
+        size: content.len() as u64,
+        added_at: Utc::now().timestamp(),
+        text: None,
+    };
+    let note = store.update(&note_id, |note| {
+        if !note.attachments.iter().any(|a| a.id == attachment.id) {
+            note.attachments.push(attachment.clone());
+            note.updated_at = attachment.added_at;
+        }
+    })?;
+    let attachment = note
+        .attachments
+        .iter()
+        .find(|a| a.id == attachment.id)
+        .cloned()
+        .unwrap_or(attachment);
+    drop(store);
+    emit_change(app, Some(&note_id), NoteOperation::Updated);
+    if ocr::is_image(&attachment.mime) && attachment.text.is_none() {
+        queue_ocr(app, &attachment.id);
+    }
+    if transcribe::is_audio(&attachment.mime) && transcription_command(app).is_some() {
+        let (app, attachment_id) = (app.clone(), attachment.id.clone());
+        std::thread::spawn(move || {
+            if let Err(e) = transcribe_into_note(&app, &note_id, &attachment_id) {
+                warn!("Failed to transcribe attachment {}: {}", attachment_id, e);
+            }
+        });
+    }
+    Ok(attachment)
+}
+
+fn transcription_command(app: &AppHandle) -> Option<String> {
+    let settings = app.state::<Mutex<Settings>>().inner();
+    settings.lock().unwrap().transcription_command.clone()
+}
+
+/// Transcribes audio attachment `attachment_id` of note `note_id` with the
+/// command in the settings, and adds the transcript to the note.
+fn transcribe_into_note(
+    app: &AppHandle,
+    note_id: &str,
+    attachment_id: &str,
+) -> Result<Note, NotesError> {
+    let command = transcription_command(app).ok_or_else(|| {
+        NotesError::Invalid("Set a transcription command in the settings first".into())
+    })?;
+    let store = app.state::<Mutex<NotesStore>>().inner();
+    let (attachment, audio) = {
+        let store = store.lock().unwrap();
+        let note = store.get(note_id)?;
+        if note.locked {
+            return Err(NotesError::Invalid(
+                "Remove the note's password before transcribing its memos".into(),
+            ));
+        }
+        let attachment = note
+            .attachments
+            .into_iter()
+            .find(|attachment| attachment.id == attachment_id)
+            .ok_or_else(|| NotesError::NotFound("Attachment not found".into()))?;
+        if !transcribe::is_audio(&attachment.mime) {
+            return Err(NotesError::Invalid(format!(
+                "{} is not an audio memo",
+                attachment.name
+            )));
+        }
+        let audio = store.read_attachment(attachment_id)?;
+        (attachment, audio)
+    };
+    let extension = Path::new(&attachment.name)
+        .extension()
+        .map_or("audio".into(), |extension| extension.to_string_lossy());
+    let transcript = transcribe::transcribe(&command, &audio, &extension)?;
+
+    // Typing since the memo was attached would otherwise overwrite it
+    flush_drafts(app)?;
+    let index = app.state::<Mutex<SearchIndex>>().inner();
+    let note = {
+        let mut store = store.lock().unwrap();
+        let note = store.update(note_id, |note| {
+            note.content =
+                transcribe::append_transcript(&note.content, &attachment.name, &transcript);
+            note.updated_at = Utc::now().timestamp();
+        })?;
+        reindex_note(index, note);
+        note.clone()
+    };
+    emit_change(app, Some(note_id), NoteOperation::Updated);
+    Ok(note)
+}
+
+/// Transcribes audio memo `attachment_id` of note `note_id` with the command
+/// in the settings, adds the transcript to the end of the note, and returns
+/// the note.
+#[tauri::command]
+async fn transcribe_attachment(
+    app: AppHandle,
+    note_id: String,
+    attachment_id: String,
+) -> Result<Note, NotesError> {
+    blocking(app, move |app| {
+        transcribe_into_note(app, &note_id, &attachment_id)
+    })
+    .await
+}
+
+/// Emitted as `run_indexer` works through its jobs, with an
+/// `IndexingProgress`.
+const INDEXING_PROGRESS: &str = "indexing://progress";
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum IndexTask {
+    /// Rebuilding the search index.
+    Search,
+    /// Reading the text in image attachments.
+    Ocr,
+    /// Embedding notes for semantic search.
+    Embeddings,
+}
+
+#[derive(Serialize, Clone)]
+struct IndexingProgress {
+    task: IndexTask,
+    done: usize,
+    total: usize,
+}
+
+fn emit_progress(app: &AppHandle, task: IndexTask, done: usize, total: usize) {
+    let progress = IndexingProgress { task, done, total };
+    if let Err(e) = app.emit(INDEXING_PROGRESS, progress) {
+        warn!("Failed to emit indexing progress: {}", e);
+    }
+}
+
+/// Work for `run_indexer`, the thread that does the slow indexing away from
+/// the commands and owns the OCR and embedding models.
+enum IndexJob {
+    /// Rebuilds the search index from every note.
+    Reindex,
+    /// Reads the text in image attachment `id`.
+    Ocr(String),
+    /// Reads the text in image attachment `id` and sends it back, for a
+    /// command waiting on it.
+    OcrNow {
+        id: String,
+        reply: mpsc::Sender<Result<String, NotesError>>,
+    },
+    /// Embeds the notes changed since they last were.
+    Embed,
+    SemanticSearch {
+        query: String,
+        k: usize,
+        reply: mpsc::Sender<Result<Vec<SemanticHit>, NotesError>>,
+    },
+}
+
+struct IndexQueue {
+    jobs: mpsc::Sender<IndexJob>,
+    /// Whether a `Reindex` is waiting, which any more requests join.
+    reindex_queued: AtomicBool,
+    /// How many `Ocr` jobs are waiting or running, for the progress.
+    ocr_queued: AtomicUsize,
+}
+
+/// Rebuilds the search index in the background, from the notes as they are
+/// by then.
+fn request_reindex(app: &AppHandle) {
+    let queue = app.state::<IndexQueue>();
+    if !queue.reindex_queued.swap(true, Ordering::SeqCst) {
+        let _ = queue.jobs.send(IndexJob::Reindex);
+    }
+}
+
+/// Where the OCR and embedding models are kept once downloaded, for every
+/// workspace.
+fn models_dir(app: &AppHandle) -> Result<PathBuf, NotesError> {
+    app.path()
+        .app_cache_dir()
+        .map(|dir| dir.join("models"))
+        .map_err(|e| NotesError::Io(format!("Failed to get app cache directory: {}", e)))
+}
+
+/// Has the text in image attachment `id` read in the background, if that's
+/// turned on in the settings.
+fn queue_ocr(app: &AppHandle, id: &str) {
+    let settings = app.state::<Mutex<Settings>>().inner();
+    if settings.lock().unwrap().ocr_images {
+        let queue = app.state::<IndexQueue>();
+        queue.ocr_queued.fetch_add(1, Ordering::SeqCst);
+        let _ = queue.jobs.send(IndexJob::Ocr(id.to_string()));
+    }
+}
+
+/// Embeds the notes changed since they last were in the background, if
+/// semantic search is turned on.
+fn request_embeddings(app: &AppHandle) {
+    let settings = app.state::<Mutex<Settings>>().inner();
+    if settings.lock().unwrap().semantic_search {
+        let _ = app.state::<IndexQueue>().jobs.send(IndexJob::Embed);
+    }
+}
+
+/// Runs the queued indexing jobs one at a time, loading the OCR and
+/// embedding models with the first job that needs each. The embedding model
+/// can't be shared between threads, so it stays on this one, which runs for
+/// as long as the app does.
+fn run_indexer(app: AppHandle, queue: mpsc::Receiver<IndexJob>) {
+    let mut ocr = None;
+    let mut embedder = None;
+    // Since the OCR jobs last ran out
+    let mut ocr_done = 0;
+    for job in queue {
+        match job {
+            IndexJob::Reindex => {
+                let queue = app.state::<IndexQueue>();
+                queue.reindex_queued.store(false, Ordering::SeqCst);
+                if let Err(e) = reindex(&app) {
+                    warn!("Failed to rebuild the search index: {}", e);
+                }
+            }
+            IndexJob::Ocr(id) => {
+                let read =
+                    load_ocr(&app, &mut ocr).and_then(|ocr| read_attachment_text(&app, ocr, &id));
+                if let Err(e) = read {
+                    warn!("Failed to read the text in attachment {}: {}", id, e);
+                }
+                let queue = app.state::<IndexQueue>();
+                let remaining = queue.ocr_queued.fetch_sub(1, Ordering::SeqCst) - 1;
+                ocr_done += 1;
+                emit_progress(&app, IndexTask::Ocr, ocr_done, ocr_done + remaining);
+                if remaining == 0 {
+                    ocr_done = 0;
+                }
+            }
+            IndexJob::OcrNow { id, reply } => {
+                let text =
+                    load_ocr(&app, &mut ocr).and_then(|ocr| read_attachment_text(&app, ocr, &id));
+                let _ = reply.send(text);
+            }
+            IndexJob::Embed => {
+                if let Err(e) =
+                    load_embedder(&app, &mut embedder).and_then(|e| update_embeddings(&app, e))
+                {
+                    warn!("Failed to update embeddings: {}", e);
+                }
+            }
+            IndexJob::SemanticSearch { query, k, reply } => {
+                let hits = load_embedder(&app, &mut embedder).and_then(|embedder| {
+                    let (embeddings, notes) = update_embeddings(&app, embedder)?;
+                    let query = embedder.embed(&query)?;
+                    Ok(embeddings.nearest(&query, k, &notes))
+                });
+                let _ = reply.send(hits);
+            }
+        }
+    }
+}
+
+/// Rebuilds the search index without holding it, so notes are saved and
+/// searched meanwhile, then catches up with the notes changed since and
+/// swaps it in.
+fn reindex(app: &AppHandle) -> Result<(), NotesError> {
+    let store = app.state::<Mutex<NotesStore>>().inner();
+    let index = app.state::<Mutex<SearchIndex>>().inner();
+    let notes = indexable_notes(&store.lock().unwrap())?;
+    let total = notes.len();
+    emit_progress(app, IndexTask::Search, 0, total);
+    let mut rebuilt = SearchIndex::new()?;
+    rebuilt.rebuild_with_progress(&notes, |done| {
+        emit_progress(app, IndexTask::Search, done, total)
+    })?;
+    {
+        let store = store.lock().unwrap();
+        let mut index = index.lock().unwrap();
+        let mut unchanged: HashMap<&str, &Note> =
+            notes.iter().map(|note| (note.id.as_str(), note)).collect();
+        for note in indexable_notes(&store)? {
+            if unchanged.remove(note.id.as_str()) != Some(&note) {
+                rebuilt.upsert(&note)?;
+            }
+        }
+        // What's left was deleted
+        for id in unchanged.keys() {
+            rebuilt.remove(id)?;
+        }
+        *index = rebuilt;
+    }
+    emit_progress(app, IndexTask::Search, total, total);
+    Ok(())
+}
+
+/// The notes the search index holds, which are none while the store is
+/// locked.
+fn indexable_notes(store: &NotesStore) -> Result<Vec<Note>, NotesError> {
+    if store.is_locked() {
+        Ok(Vec::new())
+    } else {
+        store.notes()
+    }
+}
+
+fn load_ocr<'a>(app: &AppHandle, ocr: &'a mut Option<Ocr>) -> Result<&'a Ocr, NotesError> {
+    if ocr.is_none() {
+        *ocr = Some(Ocr::load(&models_dir(app)?)?);
+    }
+    Ok(ocr.as_ref().expect("the OCR models were just loaded"))
+}
+
+/// Reads the text in image attachment `id` with `ocr` and keeps it with the
+/// attachment in every note it's attached to, where search finds it.
+fn read_attachment_text(app: &AppHandle, ocr: &Ocr, id: &str) -> Result<String, NotesError> {
+    let store = app.state::<Mutex<NotesStore>>().inner();
+    let image = {
+        let store = store.lock().unwrap();
+        let attachment = store
+            .all_notes()?
+            .iter()
+            .flat_map(|note| &note.attachments)
+            .find(|attachment| attachment.id == id)
+            .ok_or_else(|| NotesError::NotFound(format!("Attachment {} not found", id)))?;
+        if !ocr::is_image(&attachment.mime) {
+            return Err(NotesError::Invalid(format!(
+                "{} is not a PNG, JPEG, or WebP image",
+                attachment.name
+            )));
+        }
+        store.read_attachment(id)?
+    };
+    let text = ocr.read_text(&image)?;
+
+    let index = app.state::<Mutex<SearchIndex>>().inner();
+    let updated: Vec<String> = {
+        let mut store = store.lock().unwrap();
+        // Locked notes are left alone, as their text isn't sealed with them
+        let ids: Vec<String> = store
+            .all_notes()?
+            .iter()
+            .filter(|note| !note.locked && note.attachments.iter().any(|a| a.id == id))
+            .map(|note| note.id.clone())
+            .collect();
+        for note_id in &ids {
+            let note = store.update_without_undo(note_id, |note| {
+                for attachment in note.attachments.iter_mut().filter(|a| a.id == id) {
+                    attachment.text = Some(text.clone());
+                }
+            })?;
+            reindex_note(index, note);
+        }
+        ids
+    };
+    for id in &updated {
+        emit_change(app, Some(id), NoteOperation::Updated);
+    }
+    Ok(text)
+}
+
+/// Reads the text in image attachment `id` now, even with OCR turned off in
+/// the settings, and returns it. Search finds the notes it's attached to by
+/// that text from then on.
+#[tauri::command]
+async fn ocr_attachment(app: AppHandle, id: String) -> Result<String, NotesError> {
+    blocking(app, move |app| {
+        // Read where the loaded model is kept, rather than loading it again
+        let stopped = || NotesError::Internal("Text recognition has stopped".into());
+        let (reply, text) = mpsc::channel();
+        app.state::<IndexQueue>()
+            .jobs
+            .send(IndexJob::OcrNow { id, reply })
+            .map_err(|_| stopped())?;
+        text.recv().map_err(|_| stopped())?
+    })
+    .await
+}
+
+fn load_embedder<'a>(
+    app: &AppHandle,
+    embedder: &'a mut Option<Embedder>,
+) -> Result<&'a Embedder, NotesError> {
+    if embedder.is_none() {
+        *embedder = Some(Embedder::load(&models_dir(app)?)?);
+    }
+    Ok(embedder.as_ref().expect("the embedder was just loaded"))
+}
+
+/// Embeds the notes changed since they last were with `embedder` and drops
+/// the embeddings of deleted ones, returning the embeddings and the notes
+/// they're of. Trashed and locked notes aren't embedded.
+fn update_embeddings(
+    app: &AppHandle,
+    embedder: &Embedder,
+) -> Result<(Embeddings, Vec<Note>), NotesError> {
+    // The vectors say enough about a note to give away what it's about, yet
+    // aren't sealed
+    let vault = app.state::<Mutex<Vault>>().inner();
+    if vault.lock().unwrap().is_enabled() {
+        return Err(NotesError::Invalid(
+            "Semantic search is unavailable while the vault is enabled".into(),
+        ));
+    }
+    let dir = data_dir(app);
+    let notes: Vec<Note> = {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let notes = store.lock().unwrap().notes()?;
+        notes.into_iter().filter(|note| !note.locked).collect()
+    };
+    let mut embeddings = Embeddings::load(&dir)?;
+    let mut changed = embeddings.retain(&notes.iter().map(|note| note.id.as_str()).collect());
+    let stale = embeddings.stale(&notes);
+    for (done, note) in stale.iter().enumerate() {
+        match embedder.embed(&semantic::note_text(note)) {
+            Ok(vector) => {
+                embeddings.set(note, vector);
+                changed = true;
+            }
+            Err(e) => warn!("Failed to embed note {}: {}", note.id, e),
+        }
+        emit_progress(app, IndexTask::Embeddings, done + 1, stale.len());
+    }
+    if changed {
+        let current = app.state::<DataDir>().inner();
+        // Embedding takes a while, and the notes are of the workspace that
+        // was active when it started
+        if *current.0.lock().unwrap() == dir {
+            embeddings.save()?;
+        }
+    }
+    Ok((embeddings, notes))
+}
+
+/// Returns the `k` notes closest in meaning to `query`, closest first,
+/// embedding the notes changed since they last were first.
+#[tauri::command]
+async fn semantic_search(
+    app: AppHandle,
+    query: String,
+    k: Option<usize>,
+) -> Result<Vec<SemanticHit>, NotesError> {
+    blocking(app, move |app| {
+        let settings = app.state::<Mutex<Settings>>().inner();
+        if !settings.lock().unwrap().semantic_search {
+            return Err(NotesError::Invalid(
+                "Semantic search is turned off in the settings".into(),
+            ));
+        }
+        flush_drafts(app)?;
+        let stopped = || NotesError::Internal("The embedding model has stopped".into());
+        let (reply, hits) = mpsc::channel();
+        let job = IndexJob::SemanticSearch {
+            query,
+            k: k.unwrap_or(10).max(1),
+            reply,
+        };
+        app.state::<IndexQueue>()
+            .jobs
+            .send(job)
+            .map_err(|_| stopped())?;
+        hits.recv().map_err(|_| stopped())?
+    })
+    .await
+}
+
+/// Saves a snapshot of the web page at `url` as an HTML attachment of note
+/// `note_id`, linked below the line that mentions `url`.
+#[tauri::command]
+async fn archive_url(
+    app: AppHandle,
+    note_id: String,
+    url: String,
+) -> Result<Attachment, NotesError> {
+    blocking(app, move |app| {
+        if !url.starts_with("https://") && !url.starts_with("http://") {
+            return Err(NotesError::Invalid(
+                "Only http:// and https:// pages can be saved".into(),
+            ));
+        }
+        flush_drafts(app)?;
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        if store.lock().unwrap().get(&note_id)?.locked {
+            return Err(NotesError::Invalid(
+                "Remove the note's password before saving pages to it".into(),
+            ));
+        }
+        let now = Utc::now().timestamp();
+        let snapshot = snapshot::capture(&url, now)?;
+
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        let mut store = store.lock().unwrap();
+        let attachment = Attachment {
+            id: store.put_attachment(snapshot.html.as_bytes())?,
+            name: format!("{}.html", note::slugify(&snapshot.title)),
+            mime: "text/html".into(),
+            size: snapshot.html.len() as u64,
+            added_at: now,
+            text: None,
+        };
+        let note = store.update(&note_id, |note| {
+            if !note.attachments.iter().any(|a| a.id == attachment.id) {
+                note.attachments.push(attachment.clone());
+            }
+            note.content =
+                snapshot::link_snapshot(&note.content, &url, &attachment.id, &attachment.name);
+            note.updated_at = now;
+        })?;
+        reindex_note(index, note);
+        drop(store);
+        emit_change(app, Some(&note_id), NoteOperation::Updated);
+        Ok(attachment)
+    })
+    .await
+}
+
+/// Returns the content of attachment `id` as raw bytes.
+#[tauri::command]
+async fn get_attachment(app: AppHandle, id: String) -> Result<Response, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let content = store.lock().unwrap().read_attachment(&id)?;
+        Ok(Response::new(content))
+    })
+    .await
+}
+
+#[tauri::command]
+async fn remove_attachment(
+    app: AppHandle,
+    note_id: String,
+    attachment_id: String,
+) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let mut store = store.lock().unwrap();
+        store.update(&note_id, |note| {
+            note.attachments.retain(|a| a.id != attachment_id);
+            note.updated_at = Utc::now().timestamp();
+        })?;
+        // Other notes may still use the same blob
+        if let Err(e) = store.collect_garbage() {
+            warn!("Failed to clean up attachments: {}", e);
+        }
+        drop(store);
+        emit_change(app, Some(&note_id), NoteOperation::Updated);
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn list_tags(app: AppHandle) -> Result<Vec<TagCount>, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        store.lock().unwrap().list_tags()
+    })
+    .await
+}
+
+#[tauri::command]
+async fn load_notes_by_tag(app: AppHandle, tag: String) -> Result<Vec<Note>, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        Ok(redacted(store.lock().unwrap().notes_by_tag(&tag)?))
+    })
+    .await
+}
+
+#[derive(Serialize)]
+struct RevisionSummary {
+    rev: u32,
+    title: String,
+    saved_at: i64,
+}
+
+/// Lists the previous versions of a note, newest first.
+#[tauri::command]
+async fn get_note_history(app: AppHandle, id: String) -> Result<Vec<RevisionSummary>, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let history = store.lock().unwrap().history(&id)?;
+        Ok(history
+            .into_iter()
+            .rev()
+            .map(|revision| RevisionSummary {
+                rev: revision.rev,
+                title: revision.title,
+                saved_at: revision.saved_at,
+            })
+            .collect())
+    })
+    .await
+}
+
+#[derive(Serialize)]
+struct RevisionDetail {
+    #[serde(flatten)]
+    revision: Revision,
+    /// Unified diff from this revision's content to the note's current content.
+    diff: String,
+}
+
+#[tauri::command]
+async fn get_revision(app: AppHandle, id: String, rev: u32) -> Result<RevisionDetail, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let store = store.lock().unwrap();
+        let revision = store.revision(&id, rev)?;
+        let current = store.get(&id)?;
+        let diff = storage::unified_diff(
+            &revision.content,
+            &current.content,
+            &format!("revision {}", rev),
+            "current",
+        );
+        Ok(RevisionDetail { revision, diff })
+    })
+    .await
+}
+
+/// Brings back the title and content of an earlier revision. The version being
+/// replaced goes into the history, so a restore can itself be undone.
+#[tauri::command]
+async fn restore_revision(app: AppHandle, id: String, rev: u32) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        let mut store = store.lock().unwrap();
+        let revision = store.revision(&id, rev)?;
+        let note = store.update(&id, |note| {
+            note.title = revision.title;
+            note.content = revision.content;
+            note.updated_at = Utc::now().timestamp();
+        })?;
+        if note.trashed_at.is_none() {
+            reindex_note(index, note);
+        }
+        drop(store);
+        emit_change(app, Some(&id), NoteOperation::Updated);
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn export_note(
+    app: AppHandle,
+    id: String,
+    format: ExportFormat,
+    path: String,
+) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let note = store.lock().unwrap().get(&id)?;
+        export::export_note(&note, format, std::path::Path::new(&path))
+    })
+    .await
+}
+
+/// Exports every note outside the trash into `dir`, returning the number of
+/// files written.
+#[tauri::command]
+async fn export_all_notes(
+    app: AppHandle,
+    dir: String,
+    format: ExportFormat,
+) -> Result<usize, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let notes = store.lock().unwrap().notes()?;
+        let paths = export::export_notes(&notes, format, std::path::Path::new(&dir))?;
+        Ok(paths.len())
+    })
+    .await
+}
+
+/// Exports the notes `ids` with their attachments: each to its own file in
+/// the folder `dest`, or with `combined`, all of them to the file `dest`.
+/// Links between them become links within the export. Returns the number of
+/// files written, not counting attachments.
+#[tauri::command]
+async fn export_notes(
+    app: AppHandle,
+    ids: Vec<String>,
+    format: ExportFormat,
+    dest: String,
+    combined: Option<bool>,
+) -> Result<usize, NotesError> {
+    blocking(app, move |app| {
+        flush_drafts(app)?;
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let store = store.lock().unwrap();
+        let notes = ids
+            .iter()
+            .map(|id| store.get(id))
+            .collect::<Result<Vec<Note>, NotesError>>()?;
+        let paths = export::export_selection(
+            &notes,
+            |id| store.read_attachment(id),
+            format,
+            Path::new(&dest),
+            combined.unwrap_or(false),
+        )?;
+        Ok(paths.len())
+    })
+    .await
+}
+
+/// Publishes note `id` as an unlisted paste with `provider` and returns the
+/// link. Sharing it again replaces the previous paste.
+#[tauri::command]
+async fn share_note(
+    app: AppHandle,
+    id: String,
+    provider: ShareProvider,
+) -> Result<String, NotesError> {
+    blocking(app, move |app| {
+        flush_drafts(app)?;
+        let note = {
+            let store = app.state::<Mutex<NotesStore>>().inner();
+            store.lock().unwrap().get(&id)?
+        };
+        Shares::load(&data_dir(app))?.share(&note, provider, &active_workspace(app))
+    })
+    .await
+}
+
+/// Takes down the paste of note `id`, returning whether it was shared.
+#[tauri::command]
+async fn unshare_note(app: AppHandle, id: String) -> Result<bool, NotesError> {
+    blocking(app, move |app| {
+        Shares::load(&data_dir(app))?.unshare(&id, &active_workspace(app))
+    })
+    .await
+}
+
+/// Sets where `provider` publishes shared notes, for 0x0 and custom
+/// services, and the token it uses, kept in the OS keychain. An empty token
+/// removes it.
+#[tauri::command]
+async fn configure_sharing(
+    app: AppHandle,
+    provider: ShareProvider,
+    url: Option<String>,
+    token: Option<String>,
+) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let secret = match provider {
+            ShareProvider::Gist => Some(Secret::GistToken),
+            ShareProvider::Custom => Some(Secret::PasteToken),
+            ShareProvider::NullPointer => None,
+        };
+        if secret.is_none() && token.is_some() {
+            return Err(NotesError::Invalid("0x0 doesn't take a token".into()));
+        }
+        Shares::load(&data_dir(app))?.set_url(provider, url)?;
+        let (Some(secret), Some(token)) = (secret, token) else {
+            return Ok(());
+        };
+        let workspace = active_workspace(app);
+        if token.is_empty() {
+            keychain::clear(secret, &workspace).map(|_| ())
+        } else {
+            keychain::set(secret, &workspace, &token)
+        }
+    })
+    .await
+}
+
+/// Emails note `id` to `to` through the SMTP server set up with
+/// `configure_email`.
+#[tauri::command]
+async fn email_note(app: AppHandle, id: String, to: String) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        flush_drafts(app)?;
+        let note = {
+            let store = app.state::<Mutex<NotesStore>>().inner();
+            store.lock().unwrap().get(&id)?
+        };
+        email::send(&data_dir(app), &active_workspace(app), &note, &to)
+    })
+    .await
+}
+
+/// Sets the SMTP server notes are emailed through, once it accepts
+/// `password`, which is kept in the OS keychain.
+#[tauri::command]
+async fn configure_email(
+    app: AppHandle,
+    config: EmailConfig,
+    password: String,
+) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        email::configure(&data_dir(app), &active_workspace(app), &config, &password)
+    })
+    .await
+}
+
+/// Emitted as the assistant writes a reply, with an `AssistantOutput` as
+/// payload.
+const ASSISTANT_OUTPUT: &str = "notes://assistant";
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum AssistantTask {
+    Summary,
+    Title,
+}
+
+#[derive(Serialize, Clone)]
+struct AssistantOutput {
+    task: AssistantTask,
+    /// The note being summarized, for summaries.
+    note_id: Option<String>,
+    /// The reply so far.
+    text: String,
+}
+
+fn emit_assistant_output(app: &AppHandle, task: AssistantTask, note_id: Option<&str>, text: &str) {
+    let payload = AssistantOutput {
+        task,
+        note_id: note_id.map(str::to_string),
+        text: text.to_string(),
+    };
+    if let Err(e) = app.emit(ASSISTANT_OUTPUT, payload) {
+        warn!("Failed to emit assistant output: {}", e);
+    }
+}
+
+/// Has the assistant set up with `configure_assistant` summarize note `id`,
+/// streaming the summary in `ASSISTANT_OUTPUT` events as it's written.
+#[tauri::command]
+async fn summarize_note(app: AppHandle, id: String) -> Result<String, NotesError> {
+    blocking(app, move |app| {
+        flush_drafts(app)?;
+        let note = {
+            let store = app.state::<Mutex<NotesStore>>().inner();
+            store.lock().unwrap().get(&id)?
+        };
+        let assistant = Assistant::load(&data_dir(app), &active_workspace(app))?;
+        assistant.summarize(&note, |text| {
+            emit_assistant_output(app, AssistantTask::Summary, Some(&id), text)
+        })
+    })
+    .await
+}
+
+/// Has the assistant set up with `configure_assistant` suggest a title for
+/// a note of `content`, streaming it in `ASSISTANT_OUTPUT` events as it's
+/// written.
+#[tauri::command]
+async fn suggest_title(app: AppHandle, content: String) -> Result<String, NotesError> {
+    blocking(app, move |app| {
+        let assistant = Assistant::load(&data_dir(app), &active_workspace(app))?;
+        assistant.suggest_title(&content, |text| {
+            emit_assistant_output(app, AssistantTask::Title, None, text)
+        })
+    })
+    .await
+}
+
+/// Sets the service that writes summaries and titles, keeping `api_key`, if
+/// it needs one, in the OS keychain.
+#[tauri::command]
+async fn configure_assistant(
+    app: AppHandle,
+    config: AssistantConfig,
+    api_key: Option<String>,
+) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        assistant::configure(
+            &data_dir(app),
+            &active_workspace(app),
+            &config,
+            api_key.as_deref(),
+        )
+    })
+    .await
+}
+
+/// Writes every note that isn't archived, or those with one of the tags in
+/// `options`, to a static website in `dir`, with pages for them and their
+/// tags, and their attachments.
+#[tauri::command]
+async fn export_site(
+    app: AppHandle,
+    dir: String,
+    options: Option<SiteOptions>,
+) -> Result<SiteReport, NotesError> {
+    blocking(app, move |app| {
+        flush_drafts(app)?;
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let store = store.lock().unwrap();
+        site::export_site(
+            &store.notes()?,
+            |id| store.read_attachment(id),
+            Path::new(&dir),
+            &options.unwrap_or_default(),
+        )
+    })
+    .await
+}
+
+/// Writes the flashcards in notes `ids`, `Q:` and `A:` pairs and cloze
+/// deletions, to a file at `path` that Anki imports. Returns the number of
+/// cards written.
+#[tauri::command]
+async fn export_anki(app: AppHandle, ids: Vec<String>, path: String) -> Result<usize, NotesError> {
+    blocking(app, move |app| {
+        flush_drafts(app)?;
+        let notes = {
+            let store = app.state::<Mutex<NotesStore>>().inner();
+            let store = store.lock().unwrap();
+            ids.iter()
+                .map(|id| store.get(id))
+                .collect::<Result<Vec<Note>, NotesError>>()?
+        };
+        anki::export(&notes, Path::new(&path))
+    })
+    .await
+}
+
+/// Writes the whole vault, trashed notes, attachments and notebooks included,
+/// to a zip archive at `path` that `import_vault_zip` can restore. Returns the
+/// number of notes written.
+#[tauri::command]
+async fn export_vault_zip(app: AppHandle, path: String) -> Result<usize, NotesError> {
+    blocking(app, move |app| {
+        flush_drafts(app)?;
+        let notebooks = Notebooks::load(&data_dir(app))?;
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let store = store.lock().unwrap();
+        archive::export(&store, notebooks.all(), Path::new(&path))
+    })
+    .await
+}
+
+/// Imports an archive written by `export_vault_zip`, settling notes that are
+/// already here with `merge_strategy`, and other notes that match existing
+/// ones with `strategy`, which skips duplicates by default.
+#[tauri::command]
+async fn import_vault_zip(
+    app: AppHandle,
+    path: String,
+    merge_strategy: ConflictStrategy,
+    strategy: Option<ImportStrategy>,
+) -> Result<ArchiveReport, NotesError> {
+    blocking(app, move |app| {
+        flush_drafts(app)?;
+        let mut notebooks = Notebooks::load(&data_dir(app))?;
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let report = {
+            let mut store = store.lock().unwrap();
+            let report = archive::import(
+                &mut store,
+                &mut notebooks,
+                Path::new(&path),
+                merge_strategy,
+                strategy.unwrap_or_default(),
+            )?;
+            if report.imported + report.updated > 0 {
+                request_reindex(app);
+            }
+            report
+        };
+        if report.imported + report.updated + report.notebooks > 0 {
+            emit_change(app, None, NoteOperation::Reloaded);
+        }
+        Ok(report)
+    })
+    .await
+}
+
+/// Imports every note from an Evernote or Joplin export, settling notes that
+/// match existing ones with `strategy`, which skips duplicates by default.
+/// Reports what became of each item, and why those that failed did.
+#[tauri::command]
+async fn import_notes(
+    app: AppHandle,
+    path: String,
+    format: ImportFormat,
+    strategy: Option<ImportStrategy>,
+) -> Result<ImportReport, NotesError> {
+    blocking(app, move |app| {
+        let items = import::read_export(std::path::Path::new(&path), format)?;
+        import_items(app, items, strategy.unwrap_or_default(), Vec::new())
+    })
+    .await
+}
+
+fn import_items(
+    app: &AppHandle,
+    items: Vec<ImportedItem>,
+    strategy: ImportStrategy,
+    skipped: Vec<String>,
+) -> Result<ImportReport, NotesError> {
+    flush_drafts(app)?;
+    let store = app.state::<Mutex<NotesStore>>().inner();
+    let index = app.state::<Mutex<SearchIndex>>().inner();
+    let mut store = store.lock().unwrap();
+    let mut importer = Importer::new(&store, strategy)?;
+
+    let mut report = ImportReport {
+        skipped,
+        ..ImportReport::default()
+    };
+    for item in items {
+        let result = item.note.and_then(|imported| {
+            let outcome = importer.add(&mut store, imported.into_note())?;
+            if let Imported::Created(id) | Imported::Updated(id) = &outcome {
+                reindex_note(index, &store.get(id)?);
+            }
+            Ok(outcome)
+        });
+        report.record(item.source, result);
+    }
+    drop(store);
+    // One reload instead of an event per note, which could be thousands
+    if report.changed() {
+        emit_change(app, None, NoteOperation::Reloaded);
+    }
+    Ok(report)
+}
+
+/// Whether clipboard capture is on, as the flag its thread watches to know
+/// when to stop.
+struct ClipboardCapture(Mutex<Option<Arc<AtomicBool>>>);
+
+/// Starts appending text copied in any app to the Inbox note, until
+/// `stop_clipboard_capture`. What's on the clipboard already isn't added.
+/// Does nothing if capture is already on.
+#[tauri::command]
+async fn start_clipboard_capture(app: AppHandle) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let capture = app.state::<ClipboardCapture>().inner();
+        let mut running = capture.0.lock().unwrap();
+        if running.is_some() {
+            return Ok(());
+        }
+        let watch = ClipboardWatch::new(read_clipboard(app)?);
+        let on = Arc::new(AtomicBool::new(true));
+        *running = Some(on.clone());
+        let app = app.clone();
+        std::thread::spawn(move || run_clipboard_capture(app, on, watch));
+        info!("Started clipboard capture");
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn stop_clipboard_capture(app: AppHandle) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let capture = app.state::<ClipboardCapture>().inner();
+        if let Some(on) = capture.0.lock().unwrap().take() {
+            on.store(false, Ordering::SeqCst);
+            info!("Stopped clipboard capture");
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// The text on the clipboard, or `None` if it holds something else, such as
+/// an image.
+#[cfg(desktop)]
+fn read_clipboard(app: &AppHandle) -> Result<Option<String>, NotesError> {
+    use tauri_plugin_clipboard_manager::ClipboardExt;
+    Ok(app.clipboard().read_text().ok())
+}
+
+/// Apps can't watch the clipboard from the background on mobile.
+#[cfg(mobile)]
+fn read_clipboard(_app: &AppHandle) -> Result<Option<String>, NotesError> {
+    Err(NotesError::Invalid(
+        "Clipboard capture isn't available on this device".into(),
+    ))
+}
+
+fn run_clipboard_capture(app: AppHandle, on: Arc<AtomicBool>, mut watch: ClipboardWatch) {
+    loop {
+        std::thread::sleep(CLIPBOARD_POLL_INTERVAL);
+        if !on.load(Ordering::SeqCst) {
+            return;
+        }
+        let Ok(current) = read_clipboard(&app) else {
+            return;
+        };
+        if let Some(text) = watch.observe(current, std::time::Instant::now()) {
+            if let Err(e) = capture_to_inbox(&app, &text) {
+                warn!("Failed to capture copied text: {}", e);
+            }
+        }
+    }
+}
+
+/// Appends `text` to the Inbox note, creating it if there's none, unless
+/// it's there already.
+fn capture_to_inbox(app: &AppHandle, text: &str) -> Result<(), NotesError> {
+    flush_drafts(app)?;
+    let store = app.state::<Mutex<NotesStore>>().inner();
+    let index = app.state::<Mutex<SearchIndex>>().inner();
+    let mut store = store.lock().unwrap();
+    let inbox = store
+        .all_notes()?
+        .iter()
+        .find(|note| note.trashed_at.is_none() && note.title == clipboard::INBOX_TITLE)
+        .cloned();
+    let Some(inbox) = inbox else {
+        drop(store);
+        let title = clipboard::INBOX_TITLE.to_string();
+        insert_new_note(app, title, text.trim().to_string(), Vec::new())?;
+        return Ok(());
+    };
+    if inbox.locked {
+        return Err(NotesError::Invalid(
+            "Remove the Inbox note's password to capture into it".into(),
+        ));
+    }
+    let Some(content) = clipboard::append(&inbox.content, text) else {
+        return Ok(());
+    };
+    let content = limits(app).content(&content)?;
+    let note = store.update_without_undo(&inbox.id, |note| {
+        note.content = content;
+        note.updated_at = Utc::now().timestamp();
+    })?;
+    reindex_note(index, note);
+    drop(store);
+    emit_change(app, Some(&inbox.id), NoteOperation::Updated);
+    Ok(())
+}
+
+/// Imports the Markdown and text files in the folder at `path`, and in its
+/// subfolders if `recursive`, settling files that match existing notes with
+/// `strategy`. By default, files with the same title and content as a note
+/// outside the trash are reported as duplicates rather than imported.
+#[tauri::command]
+async fn import_directory(
+    app: AppHandle,
+    path: String,
+    recursive: bool,
+    strategy: Option<ImportStrategy>,
+) -> Result<ImportReport, NotesError> {
+    blocking(app, move |app| {
+        let contents = import::folder::read(Path::new(&path), recursive)?;
+        import_items(
+            app,
+            contents.items,
+            strategy.unwrap_or_default(),
+            contents.skipped,
+        )
+    })
+    .await
+}
+
+/// Emitted as files dropped on the main window are taken in, with a
+/// `DropProgress`.
+const DROP_PROGRESS: &str = "files://drop-progress";
+/// Emitted once files dropped on the main window have all been taken in,
+/// with a `DropReport`.
+const FILES_DROPPED: &str = "files://dropped";
+
+#[derive(Serialize, Clone)]
+struct DropProgress {
+    done: usize,
+    total: usize,
+}
+
+#[derive(Serialize, Clone)]
+struct DropFailure {
+    path: String,
+    error: NotesError,
+}
+
+#[derive(Serialize, Clone, Default)]
+struct DropReport {
+    /// The Markdown and text files, imported as notes.
+    notes: ImportReport,
+    /// The images and PDFs, attached to the open note.
+    attached: Vec<Attachment>,
+    failed: Vec<DropFailure>,
+    /// Files that are neither, and folders.
+    skipped: Vec<String>,
+}
+
+/// The note open in the main window, which files dropped on it are attached
+/// to. Kept up to date by the window with `set_active_note`.
+struct ActiveNote(Mutex<Option<String>>);
+
+#[tauri::command]
+fn set_active_note(app: AppHandle, id: Option<String>) {
+    *app.state::<ActiveNote>().0.lock().unwrap() = id;
+}
+
+/// Whether a dropped file of type `mime` is attached rather than imported.
+fn is_attachable(mime: &str) -> bool {
+    mime.starts_with("image/") || mime == "application/pdf"
+}
+
+/// Takes in files dropped on the main window: Markdown and text files are
+/// imported as notes, and images and PDFs are attached to the open note.
+/// Reports each file's progress as it goes, then what became of them all.
+fn take_in_dropped_files(app: &AppHandle, paths: Vec<PathBuf>) {
+    let active = app.state::<ActiveNote>().0.lock().unwrap().clone();
+    let total = paths.len();
+    let mut report = DropReport::default();
+    let mut notes = Vec::new();
+    for (done, path) in paths.into_iter().enumerate() {
+        let source = path.to_string_lossy().into_owned();
+        let mime = mime_guess::from_path(&path).first_or_octet_stream();
+        if !path.is_file() {
+            report.skipped.push(source);
+        } else if import::folder::is_note_file(&path) {
+            notes.push(ImportedItem {
+                note: import::folder::read_file(&path),
+                source,
+            });
+        } else if is_attachable(mime.essence_str()) {
+            let attached = active
+                .clone()
+                .ok_or_else(|| NotesError::Invalid("Open a note to attach files to it".into()))
+                .and_then(|note_id| {
+                    let content = fs::read(&path)
+                        .map_err(|e| NotesError::Io(format!("Failed to read {}: {}", source, e)))?;
+                    let name = path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_else(|| "attachment".to_string());
+                    attach(app, note_id, content, name, Some(mime.to_string()))
+                });
+            match attached {
+                Ok(attachment) => report.attached.push(attachment),
+                Err(error) => report.failed.push(DropFailure {
+                    path: source,
+                    error,
+                }),
+            }
+        } else {
+            report.skipped.push(source);
+        }
+        let progress = DropProgress {
+            done: done + 1,
+            total,
+        };
+        if let Err(e) = app.emit(DROP_PROGRESS, progress) {
+            warn!("Failed to emit drop progress: {}", e);
+        }
+    }
+
+    if !notes.is_empty() {
+        let sources: Vec<String> = notes.iter().map(|item| item.source.clone()).collect();
+        match import_items(app, notes, ImportStrategy::default(), Vec::new()) {
+            Ok(imported) => report.notes = imported,
+            Err(error) => report
+                .failed
+                .extend(sources.into_iter().map(|path| DropFailure {
+                    path,
+                    error: error.clone(),
+                })),
+        }
+    }
+    if !report.failed.is_empty() {
+        warn!("Failed to take in {} dropped files", report.failed.len());
+    }
+    if let Err(e) = app.emit(FILES_DROPPED, report) {
+        warn!("Failed to emit dropped files: {}", e);
+    }
+}
+
+#[derive(Serialize, Default)]
+struct RecoveryReport {
+    recovered: usize,
+    /// Notes that were readable but already in the store.
+    already_present: usize,
+    /// Notes that were cut off or too damaged to read.
+    unreadable: usize,
+    /// Where the damaged file was moved, or `None` if there was none.
+    moved_to: Option<PathBuf>,
+}
+
+/// Salvages the readable notes from a notes.json that failed to import,
+/// then moves the file aside so it isn't imported again.
+#[tauri::command]
+async fn recover_notes(app: AppHandle) -> Result<RecoveryReport, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        let mut store = store.lock().unwrap();
+        let path = data_dir(app).join("notes.json");
+        if !path.exists() {
+            return Ok(RecoveryReport::default());
+        }
+
+        let content = fs::read(&path)
+            .map_err(|e| NotesError::Io(format!("Failed to read notes file: {}", e)))?;
+        let salvaged = storage::salvage_notes(&String::from_utf8_lossy(&content));
+        let mut report = RecoveryReport {
+            unreadable: salvaged.unreadable,
+            ..RecoveryReport::default()
+        };
+        for note in salvaged.notes {
+            if store.get(&note.id).is_ok() {
+                report.already_present += 1;
+                continue;
+            }
+            store.insert(note.clone())?;
+            reindex_note(index, &note);
+            report.recovered += 1;
+        }
+        report.moved_to = Some(storage::set_aside(&path)?);
+        drop(store);
+        if report.recovered > 0 {
+            emit_change(app, None, NoteOperation::Reloaded);
+        }
+        Ok(report)
+    })
+    .await
+}
+
+/// Checks every note's content against the hash saved with it, reporting
+/// mismatches and notes that can't be decrypted. With `quarantine`, those
+/// notes are moved out of the store so the rest keep loading.
+#[tauri::command]
+async fn verify_integrity(
+    app: AppHandle,
+    quarantine: Option<bool>,
+) -> Result<IntegrityReport, NotesError> {
+    blocking(app, move |app| {
+        flush_drafts(app)?;
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let report = {
+            let mut store = store.lock().unwrap();
+            let report = store.verify_integrity(quarantine.unwrap_or(false))?;
+            if report.quarantined() > 0 {
+                request_reindex(app);
+            }
+            report
+        };
+        if report.quarantined() > 0 {
+            emit_change(app, None, NoteOperation::Reloaded);
+        }
+        Ok(report)
+    })
+    .await
+}
+
+/// Rewrites every stored note and revision to match the `compress_notes`
+/// setting, and reclaims the space that frees. Returns how many notes were
+/// rewritten.
+#[tauri::command]
+async fn compact_store(app: AppHandle) -> Result<usize, NotesError> {
+    blocking(app, move |app| {
+        flush_drafts(app)?;
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        store.lock().unwrap().compact()
+    })
+    .await
+}
+
+/// Shows where the active workspace's disk space goes, with its `top`
+/// largest notes (10 by default).
+#[tauri::command]
+async fn get_storage_report(
+    app: AppHandle,
+    top: Option<usize>,
+) -> Result<StorageReport, NotesError> {
+    blocking(app, move |app| {
+        let data_dir = data_dir(app);
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        usage::report(&data_dir, &store.lock().unwrap(), top.unwrap_or(10))
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_storage_backend(app: AppHandle) -> Result<BackendKind, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        Ok(store.lock().unwrap().backend_kind())
+    })
+    .await
+}
+
+/// Copies all notes into `backend` and switches to it, returning the number
+/// of notes copied.
+#[tauri::command]
+async fn set_storage_backend(app: AppHandle, backend: BackendKind) -> Result<usize, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let moved = store.lock().unwrap().switch_backend(backend)?;
+        if let Err(e) = watch_storage(app) {
+            warn!("{}", e);
+        }
+        Ok(moved)
+    })
+    .await
+}
+
+#[derive(Serialize)]
+struct VaultStatus {
+    enabled: bool,
+    locked: bool,
+    /// Whether `unlock_with_biometrics` can be offered in place of the
+    /// password.
+    biometric_unlock: bool,
+    /// Whether the data key is kept in the keychain, unlocking the notes at
+    /// startup.
+    key_remembered: bool,
+}
+
+#[tauri::command]
+async fn get_vault_status(app: AppHandle) -> Result<VaultStatus, NotesError> {
+    blocking(app, move |app| {
+        let vault = app.state::<Mutex<Vault>>().inner();
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let key_remembered = keychain::get(Secret::VaultKey, &active_workspace(app))
+            .unwrap_or_else(|e| {
+                warn!("{}", e);
+                None
+            })
+            .is_some();
+        let settings = app.state::<Mutex<Settings>>().inner();
+        let biometric_unlock = settings.lock().unwrap().biometric_unlock
+            && biometrics_available(app)
+            && keychain::get(Secret::BiometricKey, &active_workspace(app))
+                .is_ok_and(|key| key.is_some());
+        Ok(VaultStatus {
+            enabled: vault.lock().unwrap().is_enabled(),
+            locked: store.lock().unwrap().is_locked(),
+            biometric_unlock,
+            key_remembered,
+        })
+    })
+    .await
+}
+
+/// Sets or changes the master password. The first time a password is set,
+/// every existing note is encrypted with it.
+#[tauri::command]
+async fn set_master_password(app: AppHandle, password: String) -> Result<(), NotesError> {
+    if password.is_empty() {
+        return Err(NotesError::Invalid(
+            "Master password cannot be empty".into(),
+        ));
+    }
+    blocking(app, move |app| {
+        let vault = app.state::<Mutex<Vault>>().inner();
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let mut vault = vault.lock().unwrap();
+        let mut store = store.lock().unwrap();
+
+        let was_enabled = vault.is_enabled();
+        let cipher = vault.set_password(&password, store.cipher())?;
+        // A remembered key only fits the password it was remembered under
+        let workspace = active_workspace(app);
+        if let Ok(Some(_)) = keychain::get(Secret::VaultKey, &workspace) {
+            let remembered = vault.remembered_key(&cipher)?;
+            if let Err(e) = keychain::set(Secret::VaultKey, &workspace, &remembered) {
+                warn!("{}", e);
+            }
+        }
+        store.set_encryption(Encryption::Unlocked(cipher))?;
+        if !was_enabled {
+            store.reseal_all()?;
+        }
+        apply_biometric_settings(app, &vault, &store);
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn unlock_vault(app: AppHandle, password: String) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let vault = app.state::<Mutex<Vault>>().inner();
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let vault = vault.lock().unwrap();
+        let cipher = vault.unlock(&password)?;
+        {
+            let mut store = store.lock().unwrap();
+            store.set_encryption(Encryption::Unlocked(cipher))?;
+            request_reindex(app);
+            apply_biometric_settings(app, &vault, &store);
+        }
+        drop(vault);
+        emit_change(app, None, NoteOperation::Reloaded);
+        Ok(())
+    })
+    .await
+}
+
+#[cfg(mobile)]
+fn biometrics_available(app: &AppHandle) -> bool {
+    use tauri_plugin_biometric::BiometricExt;
+    app.biometric()
+        .status()
+        .is_ok_and(|status| status.is_available)
+}
+
+#[cfg(desktop)]
+fn biometrics_available(_app: &AppHandle) -> bool {
+    false
+}
+
+/// Asks the OS to check the user's fingerprint or face.
+#[cfg(mobile)]
+fn authenticate(app: &AppHandle) -> Result<(), NotesError> {
+    use tauri_plugin_biometric::{AuthOptions, BiometricExt};
+    app.biometric()
+        .authenticate("Unlock your notes".into(), AuthOptions::default())
+        .map_err(|e| NotesError::Invalid(format!("Biometric check failed: {}", e)))
+}
+
+#[cfg(desktop)]
+fn authenticate(_app: &AppHandle) -> Result<(), NotesError> {
+    Err(NotesError::Invalid(
+        "Biometric unlock isn't available on this device".into(),
+    ))
+}
+
+/// Keeps the data key of an unlocked vault where `unlock_with_biometrics`
+/// finds it while biometric unlock is on, and removes it when it's off.
+fn apply_biometric_settings(app: &AppHandle, vault: &Vault, store: &NotesStore) {
+    let settings = app.state::<Mutex<Settings>>().inner();
+    let enabled = settings.lock().unwrap().biometric_unlock;
+    let workspace = active_workspace(app);
+    let applied = match store.cipher() {
+        Some(cipher) if enabled && biometrics_available(app) => vault
+            .remembered_key(cipher)
+            .and_then(|key| keychain::set(Secret::BiometricKey, &workspace, &key)),
+        // Kept until the vault is next unlocked
+        _ if enabled => Ok(()),
+        _ => keychain::clear(Secret::BiometricKey, &workspace).map(|_| ()),
+    };
+    if let Err(e) = applied {
+        warn!("Failed to update biometric unlock: {}", e);
+    }
+}
+
+/// Unlocks the vault after a biometric check, with the data key saved when it
+/// was last unlocked with the password. Fails if biometric unlock is off or
+/// unavailable, leaving the password to unlock it.
+#[tauri::command]
+async fn unlock_with_biometrics(app: AppHandle) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let settings = app.state::<Mutex<Settings>>().inner();
+        if !settings.lock().unwrap().biometric_unlock {
+            return Err(NotesError::Invalid("Biometric unlock is turned off".into()));
+        }
+        authenticate(app)?;
+        let vault = app.state::<Mutex<Vault>>().inner();
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let remembered = keychain::get(Secret::BiometricKey, &active_workspace(app))?;
+        let cipher = remembered
+            .and_then(|key| vault.lock().unwrap().unlock_remembered(&key))
+            .ok_or_else(|| {
+                NotesError::Invalid(
+                    "Unlock with the master password once to set up biometric unlock".into(),
+                )
+            })?;
+        {
+            let mut store = store.lock().unwrap();
+            store.set_encryption(Encryption::Unlocked(cipher))?;
+            request_reindex(app);
+        }
+        emit_change(app, None, NoteOperation::Reloaded);
+        Ok(())
+    })
+    .await
+}
+
+/// Keeps the unlocked vault's data key in the keychain, so the notes unlock
+/// by themselves at startup until the master password changes.
+#[tauri::command]
+async fn remember_vault_key(app: AppHandle) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let vault = app.state::<Mutex<Vault>>().inner();
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let vault = vault.lock().unwrap();
+        let store = store.lock().unwrap();
+        let cipher = store.cipher().ok_or(NotesError::Locked)?;
+        let remembered = vault.remembered_key(cipher)?;
+        keychain::set(Secret::VaultKey, &active_workspace(app), &remembered)
+    })
+    .await
+}
+
+/// Removes `secret` of the active workspace from the keychain. A cleared
+/// clipper token is replaced with a new one.
+#[tauri::command]
+async fn clear_secret(app: AppHandle, secret: Secret) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        keychain::clear(secret, &active_workspace(app))?;
+        if secret == Secret::ClipperToken {
+            // Restarted with a new token
+            let clipper = app.state::<Mutex<Option<Clipper>>>().inner();
+            *clipper.lock().unwrap() = None;
+            apply_clipper_settings(app)?;
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// Forgets the data key and drops decrypted notes from memory and the search
+/// index.
+#[tauri::command]
+async fn lock_vault(app: AppHandle) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let vault = app.state::<Mutex<Vault>>().inner();
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+
+        if !vault.lock().unwrap().is_enabled() {
+            return Err(NotesError::Invalid(
+                "No master password has been set".into(),
+            ));
+        }
+        // Drafts can't be saved once the store is locked
+        flush_drafts(app)?;
+        store.lock().unwrap().set_encryption(Encryption::Locked)?;
+        index.lock().unwrap().rebuild(&[])?;
+        emit_change(app, None, NoteOperation::Reloaded);
+        Ok(())
+    })
+    .await
+}
+
+/// Lists the commits that changed a note, for the git storage backend.
+#[tauri::command]
+async fn get_note_git_log(app: AppHandle, id: String) -> Result<Vec<GitLogEntry>, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        store.lock().unwrap().git()?.log(&id)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn set_git_remote(app: AppHandle, url: String) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        store.lock().unwrap().git()?.set_remote(&url)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn git_push(app: AppHandle) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        store.lock().unwrap().git()?.push()
+    })
+    .await
+}
+
+/// Fast-forwards the notes repository to its remote and reloads the notes.
+#[tauri::command]
+async fn git_pull(app: AppHandle) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        {
+            let mut store = store.lock().unwrap();
+            store.git()?.pull()?;
+            store.reload()?;
+            request_reindex(app);
+        }
+        emit_change(app, None, NoteOperation::Reloaded);
+        Ok(())
+    })
+    .await
+}
+
+/// Points sync at a WebDAV folder, creating it if needed, and keeps the
+/// password in the OS keychain. The next sync starts from scratch, merging
+/// whatever the folder already holds. Unavailable while the vault is enabled.
+#[tauri::command]
+async fn configure_sync(
+    app: AppHandle,
+    url: String,
+    username: String,
+    password: String,
+) -> Result<(), NotesError> {
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        return Err(NotesError::Invalid(
+            "Sync URL must start with http:// or https://".into(),
+        ));
+    }
+    blocking(app, move |app| {
+        let vault = app.state::<Mutex<Vault>>().inner();
+        sync::check_target(SyncTarget::WebDav, vault.lock().unwrap().is_enabled())?;
+        let config = SyncConfig::new(url, username);
+        sync::configure_webdav(&data_dir(app), &active_workspace(app), &config, &password)
+    })
+    .await
+}
+
+/// Sets up end-to-end encrypted sync with an S3-compatible bucket. The keys
+/// and passphrase go to the OS keychain; every device syncing the bucket
+/// must use the same passphrase.
+#[tauri::command]
+async fn configure_s3_sync(
+    app: AppHandle,
+    config: S3Config,
+    access_key_id: String,
+    secret_access_key: String,
+    passphrase: String,
+) -> Result<(), NotesError> {
+    if passphrase.is_empty() {
+        return Err(NotesError::Invalid(
+            "Sync passphrase cannot be empty".into(),
+        ));
+    }
+    blocking(app, move |app| {
+        let secrets = S3Secrets {
+            access_key_id,
+            secret_access_key,
+            passphrase,
+        };
+        sync::configure_s3(&data_dir(app), &active_workspace(app), &config, secrets)
+    })
+    .await
+}
+
+/// Keeps the notes in step with the Markdown files in the folder at `path`,
+/// or stops if `path` is `None`. The first sync merges whatever the folder
+/// already holds; after that, changes on either side are synced a moment
+/// after they're made. Unavailable while the vault is enabled.
+#[tauri::command]
+async fn configure_folder_sync(app: AppHandle, path: Option<String>) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let data_dir = data_dir(app);
+        match path {
+            Some(path) => {
+                let path = PathBuf::from(path);
+                if path.starts_with(&data_dir) || data_dir.starts_with(&path) {
+                    return Err(NotesError::Invalid(
+                        "The sync folder can't contain or be inside the notes directory".into(),
+                    ));
+                }
+                let vault = app.state::<Mutex<Vault>>().inner();
+                sync::check_target(SyncTarget::Folder, vault.lock().unwrap().is_enabled())?;
+                sync::configure_folder(&data_dir, &FolderConfig { path })?;
+            }
+            None => sync::remove_config(&data_dir, SyncTarget::Folder)?,
+        }
+        watch_folder(app)
+    })
+    .await
+}
+
+/// Sets up sync with a self-hosted sync server at `url`, which authorizes
+/// requests with `token`. The token goes to the OS keychain, and the notes
+/// are synced every few minutes from then on. Unavailable while the vault is
+/// enabled.
+#[tauri::command]
+async fn configure_server_sync(
+    app: AppHandle,
+    url: String,
+    token: String,
+) -> Result<(), NotesError> {
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        return Err(NotesError::Invalid(
+            "Sync server URL must start with http:// or https://".into(),
+        ));
+    }
+    if token.is_empty() {
+        return Err(NotesError::Invalid(
+            "Sync server token cannot be empty".into(),
+        ));
+    }
+    blocking(app, move |app| {
+        let vault = app.state::<Mutex<Vault>>().inner();
+        sync::check_target(SyncTarget::Server, vault.lock().unwrap().is_enabled())?;
+        let config = ServerConfig { url };
+        sync::configure_server(&data_dir(app), &active_workspace(app), &config, &token)
+    })
+    .await
+}
+
+/// Returns the changes to the notes that a device with state vector `since`
+/// hasn't seen, or all of them, along with this device's state vector. A sync
+/// transport hands them to the other device's `apply_remote_changes`.
+#[tauri::command]
+async fn get_local_changes(
+    app: AppHandle,
+    since: Option<Vec<u8>>,
+) -> Result<LocalChanges, NotesError> {
+    blocking(app, move |app| {
+        flush_drafts(app)?;
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let store = store.lock().unwrap();
+        sync::local_changes(&data_dir(app), &store, &since.unwrap_or_default())
+    })
+    .await
+}
+
+/// Merges changes from another device's `get_local_changes` into the notes.
+/// Concurrent edits to a note's content are merged rather than kept as
+/// conflicted copies.
+#[tauri::command]
+async fn apply_remote_changes(app: AppHandle, changes: Vec<u8>) -> Result<CrdtReport, NotesError> {
+    blocking(app, move |app| {
+        flush_drafts(app)?;
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let report = {
+            let mut store = store.lock().unwrap();
+            let report = sync::apply_remote_changes(&data_dir(app), &mut store, &changes)?;
+            if report.updated + report.deleted > 0 {
+                request_reindex(app);
+            }
+            report
+        };
+        if report.updated + report.deleted > 0 {
+            emit_change(app, None, NoteOperation::Reloaded);
+        }
+        Ok(report)
+    })
+    .await
+}
+
+/// Syncs with `target`, WebDAV by default.
+#[tauri::command]
+async fn sync_now(app: AppHandle, target: Option<SyncTarget>) -> Result<SyncReport, NotesError> {
+    blocking(app, move |app| sync_with(app, target.unwrap_or_default())).await
+}
+
+/// Syncs the active workspace with `target`, reloading the notes if that
+/// changed any of them.
+fn sync_with(app: &AppHandle, target: SyncTarget) -> Result<SyncReport, NotesError> {
+    let store = app.state::<Mutex<NotesStore>>().inner();
+    let data_dir = data_dir(app);
+    let workspace = active_workspace(app);
+
+    let mut store = store.lock().unwrap();
+    let report = sync::run(&data_dir, &workspace, target, &mut store)?;
+    if report.changed_local() {
+        request_reindex(app);
+        drop(store);
+        emit_change(app, None, NoteOperation::Reloaded);
+    }
+    Ok(report)
+}
+
+/// Reloads the notes when another program, such as a file sync tool, changes
+/// where they're stored.
+struct StorageWatch(Watch);
+
+/// Starts watching where the active workspace's notes are stored, in place
+/// of wherever was watched before.
+fn watch_storage(app: &AppHandle) -> Result<(), NotesError> {
+    let data_dir = data_dir(app);
+    let store = app.state::<Mutex<NotesStore>>().inner();
+    let kind = store.lock().unwrap().backend_kind();
+    let watch = &app.state::<StorageWatch>().inner().0;
+    match kind {
+        // Along with its write-ahead log and other companion files
+        BackendKind::Sqlite => watch.watch(&data_dir, false, |path| {
+            path.file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with("notes.db"))
+        }),
+        // The file is replaced rather than written in place, so watch its
+        // directory
+        BackendKind::Json => {
+            let location = kind.location(&data_dir);
+            watch.watch(&data_dir, false, move |path| {
+                path.file_name() == location.file_name()
+            })
+        }
+        BackendKind::Markdown | BackendKind::Git | BackendKind::Sharded => {
+            watch.watch(&kind.location(&data_dir), true, |path| {
+                !path.components().any(|part| part.as_os_str() == ".git")
+            })
+        }
+    }
+}
+
+/// Reloads the notes a moment after their storage changes, when that wasn't
+/// this app's doing. Runs for as long as the app does.
+fn run_storage_watch(app: AppHandle, requests: Requests) {
+    while requests.wait(STORAGE_RELOAD_DELAY) {
+        if let Err(e) = reload_changed_notes(&app) {
+            warn!("Failed to reload changed notes: {}", e);
+        }
+    }
+}
+
+fn reload_changed_notes(app: &AppHandle) -> Result<(), NotesError> {
+    let store = app.state::<Mutex<NotesStore>>().inner();
+    let mut store = store.lock().unwrap();
+    if !store.merge_external()? {
+        return Ok(());
+    }
+    request_reindex(app);
+    drop(store);
+    info!("Reloaded notes changed by another program");
+    emit_change(app, None, NoteOperation::Reloaded);
+    Ok(())
+}
+
+/// Points the watchers at the active workspace once it has changed or moved.
+fn rewatch(app: &AppHandle) {
+    for result in [watch_storage(app), watch_folder(app)] {
+        if let Err(e) = result {
+            warn!("{}", e);
+        }
+    }
+}
+
+/// Keeps the active workspace in step with its sync folder, if it has one.
+/// Woken when either side may have changed.
+struct FolderSync(Watch);
+
+fn request_folder_sync(app: &AppHandle) {
+    if let Some(folder_sync) = app.try_state::<FolderSync>() {
+        folder_sync.0.request();
+    }
+}
+
+/// Starts watching the active workspace's sync folder, if it has one, in
+/// place of the folder watched before, and syncs it.
+fn watch_folder(app: &AppHandle) -> Result<(), NotesError> {
+    let config: Option<FolderConfig> = sync::load_config(&data_dir(app), SyncTarget::Folder)?;
+    let watch = &app.state::<FolderSync>().inner().0;
+    match config {
+        Some(config) => {
+            watch.watch(&config.path, true, |_| true)?;
+            watch.request();
+        }
+        None => watch.stop(),
+    }
+    Ok(())
+}
+
+/// Syncs the sync folder a moment after each change to it or to the notes.
+/// Runs for as long as the app does.
+fn run_folder_sync(app: AppHandle, requests: Requests) {
+    while requests.wait(FOLDER_SYNC_DELAY) {
+        if let Err(e) = sync_folder(&app) {
+            warn!("Folder sync failed: {}", e);
+        }
+    }
+}
+
+fn sync_folder(app: &AppHandle) -> Result<(), NotesError> {
+    let configured = sync::load_config::<FolderConfig>(&data_dir(app), SyncTarget::Folder)?;
+    let vault = app.state::<Mutex<Vault>>().inner();
+    // A vault enabled since the folder was set up stops folder sync
+    if configured.is_none() || vault.lock().unwrap().is_enabled() {
+        return Ok(());
+    }
+    sync_with(app, SyncTarget::Folder).map(|_| ())
+}
+
+/// Syncs with the active workspace's sync server, if it has one, every
+/// `SERVER_SYNC_INTERVAL`. Runs for as long as the app does.
+fn run_server_sync(app: AppHandle) {
+    loop {
+        let configured = sync::load_config::<ServerConfig>(&data_dir(&app), SyncTarget::Server);
+        let vault = app.state::<Mutex<Vault>>().inner();
+        // Failures are logged and recorded for the sync status
+        if matches!(configured, Ok(Some(_))) && !vault.lock().unwrap().is_enabled() {
+            let _ = sync_with(&app, SyncTarget::Server);
+        }
+        std::thread::sleep(SERVER_SYNC_INTERVAL);
+    }
+}
+
+#[tauri::command]
+async fn get_sync_status(
+    app: AppHandle,
+    target: Option<SyncTarget>,
+) -> Result<SyncStatus, NotesError> {
+    blocking(app, move |app| {
+        sync::status(&data_dir(app), target.unwrap_or_default())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_settings(app: AppHandle) -> Result<Settings, NotesError> {
+    let settings = app.state::<Mutex<Settings>>().inner();
+    Ok(settings.lock().unwrap().clone())
+}
+
+/// Changes the settings named in `patch` and returns the result.
+#[tauri::command]
+async fn update_settings(app: AppHandle, patch: serde_json::Value) -> Result<Settings, NotesError> {
+    if patch.get("data_dir").is_some() {
+        return Err(NotesError::Invalid(
+            "Use set_notes_directory to move the notes".into(),
+        ));
+    }
+    blocking(app, move |app| {
+        let settings = app.state::<Mutex<Settings>>().inner();
+        let mut settings = settings.lock().unwrap();
+        let updated = settings.patched(patch)?;
+        updated.save(&settings_path(app)?)?;
+        *settings = updated.clone();
+        drop(settings);
+        apply_clipper_settings(app)?;
+        {
+            let vault = app.state::<Mutex<Vault>>().inner();
+            let store = app.state::<Mutex<NotesStore>>().inner();
+            let mut store = store.lock().unwrap();
+            store.set_compression(updated.compress_notes);
+            apply_biometric_settings(app, &vault.lock().unwrap(), &store);
+        }
+        Ok(updated)
+    })
+    .await
+}
+
+/// Whether note contents are written compressed, per the settings.
+fn compress_notes(app: &AppHandle) -> bool {
+    let settings = app.state::<Mutex<Settings>>().inner();
+    settings.lock().unwrap().compress_notes
+}
+
+/// Starts, stops or moves the web clipper to match the settings.
+fn apply_clipper_settings(app: &AppHandle) -> Result<(), NotesError> {
+    let settings = app.state::<Mutex<Settings>>().inner();
+    let (enabled, port) = {
+        let settings = settings.lock().unwrap();
+        (settings.clipper_enabled, settings.clipper_port)
+    };
+    let clipper = app.state::<Mutex<Option<Clipper>>>().inner();
+    let mut clipper = clipper.lock().unwrap();
+    if !enabled || clipper.as_ref().is_some_and(|running| running.port != port) {
+        // Frees the port
+        *clipper = None;
+    }
+    if !enabled || clipper.is_some() {
+        return Ok(());
+    }
+    let token = clipper::token(&clipper_token_path(app)?, false)?;
+    let handle = app.clone();
+    *clipper = Some(Clipper::start(port, token, move |clip| {
+        let (title, content) = (clip.title(), clip.content());
+        insert_new_note(&handle, title, content, clip.tags)
+    })?);
+    Ok(())
+}
+
+/// Returns the token the web clipper asks clients for, or replaces it with a
+/// new one first if `regenerate`, so clients holding the old one are locked
+/// out.
+#[tauri::command]
+async fn get_clipper_token(app: AppHandle, regenerate: Option<bool>) -> Result<String, NotesError> {
+    blocking(app, move |app| {
+        let regenerate = regenerate.unwrap_or(false);
+        let token = clipper::token(&clipper_token_path(app)?, regenerate)?;
+        if regenerate {
+            // Restarted with the new token
+            let clipper = app.state::<Mutex<Option<Clipper>>>().inner();
+            *clipper.lock().unwrap() = None;
+            apply_clipper_settings(app)?;
+        }
+        Ok(token)
+    })
+    .await
+}
+
+/// Moves the active workspace's notes, vault, and sync state into `path` and
+/// uses it from now on. The directory must not already hold notes.
+#[tauri::command]
+async fn set_notes_directory(app: AppHandle, path: String) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let target = PathBuf::from(path);
+        flush_drafts(app)?;
+
+        let vault = app.state::<Mutex<Vault>>().inner();
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let dir = app.state::<DataDir>().inner();
+        let workspaces = app.state::<Mutex<Workspaces>>().inner();
+        let settings = app.state::<Mutex<Settings>>().inner();
+        let mut vault = vault.lock().unwrap();
+        let mut store = store.lock().unwrap();
+        let mut dir = dir.0.lock().unwrap();
+        let mut workspaces = workspaces.lock().unwrap();
+        let mut settings = settings.lock().unwrap();
+        let is_default = workspaces.active().is_none();
+
+        let current = dir.clone();
+        datadir::prepare(&current, &target)?;
+        let updated = Settings {
+            data_dir: Some(target.clone()),
+            ..settings.clone()
+        };
+        datadir::copy_data(&current, &target)?;
+
+        let reopened = Vault::load(&target.join("vault.json")).and_then(|new_vault| {
+            let mut new_store = workspace::open_store(&target, &new_vault)?;
+            new_store.set_compression(settings.compress_notes);
+            // Stay unlocked, since the data key hasn't changed
+            if let Some(cipher) = store.cipher() {
+                new_store.set_encryption(Encryption::Unlocked(cipher.clone()))?;
+            }
+            if is_default {
+                updated.save(&settings_path(app)?)?;
+            } else {
+                workspaces.set_active_path(&target)?;
+            }
+            Ok((new_vault, new_store))
+        });
+        let (new_vault, new_store) = match reopened {
+            Ok(opened) => opened,
+            Err(e) => {
+                datadir::remove_data(&target);
+                return Err(e);
+            }
+        };
+
+        // Close the old database before deleting it
+        *store = new_store;
+        *vault = new_vault;
+        *dir = target;
+        if is_default {
+            *settings = updated;
+        }
+        datadir::remove_data(&current);
+        drop((vault, store, dir, workspaces, settings));
+        rewatch(app);
+        Ok(())
+    })
+    .await
+}
+
+#[derive(Serialize)]
+struct VaultSummary {
+    #[serde(flatten)]
+    workspace: Workspace,
+    active: bool,
+}
+
+/// Lists every workspace ("vault" in the UI), the default one first.
+#[tauri::command]
+async fn list_vaults(app: AppHandle) -> Result<Vec<VaultSummary>, NotesError> {
+    blocking(app, move |app| {
+        let settings = app.state::<Mutex<Settings>>().inner();
+        let workspaces = app.state::<Mutex<Workspaces>>().inner();
+        let default = Workspace {
+            id: workspace::DEFAULT_ID.to_string(),
+            name: "Default".to_string(),
+            path: default_data_dir(app, &settings.lock().unwrap())?,
+        };
+        let workspaces = workspaces.lock().unwrap();
+        Ok(std::iter::once(&default)
+            .chain(workspaces.all())
+            .map(|workspace| VaultSummary {
+                active: workspace.id == workspaces.active_id(),
+                workspace: workspace.clone(),
+            })
+            .collect())
+    })
+    .await
+}
+
+/// Adds a workspace kept in `path`. Pointing it at a folder that already holds
+/// notes, e.g. one synced from another machine, opens those notes.
+#[tauri::command]
+async fn create_vault(app: AppHandle, name: String, path: String) -> Result<Workspace, NotesError> {
+    blocking(app, move |app| {
+        let settings = app.state::<Mutex<Settings>>().inner();
+        let workspaces = app.state::<Mutex<Workspaces>>().inner();
+        let default_dir = default_data_dir(app, &settings.lock().unwrap())?;
+        workspaces
+            .lock()
+            .unwrap()
+            .create(&name, Path::new(&path), &[default_dir.as_path()])
+    })
+    .await
+}
+
+/// Closes the active workspace and opens workspace `id` in its place. Its
+/// vault starts out locked if it has a master password.
+#[tauri::command]
+async fn switch_vault(app: AppHandle, id: String) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        flush_drafts(app)?;
+        let settings = app.state::<Mutex<Settings>>().inner();
+        let default_dir = default_data_dir(app, &settings.lock().unwrap())?;
+
+        let vault = app.state::<Mutex<Vault>>().inner();
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        let dir = app.state::<DataDir>().inner();
+        let workspaces = app.state::<Mutex<Workspaces>>().inner();
+        let mut vault = vault.lock().unwrap();
+        let mut store = store.lock().unwrap();
+        let mut dir = dir.0.lock().unwrap();
+        let mut workspaces = workspaces.lock().unwrap();
+
+        if workspaces.active_id() == id {
+            return Ok(());
+        }
+        let path = match workspaces.path_of(&id)? {
+            Some(path) => path.to_path_buf(),
+            None => default_dir,
+        };
+        let (new_vault, mut new_store) = workspace::open(&path)?;
+        new_store.set_compression(compress_notes(app));
+        workspace::unlock_remembered(&mut new_store, &new_vault, &id);
+        workspaces.set_active(&id)?;
+
+        *vault = new_vault;
+        *store = new_store;
+        *dir = path;
+        // Not to find the other workspace's notes until it's rebuilt
+        index.lock().unwrap().rebuild(&[])?;
+        drop((vault, store, dir, workspaces));
+        request_reindex(app);
+        emit_change(app, None, NoteOperation::Reloaded);
+        rewatch(app);
+        Ok(())
+    })
+    .await
+}
+
+/// Searches notes with the syntax described in `query`, or with `regex`, for
+/// notes whose title or content matches `query` as a regular expression.
+#[tauri::command]
+async fn search_notes(
+    app: AppHandle,
+    query: String,
+    limit: Option<usize>,
+    regex: Option<bool>,
+) -> Result<Vec<SearchHit>, NotesError> {
+    blocking(app, move |app| {
+        let limit = limit.unwrap_or(50).max(1);
+        if regex.unwrap_or(false) {
+            flush_drafts(app)?;
+            let store = app.state::<Mutex<NotesStore>>().inner();
+            let notes = store.lock().unwrap().notes()?;
+            return search::regex_search(&notes, &query, limit);
+        }
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        index.lock().unwrap().search(&query, limit)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn list_saved_searches(app: AppHandle) -> Result<Vec<SavedSearch>, NotesError> {
+    blocking(app, move |app| {
+        Ok(SavedSearches::load(&data_dir(app))?.all().to_vec())
+    })
+    .await
+}
+
+/// Saves `query` as a search named `name`, replacing saved search `id` if
+/// given.
+#[tauri::command]
+async fn save_search(
+    app: AppHandle,
+    name: String,
+    query: String,
+    id: Option<String>,
+) -> Result<SavedSearch, NotesError> {
+    blocking(app, move |app| {
+        let dir = app.state::<DataDir>().inner();
+        let dir = dir.0.lock().unwrap();
+        SavedSearches::load(&dir)?.save_search(id.as_deref(), &name, &query)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn delete_saved_search(app: AppHandle, id: String) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let dir = app.state::<DataDir>().inner();
+        let dir = dir.0.lock().unwrap();
+        SavedSearches::load(&dir)?.delete(&id)
+    })
+    .await
+}
+
+/// How many notes a saved search lists unless asked for more, as it's shown
+/// whole like a folder rather than as the best few matches.
+const SAVED_SEARCH_LIMIT: usize = 1000;
+
+/// Returns up to `limit` of the notes saved search `id` matches now.
+#[tauri::command]
+async fn run_saved_search(
+    app: AppHandle,
+    id: String,
+    limit: Option<usize>,
+) -> Result<Vec<SearchHit>, NotesError> {
+    blocking(app, move |app| {
+        let query = SavedSearches::load(&data_dir(app))?.get(&id)?.query.clone();
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        index
+            .lock()
+            .unwrap()
+            .search(&query, limit.unwrap_or(SAVED_SEARCH_LIMIT).max(1))
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_note_stats(app: AppHandle, id: String) -> Result<NoteStats, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let note = store.lock().unwrap().get(&id)?;
+        Ok(stats::note_stats(&note))
+    })
+    .await
+}
+
+/// Totals the stats of every note that isn't trashed.
+#[tauri::command]
+async fn get_vault_stats(app: AppHandle) -> Result<VaultStats, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let store = store.lock().unwrap();
+        Ok(stats::vault_stats(
+            &store.notes()?,
+            store.list_tags()?,
+            &store.edit_times()?,
+            chrono::Local::now(),
+        ))
+    })
+    .await
+}
+
+/// Loads the notes created, or last edited if `field` is `updated`, from
+/// `start` until `end`, as Unix times.
+#[tauri::command]
+async fn load_notes_between(
+    app: AppHandle,
+    start: i64,
+    end: i64,
+    field: Option<DateField>,
+) -> Result<Vec<Note>, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let notes = store.lock().unwrap().notes()?;
+        let field = field.unwrap_or_default();
+        Ok(redacted(calendar::notes_between(
+            &notes, start, end, field,
+        )?))
+    })
+    .await
+}
+
+/// Counts the notes created and last edited on each day of `year`.
+#[tauri::command]
+async fn get_calendar_heatmap(app: AppHandle, year: i32) -> Result<Vec<CalendarDay>, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let notes = store.lock().unwrap().notes()?;
+        calendar::heatmap(&notes, year, &chrono::Local)
+    })
+    .await
+}
+
+/// Returns the notes whose `[[links]]` point to note `id`.
+#[tauri::command]
+async fn get_backlinks(app: AppHandle, id: String) -> Result<Vec<LinkedNote>, NotesError> {
+    blocking(app, move |app| {
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        index
+            .lock()
+            .unwrap()
+            .links()
+            .backlinks(&id)
+            .ok_or_else(|| NotesError::NotFound("Note not found".into()))
+    })
+    .await
+}
+
+/// Returns the `[[links]]` in note `id` along with the notes they point to.
+#[tauri::command]
+async fn get_outgoing_links(app: AppHandle, id: String) -> Result<Vec<OutgoingLink>, NotesError> {
+    blocking(app, move |app| {
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        index
+            .lock()
+            .unwrap()
+            .links()
+            .outgoing(&id)
+            .ok_or_else(|| NotesError::NotFound("Note not found".into()))
+    })
+    .await
+}
+
+/// Renders Markdown as sanitized HTML for the preview: `content` if given,
+/// such as an unsaved edit, or else the content of note `id`. Code blocks
+/// are highlighted for the CSS from `get_highlight_css`, and `[[links]]`
+/// become `min-notes://note/<id>` links.
+#[tauri::command]
+async fn render_markdown(
+    app: AppHandle,
+    id: Option<String>,
+    content: Option<String>,
+) -> Result<String, NotesError> {
+    blocking(app, move |app| {
+        let content = match (content, id) {
+            (Some(content), _) => content,
+            (None, Some(id)) => {
+                flush_drafts(app)?;
+                let store = app.state::<Mutex<NotesStore>>().inner();
+                let note = store.lock().unwrap().get(&id)?;
+                if note.locked {
+                    return Err(NotesError::Invalid(
+                        "Open the locked note to render its content".into(),
+                    ));
+                }
+                note.content
+            }
+            (None, None) => {
+                return Err(NotesError::Invalid(
+                    "Give a note or the content to render".into(),
+                ))
+            }
+        };
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        let index = index.lock().unwrap();
+        Ok(markdown::render(&content, |title| {
+            index.links().resolve(title).map(|note| note.id)
+        }))
+    })
+    .await
+}
+
+/// The CSS that colors the code blocks `render_markdown` highlights, for a
+/// light or `dark` theme.
+#[tauri::command]
+async fn get_highlight_css(app: AppHandle, dark: bool) -> Result<String, NotesError> {
+    blocking(app, move |_| markdown::highlight_css(dark)).await
+}
+
+/// Returns every note that isn't trashed, joined by its links and shared
+/// tags.
+#[tauri::command]
+async fn get_notes_graph(app: AppHandle) -> Result<NotesGraph, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        let notes = store.lock().unwrap().notes()?;
+        Ok(graph::build(&notes, index.lock().unwrap().links()))
+    })
+    .await
+}
+
+/// Returns the `k` notes most related to note `id` by shared tags, links,
+/// and similar text, most related first. Text is compared by meaning when
+/// semantic search has embedded the notes.
+#[tauri::command]
+async fn get_related_notes(
+    app: AppHandle,
+    id: String,
+    k: Option<usize>,
+) -> Result<Vec<RelatedNote>, NotesError> {
+    blocking(app, move |app| {
+        flush_drafts(app)?;
+        let settings = app.state::<Mutex<Settings>>().inner();
+        let embeddings = if settings.lock().unwrap().semantic_search {
+            Some(Embeddings::load(&data_dir(app))?)
+        } else {
+            None
+        };
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        let notes = store.lock().unwrap().notes()?;
+        let index = index.lock().unwrap();
+        related::related(
+            &id,
+            &notes,
+            index.links(),
+            embeddings.as_ref(),
+            k.unwrap_or(5).max(1),
+        )
+    })
+    .await
+}
+
+/// Replaces `pattern`, literal or a regular expression, with `replacement`
+/// in the content of every note outside the trash, keeping each changed
+/// note's previous version in its history. Returns how many matches each
+/// note had; with `dry_run`, nothing is changed.
+#[tauri::command]
+async fn replace_in_notes(
+    app: AppHandle,
+    pattern: String,
+    replacement: String,
+    regex: bool,
+    dry_run: bool,
+) -> Result<Vec<NoteMatches>, NotesError> {
+    blocking(app, move |app| {
+        let replacer = Replacer::new(&pattern, &replacement, regex)?;
+        flush_drafts(app)?;
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        let matches = {
+            let mut store = store.lock().unwrap();
+            // Locked content is ciphertext, which mustn't be rewritten
+            let mut notes = store.notes()?;
+            notes.retain(|note| !note.locked);
+            let matches = replacer.matches(&notes);
+            if dry_run {
+                return Ok(matches);
+            }
+            let now = Utc::now().timestamp();
+            for found in &matches {
+                let note = store.update(&found.id, |note| {
+                    note.content = replacer.replace(&note.content);
+                    note.updated_at = now;
+                })?;
+                reindex_note(index, note);
+            }
+            matches
+        };
+        if !matches.is_empty() {
+            emit_change(app, None, NoteOperation::Reloaded);
+        }
+        Ok(matches)
+    })
+    .await
+}
+
+/// Returns the checklist items across all notes that match `filter`, open
+/// ones by default.
+#[tauri::command]
+async fn list_tasks(app: AppHandle, filter: Option<TaskFilter>) -> Result<Vec<Task>, NotesError> {
+    blocking(app, move |app| {
+        flush_drafts(app)?;
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        Ok(index
+            .lock()
+            .unwrap()
+            .tasks()
+            .list(filter.unwrap_or_default()))
+    })
+    .await
+}
+
+/// Checks or unchecks the checklist item on `line` (counting from 1) of note
+/// `note_id`.
+#[tauri::command]
+async fn toggle_task(app: AppHandle, note_id: String, line: usize) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        flush_drafts(app)?;
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        {
+            let mut store = store.lock().unwrap();
+            let content = tasks::toggle(&store.get(&note_id)?.content, line).ok_or_else(|| {
+                NotesError::Invalid(format!("Line {} is not a checklist item", line))
+            })?;
+            let note = store.update(&note_id, |note| {
+                note.content = content;
+                note.updated_at = Utc::now().timestamp();
+            })?;
+            reindex_note(index, note);
+        }
+        emit_change(app, Some(&note_id), NoteOperation::Updated);
+        Ok(())
+    })
+    .await
+}
+
+/// Sets a reminder on note `note_id` for `datetime`, in Unix seconds. Returns
+/// the new reminder.
+#[tauri::command]
+async fn set_reminder(
+    app: AppHandle,
+    note_id: String,
+    datetime: i64,
+    repeat: Option<Repeat>,
+) -> Result<Reminder, NotesError> {
+    blocking(app, move |app| {
+        let repeat = repeat.unwrap_or_default();
+        if repeat == Repeat::Never && datetime <= Utc::now().timestamp() {
+            return Err(NotesError::Invalid(
+                "Reminder time has already passed".into(),
+            ));
+        }
+        let reminder = Reminder::new(datetime, repeat);
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        store
+            .lock()
+            .unwrap()
+            .update(&note_id, |note| note.reminders.push(reminder.clone()))?;
+        emit_change(app, Some(&note_id), NoteOperation::Updated);
+        Ok(reminder)
+    })
+    .await
+}
+
+/// Returns every reminder set on a note that isn't trashed, soonest first.
+#[tauri::command]
+async fn list_reminders(app: AppHandle) -> Result<Vec<ReminderInfo>, NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        Ok(reminders::list(store.lock().unwrap().all_notes()?))
+    })
+    .await
+}
+
+#[tauri::command]
+async fn cancel_reminder(app: AppHandle, id: String) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let mut store = store.lock().unwrap();
+        let note_id = store
+            .all_notes()?
+            .iter()
+            .find(|note| note.reminders.iter().any(|reminder| reminder.id == id))
+            .map(|note| note.id.clone())
+            .ok_or_else(|| NotesError::NotFound("Reminder not found".into()))?;
+        store.update(&note_id, |note| {
+            note.reminders.retain(|reminder| reminder.id != id)
+        })?;
+        drop(store);
+        emit_change(app, Some(&note_id), NoteOperation::Updated);
+        Ok(())
+    })
+    .await
+}
+
+/// Sets note `id` to expire at `datetime`, in Unix seconds, when it's moved
+/// to the trash or, with `action` `delete`, deleted for good. A `datetime` of
+/// `None` keeps the note.
+#[tauri::command]
+async fn set_expiry(
+    app: AppHandle,
+    id: String,
+    datetime: Option<i64>,
+    action: Option<ExpiryAction>,
+) -> Result<(), NotesError> {
+    blocking(app, move |app| {
+        if datetime.is_some_and(|at| at <= Utc::now().timestamp()) {
+            return Err(NotesError::Invalid("Expiry time has already passed".into()));
+        }
+        let expiry = datetime.map(|at| Expiry {
+            at,
+            action: action.unwrap_or_default(),
+        });
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        store
+            .lock()
+            .unwrap()
+            .update(&id, |note| note.expiry = expiry)?;
+        emit_change(app, Some(&id), NoteOperation::Updated);
+        Ok(())
+    })
+    .await
+}
+
+/// Trashes or deletes each note as it expires. Notes that expired while the
+/// app was closed go on the first check; none go while the vault is locked.
+/// Deleted notes can't be undone, to keep nothing of them. Runs for as long
+/// as the app does.
+fn run_expiry(app: AppHandle) {
+    loop {
+        expire_notes(&app);
+        std::thread::sleep(EXPIRY_CHECK_INTERVAL);
+    }
+}
+
+fn expire_notes(app: &AppHandle) {
+    let store = app.state::<Mutex<NotesStore>>().inner();
+    let index = app.state::<Mutex<SearchIndex>>().inner();
+    let now = Utc::now().timestamp();
+    let mut trashed = Vec::new();
+    let mut deleted = Vec::new();
+    {
+        let mut store = store.lock().unwrap();
+        let Ok(notes) = store.all_notes() else {
+            return;
+        };
+        for (id, action) in expiry::expired(notes, now) {
+            if action == ExpiryAction::Delete {
+                deleted.push(id);
+                continue;
+            }
+            // Cleared so the note stays put if it's restored
+            match store.update_without_undo(&id, |note| {
+                note.trashed_at = Some(now);
+                note.expiry = None;
+            }) {
+                Ok(_) => trashed.push(id),
+                Err(e) => warn!("Failed to trash expired note {}: {}", id, e),
+            }
+        }
+        if let Err(e) = store.delete_without_undo(&deleted) {
+            warn!("Failed to delete expired notes: {}", e);
+            deleted.clear();
+        }
+        let mut index = index.lock().unwrap();
+        for id in trashed.iter().chain(&deleted) {
+            if let Err(e) = index.remove(id) {
+                warn!("Failed to remove note {} from search index: {}", id, e);
+            }
+        }
+    }
+    let drafts = app.state::<Mutex<Drafts>>().inner();
+    for id in &deleted {
+        drafts.lock().unwrap().discard(id);
+    }
+    for id in &trashed {
+        emit_change(app, Some(id), NoteOperation::Updated);
+    }
+    for id in &deleted {
+        emit_change(app, Some(id), NoteOperation::Deleted);
+    }
+}
+
+/// Shows a notification for each reminder as it comes due. Reminders that
+/// came due while the app was closed fire on the first check; none fire
+/// while the vault is locked, or for trashed notes. Runs for as long as the
+/// app does.
+fn run_reminders(app: AppHandle) {
+    loop {
+        fire_reminders(&app);
+        std::thread::sleep(REMINDER_CHECK_INTERVAL);
+    }
+}
+
+fn fire_reminders(app: &AppHandle) {
+    use tauri_plugin_notification::NotificationExt;
+
+    let store = app.state::<Mutex<NotesStore>>().inner();
+    let now = Utc::now().timestamp();
+    let mut fired = Vec::new();
+    {
+        let mut store = store.lock().unwrap();
+        let Ok(notes) = store.all_notes() else {
+            return;
+        };
+        let due: Vec<(String, String)> = notes
+            .iter()
+            .filter(|note| note.trashed_at.is_none() && reminders::is_due(&note.reminders, now))
+            .map(|note| (note.id.clone(), note.title.clone()))
+            .collect();
+        for (id, title) in due {
+            // Moved on before notifying, so a failed save can't repeat the
+            // notification every check
+            match store
+                .update_without_undo(&id, |note| reminders::fire_due(&mut note.reminders, now))
+            {
+                Ok(_) => fired.push((id, title)),
+                Err(e) => warn!("Failed to update reminders of note {}: {}", id, e),
+            }
+        }
+    }
+    for (id, title) in &fired {
+        let title = if title.trim().is_empty() {
+            "Untitled note"
+        } else {
+            title.as_str()
+        };
+        if let Err(e) = app
+            .notification()
+            .builder()
+            .title("Reminder")
+            .body(title)
+            .show()
+        {
+            warn!("Failed to show reminder for note {}: {}", id, e);
+        }
+        emit_change(app, Some(id), NoteOperation::Updated);
+    }
+}
+
+/// Archives the notes left unedited for `auto_archive_months` months once a
+/// day, the first time on startup. Nothing is archived while the vault is
+/// locked; the notes are looked at again the next day. Runs for as long as
+/// the app does.
+fn run_auto_archive(app: AppHandle) {
+    let mut last_run = None;
+    loop {
+        let today = chrono::Local::now().date_naive();
+        if last_run != Some(today) {
+            last_run = Some(today);
+            auto_archive_notes(&app);
+        }
+        std::thread::sleep(AUTO_ARCHIVE_CHECK_INTERVAL);
+    }
+}
+
+fn auto_archive_notes(app: &AppHandle) {
+    let months = app.state::<Mutex<Settings>>().inner();
+    let months = months.lock().unwrap().auto_archive_months;
+    if months == 0 {
+        return;
+    }
+    let store = app.state::<Mutex<NotesStore>>().inner();
+    let mut archived = 0;
+    {
+        let mut store = store.lock().unwrap();
+        let Ok(notes) = store.all_notes() else {
+            return;
+        };
+        let stale: Vec<String> = auto_archive::stale(notes, months, Utc::now().timestamp())
+            .into_iter()
+            .map(|note| note.id.clone())
+            .collect();
+        for id in stale {
+            match store.update_without_undo(&id, |note| note.archived = true) {
+                Ok(_) => archived += 1,
+                Err(e) => warn!("Failed to archive note {}: {}", id, e),
+            }
+        }
+    }
+    if archived > 0 {
+        info!(
+            "Archived {} notes left unedited for {} months",
+            archived, months
+        );
+        emit_change(app, None, NoteOperation::Reloaded);
+    }
+}
+
+/// Backs up the active workspace, keeping the newest `backups_to_keep`
+/// backups.
+fn back_up(app: &AppHandle) -> Result<BackupInfo, NotesError> {
+    flush_drafts(app)?;
+    let keep = app.state::<Mutex<Settings>>().inner();
+    let keep = keep.lock().unwrap().backups_to_keep;
+    // Holding the store keeps the notes from changing, or moving, meanwhile
+    let store = app.state::<Mutex<NotesStore>>().inner();
+    let _store = store.lock().unwrap();
+    backup::create(&data_dir(app), keep)
+}
+
+/// Backs up the active workspace whenever `backup_interval_hours` have passed
+/// since its newest backup. Runs for as long as the app does.
+fn run_scheduled_backups(app: AppHandle) {
+    loop {
+        let settings = app.state::<Mutex<Settings>>().inner();
+        let interval_hours = settings.lock().unwrap().backup_interval_hours;
+        if interval_hours > 0 {
+            let due = match backup::list(&data_dir(&app)) {
+                Ok(backups) => backups.first().is_none_or(|newest| {
+                    Utc::now().timestamp() - newest.created_at >= (interval_hours * 3600) as i64
+                }),
+                Err(e) => {
+                    warn!("Failed to check for a scheduled backup: {}", e);
+                    false
+                }
+            };
+            if due {
+                match back_up(&app) {
+                    Ok(backup) => info!("Backed up notes to {}", backup.path.display()),
+                    Err(e) => warn!("Scheduled backup failed: {}", e),
+                }
+            }
+        }
+        std::thread::sleep(BACKUP_CHECK_INTERVAL);
+    }
+}
+
+#[tauri::command]
+async fn create_backup_now(app: AppHandle) -> Result<BackupInfo, NotesError> {
+    blocking(app, back_up).await
+}
+
+/// Returns the active workspace's backups, newest first.
+#[tauri::command]
+async fn list_backups(app: AppHandle) -> Result<Vec<BackupInfo>, NotesError> {
+    blocking(app, move |app| backup::list(&data_dir(app))).await
+}
+
+/// Replaces the active workspace's notes with the backup at `path`. The
+/// current notes are backed up first, and that backup is returned.
+#[tauri::command]
+async fn restore_backup(app: AppHandle, path: String) -> Result<BackupInfo, NotesError> {
+    blocking(app, move |app| {
+        let archive = PathBuf::from(path);
+        flush_drafts(app)?;
+        let (keep, compress) = {
+            let settings = app.state::<Mutex<Settings>>().inner();
+            let settings = settings.lock().unwrap();
+            (settings.backups_to_keep, settings.compress_notes)
+        };
+
+        let vault = app.state::<Mutex<Vault>>().inner();
+        let store = app.state::<Mutex<NotesStore>>().inner();
+        let index = app.state::<Mutex<SearchIndex>>().inner();
+        let dir = app.state::<DataDir>().inner();
+        let mut vault = vault.lock().unwrap();
+        let mut store = store.lock().unwrap();
+        let dir = dir.0.lock().unwrap().clone();
+
+        let staging = backup::unpack(&dir, &archive)?;
+        // Checked before the notes are replaced, since this version couldn't
+        // open them afterwards
+        if upgrade::version(&staging)? > upgrade::SCHEMA_VERSION {
+            if let Err(e) = fs::remove_dir_all(&staging) {
+                warn!("Failed to remove restore folder: {}", e);
+            }
+            return Err(NotesError::Invalid(
+                "This backup was made by a newer version of min_notes; update to restore it".into(),
+            ));
+        }
+        // Kept past `backups_to_keep`'s pruning, since it's the newest
+        let previous = backup::create(&dir, keep)?;
+
+        // Swap in an empty store so the database is closed while its files
+        // are replaced
+        let placeholder =
+            std::env::temp_dir().join(format!("min_notes-restore-{}", Uuid::new_v4()));
+        *store = NotesStore::new(Storage::open(&placeholder)?)?;
+        let replace = |staging: &Path| {
+            backup::replace_data(&dir, staging).and_then(|_| workspace::open(&dir))
+        };
+        let (reopened, failed) = match replace(&staging) {
+            Ok(reopened) => (reopened, None),
+            // Put the previous notes back rather than leave the empty store
+            Err(e) => {
+                let put_back = backup::unpack(&dir, &previous.path).and_then(|s| replace(&s));
+                match put_back {
+                    Ok(reopened) => (reopened, Some(e)),
+                    Err(undo) => {
+                        return Err(NotesError::Io(format!(
+                            "{} Putting the previous notes back failed too ({}). They're backed \
+                             up in {}",
+                            e,
+                            undo,
+                            previous.path.display()
+                        )));
+                    }
+                }
+            }
+        };
+        if let Err(e) = fs::remove_dir_all(&placeholder) {
+            warn!("Failed to remove {}: {}", placeholder.display(), e);
+        }
+        let (new_vault, mut new_store) = reopened;
+        new_store.set_compression(compress);
+        workspace::unlock_remembered(&mut new_store, &new_vault, &active_workspace(app));
+        *vault = new_vault;
+        *store = new_store;
+        if let Some(e) = failed {
+            return Err(NotesError::Io(format!(
+                "{} The previous notes were put back; they're also backed up in {}",
+                e,
+                previous.path.display()
+            )));
+        }
+
+        // Not to find the replaced notes until it's rebuilt
+        index.lock().unwrap().rebuild(&[])?;
+        drop((vault, store));
+        request_reindex(app);
+        emit_change(app, None, NoteOperation::Reloaded);
+        rewatch(app);
+        Ok(previous)
+    })
+    .await
+}
+
+/// What's useful to know about this installation when looking into a bug
+/// report.
+#[derive(Serialize)]
+struct Diagnostics {
+    app_version: String,
+    storage_backend: BackendKind,
+    /// `None` while the vault is locked.
+    note_count: Option<usize>,
+    data_dir: PathBuf,
+    log_dir: PathBuf,
+    /// The most recent successful sync with any configured target.
+    last_synced_at: Option<i64>,
+    /// Warnings and errors logged since the app started, oldest first.
+    recent_errors: Vec<LoggedError>,
+}
+
+#[tauri::command]
+async fn get_diagnostics(app: AppHandle) -> Result<Diagnostics, NotesError> {
+    blocking(app, move |app| {
+        let data_dir = data_dir(app);
+        let mut last_synced_at = None;
+        for target in [
+            SyncTarget::WebDav,
+            SyncTarget::S3,
+            SyncTarget::Folder,
+            SyncTarget::Server,
+        ] {
+            let status = sync::status(&data_dir, target)?;
+            if status.configured {
+                last_synced_at = last_synced_at.max(status.last_synced_at);
+            }
+        }
+        let (storage_backend, note_count) = {
+            let store = app.state::<Mutex<NotesStore>>().inner();
+            let store = store.lock().unwrap();
+            (
+                store.backend_kind(),
+                store.all_notes().ok().map(<[Note]>::len),
+            )
+        };
+        let logging = app.state::<Logging>().inner();
+        Ok(Diagnostics {
+            app_version: app.package_info().version.to_string(),
+            storage_backend,
+            note_count,
+            data_dir,
+            log_dir: logging.dir().to_path_buf(),
+            last_synced_at,
+            recent_errors: logging.recent_errors(),
+        })
+    })
+    .await
+}
+
+/// The note named on the command line the app was started with, until the
+/// main window asks for it with `take_launch_note`.
+struct LaunchNote(Mutex<Option<String>>);
+
+/// Returns the note to open at startup, if the app was started with one.
+#[tauri::command]
+fn take_launch_note(app: AppHandle) -> Option<String> {
+    app.state::<LaunchNote>().0.lock().unwrap().take()
+}
+
+/// Reads the note id in a command line such as `min_notes <id>`, skipping
+/// the program and any flags the OS added.
+fn note_arg(args: &[String]) -> Option<&str> {
+    args.iter()
+        .skip(1)
+        .map(String::as_str)
+        .find(|arg| !arg.starts_with('-'))
+        .filter(|arg| note::is_valid_id(arg))
+}
+
+/// Handles a second launch of the app, whose command line is forwarded here
+/// before it exits, by showing this instance and the note it named.
+#[cfg(desktop)]
+fn handle_second_launch(app: &AppHandle, args: &[String]) {
+    match note_arg(args) {
+        Some(id) => open_note(app, id),
+        None => show_main_window(app),
+    }
+}
+
+/// Follows a parsed `min-notes://` link, creating the note first if it asks
+/// for a new one, and returns the id of the note to open.
+fn follow_link(app: &AppHandle, link: DeepLink) -> Result<String, NotesError> {
+    match link {
+        DeepLink::OpenNote(id) => Ok(id),
+        DeepLink::NewNote { title, content } => {
+            let title = if title.trim().is_empty() {
+                "Untitled".to_string()
+            } else {
+                title
+            };
+            insert_new_note(app, title, content, Vec::new())
+        }
+        DeepLink::Share(text) => capture(app, &text),
+    }
+}
+
+/// Handles the links the app is asked to open: at startup, the first one is
+/// opened once the main window asks for it, and later ones right away. On
+/// mobile, shares from other apps come in as links too, so one that launched
+/// the app is captured as it starts.
+fn init_deep_links(app: &AppHandle) {
+    use tauri_plugin_deep_link::DeepLinkExt;
+
+    // Installed builds register the scheme when they're installed
+    #[cfg(any(target_os = "linux", all(debug_assertions, windows)))]
+    if let Err(e) = app.deep_link().register_all() {
+        warn!("Failed to register {}:// links: {}", deeplink::SCHEME, e);
+    }
+    match app.deep_link().get_current() {
+        Ok(urls) => {
+            let urls = urls.unwrap_or_default();
+            match deeplink::follow_launch(&urls, |link| follow_link(app, link)) {
+                Ok(Some(id)) => *app.state::<LaunchNote>().0.lock().unwrap() = Some(id),
+                Ok(None) => {}
+                Err(e) => warn!("Failed to open {}: {}", urls[0], e),
+            }
+        }
+        Err(e) => warn!("Failed to read the link the app was opened with: {}", e),
+    }
+
+    let handle = app.clone();
+    app.deep_link().on_open_url(move |event| {
+        let app = handle.clone();
+        let urls = event.urls();
+        // Creating a note touches the disk, so keep it off the event loop
+        tauri::async_runtime::spawn_blocking(move || {
+            for url in urls {
+                match deeplink::parse(url.as_str()).and_then(|link| follow_link(&app, link)) {
+                    Ok(id) => open_note(&app, &id),
+                    Err(e) => warn!("Failed to open {}: {}", url, e),
+                }
+            }
+        });
+    });
+}
+
+/// Runs the subcommand in `args`, the command line without the program
+/// name, and returns the exit code, or `None` if there's no subcommand and
+/// the app should start.
+pub fn run_cli(args: &[String]) -> Option<i32> {
+    cli::main(args)
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    let builder = tauri::Builder::default();
+    // Registered first, so a second launch exits before anything else starts
+    #[cfg(desktop)]
+    let builder = builder.plugin(tauri_plugin_single_instance::init(|app, args, _cwd| {
+        handle_second_launch(app, &args)
+    }));
+    #[cfg(desktop)]
+    let builder = builder.plugin(tauri_plugin_clipboard_manager::init());
+    #[cfg(mobile)]
+    let builder = builder
+        .plugin(tauri_plugin_biometric::init())
+        .plugin(tauri_plugin_geolocation::init());
+    builder
+        .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_fs::init())
+        .plugin(tauri_plugin_notification::init())
+        .plugin(tauri_plugin_deep_link::init())
+        .setup(|app| {
+            let logging = logging::init(&app_data_dir(app.handle())?.join("logs"))?;
+            app.manage(logging);
+            info!("Starting min_notes {}", app.handle().package_info().version);
+
+            let settings = Settings::load(&settings_path(app.handle())?)?;
+            let workspaces = Workspaces::load(&workspaces_path(app.handle())?)?;
+            let data_dir = workspaces.active_dir(|| default_data_dir(app.handle(), &settings))?;
+            let (vault, mut store) = workspace::open(&data_dir)?;
+            store.set_compression(settings.compress_notes);
+            workspace::unlock_remembered(&mut store, &vault, workspaces.active_id());
+            app.manage(Mutex::new(vault));
+            app.manage(Mutex::new(store));
+            // Filled in the background, or once an encrypted store is unlocked
+            app.manage(Mutex::new(SearchIndex::new()?));
+            app.manage(Mutex::new(Drafts::default()));
+            app.manage(PendingPreviews::default());
+            app.manage(Mutex::new(settings));
+            app.manage(DataDir(Mutex::new(data_dir)));
+            app.manage(Mutex::new(workspaces));
+            let args: Vec<String> = std::env::args().collect();
+            app.manage(ActiveNote(Mutex::new(None)));
+            app.manage(ClipboardCapture(Mutex::new(None)));
+            app.manage(LaunchNote(Mutex::new(note_arg(&args).map(str::to_string))));
+            init_deep_links(app.handle());
+            app.manage(Mutex::new(None::<Clipper>));
+            if let Err(e) = apply_clipper_settings(app.handle()) {
+                warn!("Failed to start web clipper: {}", e);
+            }
+
+            let handle = app.handle().clone();
+            std::thread::spawn(move || run_scheduled_backups(handle));
+            let handle = app.handle().clone();
+            std::thread::spawn(move || run_reminders(handle));
+            let handle = app.handle().clone();
+            std::thread::spawn(move || run_expiry(handle));
+            let handle = app.handle().clone();
+            std::thread::spawn(move || run_auto_archive(handle));
+            let (watch, requests) = Watch::new();
+            app.manage(StorageWatch(watch));
+            let handle = app.handle().clone();
+            std::thread::spawn(move || run_storage_watch(handle, requests));
+            let (watch, requests) = Watch::new();
+            app.manage(FolderSync(watch));
+            let handle = app.handle().clone();
+            std::thread::spawn(move || run_folder_sync(handle, requests));
+            let handle = app.handle().clone();
+            std::thread::spawn(move || run_server_sync(handle));
+            let (jobs, queued) = mpsc::channel();
+            app.manage(IndexQueue {
+                jobs,
+                reindex_queued: AtomicBool::new(false),
+                ocr_queued: AtomicUsize::new(0),
+            });
+            let handle = app.handle().clone();
+            std::thread::spawn(move || run_indexer(handle, queued));
+            request_reindex(app.handle());
+            rewatch(app.handle());
+
+            #[cfg(desktop)]
+            {
+                use tauri_plugin_global_shortcut::ShortcutState;
+                tray::init(app.handle())?;
+                app.handle().plugin(
+                    tauri_plugin_global_shortcut::Builder::new()
+                        .with_shortcut(CAPTURE_SHORTCUT)?
+                        .with_handler(|app, _shortcut, event| {
+                            if event.state() == ShortcutState::Pressed {
+                                if let Err(e) = open_capture_window(app) {
+                                    warn!("{}", e);
+                                }
+                            }
+                        })
+                        .build(),
+                )?;
+            }
+            Ok(())
+        })
+        .on_window_event(|window, event| {
+            if let tauri::WindowEvent::DragDrop(tauri::DragDropEvent::Drop { paths, .. }) = event {
+                if window.label() == "main" {
+                    let (app, paths) = (window.app_handle().clone(), paths.clone());
+                    // Importing touches the disk, so keep it off the event loop
+                    tauri::async_runtime::spawn_blocking(move || {
+                        take_in_dropped_files(&app, paths)
+                    });
+                }
+            }
+            // Keep running in the tray when the main window is closed
+            #[cfg(desktop)]
+            if let tauri::WindowEvent::CloseRequested { api, .. } = event {
+                if window.label() == "main" {
+                    api.prevent_close();
+                    if let Err(e) = window.hide() {
+                        warn!("Failed to hide main window: {}", e);
+                    }
+                }
+            }
+        })
         .invoke_handler(tauri::generate_handler![
             create_note,
+            quick_capture,
+            duplicate_note,
+            merge_notes,
+            list_templates,
+            create_template,
+            update_template,
+            delete_template,
+            create_note_from_template,
+            list_notebooks,
+            create_notebook,
+            update_notebook,
+            delete_notebook,
+            move_note,
+            load_notes_in_notebook,
+            open_daily_note,
             update_note,
+            autosave_draft,
+            trash_note,
+            restore_note,
+            empty_trash,
+            load_trashed_notes,
             delete_note,
-            load_notes
+            bulk_update,
+            bulk_delete,
+            lock_note,
+            unlock_note,
+            remove_note_lock,
+            remember_vault_key,
+            clear_secret,
+            unlock_with_biometrics,
+            get_local_changes,
+            apply_remote_changes,
+            load_notes,
+            query_notes,
+            pin_note,
+            archive_note,
+            toggle_favorite,
+            set_note_color,
+            load_favorites,
+            set_note_meta,
+            query_notes_by_meta,
+            get_board,
+            move_card,
+            set_note_tags,
+            add_attachment,
+            add_photo,
+            set_active_note,
+            start_clipboard_capture,
+            stop_clipboard_capture,
+            archive_url,
+            ocr_attachment,
+            semantic_search,
+            transcribe_attachment,
+            get_attachment,
+            remove_attachment,
+            list_tags,
+            load_notes_by_tag,
+            get_note_history,
+            get_revision,
+            restore_revision,
+            export_note,
+            export_all_notes,
+            export_notes,
+            export_anki,
+            export_site,
+            share_note,
+            unshare_note,
+            configure_sharing,
+            email_note,
+            configure_email,
+            summarize_note,
+            suggest_title,
+            configure_assistant,
+            get_link_previews,
+            refresh_link_previews,
+            export_vault_zip,
+            import_vault_zip,
+            import_notes,
+            import_directory,
+            recover_notes,
+            verify_integrity,
+            compact_store,
+            get_storage_report,
+            get_storage_backend,
+            set_storage_backend,
+            get_vault_status,
+            set_master_password,
+            unlock_vault,
+            lock_vault,
+            get_note_git_log,
+            set_git_remote,
+            git_push,
+            git_pull,
+            configure_sync,
+            configure_s3_sync,
+            configure_folder_sync,
+            configure_server_sync,
+            sync_now,
+            get_sync_status,
+            get_settings,
+            update_settings,
+            set_notes_directory,
+            list_vaults,
+            create_vault,
+            switch_vault,
+            search_notes,
+            list_saved_searches,
+            save_search,
+            delete_saved_search,
+            run_saved_search,
+            load_notes_between,
+            get_calendar_heatmap,
+            record_note_opened,
+            get_recent_notes,
+            quick_switch,
+            undo,
+            redo,
+            load_note_summaries,
+            get_note,
+            preview_auto_archive,
+            load_notes_near,
+            get_note_stats,
+            get_vault_stats,
+            get_backlinks,
+            render_markdown,
+            get_highlight_css,
+            get_outgoing_links,
+            get_notes_graph,
+            get_related_notes,
+            replace_in_notes,
+            list_tasks,
+            toggle_task,
+            set_reminder,
+            list_reminders,
+            cancel_reminder,
+            set_expiry,
+            get_diagnostics,
+            create_backup_now,
+            list_backups,
+            restore_backup,
+            take_launch_note,
+            get_clipper_token
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                // Failures are logged per note
+                let _ = flush_drafts(app);
+            }
+        });
 }
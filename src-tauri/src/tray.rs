@@ -0,0 +1,114 @@
+//! The system tray icon. Its menu lists the most recently updated notes and
+//! is rebuilt whenever notes change.
+
+use crate::store::NotesStore;
+use crate::{insert_new_note, open_note, NOTES_CHANGED};
+use std::sync::Mutex;
+use tauri::menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem};
+use tauri::tray::TrayIconBuilder;
+use tauri::{AppHandle, Listener, Manager};
+use tracing::warn;
+
+const TRAY_ID: &str = "main";
+const RECENT_NOTES: usize = 5;
+/// Longer titles are cut short so the menu stays narrow.
+const MAX_TITLE_CHARS: usize = 40;
+
+const NEW_NOTE_ID: &str = "new-note";
+const QUIT_ID: &str = "quit";
+const OPEN_PREFIX: &str = "open:";
+
+pub fn init(app: &AppHandle) -> tauri::Result<()> {
+    let mut tray = TrayIconBuilder::with_id(TRAY_ID)
+        .tooltip("min_notes")
+        .menu(&build_menu(app)?)
+        .on_menu_event(handle_menu_event);
+    if let Some(icon) = app.default_window_icon() {
+        tray = tray.icon(icon.clone());
+    }
+    tray.build(app)?;
+
+    let handle = app.clone();
+    app.listen(NOTES_CHANGED, move |_| {
+        if let Err(e) = refresh(&handle) {
+            warn!("Failed to update tray menu: {}", e);
+        }
+    });
+    Ok(())
+}
+
+fn refresh(app: &AppHandle) -> tauri::Result<()> {
+    if let Some(tray) = app.tray_by_id(TRAY_ID) {
+        tray.set_menu(Some(build_menu(app)?))?;
+    }
+    Ok(())
+}
+
+fn build_menu(app: &AppHandle) -> tauri::Result<Menu<tauri::Wry>> {
+    let menu = Menu::new(app)?;
+    let store = app.state::<Mutex<NotesStore>>().inner();
+    // A locked store has nothing to list
+    let recent = store
+        .lock()
+        .unwrap()
+        .recent(RECENT_NOTES)
+        .unwrap_or_default();
+    for note in &recent {
+        let id = format!("{}{}", OPEN_PREFIX, note.id);
+        menu.append(&MenuItem::with_id(
+            app,
+            id,
+            menu_title(&note.title),
+            true,
+            None::<&str>,
+        )?)?;
+    }
+    if !recent.is_empty() {
+        menu.append(&PredefinedMenuItem::separator(app)?)?;
+    }
+    menu.append(&MenuItem::with_id(
+        app,
+        NEW_NOTE_ID,
+        "New note",
+        true,
+        None::<&str>,
+    )?)?;
+    menu.append(&MenuItem::with_id(
+        app,
+        QUIT_ID,
+        "Quit",
+        true,
+        None::<&str>,
+    )?)?;
+    Ok(menu)
+}
+
+fn menu_title(title: &str) -> String {
+    let title = title.trim();
+    if title.is_empty() {
+        return "Untitled".to_string();
+    }
+    if title.chars().count() > MAX_TITLE_CHARS {
+        let short: String = title.chars().take(MAX_TITLE_CHARS - 1).collect();
+        return format!("{}…", short);
+    }
+    title.to_string()
+}
+
+fn handle_menu_event(app: &AppHandle, event: MenuEvent) {
+    let id = event.id().as_ref();
+    if id == QUIT_ID {
+        app.exit(0);
+    } else if id == NEW_NOTE_ID {
+        // Saving touches the disk, so keep it off the event loop
+        let app = app.clone();
+        tauri::async_runtime::spawn_blocking(move || {
+            match insert_new_note(&app, "Untitled".into(), String::new(), Vec::new()) {
+                Ok(id) => open_note(&app, &id),
+                Err(e) => warn!("Failed to create note: {}", e),
+            }
+        });
+    } else if let Some(note_id) = id.strip_prefix(OPEN_PREFIX) {
+        open_note(app, note_id);
+    }
+}
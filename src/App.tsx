@@ -1,13 +1,64 @@
 import { useState, useEffect, useRef } from "react";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import { useDebounce } from "./hooks/debounce";
+import { errorMessage, isNotesError } from "./errors";
 import "./styles.css";
 
 interface Note {
   id: string;
   title: string;
   content: string;
-  timestamp: number;
+  created_at: number;
+  updated_at: number;
+  tags: string[];
+  pinned: boolean;
+  archived: boolean;
+  favorite: boolean;
+  color: string | null;
+  notebook_id: string | null;
+  locked: boolean;
+  content_hash: string;
+  attachments: Attachment[];
+  reminders: Reminder[];
+  meta?: Record<string, string>;
+}
+
+interface Attachment {
+  id: string;
+  name: string;
+  mime: string;
+  size: number;
+  added_at: number;
+  text?: string;
+}
+
+interface Reminder {
+  id: string;
+  due_at: number;
+  repeat: "never" | "daily" | "weekly" | "monthly";
+}
+
+interface VaultStatus {
+  enabled: boolean;
+  locked: boolean;
+  biometric_unlock: boolean;
+  key_remembered: boolean;
+}
+
+interface SearchHit {
+  id: string;
+  title: string;
+  score: number;
+  snippet: string;
+  highlights: Highlight[];
+  title_highlights: Highlight[];
+}
+
+/** Character offsets, end exclusive. */
+interface Highlight {
+  start: number;
+  end: number;
 }
 
 function App() {
@@ -18,13 +69,60 @@ function App() {
   const [isLoading, setIsLoading] = useState(false);
   const [message, setMessage] = useState("");
   const [isSaving, setIsSaving] = useState(false);
+  const [isLocked, setIsLocked] = useState(false);
+  const [password, setPassword] = useState("");
+  const [searchQuery, setSearchQuery] = useState("");
+  const [searchHits, setSearchHits] = useState<SearchHit[] | null>(null);
   const textareaRef = useRef<HTMLTextAreaElement>(null);
   const debouncedContent = useDebounce(selectedNote?.content, 800);
+  const debouncedQuery = useDebounce(searchQuery, 300);
 
   useEffect(() => {
-    loadNotes();
+    invoke<VaultStatus>("get_vault_status").then((status) => {
+      setIsLocked(status.locked);
+      if (!status.locked) loadNotes();
+    });
   }, []);
 
+  // Pick up changes made from other windows and background syncs
+  useEffect(() => {
+    const unlisten = listen("notes://changed", () => loadNotes());
+    return () => {
+      unlisten.then((stop) => stop());
+    };
+  }, []);
+
+  // Notes picked from the tray menu, named when launching the app, or named
+  // when launching it again while it's running
+  useEffect(() => {
+    const openNote = async (id: string) => {
+      const loadedNotes = await invoke<Note[]>("load_notes");
+      setNotes(loadedNotes);
+      const note = loadedNotes.find((note) => note.id === id);
+      if (note) handleSelctedNote(note);
+    };
+    invoke<string | null>("take_launch_note").then((id) => {
+      if (id) openNote(id);
+    });
+    const unlisten = listen<string>("notes://open", (event) => openNote(event.payload));
+    return () => {
+      unlisten.then((stop) => stop());
+    };
+  }, []);
+
+  const unlockVault = async () => {
+    setMessage("");
+    try {
+      await invoke("unlock_vault", { password });
+      setPassword("");
+      setIsLocked(false);
+      await loadNotes();
+    } catch (error) {
+      console.error("Failed to unlock vault:", error);
+      setMessage(errorMessage(error));
+    }
+  };
+
   const handleSelctedNote = (note: Note) => {
     setSelectedNote(note);
     setIsEditingTitle(false);
@@ -47,17 +145,29 @@ function App() {
     setMessage("");
     setIsSaving(true);
     try {
-      await invoke("update_note", {
+      const saved = await invoke<Note>("update_note", {
         id: selectedNote?.id,
         content: selectedNote?.content,
         title: selectedNote?.title || "Untitled",
+        updatedAt: selectedNote?.updated_at,
       });
+      // Later saves are of this version
+      setSelectedNote((current) =>
+        current?.id === saved.id ? { ...current, updated_at: saved.updated_at } : current
+      );
       await loadNotes();
+      setMessage("Note updated automatically!");
     } catch (error) {
       console.error("Failed to update note:", error);
-      setMessage("Failed to update note");
+      if (isNotesError(error) && error.current) {
+        const current = error.current as Note;
+        setSelectedNote(current);
+        setOriginalNote({ title: current.title, content: current.content });
+        setMessage(error.message);
+      } else {
+        setMessage("Failed to update note");
+      }
     } finally {
-      setMessage("Note updated automatically!");
       setIsSaving(false);
     }
   };
@@ -71,13 +181,36 @@ function App() {
     }
   }, [debouncedContent, selectedNote?.title, originalNote]);
 
+  useEffect(() => {
+    if (!debouncedQuery.trim()) {
+      setSearchHits(null);
+      return;
+    }
+    invoke<SearchHit[]>("search_notes", { query: debouncedQuery })
+      .then(setSearchHits)
+      .catch((error) => {
+        console.error("Failed to search notes:", error);
+        setMessage("Failed to search notes");
+      });
+  }, [debouncedQuery, notes]);
+
+  const visibleNotes = searchHits
+    ? searchHits
+      .map((hit) => notes.find((note) => note.id === hit.id))
+      .filter((note): note is Note => note !== undefined)
+    : notes;
+
   const loadNotes = async () => {
     try {
       const loadedNotes = await invoke<Note[]>("load_notes");
       setNotes(loadedNotes);
     } catch (error) {
       console.error("Failed to load notes:", error);
-      setMessage("Failed to load notes");
+      if (isNotesError(error) && error.kind === "locked") {
+        setIsLocked(true);
+        return;
+      }
+      setMessage(errorMessage(error));
     }
   };
 
@@ -106,16 +239,47 @@ function App() {
   const handleDelete = async (id: string) => {
     setMessage("");
     try {
-      await invoke("delete_note", { id });
-      setMessage("Note deleted!");
+      await invoke("trash_note", { id });
+      setMessage("Note moved to trash!");
       setSelectedNote(null);
       await loadNotes();
     } catch (error) {
-      console.error("Failed to delete note:", error);
-      setMessage("Failed to delete note");
+      console.error("Failed to trash note:", error);
+      setMessage("Failed to trash note");
     }
   };
 
+  if (isLocked) {
+    return (
+      <main className="flex flex-col items-center justify-center bg-slate-900 h-full w-full text-slate-100">
+        <span className="text-2xl mb-4">🔒</span>
+        <form
+          className="flex gap-2"
+          onSubmit={(e) => {
+            e.preventDefault();
+            unlockVault();
+          }}
+        >
+          <input
+            type="password"
+            value={password}
+            autoFocus
+            onChange={(e) => setPassword(e.target.value)}
+            className="px-2 py-1 rounded bg-slate-800 text-slate-100 focus:outline-none"
+            placeholder="Master password"
+          />
+          <button
+            type="submit"
+            className="bg-blue-600 text-white px-3 py-1 rounded hover:bg-blue-900"
+          >
+            Unlock
+          </button>
+        </form>
+        {message && <p className="mt-2 text-sm text-red-400">{message}</p>}
+      </main>
+    );
+  }
+
   return (
     <main className="flex bg-slate-900 h-full w-full">
       <div className="w-1/4 px-2 pt-4">
@@ -131,12 +295,21 @@ function App() {
               +
             </button>
           </div>
+          <input
+            type="search"
+            value={searchQuery}
+            onChange={(e) => setSearchQuery(e.target.value)}
+            className="mb-2 px-2 py-1 rounded bg-slate-800 text-slate-100 placeholder-slate-400 focus:outline-none"
+            placeholder="Search notes..."
+          />
           <div className="overflow-y-auto flex-1">
-            {notes.length === 0 ? (
-              <p className="text-slate-100 text-center mt-8">No notes yet.</p>
+            {visibleNotes.length === 0 ? (
+              <p className="text-slate-100 text-center mt-8">
+                {searchHits ? "No matching notes." : "No notes yet."}
+              </p>
             ) : (
               <ul>
-                {notes.map((note) => (
+                {visibleNotes.map((note) => (
                   <li
                     key={note.id}
                     className={`p-3 mb-2 rounded cursor-pointer border ${selectedNote?.id === note.id
@@ -149,7 +322,7 @@ function App() {
                       {note.title}
                     </div>
                     <div className="text-xs text-slate-100">
-                      {formatDate(note.timestamp)}
+                      {formatDate(note.updated_at)}
                     </div>
                   </li>
                 ))}
@@ -189,7 +362,7 @@ function App() {
                   </h2>
                 )}
                 <div className="text-xs text-slate-100 mb-4">
-                  {formatDate(selectedNote.timestamp)}
+                  {formatDate(selectedNote.updated_at)}
                 </div>
               </div>
               <textarea
@@ -0,0 +1,55 @@
+import { useState } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { getCurrentWindow } from "@tauri-apps/api/window";
+import { errorMessage, isNotesError } from "./errors";
+import "./styles.css";
+
+function QuickCapture() {
+  const [content, setContent] = useState("");
+  const [message, setMessage] = useState("");
+
+  const close = async () => {
+    setContent("");
+    setMessage("");
+    await getCurrentWindow().hide();
+  };
+
+  const save = async () => {
+    try {
+      await invoke("quick_capture", { content });
+      await close();
+    } catch (error) {
+      console.error("Failed to capture note:", error);
+      setMessage(
+        isNotesError(error) && error.kind === "locked"
+          ? "Unlock your notes in the main window first"
+          : errorMessage(error)
+      );
+    }
+  };
+
+  const handleKeyDown = (e: React.KeyboardEvent<HTMLTextAreaElement>) => {
+    if (e.key === "Enter" && (e.metaKey || e.ctrlKey)) {
+      e.preventDefault();
+      save();
+    } else if (e.key === "Escape") {
+      close();
+    }
+  };
+
+  return (
+    <main className="flex flex-col bg-slate-900 h-full w-full p-2 text-slate-100">
+      <textarea
+        autoFocus
+        value={content}
+        placeholder="Jot something down… (Ctrl+Enter to save, Esc to close)"
+        onChange={(e) => setContent(e.target.value)}
+        onKeyDown={handleKeyDown}
+        className="flex-1 w-full p-1 resize-none font-mono bg-slate-900 text-slate-100 focus:outline-none"
+      />
+      {message && <p className="text-sm text-red-400">{message}</p>}
+    </main>
+  );
+}
+
+export default QuickCapture;
@@ -0,0 +1,41 @@
+// Errors rejected by commands, as serialized by the backend's NotesError.
+
+export type NotesErrorKind =
+  | "io"
+  | "serde"
+  | "database"
+  | "search"
+  | "crypto"
+  | "network"
+  | "git"
+  | "keychain"
+  | "notFound"
+  | "locked"
+  | "busy"
+  | "conflict"
+  | "invalid"
+  | "validation"
+  | "internal";
+
+export interface NotesError {
+  kind: NotesErrorKind;
+  message: string;
+  // For "validation" errors, the note field that was refused.
+  field?: "title" | "content";
+  // For "conflict" errors from a note saved elsewhere in the meantime, the
+  // stored copy of the note.
+  current?: unknown;
+}
+
+export function isNotesError(error: unknown): error is NotesError {
+  return (
+    typeof error === "object" &&
+    error !== null &&
+    "kind" in error &&
+    "message" in error
+  );
+}
+
+export function errorMessage(error: unknown): string {
+  return isNotesError(error) ? error.message : String(error);
+}
@@ -1,9 +1,10 @@
 import React from "react";
 import ReactDOM from "react-dom/client";
 import App from "./App";
+import QuickCapture from "./QuickCapture";
 
 ReactDOM.createRoot(document.getElementById("root") as HTMLElement).render(
   <React.StrictMode>
-    <App />
+    {window.location.hash === "#capture" ? <QuickCapture /> : <App />}
   </React.StrictMode>,
 );